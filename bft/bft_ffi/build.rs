@@ -0,0 +1,11 @@
+//! Writes the C header for the library's functions to include/bft.h
+
+fn main() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("Cargo sets the manifest dir");
+    let config = cbindgen::Config::from_root_or_default(&crate_dir);
+    cbindgen::generate_with_config(&crate_dir, config)
+        .expect("The library's interface can be described in C")
+        .write_to_file(format!("{crate_dir}/include/bft.h"));
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+}
@@ -0,0 +1,116 @@
+#ifndef BFT_H
+#define BFT_H
+
+/* Generated by cbindgen from bft_ffi. Don't edit it by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * A machine and the program loaded into it, along with the output and error of its last run
+ */
+typedef struct BftMachine BftMachine;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a machine with `cells` cells, or the default number if it's 0, which may grow if
+ * `extensible` is true
+ *
+ * Free it with [bft_machine_free].
+ */
+struct BftMachine *bft_machine_new(size_t cells, bool extensible);
+
+/**
+ * Frees a machine made by [bft_machine_new]
+ *
+ * # Safety
+ * `machine` must be null or a machine from [bft_machine_new] that hasn't been freed yet.
+ */
+void bft_machine_free(struct BftMachine *machine);
+
+/**
+ * Limits the steps, bytes of output and cells of tape each run may use, where 0 means no limit
+ *
+ * # Safety
+ * `machine` must be a live machine from [bft_machine_new].
+ */
+void bft_machine_set_limits(struct BftMachine *machine,
+                            uint64_t max_steps,
+                            uint64_t max_output_bytes,
+                            size_t max_tape);
+
+/**
+ * Parses the NUL-terminated `source` and loads it into the machine, replacing any program loaded
+ * before
+ *
+ * # Safety
+ * `machine` must be a live machine from [bft_machine_new], and `source` a NUL-terminated string.
+ */
+int bft_machine_load(struct BftMachine *machine, const char *source);
+
+/**
+ * Runs the loaded program from the start on a fresh tape, reading `input_length` bytes from
+ * `input`
+ *
+ * Output is kept for [bft_machine_output], even when the run fails.
+ *
+ * # Safety
+ * `machine` must be a live machine from [bft_machine_new], and `input` must point to
+ * `input_length` bytes, or may be null if `input_length` is 0.
+ */
+int bft_machine_run(struct BftMachine *machine, const uint8_t *input, size_t input_length);
+
+/**
+ * The output of the last run, setting `length` to how many bytes there are
+ *
+ * # Safety
+ * `machine` must be a live machine from [bft_machine_new], and `length` must point to a size_t.
+ */
+const uint8_t *bft_machine_output(const struct BftMachine *machine, size_t *length);
+
+/**
+ * The tape as the last run left it, setting `length` to how many cells there are
+ *
+ * Returns null, with a length of 0, if nothing has been run since the program was loaded.
+ *
+ * # Safety
+ * `machine` must be a live machine from [bft_machine_new], and `length` must point to a size_t.
+ */
+const uint8_t *bft_machine_cells(const struct BftMachine *machine, size_t *length);
+
+/**
+ * The cell the head was on when the last run ended
+ *
+ * # Safety
+ * `machine` must be a live machine from [bft_machine_new].
+ */
+size_t bft_machine_head(const struct BftMachine *machine);
+
+/**
+ * A message saying why the last call failed, or null if it succeeded
+ *
+ * # Safety
+ * `machine` must be a live machine from [bft_machine_new].
+ */
+const char *bft_machine_error(const struct BftMachine *machine);
+
+/**
+ * The stable code of the error that made the last call fail, such as "E0204", or null if it
+ * succeeded
+ *
+ * # Safety
+ * `machine` must be a live machine from [bft_machine_new].
+ */
+const char *bft_machine_error_code(const struct BftMachine *machine);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BFT_H */
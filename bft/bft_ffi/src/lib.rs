@@ -0,0 +1,278 @@
+//! A C interface to the interpreter, so programs in other languages can embed it
+//!
+//! Build the library with `cargo build -p bft_ffi` and include `include/bft.h`, which is
+//! regenerated from this file on every build.
+//!
+//! ```c
+//! #include "bft.h"
+//!
+//! BftMachine *machine = bft_machine_new(30000, false);
+//! bft_machine_set_limits(machine, 1000000, 0, 0);
+//! if (bft_machine_load(machine, ",[.,]") != 0 ||
+//!     bft_machine_run(machine, (const uint8_t *)"hi", 2) != 0) {
+//!     fprintf(stderr, "%s: %s\n", bft_machine_error_code(machine), bft_machine_error(machine));
+//! }
+//! size_t length;
+//! const uint8_t *output = bft_machine_output(machine, &length);
+//! bft_machine_free(machine);
+//! ```
+//!
+//! Functions that can fail return 0 on success and -1 on failure, after which
+//! [bft_machine_error] says why. Pointers returned by the library stay valid until the next call
+//! that changes the machine.
+
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::num::NonZeroUsize;
+use std::ptr;
+
+use bft_interp::error::BftError;
+use bft_interp::limits::Limits;
+use bft_interp::messages::{Lang, MessageId};
+use bft_interp::Machine;
+use bft_types::{DecoratedProgram, Program};
+
+/// A machine and the program loaded into it, along with the output and error of its last run
+pub struct BftMachine {
+    cells: Option<NonZeroUsize>,
+    extensible: bool,
+    limits: Limits,
+    program: Option<DecoratedProgram>,
+    machine: Option<Machine<'static, u8>>,
+    output: Vec<u8>,
+    error: Option<(CString, CString)>,
+}
+
+impl BftMachine {
+    /// Records `error` as the reason the last call failed, and returns the failure status
+    fn fail(&mut self, error: BftError) -> c_int {
+        let code = CString::new(error.code()).expect("Codes are plain ASCII");
+        // Messages can't contain NUL bytes, but the file names in them could
+        let message = CString::new(error.to_string().replace('\0', "\\0"))
+            .expect("NUL bytes have been replaced");
+        self.error = Some((code, message));
+        -1
+    }
+}
+
+/// Creates a machine with `cells` cells, or the default number if it's 0, which may grow if
+/// `extensible` is true
+///
+/// Free it with [bft_machine_free].
+#[no_mangle]
+pub extern "C" fn bft_machine_new(cells: usize, extensible: bool) -> *mut BftMachine {
+    Box::into_raw(Box::new(BftMachine {
+        cells: NonZeroUsize::new(cells),
+        extensible,
+        limits: Limits::default(),
+        program: None,
+        machine: None,
+        output: Vec::new(),
+        error: None,
+    }))
+}
+
+/// Frees a machine made by [bft_machine_new]
+///
+/// # Safety
+/// `machine` must be null or a machine from [bft_machine_new] that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn bft_machine_free(machine: *mut BftMachine) {
+    if !machine.is_null() {
+        drop(Box::from_raw(machine));
+    }
+}
+
+/// Limits the steps, bytes of output and cells of tape each run may use, where 0 means no limit
+///
+/// # Safety
+/// `machine` must be a live machine from [bft_machine_new].
+#[no_mangle]
+pub unsafe extern "C" fn bft_machine_set_limits(
+    machine: *mut BftMachine,
+    max_steps: u64,
+    max_output_bytes: u64,
+    max_tape: usize,
+) {
+    let machine = &mut *machine;
+    machine.limits = Limits {
+        max_steps: (max_steps != 0).then_some(max_steps),
+        max_output_bytes: (max_output_bytes != 0).then_some(max_output_bytes),
+        max_tape: (max_tape != 0).then_some(max_tape),
+        max_loop_iterations: None,
+    };
+}
+
+/// Parses the NUL-terminated `source` and loads it into the machine, replacing any program loaded
+/// before
+///
+/// # Safety
+/// `machine` must be a live machine from [bft_machine_new], and `source` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn bft_machine_load(
+    machine: *mut BftMachine,
+    source: *const c_char,
+) -> c_int {
+    let machine = &mut *machine;
+    machine.error = None;
+    let source = CStr::from_ptr(source).to_string_lossy();
+    match DecoratedProgram::from_program(&Program::new("<ffi>", &source)) {
+        Ok(program) => {
+            machine.program = Some(program);
+            machine.machine = None;
+            0
+        }
+        Err(error) => machine.fail(error.into()),
+    }
+}
+
+/// Runs the loaded program from the start on a fresh tape, reading `input_length` bytes from
+/// `input`
+///
+/// Output is kept for [bft_machine_output], even when the run fails.
+///
+/// # Safety
+/// `machine` must be a live machine from [bft_machine_new], and `input` must point to
+/// `input_length` bytes, or may be null if `input_length` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn bft_machine_run(
+    machine: *mut BftMachine,
+    input: *const u8,
+    input_length: usize,
+) -> c_int {
+    let machine = &mut *machine;
+    machine.error = None;
+    machine.output.clear();
+    let Some(program) = machine.program.clone() else {
+        return machine.fail(BftError::Config(
+            Lang::English.template(MessageId::NoProgram).to_string(),
+        ));
+    };
+    let mut input = if input_length == 0 {
+        &[][..]
+    } else {
+        std::slice::from_raw_parts(input, input_length)
+    };
+    let mut vm = Machine::owning(machine.cells, machine.extensible, program);
+    vm.set_limits(machine.limits);
+    let result = vm.run(&mut input, &mut machine.output);
+    machine.machine = Some(vm);
+    match result {
+        Ok(()) => 0,
+        Err(error) => machine.fail(error.into()),
+    }
+}
+
+/// The output of the last run, setting `length` to how many bytes there are
+///
+/// # Safety
+/// `machine` must be a live machine from [bft_machine_new], and `length` must point to a size_t.
+#[no_mangle]
+pub unsafe extern "C" fn bft_machine_output(
+    machine: *const BftMachine,
+    length: *mut usize,
+) -> *const u8 {
+    let machine = &*machine;
+    *length = machine.output.len();
+    machine.output.as_ptr()
+}
+
+/// The tape as the last run left it, setting `length` to how many cells there are
+///
+/// Returns null, with a length of 0, if nothing has been run since the program was loaded.
+///
+/// # Safety
+/// `machine` must be a live machine from [bft_machine_new], and `length` must point to a size_t.
+#[no_mangle]
+pub unsafe extern "C" fn bft_machine_cells(
+    machine: *const BftMachine,
+    length: *mut usize,
+) -> *const u8 {
+    let machine = &*machine;
+    match &machine.machine {
+        Some(vm) => {
+            *length = vm.cells().len();
+            vm.cells().as_ptr()
+        }
+        None => {
+            *length = 0;
+            ptr::null()
+        }
+    }
+}
+
+/// The cell the head was on when the last run ended
+///
+/// # Safety
+/// `machine` must be a live machine from [bft_machine_new].
+#[no_mangle]
+pub unsafe extern "C" fn bft_machine_head(machine: *const BftMachine) -> usize {
+    (*machine).machine.as_ref().map_or(0, Machine::head)
+}
+
+/// A message saying why the last call failed, or null if it succeeded
+///
+/// # Safety
+/// `machine` must be a live machine from [bft_machine_new].
+#[no_mangle]
+pub unsafe extern "C" fn bft_machine_error(machine: *const BftMachine) -> *const c_char {
+    match &(*machine).error {
+        Some((_, message)) => message.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// The stable code of the error that made the last call fail, such as "E0204", or null if it
+/// succeeded
+///
+/// # Safety
+/// `machine` must be a live machine from [bft_machine_new].
+#[no_mangle]
+pub unsafe extern "C" fn bft_machine_error_code(machine: *const BftMachine) -> *const c_char {
+    match &(*machine).error {
+        Some((code, _)) => code.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn text(ptr: *const c_char) -> &'static str {
+        CStr::from_ptr(ptr).to_str().unwrap()
+    }
+
+    #[test]
+    fn programs_are_loaded_and_run() {
+        unsafe {
+            let machine = bft_machine_new(0, false);
+            assert_eq!(bft_machine_load(machine, c",[.+,]".as_ptr()), 0);
+            assert_eq!(bft_machine_run(machine, b"ab".as_ptr(), 2), -1);
+            assert_eq!(text(bft_machine_error_code(machine)), "E0203");
+
+            let mut length = 0;
+            let output = bft_machine_output(machine, &mut length);
+            assert_eq!(std::slice::from_raw_parts(output, length), b"ab");
+            let cells = bft_machine_cells(machine, &mut length);
+            assert_eq!(std::slice::from_raw_parts(cells, length)[0], b'c');
+
+            bft_machine_set_limits(machine, 0, 1, 0);
+            assert_eq!(bft_machine_run(machine, b"ab".as_ptr(), 2), -1);
+            assert_eq!(text(bft_machine_error_code(machine)), "E0204");
+            bft_machine_free(machine);
+        }
+    }
+
+    #[test]
+    fn parse_errors_are_reported() {
+        unsafe {
+            let machine = bft_machine_new(0, false);
+            assert_eq!(bft_machine_load(machine, c"+]".as_ptr()), -1);
+            assert_eq!(text(bft_machine_error_code(machine)), "E0101");
+            assert!(!bft_machine_error(machine).is_null());
+            assert_eq!(bft_machine_run(machine, ptr::null(), 0), -1);
+            assert_eq!(text(bft_machine_error_code(machine)), "E0301");
+            bft_machine_free(machine);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Times the reference interpreter's dispatch through a table of handlers on dbfi and mandelbrot
+//!
+//! The table replaced a loop that matched on each instruction as it came to it. Timed the same way
+//! on a release build, fastest of three runs:
+//!
+//! | program    | match (c31bf0a^) | table  | speedup |
+//! |------------|------------------|--------|---------|
+//! | dbfi       | 9.98s            | 2.73s  | 3.66x   |
+//! | mandelbrot | 30.73s           | 8.12s  | 3.78x   |
+//!
+//! To time the match loop again, check out the commit before c31bf0a and copy this file into it.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use bft_interp::Machine;
+use bft_types::{DecoratedProgram, Program};
+
+/// How many times each program is run, keeping the fastest
+const RUNS: usize = 3;
+
+fn main() {
+    let programs: [(&str, &str, &[u8]); 2] = [
+        (
+            "dbfi",
+            include_str!("../../programs/dbfi.b"),
+            concat!(include_str!("../../programs/sierpinski.b"), "!").as_bytes(),
+        ),
+        (
+            "mandelbrot",
+            include_str!("../../programs/mandelbrot.b"),
+            b"",
+        ),
+    ];
+    println!("{:<12}{:>12}", "program", "time");
+    for (name, source, input) in programs {
+        let prog = DecoratedProgram::from_program(&Program::new(name, source)).unwrap();
+        let time = fastest(|| {
+            let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+            machine.run(&mut &input[..], &mut io::sink()).unwrap();
+        });
+        println!("{:<12}{:>12}", name, format!("{time:.2?}"));
+    }
+}
+
+fn fastest(mut run: impl FnMut()) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
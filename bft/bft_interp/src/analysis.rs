@@ -0,0 +1,559 @@
+//! Abstract interpretation of cell values
+//!
+//! Works out the range and parity of values each cell may hold at every point in a program,
+//! without running it. This assumes 8-bit wrapping cells on a tape that starts zeroed, which is
+//! how `Machine<u8>` behaves.
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use bft_types::{DecoratedProgram, PositionedInstruction, RawInstruction};
+
+use crate::messages::{Lang, Localize};
+
+/// How many times a loop body is re-analysed before any values still changing are given up on
+const WIDEN_AFTER: usize = 3;
+
+/// Whether a cell's value is known to be even or odd
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Parity {
+    Even,
+    Odd,
+    Unknown,
+}
+
+impl Parity {
+    fn of(value: u8) -> Parity {
+        if value.is_multiple_of(2) {
+            Parity::Even
+        } else {
+            Parity::Odd
+        }
+    }
+
+    fn flip(self) -> Parity {
+        match self {
+            Parity::Even => Parity::Odd,
+            Parity::Odd => Parity::Even,
+            Parity::Unknown => Parity::Unknown,
+        }
+    }
+
+    fn join(self, other: Parity) -> Parity {
+        if self == other {
+            self
+        } else {
+            Parity::Unknown
+        }
+    }
+}
+
+/// Every value a cell might hold, described as an inclusive range and a parity
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CellRange {
+    min: u8,
+    max: u8,
+    parity: Parity,
+}
+
+impl CellRange {
+    /// A cell holding exactly one known value
+    pub fn exactly(value: u8) -> CellRange {
+        CellRange {
+            min: value,
+            max: value,
+            parity: Parity::of(value),
+        }
+    }
+
+    /// A cell that may hold any value
+    pub fn unknown() -> CellRange {
+        CellRange {
+            min: u8::MIN,
+            max: u8::MAX,
+            parity: Parity::Unknown,
+        }
+    }
+
+    /// The smallest value the cell may hold
+    pub fn min(&self) -> u8 {
+        self.min
+    }
+
+    /// The largest value the cell may hold
+    pub fn max(&self) -> u8 {
+        self.max
+    }
+
+    /// Whether the cell's value is known to be even or odd
+    pub fn parity(&self) -> Parity {
+        self.parity
+    }
+
+    /// Returns whether the cell is known to hold zero
+    pub fn is_zero(&self) -> bool {
+        self.max == 0
+    }
+
+    /// Returns whether the cell might hold zero
+    pub fn may_be_zero(&self) -> bool {
+        self.min == 0 && self.parity != Parity::Odd
+    }
+
+    /// Tightens the range to agree with the parity, or returns None if no value satisfies both
+    fn normalised(mut self) -> Option<CellRange> {
+        if self.min == self.max {
+            self.parity = Parity::of(self.min);
+        } else if self.parity != Parity::Unknown {
+            if Parity::of(self.min) != self.parity {
+                self.min += 1;
+            }
+            if Parity::of(self.max) != self.parity {
+                self.max -= 1;
+            }
+            if self.min > self.max {
+                return None;
+            }
+        }
+        Some(self)
+    }
+
+    /// Adds one to every possible value, also returning whether that may have wrapped around
+    fn increment(self) -> (CellRange, bool) {
+        if self.min == self.max {
+            (
+                CellRange::exactly(self.min.wrapping_add(1)),
+                self.min == u8::MAX,
+            )
+        } else if self.max == u8::MAX {
+            let parity = self.parity.flip();
+            (CellRange::unknown().with_parity(parity), true)
+        } else {
+            let range = CellRange {
+                min: self.min + 1,
+                max: self.max + 1,
+                parity: self.parity.flip(),
+            };
+            (range, false)
+        }
+    }
+
+    /// Subtracts one from every possible value, also returning whether that may have wrapped around
+    fn decrement(self) -> (CellRange, bool) {
+        if self.min == self.max {
+            (
+                CellRange::exactly(self.min.wrapping_sub(1)),
+                self.min == u8::MIN,
+            )
+        } else if self.min == u8::MIN {
+            let parity = self.parity.flip();
+            (CellRange::unknown().with_parity(parity), true)
+        } else {
+            let range = CellRange {
+                min: self.min - 1,
+                max: self.max - 1,
+                parity: self.parity.flip(),
+            };
+            (range, false)
+        }
+    }
+
+    fn with_parity(self, parity: Parity) -> CellRange {
+        CellRange { parity, ..self }
+            .normalised()
+            .expect("Every parity fits in a full range")
+    }
+
+    fn join(self, other: CellRange) -> CellRange {
+        CellRange {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            parity: self.parity.join(other.parity),
+        }
+    }
+
+    fn non_zero(self) -> Option<CellRange> {
+        if self.is_zero() {
+            None
+        } else if self.min == 0 {
+            CellRange { min: 1, ..self }.normalised()
+        } else {
+            Some(self)
+        }
+    }
+
+    fn zero(self) -> Option<CellRange> {
+        if self.may_be_zero() {
+            Some(CellRange::exactly(0))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for CellRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            return write!(f, "{}", self.min);
+        }
+        write!(f, "{}..={}", self.min, self.max)?;
+        match self.parity {
+            Parity::Even => write!(f, " (even)"),
+            Parity::Odd => write!(f, " (odd)"),
+            Parity::Unknown => Ok(()),
+        }
+    }
+}
+
+/// Something suspicious found while analysing a program
+#[derive(Debug, Clone, Copy)]
+pub enum Finding {
+    /// The loop's cell is always zero when the loop is reached, so its body never runs
+    DeadLoop(PositionedInstruction),
+    /// The loop's cell can never be zero when it's tested, so the loop never finishes
+    InfiniteLoop(PositionedInstruction),
+    /// The instruction may wrap its cell's value around past 0 or 255
+    PossibleWrap(PositionedInstruction),
+    /// The instruction always moves the head below zero
+    SeekTooLow(PositionedInstruction),
+}
+
+impl Finding {
+    /// The instruction the finding is about
+    pub fn instruction(&self) -> PositionedInstruction {
+        match self {
+            Self::DeadLoop(instruction)
+            | Self::InfiniteLoop(instruction)
+            | Self::PossibleWrap(instruction)
+            | Self::SeekTooLow(instruction) => *instruction,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_in(Lang::English, f)
+    }
+}
+
+/// What is known about the machine at one point in the program
+///
+/// A cell missing from `cells` has never been touched, so is still zero. If `head` is None,
+/// the head could be anywhere and nothing is known about any cell.
+#[derive(Debug, Clone, PartialEq)]
+struct State {
+    head: Option<usize>,
+    cells: BTreeMap<usize, CellRange>,
+}
+
+impl State {
+    fn start() -> State {
+        State {
+            head: Some(0),
+            cells: BTreeMap::new(),
+        }
+    }
+
+    fn lost() -> State {
+        State {
+            head: None,
+            cells: BTreeMap::new(),
+        }
+    }
+
+    fn current(&self) -> CellRange {
+        match self.head {
+            Some(head) => self
+                .cells
+                .get(&head)
+                .copied()
+                .unwrap_or(CellRange::exactly(0)),
+            None => CellRange::unknown(),
+        }
+    }
+
+    fn set_current(&mut self, range: CellRange) {
+        if let Some(head) = self.head {
+            self.cells.insert(head, range);
+        }
+    }
+
+    /// Narrows the current cell with `refine`, returning None if that's impossible
+    fn refined(mut self, refine: fn(CellRange) -> Option<CellRange>) -> Option<State> {
+        let range = refine(self.current())?;
+        self.set_current(range);
+        Some(self)
+    }
+
+    fn join(&self, other: &State) -> State {
+        if self.head.is_none() || self.head != other.head {
+            return State::lost();
+        }
+        let mut cells = self.cells.clone();
+        for (index, range) in &other.cells {
+            let joined = range.join(cells.get(index).copied().unwrap_or(CellRange::exactly(0)));
+            cells.insert(*index, joined);
+        }
+        for (index, range) in cells.iter_mut() {
+            if !other.cells.contains_key(index) {
+                *range = range.join(CellRange::exactly(0));
+            }
+        }
+        State {
+            head: self.head,
+            cells,
+        }
+    }
+
+    /// Like join, but gives up on anything that's still changing so loops reach a fixed point
+    fn widen(&self, next: &State) -> State {
+        let mut widened = self.join(next);
+        for (index, range) in widened.cells.iter_mut() {
+            if self.cells.get(index) != Some(range) {
+                *range = CellRange::unknown();
+            }
+        }
+        widened
+    }
+}
+
+/// The result of abstractly interpreting a program
+pub struct Analysis {
+    /// The range of the current cell before each instruction, or None if it's unreachable
+    ranges: Vec<Option<CellRange>>,
+    findings: Vec<Finding>,
+}
+
+impl Analysis {
+    /// Analyses a program, working out what values its cells can hold and finding suspicious code
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::analysis::{Analysis, Finding};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "[-]+++[-]")
+    /// ).unwrap();
+    /// let analysis = Analysis::from_program(&prog);
+    /// assert!(matches!(analysis.findings(), [Finding::DeadLoop(_)]));
+    /// assert_eq!(analysis.range_before(7).unwrap().min(), 1);
+    /// ```
+    pub fn from_program(prog: &DecoratedProgram) -> Analysis {
+        let _span =
+            tracing::debug_span!("analyse", file = %bft_types::display_file(prog.file())).entered();
+        let mut analyser = Analyser::new(prog);
+        analyser.block(0, analyser.instructions.len(), Some(State::start()), true);
+        tracing::debug!(findings = analyser.findings.len(), "Analysed program");
+        Analysis {
+            ranges: analyser.ranges,
+            findings: analyser.findings,
+        }
+    }
+
+    /// Returns the values the current cell may hold just before the instruction at `index` runs
+    ///
+    /// Returns None if that instruction can never be reached.
+    pub fn range_before(&self, index: usize) -> Option<CellRange> {
+        self.ranges.get(index).copied().flatten()
+    }
+
+    /// Returns everything suspicious found in the program, in program order
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+}
+
+struct Analyser {
+    instructions: Vec<PositionedInstruction>,
+    /// For each bracket, the index of its partner
+    closers: Vec<usize>,
+    ranges: Vec<Option<CellRange>>,
+    findings: Vec<Finding>,
+}
+
+impl Analyser {
+    fn new(prog: &DecoratedProgram) -> Analyser {
+        let instructions: Vec<PositionedInstruction> = prog
+            .decorated_instructions()
+            .iter()
+            .map(|i| i.instruction())
+            .collect();
+        Analyser {
+            ranges: vec![None; instructions.len()],
+            instructions,
+            closers: crate::matching_brackets(prog),
+            findings: Vec::new(),
+        }
+    }
+
+    /// Analyses the instructions from `start` up to `end`, returning the state afterwards
+    ///
+    /// Ranges and findings are only stored when `record` is set, so that the passes made while
+    /// searching for a loop's fixed point don't leave stale results behind.
+    fn block(
+        &mut self,
+        start: usize,
+        end: usize,
+        mut state: Option<State>,
+        record: bool,
+    ) -> Option<State> {
+        let mut index = start;
+        while index < end {
+            if record {
+                self.ranges[index] = state.as_ref().map(State::current);
+            }
+            let instruction = self.instructions[index];
+            let mut current = state?;
+            match instruction.instruction() {
+                RawInstruction::IncrementDataPointer => {
+                    current.head = current.head.map(|head| head + 1);
+                }
+                RawInstruction::DecrementDataPointer => match current.head {
+                    Some(0) => {
+                        if record {
+                            self.findings.push(Finding::SeekTooLow(instruction));
+                        }
+                        return None;
+                    }
+                    Some(head) => current.head = Some(head - 1),
+                    None => {}
+                },
+                RawInstruction::IncrementByte | RawInstruction::DecrementByte => {
+                    let (range, may_wrap) =
+                        if *instruction.instruction() == RawInstruction::IncrementByte {
+                            current.current().increment()
+                        } else {
+                            current.current().decrement()
+                        };
+                    if record && may_wrap && current.head.is_some() {
+                        self.findings.push(Finding::PossibleWrap(instruction));
+                    }
+                    current.set_current(range);
+                }
+                RawInstruction::GetByte => current.set_current(CellRange::unknown()),
+                RawInstruction::PutByte => {}
+                RawInstruction::OpenLoop => {
+                    let closer = self.closers[index];
+                    state = self.analyse_loop(index, closer, current, record);
+                    index = closer + 1;
+                    continue;
+                }
+                RawInstruction::CloseLoop => unreachable!("Loops are analysed as a whole"),
+            }
+            state = Some(current);
+            index += 1;
+        }
+        state
+    }
+
+    /// Analyses the loop between `opener` and `closer`, returning the state once it's finished
+    fn analyse_loop(
+        &mut self,
+        opener: usize,
+        closer: usize,
+        entry: State,
+        record: bool,
+    ) -> Option<State> {
+        if record && entry.current().is_zero() {
+            self.findings
+                .push(Finding::DeadLoop(self.instructions[opener]));
+        }
+
+        // The state whenever the loop's cell is tested, on the way in or coming round again
+        let mut test = entry.clone();
+        for iteration in 0.. {
+            let body = test.clone().refined(CellRange::non_zero);
+            let next = match self.block(opener + 1, closer, body, false) {
+                Some(after_body) => entry.join(&after_body),
+                None => entry.clone(),
+            };
+            // Widening only ever grows the state, so it's sure to stop changing eventually
+            let next = if iteration >= WIDEN_AFTER {
+                test.widen(&next)
+            } else {
+                next
+            };
+            if next == test {
+                break;
+            }
+            test = next;
+        }
+
+        if record {
+            let body = test.clone().refined(CellRange::non_zero);
+            let after_body = self.block(opener + 1, closer, body, true);
+            self.ranges[closer] = after_body.as_ref().map(State::current);
+        }
+        let exit = test.refined(CellRange::zero);
+        if record && exit.is_none() {
+            self.findings
+                .push(Finding::InfiniteLoop(self.instructions[opener]));
+        }
+        exit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::Program;
+
+    fn analyse(code: &str) -> Analysis {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", code)).unwrap();
+        Analysis::from_program(&prog)
+    }
+
+    #[test]
+    fn tracks_straight_line_values() {
+        let analysis = analyse("+++>++<.");
+        assert_eq!(analysis.range_before(7), Some(CellRange::exactly(3)));
+        assert!(analysis.findings().is_empty());
+    }
+
+    #[test]
+    fn clear_loop_ends_at_zero() {
+        let analysis = analyse(",[-].");
+        assert_eq!(analysis.range_before(4), Some(CellRange::exactly(0)));
+        assert!(analysis.findings().is_empty());
+    }
+
+    #[test]
+    fn finds_suspicious_code() {
+        let analysis = analyse("+[]<");
+        assert!(matches!(analysis.findings(), [Finding::InfiniteLoop(_)]));
+        assert_eq!(analysis.range_before(3), None);
+
+        assert!(matches!(
+            analyse("-").findings(),
+            [Finding::PossibleWrap(_)]
+        ));
+        assert!(matches!(analyse("<").findings(), [Finding::SeekTooLow(_)]));
+    }
+
+    #[test]
+    fn parity_proves_loops_never_finish() {
+        let analysis = analyse("+++[--]");
+        assert!(matches!(
+            analysis.findings(),
+            [Finding::PossibleWrap(_), Finding::InfiniteLoop(_)]
+        ));
+    }
+
+    #[test]
+    fn unbalanced_loops_lose_track_of_the_head() {
+        let analysis = analyse("+[>+]<.");
+        assert_eq!(analysis.range_before(6), Some(CellRange::unknown()));
+    }
+
+    #[test]
+    fn widened_loops_reach_a_fixed_point() {
+        // Nested loops where the body narrows a widened cell again used to never finish
+        let analysis = analyse("++++++++[>+>++++<<-]>++>>+<[-[>>+<<-]+>>]>+[-<<<[->[+[-]+>++>>>-<<]<[<]>>++++++[<<+++++>>-]+<<++.[-]<<]>.>+[>>]>+]");
+        assert!(!analysis
+            .findings()
+            .iter()
+            .any(|finding| matches!(finding, Finding::InfiniteLoop(_) | Finding::DeadLoop(_))));
+    }
+}
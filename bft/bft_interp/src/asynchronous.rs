@@ -0,0 +1,147 @@
+//! Running programs whose input and output have to be awaited, such as network sockets
+//!
+//! The readers and writers are the runtime-agnostic [futures_io] traits. Tokio's can be adapted
+//! to them with `tokio_util::compat`.
+
+use alloc::vec::Vec;
+use core::future::poll_fn;
+use core::pin::Pin;
+
+use bft_types::RawInstruction;
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::io;
+use crate::{CellKind, Machine, VMError};
+
+impl<'a, T: CellKind> Machine<'a, T> {
+    /// Runs the program from the start like [Machine::run], awaiting `input` and `output` instead
+    /// of blocking on them
+    ///
+    /// Every other instruction runs without yielding, so a program that computes for a long time
+    /// between reads and writes should be given a step limit.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp;
+    /// # use bft_types;
+    /// # futures_executor::block_on(async {
+    /// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",[.-]")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// let mut output = Vec::new();
+    /// interp.run_async(&mut &[3][..], &mut output).await.unwrap();
+    /// assert_eq!(output, [3, 2, 1]);
+    /// # });
+    /// ```
+    pub async fn run_async<R, W>(&mut self, input: &mut R, output: &mut W) -> Result<(), VMError>
+    where
+        R: AsyncRead + Unpin + ?Sized,
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        self.next = 0;
+        let mut written = Vec::new();
+        while let Some(instruction) = self.prog.decorated_instructions().get(self.next) {
+            let mut byte = [0];
+            let mut read = 0;
+            if *instruction.instruction().instruction() == RawInstruction::GetByte {
+                // Show any prompt before waiting for the answer
+                self.flush_async(output).await?;
+                read = poll_fn(|cx| Pin::new(&mut *input).poll_read(cx, &mut byte))
+                    .await
+                    .map_err(|e| self.async_error(e))?;
+            }
+            self.step(&mut &byte[..read], &mut written)?;
+            if !written.is_empty() {
+                let mut rest = &written[..];
+                while !rest.is_empty() {
+                    match poll_fn(|cx| Pin::new(&mut *output).poll_write(cx, rest)).await {
+                        Ok(0) => return Err(self.async_error(io::ErrorKind::WriteZero.into())),
+                        Ok(count) => rest = &rest[count..],
+                        Err(e) => return Err(self.async_error(e)),
+                    }
+                }
+                written.clear();
+                if !self.unflushed {
+                    // The byte was written to `written`, so the Machine thinks it's flushed
+                    self.unflushed = true;
+                    self.flush_async(output).await?;
+                }
+            }
+        }
+        self.flush_async(output).await?;
+        tracing::debug!(steps = self.steps, "Program finished");
+        Ok(())
+    }
+
+    /// Flushes `output` if anything has been written to it without being flushed
+    async fn flush_async<W: AsyncWrite + Unpin + ?Sized>(
+        &mut self,
+        output: &mut W,
+    ) -> Result<(), VMError> {
+        if !self.unflushed {
+            return Ok(());
+        }
+        self.unflushed = false;
+        poll_fn(|cx| Pin::new(&mut *output).poll_flush(cx))
+            .await
+            .map_err(|e| self.async_error(e))
+    }
+
+    /// Wraps an I/O error from the instruction about to be stepped
+    fn async_error(&self, source: io::Error) -> VMError {
+        let index = self.next.min(self.prog.decorated_instructions().len() - 1);
+        VMError::IOError {
+            instruction: self.prog.decorated_instructions()[index].instruction(),
+            source,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::{DecoratedProgram, Program};
+    use core::task::{Context, Poll};
+
+    /// Makes the interpreter wait before each byte it reads
+    struct Slow<'a> {
+        data: &'a [u8],
+        ready: bool,
+    }
+
+    impl AsyncRead for Slow<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<std::io::Result<usize>> {
+            if !self.ready {
+                self.ready = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            self.ready = false;
+            Pin::new(&mut self.data).poll_read(cx, buf)
+        }
+    }
+
+    #[test]
+    fn async_runs_match_blocking_runs() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", ",[.,]")).unwrap();
+        let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+        let mut input = Slow {
+            data: b"hi",
+            ready: false,
+        };
+        let mut output = Vec::new();
+        let result = futures_executor::block_on(machine.run_async(&mut input, &mut output));
+        assert!(matches!(result, Err(VMError::IOError { .. })));
+        assert_eq!(output, b"hi");
+
+        let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+        let mut blocking = Vec::new();
+        let _ = machine.run(&mut &b"hi"[..], &mut blocking);
+        assert_eq!(output, blocking);
+    }
+}
@@ -0,0 +1,840 @@
+//! Optimised bytecode
+//!
+//! A DecoratedProgram can be compiled into a shorter list of operations, where runs of
+//! instructions are fused into one operation and loop jumps are resolved ahead of time. Bytecode
+//! can also be written out as bytes and loaded back, so the work of compiling a large program only
+//! has to be done once.
+//!
+//! Each operation remembers which run of the program's instructions it was compiled from, so
+//! anything reporting on an optimised run can still point at the source.
+//!
+//! Straight-line runs of `+`, `-`, `<` and `>` that [superoptimise](crate::superopt) to fewer
+//! operations are compiled into their shortest form behind an [Op::Guard].
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+use bft_types::fingerprint::Fingerprint;
+use bft_types::span::SourceSpan;
+use bft_types::{DecoratedInstruction, DecoratedProgram, RawInstruction};
+
+use thiserror::Error;
+
+use crate::superopt::Fragment;
+
+/// Identifies a file as bft bytecode
+const MAGIC: &[u8; 4] = b"BFC\0";
+
+/// The version of the byte format written by [Bytecode::to_bytes]
+///
+/// This must change whenever the format or the meaning of any operation changes.
+pub const FORMAT_VERSION: u16 = 4;
+
+/// One operation of optimised bytecode
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Op {
+    /// Add to the value of the current cell, wrapping around
+    Add(isize),
+    /// Move the head left (negative) or right (positive)
+    Move(isize),
+    /// Set the current cell to zero, compiled from `[-]` or `[+]`
+    Clear,
+    /// Move the head left (negative) or right (positive) this many cells at a time until it's on
+    /// a zero cell, compiled from loops like `[>]` or `[<<]`
+    Scan(isize),
+    /// If the current cell is zero, jump past the operation at this index
+    JumpIfZero(usize),
+    /// If the current cell is non-zero, jump past the operation at this index
+    JumpIfNonZero(usize),
+    /// Read a byte of input into the current cell
+    Input,
+    /// Write the current cell as a byte of output
+    Output,
+    /// If the head can reach every cell from `low` to `high` cells away without leaving the tape,
+    /// carry on with the next `ops` operations, the superoptimised form of a straight-line run.
+    /// Otherwise run the instructions of that run one at a time and skip those operations, so that
+    /// the head meets the edge of the tape just as it would have
+    Guard { low: isize, high: isize, ops: usize },
+}
+
+impl Op {
+    /// The short name of the operation, as shown by Display
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Add(_) => "add",
+            Self::Move(_) => "move",
+            Self::Clear => "clear",
+            Self::Scan(_) => "scan",
+            Self::JumpIfZero(_) => "jz",
+            Self::JumpIfNonZero(_) => "jnz",
+            Self::Input => "in",
+            Self::Output => "out",
+            Self::Guard { .. } => "guard",
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mnemonic())?;
+        match self {
+            Self::Add(argument) | Self::Move(argument) | Self::Scan(argument) => {
+                write!(f, " {argument}")
+            }
+            Self::JumpIfZero(target) | Self::JumpIfNonZero(target) => write!(f, " {target}"),
+            Self::Guard { low, high, ops } => write!(f, " {low}..{high} {ops}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Errors that may occur while loading bytecode
+#[derive(Error, Debug)]
+pub enum BytecodeError {
+    #[error("Not a bytecode file")]
+    BadMagic,
+    #[error("Bytecode format version {0} isn't supported, expected version {FORMAT_VERSION}")]
+    UnsupportedVersion(u16),
+    #[error("Bytecode ended unexpectedly")]
+    Truncated,
+    #[error("Unknown operation {0} at offset {1}")]
+    UnknownOp(u8, usize),
+    #[error("Jump to {0} at offset {1} is past the end of the bytecode")]
+    BadJump(usize, usize),
+    #[error("Scan at offset {0} doesn't move")]
+    BadScan(usize),
+    #[error("Jump at operation {0} isn't paired with a jump back from its target")]
+    UnpairedJump(usize),
+    #[error("Operation {0} isn't compiled from any instructions")]
+    NoInstructions(usize),
+    #[error("Guard at operation {0} doesn't cover the head or guards more than adds and moves")]
+    BadGuard(usize),
+}
+
+/// A program compiled into optimised bytecode
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bytecode {
+    fingerprint: Fingerprint,
+    ops: Vec<Op>,
+    /// For each operation, the index of the first instruction in the program it was compiled from
+    origins: Vec<usize>,
+    /// For each operation, how many instructions it was compiled from
+    lengths: Vec<usize>,
+}
+
+impl Bytecode {
+    /// Compiles a program into bytecode
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::bytecode::{Bytecode, Op};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+++>>[-]<.")
+    /// ).unwrap();
+    /// let bytecode = Bytecode::compile(&prog);
+    /// assert_eq!(bytecode.ops(), [Op::Add(3), Op::Move(2), Op::Clear, Op::Move(-1), Op::Output]);
+    /// ```
+    pub fn compile(prog: &DecoratedProgram) -> Bytecode {
+        let _span = tracing::debug_span!("optimise", file = %bft_types::display_file(prog.file()))
+            .entered();
+        let instructions = prog.decorated_instructions();
+        let mut ops: Vec<Op> = Vec::new();
+        let mut origins = Vec::new();
+        let mut lengths = Vec::new();
+        let mut openers = Vec::new();
+        let mut index = 0;
+        while index < instructions.len() {
+            if let Some((guarded, end)) = superoptimise_run(instructions, index) {
+                for op in guarded {
+                    ops.push(op);
+                    origins.push(index);
+                    lengths.push(end - index);
+                }
+                index = end;
+                continue;
+            }
+            let origin = index;
+            let raw = |index: usize| *instructions[index].instruction().instruction();
+            let op = match &instructions[index] {
+                DecoratedInstruction::OpenLoop { .. } if is_clear_loop(prog, index) => {
+                    index += 2;
+                    Op::Clear
+                }
+                DecoratedInstruction::OpenLoop { .. } => match scan_stride(prog, index) {
+                    Some(stride) => {
+                        index += stride.unsigned_abs() + 1;
+                        Op::Scan(stride)
+                    }
+                    None => {
+                        openers.push(ops.len());
+                        // Filled in once the loop is closed
+                        Op::JumpIfZero(0)
+                    }
+                },
+                DecoratedInstruction::CloseLoop { .. } => {
+                    let opener = openers.pop().expect("DecoratedPrograms are balanced");
+                    ops[opener] = Op::JumpIfZero(ops.len());
+                    Op::JumpIfNonZero(opener)
+                }
+                _ => match raw(index) {
+                    RawInstruction::IncrementByte | RawInstruction::DecrementByte => {
+                        let mut amount: isize = 0;
+                        while index < instructions.len() {
+                            match raw(index) {
+                                RawInstruction::IncrementByte => amount += 1,
+                                RawInstruction::DecrementByte => amount -= 1,
+                                _ => break,
+                            }
+                            index += 1;
+                        }
+                        index -= 1;
+                        Op::Add(amount)
+                    }
+                    // Moves are only fused in one direction, so a head that moves out of bounds
+                    // stops in the same place as it would have done in the original program.
+                    direction @ (RawInstruction::IncrementDataPointer
+                    | RawInstruction::DecrementDataPointer) => {
+                        let mut offset: isize = 0;
+                        while index < instructions.len() && raw(index) == direction {
+                            offset += 1;
+                            index += 1;
+                        }
+                        index -= 1;
+                        if direction == RawInstruction::DecrementDataPointer {
+                            offset = -offset;
+                        }
+                        Op::Move(offset)
+                    }
+                    RawInstruction::GetByte => Op::Input,
+                    RawInstruction::PutByte => Op::Output,
+                    RawInstruction::OpenLoop | RawInstruction::CloseLoop => {
+                        unreachable!("Brackets are always decorated")
+                    }
+                },
+            };
+            index += 1;
+            // Instructions that cancel out, like `+-`, aren't part of any operation
+            if op != Op::Add(0) {
+                ops.push(op);
+                origins.push(origin);
+                lengths.push(index - origin);
+            }
+        }
+        tracing::debug!(
+            instructions = instructions.len(),
+            ops = ops.len(),
+            "Compiled bytecode"
+        );
+        Bytecode {
+            fingerprint: prog.fingerprint(),
+            ops,
+            origins,
+            lengths,
+        }
+    }
+
+    /// Translates a program into bytecode one instruction at a time, without optimising it
+    ///
+    /// Each operation's index is the index of the instruction it came from, which is how the
+    /// reference interpreter in [Machine::run](crate::Machine::run) numbers its steps.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::bytecode::{Bytecode, Op};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "++[-]")
+    /// ).unwrap();
+    /// let bytecode = Bytecode::compile_unoptimised(&prog);
+    /// assert_eq!(
+    ///     bytecode.ops(),
+    ///     [Op::Add(1), Op::Add(1), Op::JumpIfZero(4), Op::Add(-1), Op::JumpIfNonZero(2)]
+    /// );
+    /// ```
+    pub fn compile_unoptimised(prog: &DecoratedProgram) -> Bytecode {
+        let partners = crate::matching_brackets(prog);
+        let instructions = prog.decorated_instructions();
+        Bytecode {
+            fingerprint: prog.fingerprint(),
+            ops: (0..instructions.len())
+                .map(|index| instruction_op(&instructions[index], partners[index]))
+                .collect(),
+            origins: (0..instructions.len()).collect(),
+            lengths: vec![1; instructions.len()],
+        }
+    }
+
+    /// The fingerprint of the program this was compiled from
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint
+    }
+
+    /// The compiled operations
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// For each operation, the index of the instruction in the original program it starts at
+    pub fn origins(&self) -> &[usize] {
+        &self.origins
+    }
+
+    /// The indices of the instructions in the original program an operation was compiled from
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::bytecode::Bytecode;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+++\n[-]")
+    /// ).unwrap();
+    /// let bytecode = Bytecode::compile(&prog);
+    /// assert_eq!(bytecode.provenance(1), 3..6);
+    /// let span = bytecode.span(1, &prog).unwrap();
+    /// assert_eq!((span.line, span.column, span.range()), (2, 1, 4..7));
+    /// ```
+    pub fn provenance(&self, op: usize) -> Range<usize> {
+        let origin = self.origins[op];
+        origin..origin + self.lengths[op]
+    }
+
+    /// Where in `prog`'s source an operation was compiled from, covering every instruction it
+    /// came from and whatever lies between them
+    ///
+    /// Returns None if the operation doesn't come from `prog`.
+    pub fn span(&self, op: usize, prog: &DecoratedProgram) -> Option<SourceSpan> {
+        let instructions = self.provenance(op);
+        let mut span = prog.span(instructions.start)?;
+        let last = prog.span(instructions.end.checked_sub(1)?)?;
+        span.len = last.range().end - span.offset;
+        Some(span)
+    }
+
+    /// Writes the bytecode out in a compact binary form
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::bytecode::Bytecode;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+[->+<]")
+    /// ).unwrap();
+    /// let bytecode = Bytecode::compile(&prog);
+    /// assert_eq!(Bytecode::from_bytes(&bytecode.to_bytes()).unwrap(), bytecode);
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.fingerprint.as_u64().to_le_bytes());
+        bytes.extend_from_slice(&(self.ops.len() as u64).to_le_bytes());
+        for ((op, origin), length) in self.ops.iter().zip(&self.origins).zip(&self.lengths) {
+            let (tag, operand) = match *op {
+                Op::Add(amount) => (0, amount as u64),
+                Op::Move(offset) => (1, offset as u64),
+                Op::Clear => (2, 0),
+                Op::JumpIfZero(target) => (3, target as u64),
+                Op::JumpIfNonZero(target) => (4, target as u64),
+                Op::Input => (5, 0),
+                Op::Output => (6, 0),
+                Op::Scan(stride) => (7, stride as u64),
+                Op::Guard { ops, .. } => (8, ops as u64),
+            };
+            bytes.push(tag);
+            bytes.extend_from_slice(&operand.to_le_bytes());
+            // A guard's reach follows its operand
+            if let Op::Guard { low, high, .. } = *op {
+                bytes.extend_from_slice(&(low as u64).to_le_bytes());
+                bytes.extend_from_slice(&(high as u64).to_le_bytes());
+            }
+            bytes.extend_from_slice(&(*origin as u64).to_le_bytes());
+            bytes.extend_from_slice(&(*length as u64).to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Loads bytecode written by [Bytecode::to_bytes], and [verifies](Bytecode::verify) it
+    pub fn from_bytes(bytes: &[u8]) -> Result<Bytecode, BytecodeError> {
+        let mut reader = Reader { bytes, offset: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(BytecodeError::BadMagic);
+        }
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != FORMAT_VERSION {
+            return Err(BytecodeError::UnsupportedVersion(version));
+        }
+        let fingerprint = Fingerprint::from(u64::from_le_bytes(reader.array()?));
+        let count = u64::from_le_bytes(reader.array()?) as usize;
+
+        let mut ops = Vec::new();
+        let mut origins = Vec::new();
+        let mut lengths = Vec::new();
+        for _ in 0..count {
+            let offset = reader.offset;
+            let [tag] = reader.array()?;
+            let operand = u64::from_le_bytes(reader.array()?);
+            ops.push(match tag {
+                0 => Op::Add(operand as isize),
+                1 => Op::Move(operand as isize),
+                2 => Op::Clear,
+                3 | 4 if operand as usize >= count => {
+                    return Err(BytecodeError::BadJump(operand as usize, offset))
+                }
+                3 => Op::JumpIfZero(operand as usize),
+                4 => Op::JumpIfNonZero(operand as usize),
+                5 => Op::Input,
+                6 => Op::Output,
+                7 if operand == 0 => return Err(BytecodeError::BadScan(offset)),
+                7 => Op::Scan(operand as isize),
+                8 => Op::Guard {
+                    low: u64::from_le_bytes(reader.array()?) as isize,
+                    high: u64::from_le_bytes(reader.array()?) as isize,
+                    ops: operand as usize,
+                },
+                _ => return Err(BytecodeError::UnknownOp(tag, offset)),
+            });
+            origins.push(u64::from_le_bytes(reader.array()?) as usize);
+            lengths.push(u64::from_le_bytes(reader.array()?) as usize);
+        }
+        let bytecode = Bytecode {
+            fingerprint,
+            ops,
+            origins,
+            lengths,
+        };
+        bytecode.verify()?;
+        Ok(bytecode)
+    }
+
+    /// Checks that every jump lands in the bytecode on a jump back to it, opening a loop before
+    /// closing it, and that every operation is compiled from some instructions
+    ///
+    /// Compiled bytecode always passes, and loaded bytecode is checked as it's loaded, so engines
+    /// can follow jumps without checking them at each step.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::bytecode::{Bytecode, BytecodeError};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+[-]")
+    /// ).unwrap();
+    /// let mut bytes = Bytecode::compile_unoptimised(&prog).to_bytes();
+    /// // Point the closing jump at itself, which leaves the opening one unpaired
+    /// bytes[22 + 3 * 25 + 1] = 3;
+    /// assert!(matches!(
+    ///     Bytecode::from_bytes(&bytes),
+    ///     Err(BytecodeError::UnpairedJump(1))
+    /// ));
+    /// ```
+    pub fn verify(&self) -> Result<(), BytecodeError> {
+        for (index, op) in self.ops.iter().enumerate() {
+            let paired = match *op {
+                Op::JumpIfZero(target) => {
+                    target > index && self.ops.get(target) == Some(&Op::JumpIfNonZero(index))
+                }
+                Op::JumpIfNonZero(target) => {
+                    target < index && self.ops.get(target) == Some(&Op::JumpIfZero(index))
+                }
+                _ => true,
+            };
+            if !paired {
+                return Err(BytecodeError::UnpairedJump(index));
+            }
+            if let Op::Guard { low, high, ops } = *op {
+                let guarded = self.ops.get(index + 1..).and_then(|rest| rest.get(..ops));
+                let straight = guarded.is_some_and(|guarded| {
+                    guarded
+                        .iter()
+                        .all(|op| matches!(op, Op::Add(_) | Op::Move(_)))
+                });
+                if low > 0 || high < 0 || !straight {
+                    return Err(BytecodeError::BadGuard(index));
+                }
+            }
+            if self.lengths[index] == 0
+                || self.origins[index]
+                    .checked_add(self.lengths[index])
+                    .is_none()
+            {
+                return Err(BytecodeError::NoInstructions(index));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether every operation comes from instructions within `prog`, and moves no
+    /// further than its length
+    ///
+    /// Bytecode compiled from `prog` always fits it, but loaded bytecode may not.
+    pub(crate) fn fits(&self, prog: &DecoratedProgram) -> bool {
+        let length = prog.decorated_instructions().len();
+        let mut ops = self.ops.iter().zip(&self.origins).zip(&self.lengths);
+        ops.all(|((op, origin), instructions)| {
+            let span = match op {
+                Op::Move(offset) => offset.unsigned_abs(),
+                Op::Scan(stride) => stride.unsigned_abs() + 2,
+                _ => 1,
+            };
+            origin
+                .checked_add(span.max(*instructions))
+                .is_some_and(|end| end <= length)
+        })
+    }
+}
+
+impl fmt::Display for Bytecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, op) in self.ops.iter().enumerate() {
+            writeln!(f, "{index}: {op}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The operation that does the same as a single instruction
+///
+/// `partner` is the index of the matching bracket, if the instruction is a bracket.
+pub(crate) fn instruction_op(instruction: &DecoratedInstruction, partner: usize) -> Op {
+    match instruction.instruction().instruction() {
+        RawInstruction::IncrementByte => Op::Add(1),
+        RawInstruction::DecrementByte => Op::Add(-1),
+        RawInstruction::IncrementDataPointer => Op::Move(1),
+        RawInstruction::DecrementDataPointer => Op::Move(-1),
+        RawInstruction::GetByte => Op::Input,
+        RawInstruction::PutByte => Op::Output,
+        RawInstruction::OpenLoop => Op::JumpIfZero(partner),
+        RawInstruction::CloseLoop => Op::JumpIfNonZero(partner),
+    }
+}
+
+/// Returns whether the loop opened at `index` is `[-]` or `[+]`
+fn is_clear_loop(prog: &DecoratedProgram, index: usize) -> bool {
+    let instructions = prog.decorated_instructions();
+    let raw = |index: usize| {
+        instructions
+            .get(index)
+            .map(|instruction| *instruction.instruction().instruction())
+    };
+    matches!(
+        raw(index + 1),
+        Some(RawInstruction::IncrementByte | RawInstruction::DecrementByte)
+    ) && raw(index + 2) == Some(RawInstruction::CloseLoop)
+}
+
+/// Returns how far the loop opened at `index` moves the head each time round, if all it does is
+/// move the head one way, like `[>]` or `[<<]`
+fn scan_stride(prog: &DecoratedProgram, index: usize) -> Option<isize> {
+    let instructions = prog.decorated_instructions();
+    let raw = |index: usize| {
+        instructions
+            .get(index)
+            .map(|instruction| *instruction.instruction().instruction())
+    };
+    let direction = raw(index + 1)?;
+    let sign = match direction {
+        RawInstruction::IncrementDataPointer => 1,
+        RawInstruction::DecrementDataPointer => -1,
+        _ => return None,
+    };
+    let moves = (index + 1..)
+        .take_while(|index| raw(*index) == Some(direction))
+        .count();
+    (raw(index + 1 + moves) == Some(RawInstruction::CloseLoop)).then_some(sign * moves as isize)
+}
+
+/// Superoptimises the straight-line run of instructions starting at `start`, returning an
+/// [Op::Guard] and the run's shortest form, and the index of the instruction after the run
+///
+/// Returns None if `start` isn't the start of a run, or if the shortest form and its guard need
+/// no fewer operations than fusing the run as it stands.
+fn superoptimise_run(
+    instructions: &[DecoratedInstruction],
+    start: usize,
+) -> Option<(Vec<Op>, usize)> {
+    let straight = |index: usize| {
+        let raw = *instructions.get(index)?.instruction().instruction();
+        Fragment::from_instructions(&[raw]).map(|_| raw)
+    };
+    if start.checked_sub(1).and_then(straight).is_some() {
+        return None;
+    }
+    let run: Vec<RawInstruction> = (start..).map_while(straight).collect();
+    let fragment = Fragment::from_instructions(&run)?;
+    // The fragment wraps at 8 bits, but cells may be wider, so each change is summed in full
+    let (mut head, mut low, mut high) = (0, 0, 0);
+    let mut deltas: BTreeMap<isize, isize> = BTreeMap::new();
+    for instruction in &run {
+        match instruction {
+            RawInstruction::IncrementByte => *deltas.entry(head).or_insert(0) += 1,
+            RawInstruction::DecrementByte => *deltas.entry(head).or_insert(0) -= 1,
+            RawInstruction::IncrementDataPointer => head += 1,
+            _ => head -= 1,
+        }
+        (low, high) = (low.min(head), high.max(head));
+    }
+    // A change of a whole number of 8-bit wraps would be left out of the shortest form
+    if deltas
+        .iter()
+        .any(|(offset, delta)| *delta != 0 && fragment.delta(*offset) == 0)
+    {
+        return None;
+    }
+    let mut shortest = fuse(&fragment.shortest());
+    let mut head = 0;
+    for op in &mut shortest {
+        match op {
+            Op::Move(offset) => head += *offset,
+            Op::Add(amount) => *amount = deltas[&head],
+            _ => {}
+        }
+    }
+    if shortest.len() + 1 >= fuse(&run).len() {
+        return None;
+    }
+    let mut ops = vec![Op::Guard {
+        low,
+        high,
+        ops: shortest.len(),
+    }];
+    ops.extend(shortest);
+    Some((ops, start + run.len()))
+}
+
+/// Fuses straight-line code into adds and moves the way [Bytecode::compile] does
+fn fuse(code: &[RawInstruction]) -> Vec<Op> {
+    let mut ops: Vec<Op> = Vec::new();
+    for instruction in code {
+        let op = match instruction {
+            RawInstruction::IncrementByte => Op::Add(1),
+            RawInstruction::DecrementByte => Op::Add(-1),
+            RawInstruction::IncrementDataPointer => Op::Move(1),
+            RawInstruction::DecrementDataPointer => Op::Move(-1),
+            _ => unreachable!("Only straight-line code is fused"),
+        };
+        match (ops.last_mut(), op) {
+            (Some(Op::Add(total)), Op::Add(amount)) => *total += amount,
+            (Some(Op::Move(total)), Op::Move(offset)) if total.signum() == offset.signum() => {
+                *total += offset
+            }
+            _ => ops.push(op),
+        }
+    }
+    ops.retain(|op| *op != Op::Add(0));
+    ops
+}
+
+/// Reads fixed-size pieces from a byte slice
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], BytecodeError> {
+        let taken = self
+            .bytes
+            .get(self.offset..self.offset + length)
+            .ok_or(BytecodeError::Truncated)?;
+        self.offset += length;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], BytecodeError> {
+        Ok(self.take(N)?.try_into().expect("Took exactly N bytes"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, VMError};
+    use bft_types::generate::seeded_bytes;
+    use bft_types::Program;
+    use core::num::NonZeroUsize;
+
+    fn compile(code: &str) -> (DecoratedProgram, Bytecode) {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", code)).unwrap();
+        let bytecode = Bytecode::compile(&prog);
+        (prog, bytecode)
+    }
+
+    #[test]
+    fn loops_are_resolved() {
+        let (_, bytecode) = compile("+[>+-<<-]");
+        assert_eq!(
+            bytecode.ops(),
+            [
+                Op::Add(1),
+                Op::JumpIfZero(5),
+                Op::Move(1),
+                Op::Move(-2),
+                Op::Add(-1),
+                Op::JumpIfNonZero(1)
+            ]
+        );
+        assert_eq!(bytecode.origins(), [0, 1, 2, 5, 7, 8]);
+        let provenance: Vec<_> = (0..6).map(|op| bytecode.provenance(op)).collect();
+        assert_eq!(provenance, [0..1, 1..2, 2..3, 5..7, 7..8, 8..9]);
+    }
+
+    #[test]
+    fn straight_line_runs_are_superoptimised() {
+        // Fused as it stands this is nine operations, alternating between the two cells
+        let (prog, bytecode) = compile("+>+<+>+<+.");
+        assert_eq!(
+            bytecode.ops(),
+            [
+                Op::Guard {
+                    low: 0,
+                    high: 1,
+                    ops: 4
+                },
+                Op::Add(3),
+                Op::Move(1),
+                Op::Add(2),
+                Op::Move(-1),
+                Op::Output
+            ]
+        );
+        assert_eq!(bytecode.provenance(3), 0..9);
+        assert_eq!(
+            Bytecode::from_bytes(&bytecode.to_bytes()).unwrap(),
+            bytecode
+        );
+        // Changes are summed in full for cells wider than a byte, and a tape too short for the
+        // run falls back to its instructions, stopping where they would
+        for size in [1, 2] {
+            let mut machine: Machine<u16> = Machine::new(NonZeroUsize::new(size), false, &prog);
+            let result = machine.run_bytecode(&bytecode, &mut std::io::empty(), &mut Vec::new());
+            let mut reference: Machine<u16> = Machine::new(NonZeroUsize::new(size), false, &prog);
+            let expected = reference.run(&mut std::io::empty(), &mut Vec::new());
+            let describe = |result: Result<(), VMError>| result.map_err(|e| e.to_string());
+            assert_eq!(describe(result), describe(expected), "{size} cells");
+            assert_eq!(machine.cells(), reference.cells(), "{size} cells");
+            assert_eq!(machine.head(), reference.head(), "{size} cells");
+        }
+        let (_, bytecode) = compile(&"+".repeat(256));
+        assert_eq!(bytecode.ops(), [Op::Add(256)]);
+    }
+
+    #[test]
+    fn bad_bytes_are_rejected() {
+        let (_, bytecode) = compile("+[-].");
+        let bytes = bytecode.to_bytes();
+        assert!(matches!(
+            Bytecode::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(BytecodeError::Truncated)
+        ));
+        assert!(matches!(
+            Bytecode::from_bytes(b"nope"),
+            Err(BytecodeError::BadMagic)
+        ));
+        let mut future = bytes.clone();
+        future[4] = 99;
+        assert!(matches!(
+            Bytecode::from_bytes(&future),
+            Err(BytecodeError::UnsupportedVersion(99))
+        ));
+        // The length of the second operation, `[-]` compiled to a clear
+        let mut empty = bytes.clone();
+        empty[22 + 25 + 17] = 0;
+        assert!(matches!(
+            Bytecode::from_bytes(&empty),
+            Err(BytecodeError::NoInstructions(1))
+        ));
+    }
+
+    #[test]
+    fn corrupt_bytecode_never_panics() {
+        let (prog, bytecode) = compile("+[>+<-]>.");
+        let bytes = bytecode.to_bytes();
+        let mut seed = 1;
+        for _ in 0..2000 {
+            let mut corrupt = bytes.clone();
+            let random = seeded_bytes(&mut seed, 3);
+            let index = usize::from(u16::from_be_bytes([random[0], random[1]])) % corrupt.len();
+            corrupt[index] = random[2];
+            if let Ok(loaded) = Bytecode::from_bytes(&corrupt) {
+                let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+                machine.set_limits(crate::limits::Limits {
+                    max_steps: Some(1000),
+                    ..Default::default()
+                });
+                let _ = machine.run_bytecode(&loaded, &mut std::io::empty(), &mut std::io::sink());
+            }
+        }
+    }
+
+    #[test]
+    fn runs_hello_world() {
+        let (prog, bytecode) = compile(
+            "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.",
+        );
+        let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+        let mut output = Vec::new();
+        machine
+            .run_bytecode(&bytecode, &mut std::io::empty(), &mut output)
+            .unwrap();
+        assert_eq!(output, b"Hello World!\n");
+    }
+
+    #[test]
+    fn errors_point_at_the_failing_instruction() {
+        let (prog, bytecode) = compile("+>>><<<<<");
+        let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+        let error = machine
+            .run_bytecode(&bytecode, &mut std::io::empty(), &mut std::io::sink())
+            .unwrap_err();
+        let mut reference: Machine<u8> = Machine::new(None, false, &prog);
+        let expected = reference
+            .run(&mut std::io::empty(), &mut std::io::sink())
+            .unwrap_err();
+        assert_eq!(error.to_string(), expected.to_string());
+        assert_eq!(machine.head(), reference.head());
+    }
+    #[test]
+    fn scans_stop_where_their_loops_would() {
+        let (_, bytecode) = compile("[>][<<]");
+        assert_eq!(bytecode.ops(), [Op::Scan(1), Op::Scan(-2)]);
+        // Each program is run on tapes of 40 and 80 cells, so that some scans cover more than a
+        // chunk, with the tape fixed and growable, and with and without a loop limit
+        let programs = [
+            "+>+>+>+>>+>+<<<<<<[>]+[<]",
+            "->->->->->->->->->->->->->->->->->->->->->->->->->->->->->->->->->->->-[<]<[<<]",
+            "+[>+]",
+            ">>>>>+[<+]",
+            "+[>>>+]",
+            "+>>+[<<<]",
+        ];
+        for code in programs {
+            let (prog, bytecode) = compile(code);
+            for (size, may_grow, limit) in
+                [(40, false, None), (80, true, None), (40, true, Some(9))]
+            {
+                let limits = crate::limits::Limits {
+                    max_loop_iterations: limit,
+                    max_tape: Some(60),
+                    ..Default::default()
+                };
+                let mut machine: Machine<u8> =
+                    Machine::new(Some(size.try_into().unwrap()), may_grow, &prog);
+                machine.set_limits(limits);
+                let result =
+                    machine.run_bytecode(&bytecode, &mut std::io::empty(), &mut std::io::sink());
+                let mut reference: Machine<u8> =
+                    Machine::new(Some(size.try_into().unwrap()), may_grow, &prog);
+                reference.set_limits(limits);
+                let expected = reference.run(&mut std::io::empty(), &mut std::io::sink());
+                let describe = |result: Result<(), VMError>| result.map_err(|e| e.to_string());
+                assert_eq!(describe(result), describe(expected), "{code} on {size}");
+                assert_eq!(machine.head(), reference.head(), "{code} on {size}");
+                assert_eq!(machine.cells(), reference.cells(), "{code} on {size}");
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Spotting programs that will never finish
+//!
+//! A Machine that comes back to exactly the same state, with the same instruction next, the same
+//! head and the same cells, without reading any input in between, will go round the same way
+//! forever. With detection on, the Machine saves its state every so often and compares later
+//! states against it, stopping with [VMError::InfiniteLoop] when one repeats. The saved state is
+//! replaced after a doubling number of checks, so a cycle of any length is eventually caught.
+
+use alloc::vec::Vec;
+
+use crate::{CellKind, Machine, VMError};
+
+/// How often the state is checked while detection is on, unless told otherwise
+pub const DEFAULT_INTERVAL: u64 = 1024;
+
+/// The state of a Machine when it was saved
+#[derive(Clone)]
+struct Saved<T> {
+    steps: u64,
+    instruction: usize,
+    head: usize,
+    /// A hash of the cells, so most states can be told apart without comparing every cell
+    hash: u64,
+    cells: Vec<T>,
+}
+
+/// Remembers a state to compare later ones against
+#[derive(Clone)]
+pub(crate) struct CycleDetector<T> {
+    interval: u64,
+    /// How many checks there have been since the state was last saved
+    checks: u64,
+    /// How many checks to make before saving the state again
+    power: u64,
+    saved: Option<Saved<T>>,
+}
+
+impl<T> CycleDetector<T> {
+    pub(crate) fn new(interval: u64) -> CycleDetector<T> {
+        CycleDetector {
+            interval: interval.max(1),
+            checks: 0,
+            power: 1,
+            saved: None,
+        }
+    }
+
+    /// Forgets the saved state, as input may make the same state go a different way
+    pub(crate) fn forget(&mut self) {
+        self.saved = None;
+    }
+
+    /// Starts again, as if the Machine had just been created
+    pub(crate) fn reset(&mut self) {
+        self.checks = 0;
+        self.power = 1;
+        self.forget();
+    }
+}
+
+impl<T: CellKind> Machine<'_, T> {
+    /// Checks for a repeated state if one is due, before the instruction at the instruction
+    /// pointer is executed
+    #[inline]
+    pub(crate) fn check_cycle(&mut self) -> Result<(), VMError> {
+        if self.steps < self.next_cycle_check {
+            return Ok(());
+        }
+        self.compare_state()
+    }
+
+    #[cold]
+    fn compare_state(&mut self) -> Result<(), VMError> {
+        let Some(detector) = &mut self.cycles else {
+            self.next_cycle_check = u64::MAX;
+            return Ok(());
+        };
+        // Hashing the tape takes a while, so big tapes are checked less often
+        self.next_cycle_check = self.steps + detector.interval.max(self.cells.len() as u64);
+        let hash = self.cells.iter().fold(FNV_OFFSET_BASIS, |hash, cell| {
+            (hash ^ u64::from(cell.get_value())).wrapping_mul(FNV_PRIME)
+        });
+        let repeated = detector.saved.as_ref().filter(|saved| {
+            saved.hash == hash
+                && saved.instruction == self.instruction_pointer
+                && saved.head == self.head
+                && saved.cells == self.cells
+        });
+        if let Some(saved) = repeated {
+            let period = self.steps - saved.steps;
+            return Err(self.at_instruction(|instruction| VMError::InfiniteLoop {
+                instruction,
+                period,
+            }));
+        }
+        detector.checks += 1;
+        if detector.saved.is_none() || detector.checks >= detector.power {
+            let saved = detector.saved.get_or_insert_with(|| Saved {
+                steps: 0,
+                instruction: 0,
+                head: 0,
+                hash: 0,
+                cells: Vec::new(),
+            });
+            saved.steps = self.steps;
+            saved.instruction = self.instruction_pointer;
+            saved.head = self.head;
+            saved.hash = hash;
+            saved.cells.clone_from(&self.cells);
+            detector.checks = 0;
+            detector.power = detector.power.saturating_mul(2);
+        }
+        Ok(())
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+#[cfg(test)]
+mod tests {
+    use crate::bytecode::Bytecode;
+    use crate::{Machine, VMError};
+    use bft_types::{DecoratedProgram, Program};
+
+    #[test]
+    fn repeated_states_are_caught_on_every_engine() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", "+[>+<[-]+]")).unwrap();
+        let bytecode = Bytecode::compile(&prog);
+        for engine in 0..3 {
+            let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+            machine.set_loop_detection(Some(7));
+            let (mut input, mut output) = (std::io::empty(), std::io::sink());
+            let result = match engine {
+                0 => machine.run(&mut input, &mut output),
+                1 => machine.run_bytecode(&bytecode, &mut input, &mut output),
+                _ => loop {
+                    match machine.step(&mut input, &mut output) {
+                        Ok(true) => {}
+                        Ok(false) => break Ok(()),
+                        Err(e) => break Err(e),
+                    }
+                },
+            };
+            // Cell 1 counts up, so the state only repeats once it has wrapped around
+            assert!(
+                matches!(result, Err(VMError::InfiniteLoop { period, .. }) if period % 256 == 0),
+                "{result:?}"
+            );
+        }
+
+        // Reading input makes it a different loop each time round
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", "+[,+]")).unwrap();
+        let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+        machine.set_loop_detection(Some(1));
+        machine.set_limits(crate::limits::Limits {
+            max_steps: Some(10_000),
+            ..Default::default()
+        });
+        let mut input = std::io::repeat(7);
+        let result = machine.run(&mut input, &mut std::io::sink());
+        assert!(matches!(result, Err(VMError::LimitExceeded { .. })));
+    }
+}
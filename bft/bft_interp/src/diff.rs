@@ -0,0 +1,194 @@
+//! What changed between two snapshots of a Machine
+//!
+//! Take a [Snapshot] before and after running part of a program, such as one pass of a loop, and
+//! [Snapshot::diff] says which cells it changed and from what to what, and how far the head, the
+//! instruction pointer and the step count moved. This needs the `std` feature, as snapshots do.
+
+use std::fmt;
+
+use bft_types::PositionedInstruction;
+
+use crate::view::Snapshot;
+
+/// A cell that holds a different value in the later snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CellChange<T> {
+    pub cell: usize,
+    pub before: T,
+    pub after: T,
+}
+
+/// The differences between two snapshots, made by [Snapshot::diff]
+///
+/// Displaying it gives a summary, with one line for each changed cell.
+#[derive(Debug, Clone)]
+pub struct SnapshotDiff<T> {
+    /// The step counts before and after
+    pub steps: (u64, u64),
+    /// The head positions before and after
+    pub head: (usize, usize),
+    /// The instructions about to run before and after, or None where the program had stopped
+    pub instruction: (Option<PositionedInstruction>, Option<PositionedInstruction>),
+    /// How many bytes were written in between
+    pub output_bytes: u64,
+    /// The cells that changed, in order
+    pub changes: Vec<CellChange<T>>,
+}
+
+impl<T> SnapshotDiff<T> {
+    /// Whether nothing changed at all
+    pub fn is_empty(&self) -> bool {
+        self.steps.0 == self.steps.1 && self.head.0 == self.head.1 && self.changes.is_empty()
+    }
+}
+
+impl<T: Clone + Default + PartialEq> Snapshot<T> {
+    /// Compares this snapshot with a later one of the same Machine
+    ///
+    /// Only cells that are in both snapshots' windows are compared, except that cells past the
+    /// end of a tape that has since grown count as zero.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::view::Snapshot;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+++[>++<-]")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// for _ in 0..4 {
+    ///     interp.step(&mut std::io::empty(), &mut std::io::sink()).unwrap();
+    /// }
+    /// let before = Snapshot::of(&interp);
+    /// // One pass of the loop
+    /// for _ in 0..6 {
+    ///     interp.step(&mut std::io::empty(), &mut std::io::sink()).unwrap();
+    /// }
+    /// let diff = before.diff(&Snapshot::of(&interp));
+    /// assert_eq!(
+    ///     diff.to_string(),
+    ///     "steps: 4 -> 10 (+6)\n\
+    ///      head: 0 -> 0 (+0)\n\
+    ///      next: 1:5 -> 1:5\n\
+    ///      2 cells changed:\n\
+    ///      \x20      0: 3 -> 2\n\
+    ///      \x20      1: 0 -> 2\n"
+    /// );
+    /// ```
+    pub fn diff(&self, later: &Snapshot<T>) -> SnapshotDiff<T> {
+        let start = self.start.min(later.start);
+        let end = (self.start + self.cells.len()).max(later.start + later.cells.len());
+        let changes = (start..end)
+            .filter_map(|cell| {
+                let before = self.cell(cell)?;
+                let after = later.cell(cell)?;
+                (before != after).then(|| CellChange {
+                    cell,
+                    before,
+                    after,
+                })
+            })
+            .collect();
+        SnapshotDiff {
+            steps: (self.steps, later.steps),
+            head: (self.head, later.head),
+            instruction: (self.instruction, later.instruction),
+            output_bytes: later.output_bytes.saturating_sub(self.output_bytes),
+            changes,
+        }
+    }
+
+    /// The value of a cell, if it's in the window or past the end of the tape
+    fn cell(&self, cell: usize) -> Option<T> {
+        match cell.checked_sub(self.start) {
+            Some(offset) if offset < self.cells.len() => Some(self.cells[offset].clone()),
+            _ if cell >= self.tape_len => Some(T::default()),
+            _ => None,
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for SnapshotDiff<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (steps, head) = (self.steps, self.head);
+        writeln!(
+            f,
+            "steps: {} -> {} ({:+})",
+            steps.0,
+            steps.1,
+            i128::from(steps.1) - i128::from(steps.0)
+        )?;
+        writeln!(
+            f,
+            "head: {} -> {} ({:+})",
+            head.0,
+            head.1,
+            head.1 as i128 - head.0 as i128
+        )?;
+        let position = |instruction: Option<PositionedInstruction>| match instruction {
+            Some(instruction) => format!("{}:{}", instruction.line(), instruction.character()),
+            None => "end".to_string(),
+        };
+        writeln!(
+            f,
+            "next: {} -> {}",
+            position(self.instruction.0),
+            position(self.instruction.1)
+        )?;
+        if self.output_bytes > 0 {
+            writeln!(f, "output: {} bytes", self.output_bytes)?;
+        }
+        match self.changes.len() {
+            0 => writeln!(f, "no cells changed"),
+            1 => writeln!(f, "1 cell changed:"),
+            changed => writeln!(f, "{changed} cells changed:"),
+        }?;
+        for change in &self.changes {
+            writeln!(
+                f,
+                "{:>8}: {} -> {}",
+                change.cell, change.before, change.after
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_cells_both_snapshots_cover_are_compared() {
+        let before = Snapshot {
+            steps: 10,
+            head: 5,
+            start: 4,
+            cells: vec![1u8, 2, 3],
+            tape_len: 7,
+            ..Snapshot::default()
+        };
+        let after = Snapshot {
+            steps: 12,
+            head: 7,
+            output_bytes: 1,
+            start: 5,
+            cells: vec![2, 4, 9, 1],
+            tape_len: 9,
+            ..Snapshot::default()
+        };
+        let diff = before.diff(&after);
+        let changed: Vec<(usize, u8, u8)> = diff
+            .changes
+            .iter()
+            .map(|change| (change.cell, change.before, change.after))
+            .collect();
+        // Cell 4 isn't in the later window, but 7 and 8 are past the end of the earlier tape
+        assert_eq!(changed, [(6, 3, 4), (7, 0, 9), (8, 0, 1)]);
+        assert_eq!(diff.output_bytes, 1);
+        assert!(diff
+            .to_string()
+            .starts_with("steps: 10 -> 12 (+2)\nhead: 5 -> 7 (+2)\n"));
+        assert!(before.diff(&before).is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+//! Bytecode written out for people to read
+//!
+//! A [Disassembly] lists each operation with its operand, where a jump lands, and which part of
+//! the program it was compiled from, for checking what the compiler and optimiser made of a
+//! program. Without [Symbols] the source is given as instruction indices; with them, as lines and
+//! characters.
+
+use alloc::format;
+use core::fmt;
+
+use crate::bytecode::{Bytecode, Op};
+use crate::symbols::Symbols;
+
+/// Bytecode ready to be displayed as one line per operation
+pub struct Disassembly<'a> {
+    bytecode: &'a Bytecode,
+    symbols: Option<&'a Symbols>,
+}
+
+impl<'a> Disassembly<'a> {
+    pub fn new(bytecode: &'a Bytecode) -> Disassembly<'a> {
+        Disassembly {
+            bytecode,
+            symbols: None,
+        }
+    }
+
+    /// Gives source positions from `symbols`, which should have been made for the same bytecode
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::{bytecode::Bytecode, disasm::Disassembly, symbols::Symbols};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "++\n[->+<]")
+    /// ).unwrap();
+    /// let bytecode = Bytecode::compile(&prog);
+    /// let symbols = Symbols::new(&bytecode, &prog);
+    /// let listing = Disassembly::new(&bytecode).with_symbols(&symbols).to_string();
+    /// assert_eq!(listing.lines().nth(2), Some("    1  jz 6       ; 2:1, jumps to 7"));
+    /// assert_eq!(listing.lines().nth(3), Some("    2  add -1     ; 2:2"));
+    /// ```
+    pub fn with_symbols(self, symbols: &'a Symbols) -> Disassembly<'a> {
+        Disassembly {
+            symbols: Some(symbols),
+            ..self
+        }
+    }
+}
+
+/// Writes a header line, then a line for each operation
+impl fmt::Display for Disassembly<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ops = self.bytecode.ops();
+        write!(
+            f,
+            "; {} operations compiled from {}",
+            ops.len(),
+            self.bytecode.fingerprint()
+        )?;
+        match self.symbols {
+            Some(symbols) => writeln!(f, " ({})", symbols.file)?,
+            None => writeln!(f)?,
+        }
+        for (index, op) in ops.iter().enumerate() {
+            let text = format!("{op}");
+            write!(f, "{index:>5}  {text:<10} ; ")?;
+            match self.symbols.and_then(|symbols| symbols.find(index)) {
+                Some(symbol) if symbol.start == symbol.end => {
+                    write!(f, "{}:{}", symbol.start.0, symbol.start.1)?
+                }
+                Some(symbol) => write!(
+                    f,
+                    "{}:{}-{}:{}",
+                    symbol.start.0, symbol.start.1, symbol.end.0, symbol.end.1
+                )?,
+                None => {
+                    let instructions = self.bytecode.provenance(index);
+                    match instructions.len() {
+                        1 => write!(f, "instruction {}", instructions.start)?,
+                        _ => write!(
+                            f,
+                            "instructions {}-{}",
+                            instructions.start,
+                            instructions.end - 1
+                        )?,
+                    }
+                }
+            }
+            match op {
+                Op::JumpIfZero(target) | Op::JumpIfNonZero(target) => {
+                    writeln!(f, ", jumps to {}", target + 1)?
+                }
+                _ => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use bft_types::{DecoratedProgram, Program};
+
+    #[test]
+    fn listings_point_at_instructions_without_symbols() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", "+++[>]")).unwrap();
+        let bytecode = Bytecode::compile(&prog);
+        let listing = Disassembly::new(&bytecode).to_string();
+        assert_eq!(
+            listing,
+            format!(
+                "; 2 operations compiled from {}\n\
+                 \x20   0  add 3      ; instructions 0-2\n\
+                 \x20   1  scan 1     ; instructions 3-5\n",
+                prog.fingerprint()
+            )
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! Hex dumps of the tape, in the style of `xxd`
+//!
+//! Each line starts with the index of its first cell, in hex, then the cells' values in groups,
+//! then the cells as characters. Large tapes that are mostly zero can skip the lines that are all
+//! zero, which are replaced by a `*` line for each run of them, as `xxd -a` does.
+
+use alloc::string::String;
+use core::fmt::{self, Write};
+use core::mem::size_of;
+
+use crate::CellKind;
+
+/// The base cell values are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Radix {
+    Binary,
+    Octal,
+    Decimal,
+    #[default]
+    Hex,
+}
+
+impl Radix {
+    /// How many digits the largest value of a cell with this many bits takes
+    fn digits(self, bits: u32) -> usize {
+        let max = u64::MAX >> (64 - bits);
+        let base = match self {
+            Radix::Binary => 2,
+            Radix::Octal => 8,
+            Radix::Decimal => 10,
+            Radix::Hex => 16,
+        };
+        max.ilog(base) as usize + 1
+    }
+}
+
+/// How to lay out a dump
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DumpFormat {
+    pub radix: Radix,
+    /// How many cells are written together before a space, as in `xxd -g`
+    pub group: usize,
+    /// How many cells go on a line
+    pub columns: usize,
+    /// Whether to end each line with the cells as characters, with `.` for unprintable ones
+    pub chars: bool,
+    /// Whether to leave out lines that are all zero
+    pub non_zero: bool,
+}
+
+impl Default for DumpFormat {
+    /// The same layout as plain `xxd`: hex, two cells to a group and sixteen to a line
+    fn default() -> DumpFormat {
+        DumpFormat {
+            radix: Radix::Hex,
+            group: 2,
+            columns: 16,
+            chars: true,
+            non_zero: false,
+        }
+    }
+}
+
+impl DumpFormat {
+    /// Dumps `cells`, numbering them from `start`
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::dump::DumpFormat;
+    /// let format = DumpFormat { columns: 4, non_zero: true, ..DumpFormat::default() };
+    /// let tape: [u8; 12] = [b'H', b'i', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff];
+    /// assert_eq!(
+    ///     format.dump(&tape, 0),
+    ///     "00000000: 4869 0000  Hi..\n\
+    ///      *\n\
+    ///      00000008: 0000 00ff  ....\n"
+    /// );
+    /// ```
+    pub fn dump<T: CellKind + Copy + Into<u64>>(&self, cells: &[T], start: usize) -> String {
+        let mut dumped = String::new();
+        // Writing to a String can't fail
+        let _ = self.write(cells, start, &mut dumped);
+        dumped
+    }
+
+    /// Writes the dump of `cells`, numbering them from `start`, to `out`
+    pub fn write<T: CellKind + Copy + Into<u64>>(
+        &self,
+        cells: &[T],
+        start: usize,
+        out: &mut impl Write,
+    ) -> fmt::Result {
+        let (group, columns) = (self.group.max(1), self.columns.max(1));
+        let digits = self.radix.digits(size_of::<T>() as u32 * 8);
+        // Lines are padded to the width of a full one, so the characters line up
+        let width = columns * digits + columns.div_ceil(group) - 1;
+        let mut skipping = false;
+        for (line, chunk) in cells.chunks(columns).enumerate() {
+            if self.non_zero && chunk.iter().all(CellKind::is_zero) {
+                if !skipping {
+                    writeln!(out, "*")?;
+                    skipping = true;
+                }
+                continue;
+            }
+            skipping = false;
+            write!(out, "{:08x}: ", start + line * columns)?;
+            let mut values = String::new();
+            for (i, &cell) in chunk.iter().enumerate() {
+                if i > 0 && i % group == 0 {
+                    values.push(' ');
+                }
+                let value: u64 = cell.into();
+                let _ = match self.radix {
+                    Radix::Binary => write!(values, "{value:0digits$b}"),
+                    Radix::Octal => write!(values, "{value:0digits$o}"),
+                    Radix::Decimal => write!(values, "{value:0digits$}"),
+                    Radix::Hex => write!(values, "{value:0digits$x}"),
+                };
+            }
+            if !self.chars {
+                writeln!(out, "{values}")?;
+                continue;
+            }
+            write!(out, "{values:width$}  ")?;
+            for &cell in chunk {
+                match u8::try_from(cell.into()) {
+                    Ok(byte) if byte.is_ascii_graphic() || byte == b' ' => {
+                        out.write_char(byte as char)?
+                    }
+                    _ => out.write_char('.')?,
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_cells_use_wider_columns() {
+        let format = DumpFormat {
+            radix: Radix::Decimal,
+            group: 1,
+            columns: 3,
+            ..DumpFormat::default()
+        };
+        assert_eq!(
+            format.dump(&[65u16, 300, 7, 0], 16),
+            "00000010: 00065 00300 00007  A..\n\
+             00000013: 00000              .\n"
+        );
+        let format = DumpFormat {
+            radix: Radix::Binary,
+            chars: false,
+            ..DumpFormat::default()
+        };
+        assert_eq!(format.dump(&[5u8], 0), "00000000: 00000101\n");
+        assert_eq!(Radix::Octal.digits(32), 11);
+    }
+}
@@ -0,0 +1,123 @@
+//! One error type for everything that can stop a program being loaded and run
+//!
+//! Embedders can match on the category of a [BftError], or report its [code](BftError::code),
+//! instead of handling each crate's errors separately.
+
+use alloc::string::String;
+use core::fmt;
+
+use bft_types::span::SourceSpan;
+use bft_types::{display_file, FileNameBuf, ParseError, ParseErrorKind};
+use thiserror::Error;
+
+use crate::{io, VMError};
+
+/// Anything that went wrong loading, checking or running a program
+#[derive(Error, Debug)]
+pub enum BftError {
+    /// Reading or writing a file failed
+    Io(#[from] io::Error),
+    /// The program isn't valid Brainfuck
+    Parse(#[from] ParseError),
+    /// The program failed while it was running, in `file` if it's known
+    Vm {
+        file: Option<FileNameBuf>,
+        #[source]
+        source: VMError,
+    },
+    /// The options or supporting files given were unusable
+    Config(String),
+}
+
+impl BftError {
+    /// A code for the kind of error, which stays the same between releases
+    ///
+    /// | Code  | Error                                         |
+    /// |-------|-----------------------------------------------|
+    /// | E0001 | I/O error                                     |
+    /// | E0101 | A loop was closed without being opened        |
+    /// | E0102 | A loop was opened without being closed        |
+    /// | E0201 | The head moved below the first cell           |
+    /// | E0202 | The head moved past the last cell             |
+    /// | E0203 | I/O error while the program was running       |
+    /// | E0204 | The program went over a limit                 |
+    /// | E0205 | The bytecode doesn't match the program        |
+    /// | E0206 | The program is stuck in a loop forever        |
+    /// | E0207 | A loop went round too many times              |
+    /// | E0208 | A range of instructions splits a loop         |
+    /// | E0209 | The program is empty                          |
+    /// | E0301 | Bad options or supporting files               |
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::error::BftError;
+    /// # use bft_types;
+    /// let prog = bft_types::Program::new("<None>", "[");
+    /// let result = bft_types::DecoratedProgram::from_program(&prog).map_err(BftError::from);
+    /// assert_eq!(result.err().unwrap().code(), "E0102");
+    /// ```
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Io(_) => "E0001",
+            Self::Parse(error) => match error.kind() {
+                ParseErrorKind::UnopenedBracket => "E0101",
+                ParseErrorKind::UnclosedBracket => "E0102",
+            },
+            Self::Vm { source, .. } => match source {
+                VMError::SeekTooLow(_) => "E0201",
+                VMError::SeekTooHigh(_) => "E0202",
+                VMError::IOError { .. } => "E0203",
+                VMError::LimitExceeded { .. } => "E0204",
+                VMError::BytecodeMismatch => "E0205",
+                VMError::InfiniteLoop { .. } => "E0206",
+                VMError::LoopLimitExceeded(_) => "E0207",
+                VMError::SplitLoop { .. } => "E0208",
+                VMError::EmptyProgram => "E0209",
+            },
+            Self::Config(_) => "E0301",
+        }
+    }
+
+    /// Where in the program the error is, if that's known
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::error::BftError;
+    /// # use bft_types;
+    /// let prog = bft_types::Program::new("<None>", "+\n  ]");
+    /// let error = BftError::from(bft_types::DecoratedProgram::from_program(&prog).err().unwrap());
+    /// let span = error.span().unwrap();
+    /// assert_eq!((span.line, span.column, span.offset), (2, 3, 4));
+    /// ```
+    pub fn span(&self) -> Option<SourceSpan> {
+        match self {
+            Self::Parse(error) => Some(error.span()),
+            Self::Vm {
+                file: Some(file),
+                source,
+            } => source.span(file),
+            Self::Io(_) | Self::Vm { file: None, .. } | Self::Config(_) => None,
+        }
+    }
+}
+
+impl From<VMError> for BftError {
+    fn from(source: VMError) -> BftError {
+        BftError::Vm { file: None, source }
+    }
+}
+
+impl fmt::Display for BftError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{error}"),
+            Self::Parse(error) => write!(f, "{error}"),
+            Self::Vm {
+                file: Some(file),
+                source,
+            } => write!(f, "{}: {source}", display_file(file)),
+            Self::Vm { file: None, source } => write!(f, "{source}"),
+            Self::Config(message) => write!(f, "{message}"),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! A live feed of what a program does, sent from the thread running it
+//!
+//! [Machine::spawn_with_events] runs a program on a worker thread and sends an [Event] down a
+//! channel for every instruction, cell change and byte of I/O, so a visualiser only has to read
+//! the channel. The channel is bounded, so a visualiser that falls behind slows the program down
+//! rather than letting events pile up. This needs the `std` feature.
+
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use bft_types::PositionedInstruction;
+
+use crate::bytecode::Op;
+use crate::io::{Read, Write};
+use crate::trace::{Io, Step, Tracer};
+use crate::{CellKind, Machine, VMError};
+
+/// How many events can wait in the channel before the program pauses for the reader to catch up
+pub const CAPACITY: usize = 1024;
+
+/// Something a running program did
+#[derive(Debug, Clone)]
+pub enum Event<T> {
+    /// An instruction is about to be executed
+    Executed {
+        /// How many instructions have been executed, counting this one
+        step: u64,
+        instruction: PositionedInstruction,
+        op: Op,
+        head: usize,
+    },
+    /// The last instruction changed a cell
+    CellChanged { cell: usize, old: T, new: T },
+    /// The program read a byte
+    Input(Io),
+    /// The program wrote a byte
+    Output(Io),
+    /// The program stopped, whether it finished or failed. Nothing is sent after this
+    Finished,
+}
+
+/// A program running on a worker thread, made by [Machine::spawn_with_events]
+pub struct EventRun<T> {
+    /// Every event, in the order they happened
+    pub events: Receiver<Event<T>>,
+    handle: JoinHandle<(Machine<'static, T>, Result<(), VMError>)>,
+}
+
+impl<T> EventRun<T> {
+    /// Waits for the program to stop, returning the Machine and how the run ended
+    ///
+    /// Events that haven't been read stay in [EventRun::events] until this is called, so a
+    /// caller that doesn't read them all should drop the receiver first, or the program may never
+    /// stop.
+    pub fn join(self) -> (Machine<'static, T>, Result<(), VMError>) {
+        drop(self.events);
+        match self.handle.join() {
+            Ok(finished) => finished,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl<T: CellKind + Send + 'static> Machine<'static, T> {
+    /// Runs the program from the start on a new thread, sending an [Event] for everything it does
+    ///
+    /// The program is interpreted an instruction at a time, so that every cell change is seen.
+    /// If the receiver is dropped, the program carries on without sending anything.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::events::Event;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+.")
+    /// ).unwrap();
+    /// let interp: bft_interp::Machine<u8> = bft_interp::Machine::owning(None, false, prog);
+    /// let run = interp.spawn_with_events(std::io::empty(), Vec::new());
+    /// let changes: Vec<(usize, u8, u8)> = run
+    ///     .events
+    ///     .iter()
+    ///     .filter_map(|event| match event {
+    ///         Event::CellChanged { cell, old, new } => Some((cell, old, new)),
+    ///         _ => None,
+    ///     })
+    ///     .collect();
+    /// assert_eq!(changes, [(0, 0, 1)]);
+    /// ```
+    pub fn spawn_with_events<R, W>(mut self, mut input: R, mut output: W) -> EventRun<T>
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let (sender, events) = mpsc::sync_channel(CAPACITY);
+        let handle = thread::spawn(move || {
+            let mut sender = EventSender {
+                sender: Some(sender),
+                watched: None,
+            };
+            let result = self.run_traced(&mut input, &mut output, &mut sender);
+            sender.cell_changes(self.cells());
+            sender.send(Event::Finished);
+            (self, result)
+        });
+        EventRun { events, handle }
+    }
+}
+
+/// Turns what a [Tracer] sees into events
+struct EventSender<T> {
+    /// None once the receiver has gone
+    sender: Option<SyncSender<Event<T>>>,
+    /// The cell the last instruction could have changed, and its value before it ran
+    watched: Option<(usize, T)>,
+}
+
+impl<T: CellKind> EventSender<T> {
+    fn send(&mut self, event: Event<T>) {
+        if let Some(sender) = &self.sender {
+            if sender.send(event).is_err() {
+                self.sender = None;
+            }
+        }
+    }
+
+    /// Sends a [Event::CellChanged] if the last instruction changed the cell it was on
+    fn cell_changes(&mut self, cells: &[T]) {
+        if let Some((cell, old)) = self.watched.take() {
+            if let Some(new) = cells.get(cell).filter(|&new| *new != old) {
+                let new = new.clone();
+                self.send(Event::CellChanged { cell, old, new });
+            }
+        }
+    }
+}
+
+impl<T: CellKind> Tracer<T> for EventSender<T> {
+    fn step(&mut self, step: &Step<'_, T>) {
+        self.cell_changes(step.cells);
+        self.send(Event::Executed {
+            step: step.step,
+            instruction: step.instruction,
+            op: step.op,
+            head: step.head,
+        });
+        self.watched = Some((step.head, step.cells[step.head].clone()));
+    }
+
+    fn input(&mut self, io: &Io) {
+        self.send(Event::Input(*io));
+    }
+
+    fn output(&mut self, io: &Io) {
+        self.send(Event::Output(*io));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::{DecoratedProgram, Program};
+
+    #[test]
+    fn events_follow_the_program() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", ",+>.")).unwrap();
+        let machine: Machine<u8> = Machine::owning(None, false, prog);
+        let run = machine.spawn_with_events(&b"a"[..], Vec::new());
+        let events: Vec<Event<u8>> = run.events.iter().collect();
+        let kinds: Vec<&str> = events
+            .iter()
+            .map(|event| match event {
+                Event::Executed { .. } => "executed",
+                Event::CellChanged { .. } => "changed",
+                Event::Input(_) => "input",
+                Event::Output(_) => "output",
+                Event::Finished => "finished",
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "executed", "input", "changed", "executed", "changed", "executed", "executed",
+                "output", "finished"
+            ]
+        );
+        assert!(matches!(
+            events[4],
+            Event::CellChanged {
+                cell: 0,
+                old: b'a',
+                new: b'b'
+            }
+        ));
+        let (machine, result) = run.join();
+        assert!(result.is_ok());
+        assert_eq!(machine.head(), 1);
+    }
+}
@@ -0,0 +1,325 @@
+//! Testing interactive programs by talking to them
+//!
+//! A [Script] alternates between sending a program input and expecting it to write some output,
+//! like the classic `expect` tool. Scripts are text, with one action per line:
+//!
+//! ```text
+//! # Comments start with a hash
+//! expect What's your name?
+//! send Ada\n
+//! timeout 5
+//! expect Hello, Ada
+//! close
+//! ```
+//!
+//! The text after `send` and `expect` may use the escapes that [unescape] understands. `expect`
+//! waits up to `timeout` seconds, 2 by default, for the text to appear in the output, skipping
+//! anything written before it. `close` ends the program's input.
+
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bft_types::DecoratedProgram;
+use thiserror::Error;
+
+use crate::bytecode::Bytecode;
+use crate::limits::Limits;
+use crate::{Machine, VMError};
+
+/// How long `expect` waits if the script doesn't say
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Converts text with `\n`, `\t`, `\r`, `\0`, `\\` and `\xNN` escapes into the bytes it represents
+///
+/// # Examples
+/// ```
+/// # use bft_interp::expect::unescape;
+/// assert_eq!(unescape(r"a\n\x41").unwrap(), b"a\nA");
+/// assert!(unescape(r"\q").is_err());
+/// ```
+pub fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        let Some((&escape, tail)) = rest.split_first() else {
+            return Err("text can't end with a lone \\".to_string());
+        };
+        rest = tail;
+        bytes.push(match escape {
+            b'n' => b'\n',
+            b't' => b'\t',
+            b'r' => b'\r',
+            b'0' => 0,
+            b'\\' => b'\\',
+            b'x' => {
+                let digits = rest
+                    .get(..2)
+                    .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+                    .and_then(|digits| std::str::from_utf8(digits).ok());
+                let value = digits.and_then(|digits| u8::from_str_radix(digits, 16).ok());
+                rest = rest.get(2..).unwrap_or_default();
+                value.ok_or("\\x must be followed by two hex digits")?
+            }
+            other => return Err(format!("unknown escape \\{}", other as char)),
+        });
+    }
+    Ok(bytes)
+}
+
+/// Converts bytes into text that [unescape] turns back into the same bytes
+///
+/// Printable ASCII is kept as it is, and everything else is escaped.
+///
+/// # Examples
+/// ```
+/// # use bft_interp::expect::{escape, unescape};
+/// assert_eq!(escape(b"a\n\x7f\\"), r"a\n\x7f\\");
+/// assert_eq!(unescape(&escape(b"\0\xff")).unwrap(), b"\0\xff");
+/// ```
+pub fn escape(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for &byte in bytes {
+        match byte {
+            b'\n' => text.push_str("\\n"),
+            b'\t' => text.push_str("\\t"),
+            b'\r' => text.push_str("\\r"),
+            0 => text.push_str("\\0"),
+            b'\\' => text.push_str("\\\\"),
+            b' '..=b'~' => text.push(byte as char),
+            _ => text.push_str(&format!("\\x{byte:02x}")),
+        }
+    }
+    text
+}
+
+/// One line of a [Script]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Give the program these bytes of input
+    Send(Vec<u8>),
+    /// Wait for the program to write these bytes
+    Expect { text: Vec<u8>, timeout: Duration },
+    /// End the program's input
+    Close,
+}
+
+/// A conversation to have with a program
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    /// Each action, with the line it was on
+    actions: Vec<(usize, Action)>,
+}
+
+impl Script {
+    /// Reads a script from its text
+    pub fn parse(text: &str) -> Result<Script, ScriptError> {
+        let mut script = Script::default();
+        let mut timeout = DEFAULT_TIMEOUT;
+        for (index, line) in text.lines().enumerate() {
+            let bad = |reason| ScriptError {
+                line: index + 1,
+                reason,
+            };
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+            let action = match command {
+                "send" => Action::Send(unescape(argument).map_err(bad)?),
+                "expect" => Action::Expect {
+                    text: unescape(argument).map_err(bad)?,
+                    timeout,
+                },
+                "close" => Action::Close,
+                "timeout" => {
+                    timeout = argument
+                        .trim()
+                        .parse()
+                        .ok()
+                        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                        .ok_or_else(|| bad("timeout needs a number of seconds".to_string()))?;
+                    continue;
+                }
+                other => return Err(bad(format!("unknown action {other}"))),
+            };
+            script.actions.push((index + 1, action));
+        }
+        Ok(script)
+    }
+
+    /// Returns each action, in order
+    pub fn actions(&self) -> impl Iterator<Item = &Action> {
+        self.actions.iter().map(|(_, action)| action)
+    }
+
+    /// Runs `prog` on its own thread, sending and expecting as the script says
+    ///
+    /// The program can't be stopped while it runs without reading or writing, so if it might
+    /// loop forever set a step limit: otherwise it carries on in the background once the script
+    /// has finished.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::{expect::Script, limits::Limits};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",[+.,]")
+    /// ).unwrap();
+    /// let script = Script::parse("send abc\nexpect bcd").unwrap();
+    /// assert!(script.run(&prog, Limits::default()).is_ok());
+    /// let script = Script::parse("timeout 0.1\nsend a\nexpect a").unwrap();
+    /// assert!(script.run(&prog, Limits::default()).is_err());
+    /// ```
+    pub fn run(&self, prog: &DecoratedProgram, limits: Limits) -> Result<(), ExpectError> {
+        let (input, program_input) = mpsc::channel();
+        let (program_output, output) = mpsc::channel();
+        let prog = prog.clone();
+        let mut program = Some(thread::spawn(move || {
+            let bytecode = Bytecode::compile(&prog);
+            let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+            machine.set_limits(limits);
+            machine.run_bytecode(
+                &bytecode,
+                &mut ChannelReader {
+                    bytes: program_input,
+                    pending: Vec::new(),
+                },
+                &mut ChannelWriter(program_output),
+            )
+        }));
+        let mut input = Some(input);
+        let mut seen = Vec::new();
+        for (line, action) in &self.actions {
+            let (text, timeout) = match action {
+                Action::Send(bytes) => {
+                    // If the program has finished, the next expect finds out
+                    if let Some(input) = &input {
+                        let _ = input.send(bytes.clone());
+                    }
+                    continue;
+                }
+                Action::Close => {
+                    input = None;
+                    continue;
+                }
+                Action::Expect { text, timeout } => (text, *timeout),
+            };
+            let deadline = Instant::now() + timeout;
+            let end = loop {
+                if let Some(start) = find(&seen, text) {
+                    break start + text.len();
+                }
+                let wait = deadline.saturating_duration_since(Instant::now());
+                match output.recv_timeout(wait) {
+                    Ok(byte) => seen.push(byte),
+                    Err(RecvTimeoutError::Timeout) => {
+                        return Err(ExpectError::Timeout {
+                            line: *line,
+                            expected: describe(text),
+                            output: describe(&seen),
+                        })
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        let result = program.take().map(thread::JoinHandle::join);
+                        return Err(ExpectError::Finished {
+                            line: *line,
+                            expected: describe(text),
+                            output: describe(&seen),
+                            error: result.and_then(Result::ok).and_then(Result::err),
+                        });
+                    }
+                }
+            };
+            seen.drain(..end);
+        }
+        Ok(())
+    }
+}
+
+/// Returns where `needle` first appears in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Shows bytes as text, escaping anything unprintable
+fn describe(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).escape_debug().to_string()
+}
+
+/// Input that the script sends, which ends when the script closes it or finishes
+struct ChannelReader {
+    bytes: Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.bytes.recv() {
+                Ok(bytes) => self.pending = bytes,
+                Err(_) => return Ok(0),
+            }
+        }
+        let count = buf.len().min(self.pending.len());
+        buf[..count].copy_from_slice(&self.pending[..count]);
+        self.pending.drain(..count);
+        Ok(count)
+    }
+}
+
+/// Output for the script to expect, which fails once the script has finished
+struct ChannelWriter(Sender<u8>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            self.0
+                .send(byte)
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A line of a script that doesn't make sense
+#[derive(Error, Debug)]
+#[error("Line {line} of the script is invalid: {reason}")]
+pub struct ScriptError {
+    pub line: usize,
+    pub reason: String,
+}
+
+/// Ways a program can fail to follow a script
+#[derive(Error, Debug)]
+pub enum ExpectError {
+    #[error("Line {line} timed out waiting for \"{expected}\". The program wrote \"{output}\"")]
+    Timeout {
+        line: usize,
+        expected: String,
+        output: String,
+    },
+    #[error("Line {line} expected \"{expected}\", but the program finished after writing \"{output}\"{}", .error.as_ref().map_or(String::new(), |e| format!(": {e}")))]
+    Finished {
+        line: usize,
+        expected: String,
+        output: String,
+        /// Why the program finished, if it failed
+        error: Option<VMError>,
+    },
+}
@@ -0,0 +1,556 @@
+//! Programs annotated with the structure they appear to have
+//!
+//! An [Explanation] splits a program into pieces: runs of the same kind of instruction, loop
+//! brackets, and whole loops that follow a common idiom such as `[-]` or `[->>+<<]`. Each piece
+//! is described in terms of the cells it works on, as far as they can be told without running the
+//! program, and is nested as deeply as its loop.
+//!
+//! Cells are numbered from where the head starts. The head's position is followed through moves
+//! and loops that end where they started; after a loop that doesn't, such as `[>]`, it isn't
+//! known, and the cells used from there on go uncounted. Printed constants come from
+//! [Analysis], so they assume 8-bit cells.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use bft_types::{DecoratedProgram, PositionedInstruction, RawInstruction};
+
+use crate::analysis::Analysis;
+
+/// What a piece of a program does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Piece {
+    /// `+` and `-`, adding `amount` to a cell
+    Add { amount: isize, cell: Option<usize> },
+    /// `>` and `<`, moving the head `distance` cells right, to cell `to` if it's known
+    Move { distance: isize, to: Option<usize> },
+    /// `,` read `times` times into a cell
+    Read { times: usize, cell: Option<usize> },
+    /// `.` printed `times` times from a cell, which is known to hold `constant` if it's given
+    Print {
+        times: usize,
+        cell: Option<usize>,
+        constant: Option<u8>,
+    },
+    /// The `[` of a loop that doesn't follow an idiom, testing a cell
+    Loop { cell: Option<usize> },
+    /// The `]` of a loop that doesn't follow an idiom
+    EndLoop,
+    /// `[-]` or `[+]`, which set a cell to zero
+    Clear { cell: Option<usize> },
+    /// A loop like `[>]` or `[<<]`, moving the head `stride` cells at a time until it finds a zero
+    Scan { stride: isize },
+    /// A loop like `[->+>+<<]`, emptying a cell into the cells at each offset from it, adding
+    /// the value times the factor to each
+    Transfer {
+        cell: Option<usize>,
+        targets: Vec<(isize, isize)>,
+    },
+}
+
+/// Writes the piece as a short English phrase
+impl fmt::Display for Piece {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Piece::Add { amount, cell } if *amount < 0 => {
+                write!(f, "subtract {} from {}", amount.unsigned_abs(), Cell(*cell))
+            }
+            Piece::Add { amount, cell } => write!(f, "add {amount} to {}", Cell(*cell)),
+            Piece::Move { to: Some(to), .. } => write!(f, "move to cell {to}"),
+            Piece::Move { distance, to: None } => {
+                write!(
+                    f,
+                    "move {} {}",
+                    distance.unsigned_abs(),
+                    direction(*distance)
+                )
+            }
+            Piece::Read { times, cell } => {
+                write!(f, "read {} into {}", Times(*times, "byte"), Cell(*cell))
+            }
+            Piece::Print {
+                times,
+                constant: Some(constant),
+                ..
+            } => {
+                if constant.is_ascii_graphic() || *constant == b' ' {
+                    write!(f, "print constant '{}'", *constant as char)?;
+                } else {
+                    write!(f, "print constant {constant}")?;
+                }
+                match times {
+                    1 => Ok(()),
+                    times => write!(f, " {times} times"),
+                }
+            }
+            Piece::Print { times, cell, .. } => match times {
+                1 => write!(f, "print {}", Cell(*cell)),
+                times => write!(f, "print {} {times} times", Cell(*cell)),
+            },
+            Piece::Loop { cell } => write!(f, "loop while {} isn't zero", Cell(*cell)),
+            Piece::EndLoop => write!(f, "end of loop"),
+            Piece::Clear { cell } => write!(f, "clear {}", Cell(*cell)),
+            Piece::Scan { stride } => {
+                write!(f, "move {} to the next zero cell", direction(*stride))?;
+                match stride.unsigned_abs() {
+                    1 => Ok(()),
+                    stride => write!(f, ", {stride} cells at a time"),
+                }
+            }
+            Piece::Transfer { cell, targets } => match targets[..] {
+                [(offset, 1)] => write!(f, "move {} to cell{offset:+}", Cell(*cell)),
+                [(offset, factor)] => {
+                    write!(
+                        f,
+                        "multiply {} by {factor} into cell{offset:+}",
+                        Cell(*cell)
+                    )
+                }
+                _ => {
+                    write!(f, "copy {} to ", Cell(*cell))?;
+                    for (index, (offset, factor)) in targets.iter().enumerate() {
+                        match index {
+                            0 => {}
+                            _ if index + 1 == targets.len() => write!(f, " and ")?,
+                            _ => write!(f, ", ")?,
+                        }
+                        write!(f, "cell{offset:+}")?;
+                        if *factor != 1 {
+                            write!(f, " (×{factor})")?;
+                        }
+                    }
+                    Ok(())
+                }
+            },
+        }
+    }
+}
+
+/// A cell by its number, or "the current cell" if that isn't known
+struct Cell(Option<usize>);
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(cell) => write!(f, "cell {cell}"),
+            None => write!(f, "the current cell"),
+        }
+    }
+}
+
+/// A count of things, as "1 byte" or "3 bytes"
+struct Times(usize, &'static str);
+
+impl fmt::Display for Times {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            1 => write!(f, "1 {}", self.1),
+            count => write!(f, "{count} {}s", self.1),
+        }
+    }
+}
+
+fn direction(distance: isize) -> &'static str {
+    if distance < 0 {
+        "left"
+    } else {
+        "right"
+    }
+}
+
+/// One piece of a program, where it starts, and how deeply it's nested in loops
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub start: PositionedInstruction,
+    pub depth: usize,
+    /// The piece's instructions, without comments
+    pub code: String,
+    pub piece: Piece,
+}
+
+/// How the program uses a cell, counting each piece once however many times it runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub changes: usize,
+    pub loops: usize,
+    pub reads: usize,
+    pub prints: usize,
+}
+
+/// A program split into pieces, with what each one does and how the cells are used
+pub struct Explanation {
+    lines: Vec<Line>,
+    cells: BTreeMap<usize, Usage>,
+    lost: Option<PositionedInstruction>,
+}
+
+impl Explanation {
+    /// Explains a program
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::explain::Explanation;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "++++++++++.[-]++++++++[>+++++++++<-]>.[->+>+<<]")
+    /// ).unwrap();
+    /// let explanation = Explanation::from_program(&prog);
+    /// let notes: Vec<_> = explanation.lines().iter().map(|line| line.piece.to_string()).collect();
+    /// assert_eq!(notes, [
+    ///     "add 10 to cell 0",
+    ///     "print constant 10",
+    ///     "clear cell 0",
+    ///     "add 8 to cell 0",
+    ///     "multiply cell 0 by 9 into cell+1",
+    ///     "move to cell 1",
+    ///     "print cell 1",
+    ///     "copy cell 1 to cell+1 and cell+2",
+    /// ]);
+    /// assert_eq!(explanation.cells()[&3].changes, 1);
+    /// ```
+    pub fn from_program(prog: &DecoratedProgram) -> Explanation {
+        let mut explainer = Explainer {
+            instructions: prog
+                .decorated_instructions()
+                .iter()
+                .map(|instruction| instruction.instruction())
+                .collect(),
+            partners: crate::matching_brackets(prog),
+            analysis: Analysis::from_program(prog),
+            explanation: Explanation {
+                lines: Vec::new(),
+                cells: BTreeMap::new(),
+                lost: None,
+            },
+        };
+        let end = explainer.instructions.len();
+        explainer.block(0, end, 0, Some(0));
+        explainer.explanation
+    }
+
+    /// Every piece of the program, in order
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// How each cell whose number is known is used
+    pub fn cells(&self) -> &BTreeMap<usize, Usage> {
+        &self.cells
+    }
+
+    /// Where the head's position stopped being known, if it did
+    pub fn lost(&self) -> Option<PositionedInstruction> {
+        self.lost
+    }
+}
+
+/// Writes each piece on a line with its position and code, then a line for each cell's usage
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .lines
+            .iter()
+            .map(|line| line.depth * 2 + line.code.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(32);
+        for line in &self.lines {
+            let position = alloc::format!("{}:{}", line.start.line(), line.start.character());
+            let indent = line.depth * 2;
+            let code_width = width.saturating_sub(indent);
+            writeln!(
+                f,
+                "{position:<8} {:indent$}{:<code_width$}  {}",
+                "", line.code, line.piece
+            )?;
+        }
+        if !self.cells.is_empty() {
+            writeln!(f)?;
+        }
+        for (cell, usage) in &self.cells {
+            write!(f, "cell {cell}:")?;
+            let counts = [
+                ("changed", usage.changes, "time"),
+                ("tests", usage.loops, "loop"),
+                ("read", usage.reads, "time"),
+                ("printed", usage.prints, "time"),
+            ];
+            let counts = counts.into_iter().filter(|(_, count, _)| *count > 0);
+            for (index, (what, count, noun)) in counts.enumerate() {
+                let separator = if index == 0 { " " } else { ", " };
+                write!(f, "{separator}{what} {}", Times(count, noun))?;
+            }
+            writeln!(f)?;
+        }
+        if let Some(lost) = self.lost {
+            writeln!(
+                f,
+                "The head's position isn't known after {}:{}, so later cells aren't counted",
+                lost.line(),
+                lost.character()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct Explainer {
+    instructions: Vec<PositionedInstruction>,
+    partners: Vec<usize>,
+    analysis: Analysis,
+    explanation: Explanation,
+}
+
+impl Explainer {
+    fn raw(&self, index: usize) -> RawInstruction {
+        *self.instructions[index].instruction()
+    }
+
+    fn code(&self, range: core::ops::Range<usize>) -> String {
+        self.instructions[range]
+            .iter()
+            .map(|instruction| instruction.instruction().to_byte() as char)
+            .collect()
+    }
+
+    fn count(&mut self, cell: Option<usize>, update: impl FnOnce(&mut Usage)) {
+        if let Some(cell) = cell {
+            update(self.explanation.cells.entry(cell).or_default());
+        }
+    }
+
+    fn push(&mut self, range: core::ops::Range<usize>, depth: usize, piece: Piece) {
+        let line = Line {
+            start: self.instructions[range.start],
+            depth,
+            code: self.code(range),
+            piece,
+        };
+        self.explanation.lines.push(line);
+    }
+
+    /// Notes that the head's position is lost at `index`, unless it was lost already
+    fn lose(&mut self, index: usize) {
+        self.explanation
+            .lost
+            .get_or_insert(self.instructions[index]);
+    }
+
+    /// Explains the instructions from `start` up to `end`, with the head on cell `head`,
+    /// returning where the head ends up
+    fn block(
+        &mut self,
+        start: usize,
+        end: usize,
+        depth: usize,
+        mut head: Option<usize>,
+    ) -> Option<usize> {
+        let mut index = start;
+        while index < end {
+            let raw = self.raw(index);
+            let run_of = |kinds: &[RawInstruction]| {
+                (index..end)
+                    .take_while(|index| kinds.contains(&self.raw(*index)))
+                    .count()
+            };
+            match raw {
+                RawInstruction::IncrementByte | RawInstruction::DecrementByte => {
+                    let length =
+                        run_of(&[RawInstruction::IncrementByte, RawInstruction::DecrementByte]);
+                    let amount = self.net(index..index + length, RawInstruction::IncrementByte);
+                    self.count(head, |usage| usage.changes += 1);
+                    self.push(
+                        index..index + length,
+                        depth,
+                        Piece::Add { amount, cell: head },
+                    );
+                    index += length;
+                }
+                RawInstruction::IncrementDataPointer | RawInstruction::DecrementDataPointer => {
+                    let length = run_of(&[
+                        RawInstruction::IncrementDataPointer,
+                        RawInstruction::DecrementDataPointer,
+                    ]);
+                    let distance =
+                        self.net(index..index + length, RawInstruction::IncrementDataPointer);
+                    head = head.and_then(|head| head.checked_add_signed(distance));
+                    if head.is_none() {
+                        self.lose(index);
+                    }
+                    self.push(
+                        index..index + length,
+                        depth,
+                        Piece::Move { distance, to: head },
+                    );
+                    index += length;
+                }
+                RawInstruction::GetByte => {
+                    let times = run_of(&[RawInstruction::GetByte]);
+                    self.count(head, |usage| usage.reads += 1);
+                    self.push(
+                        index..index + times,
+                        depth,
+                        Piece::Read { times, cell: head },
+                    );
+                    index += times;
+                }
+                RawInstruction::PutByte => {
+                    let times = run_of(&[RawInstruction::PutByte]);
+                    let constant = self
+                        .analysis
+                        .range_before(index)
+                        .filter(|range| range.min() == range.max())
+                        .map(|range| range.min());
+                    self.count(head, |usage| usage.prints += 1);
+                    let piece = Piece::Print {
+                        times,
+                        cell: head,
+                        constant,
+                    };
+                    self.push(index..index + times, depth, piece);
+                    index += times;
+                }
+                RawInstruction::OpenLoop => {
+                    let closer = self.partners[index];
+                    if let Some(piece) = self.idiom(index, closer, head) {
+                        match &piece {
+                            Piece::Clear { .. } => self.count(head, |usage| usage.changes += 1),
+                            Piece::Scan { .. } => {
+                                self.lose(index);
+                                head = None;
+                            }
+                            Piece::Transfer { targets, .. } => {
+                                self.count(head, |usage| {
+                                    usage.loops += 1;
+                                    usage.changes += 1;
+                                });
+                                for (offset, _) in targets.clone() {
+                                    let target =
+                                        head.and_then(|head| head.checked_add_signed(offset));
+                                    self.count(target, |usage| usage.changes += 1);
+                                }
+                            }
+                            _ => {}
+                        }
+                        self.push(index..closer + 1, depth, piece);
+                        index = closer + 1;
+                        continue;
+                    }
+                    self.count(head, |usage| usage.loops += 1);
+                    self.push(index..index + 1, depth, Piece::Loop { cell: head });
+                    let balanced = self.movement(index + 1, closer) == Some(0);
+                    if !balanced {
+                        self.lose(index);
+                        head = None;
+                    }
+                    self.block(index + 1, closer, depth + 1, head);
+                    self.push(closer..closer + 1, depth, Piece::EndLoop);
+                    index = closer + 1;
+                }
+                RawInstruction::CloseLoop => unreachable!("Loops are explained from their start"),
+            }
+        }
+        head
+    }
+
+    /// How many of the instructions in `range` are `up`, less how many aren't
+    fn net(&self, range: core::ops::Range<usize>, up: RawInstruction) -> isize {
+        range
+            .map(|index| if self.raw(index) == up { 1 } else { -1 })
+            .sum()
+    }
+
+    /// How far the instructions from `start` up to `end` move the head, if that's always the same
+    fn movement(&self, start: usize, end: usize) -> Option<isize> {
+        let mut distance = 0;
+        let mut index = start;
+        while index < end {
+            match self.raw(index) {
+                RawInstruction::IncrementDataPointer => distance += 1,
+                RawInstruction::DecrementDataPointer => distance -= 1,
+                RawInstruction::OpenLoop => {
+                    let closer = self.partners[index];
+                    if self.movement(index + 1, closer) != Some(0) {
+                        return None;
+                    }
+                    index = closer;
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+        Some(distance)
+    }
+
+    /// Recognises the loop from `open` to `close` as an idiom, if it is one
+    fn idiom(&self, open: usize, close: usize, head: Option<usize>) -> Option<Piece> {
+        let body = open + 1..close;
+        let mut changes: BTreeMap<isize, isize> = BTreeMap::new();
+        let mut offset = 0;
+        for index in body.clone() {
+            match self.raw(index) {
+                RawInstruction::IncrementDataPointer => offset += 1,
+                RawInstruction::DecrementDataPointer => offset -= 1,
+                RawInstruction::IncrementByte => *changes.entry(offset).or_default() += 1,
+                RawInstruction::DecrementByte => *changes.entry(offset).or_default() -= 1,
+                _ => return None,
+            }
+        }
+        changes.retain(|_, change| *change != 0);
+        let straight = body
+            .clone()
+            .all(|index| self.raw(index) == self.raw(body.start));
+        if changes.is_empty() && offset != 0 && straight {
+            return Some(Piece::Scan { stride: offset });
+        }
+        if offset != 0 {
+            return None;
+        }
+        match changes.remove(&0) {
+            Some(-1 | 1) if changes.is_empty() => Some(Piece::Clear { cell: head }),
+            Some(-1) => Some(Piece::Transfer {
+                cell: head,
+                targets: changes.into_iter().collect(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use bft_types::Program;
+
+    #[test]
+    fn lost_heads_stop_cells_being_counted() {
+        let prog =
+            DecoratedProgram::from_program(&Program::new("<test>", "+[>,.<-]>,[>]>+[<]")).unwrap();
+        let explanation = Explanation::from_program(&prog);
+        assert_eq!(explanation.lost().unwrap().character(), 11);
+        assert_eq!(
+            explanation.to_string(),
+            "1:1      +    add 1 to cell 0\n\
+             1:2      [    loop while cell 0 isn't zero\n\
+             1:3        >  move to cell 1\n\
+             1:4        ,  read 1 byte into cell 1\n\
+             1:5        .  print cell 1\n\
+             1:6        <  move to cell 0\n\
+             1:7        -  subtract 1 from cell 0\n\
+             1:8      ]    end of loop\n\
+             1:9      >    move to cell 1\n\
+             1:10     ,    read 1 byte into cell 1\n\
+             1:11     [>]  move right to the next zero cell\n\
+             1:14     >    move 1 right\n\
+             1:15     +    add 1 to the current cell\n\
+             1:16     [<]  move left to the next zero cell\n\
+             \n\
+             cell 0: changed 2 times, tests 1 loop\n\
+             cell 1: read 2 times, printed 1 time\n\
+             The head's position isn't known after 1:11, so later cells aren't counted\n"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! A tape whose size is fixed when the program is compiled
+//!
+//! A [FixedTape] keeps its cells in an array rather than a vector, so running a program on it
+//! allocates nothing, which suits embedded targets with no heap to spare. Knowing the size up
+//! front also leaves out the checks for growing the tape. It runs [Bytecode] compiled from the
+//! program, without a Machine's limits, policies, tracing or stepping.
+
+use bft_types::{DecoratedProgram, PositionedInstruction, RawInstruction};
+
+use crate::bytecode::{Bytecode, Op};
+use crate::io::{Read, Write};
+use crate::{scan_for_zero, CellKind, VMError};
+
+/// `N` cells of type `T`, and a head that moves over them
+#[derive(Debug, Clone)]
+pub struct FixedTape<T, const N: usize> {
+    cells: [T; N],
+    head: usize,
+    steps: u64,
+}
+
+impl<T: CellKind, const N: usize> FixedTape<T, N> {
+    /// Makes a tape with every cell zero and the head on the first one
+    pub fn new() -> FixedTape<T, N> {
+        const { assert!(N > 0, "A tape needs at least one cell") };
+        FixedTape {
+            cells: core::array::from_fn(|_| T::default()),
+            head: 0,
+            steps: 0,
+        }
+    }
+
+    pub fn cells(&self) -> &[T; N] {
+        &self.cells
+    }
+
+    pub fn head(&self) -> usize {
+        self.head
+    }
+
+    /// How many operations have been executed, counting every run
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Runs bytecode compiled from `prog` until it finishes, like
+    /// [Machine::run_bytecode](crate::Machine::run_bytecode) on a tape that can't grow
+    ///
+    /// Output is flushed once, when the program finishes.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::{bytecode::Bytecode, fixed::FixedTape, VMError};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",[->+>+<<]>.>.>")
+    /// ).unwrap();
+    /// let bytecode = Bytecode::compile(&prog);
+    /// let mut tape: FixedTape<u8, 3> = FixedTape::new();
+    /// let mut output = Vec::new();
+    /// let result = tape.run(&prog, &bytecode, &mut &[7][..], &mut output);
+    /// assert_eq!(output, [7, 7]);
+    /// assert!(matches!(result, Err(VMError::SeekTooHigh(_))));
+    /// assert_eq!(tape.cells(), &[0, 7, 7]);
+    /// ```
+    pub fn run(
+        &mut self,
+        prog: &DecoratedProgram,
+        bytecode: &Bytecode,
+        input: &mut impl Read,
+        output: &mut impl Write,
+    ) -> Result<(), VMError> {
+        if bytecode.fingerprint() != prog.fingerprint() || !bytecode.fits(prog) {
+            return Err(VMError::BytecodeMismatch);
+        }
+        let (ops, origins) = (bytecode.ops(), bytecode.origins());
+        // The instruction `offset` instructions on from the first one `pc` was compiled from
+        let instruction = |pc: usize, offset: usize| -> PositionedInstruction {
+            prog.decorated_instructions()[origins[pc] + offset].instruction()
+        };
+        let mut last_output = None;
+        let mut pc = 0;
+        while let Some(op) = ops.get(pc) {
+            self.steps += 1;
+            match *op {
+                Op::Add(amount) => self.cells[self.head].add(amount),
+                Op::Move(offset) => {
+                    let distance = offset.unsigned_abs();
+                    if offset < 0 {
+                        if distance > self.head {
+                            let error = VMError::SeekTooLow(instruction(pc, self.head));
+                            self.head = 0;
+                            return Err(error);
+                        }
+                        self.head -= distance;
+                    } else {
+                        if distance >= N - self.head {
+                            let error = VMError::SeekTooHigh(instruction(pc, N - 1 - self.head));
+                            self.head = N - 1;
+                            return Err(error);
+                        }
+                        self.head += distance;
+                    }
+                }
+                Op::Clear => self.cells[self.head] = T::default(),
+                Op::Scan(stride) => match scan_for_zero(&self.cells, self.head, stride) {
+                    Ok(zero) => self.head = zero,
+                    // The failing move is counted from the first one in the loop's body
+                    Err(last) if stride > 0 => {
+                        self.head = N - 1;
+                        return Err(VMError::SeekTooHigh(instruction(pc, N - last)));
+                    }
+                    Err(last) => {
+                        self.head = 0;
+                        return Err(VMError::SeekTooLow(instruction(pc, last + 1)));
+                    }
+                },
+                Op::JumpIfZero(target) => {
+                    if self.cells[self.head].is_zero() {
+                        pc = target;
+                    }
+                }
+                Op::JumpIfNonZero(target) => {
+                    if !self.cells[self.head].is_zero() {
+                        pc = target;
+                    }
+                }
+                Op::Input => {
+                    let mut buffer = [0; 1];
+                    input
+                        .read_exact(&mut buffer)
+                        .map_err(|source| VMError::IOError {
+                            instruction: instruction(pc, 0),
+                            source,
+                        })?;
+                    self.cells[self.head].set_value(buffer[0]);
+                }
+                Op::Output => {
+                    last_output = Some(pc);
+                    output
+                        .write_all(&[self.cells[self.head].get_value()])
+                        .map_err(|source| VMError::IOError {
+                            instruction: instruction(pc, 0),
+                            source,
+                        })?;
+                }
+                Op::Guard { low, high, ops } => {
+                    let reaches = self.head.checked_add_signed(low).is_some()
+                        && self
+                            .head
+                            .checked_add_signed(high)
+                            .is_some_and(|highest| highest < N);
+                    if !reaches {
+                        for offset in 0..bytecode.provenance(pc).len() {
+                            let straight = instruction(pc, offset);
+                            match straight.instruction() {
+                                RawInstruction::IncrementByte => self.cells[self.head].increment(),
+                                RawInstruction::DecrementByte => self.cells[self.head].decrement(),
+                                RawInstruction::IncrementDataPointer if self.head == N - 1 => {
+                                    return Err(VMError::SeekTooHigh(straight))
+                                }
+                                RawInstruction::IncrementDataPointer => self.head += 1,
+                                RawInstruction::DecrementDataPointer if self.head == 0 => {
+                                    return Err(VMError::SeekTooLow(straight))
+                                }
+                                RawInstruction::DecrementDataPointer => self.head -= 1,
+                                _ => return Err(VMError::BytecodeMismatch),
+                            }
+                        }
+                        pc += ops;
+                    }
+                }
+            }
+            pc += 1;
+        }
+        match last_output {
+            Some(pc) => output.flush().map_err(|source| VMError::IOError {
+                instruction: instruction(pc, 0),
+                source,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: CellKind, const N: usize> Default for FixedTape<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+    use bft_types::Program;
+
+    #[test]
+    fn fixed_tapes_match_the_machine() {
+        for code in [
+            "++[>+++[>++<-]<-]>>.",
+            "+[>+]",
+            ">>>[-]<<<<",
+            ",[.,]",
+            "+<+>+<+",
+            ">>>>>>+>+<+>+<+",
+        ] {
+            let prog = DecoratedProgram::from_program(&Program::new("<test>", code)).unwrap();
+            let bytecode = Bytecode::compile(&prog);
+            let mut machine: Machine<u16> = Machine::new(Some(8.try_into().unwrap()), false, &prog);
+            let mut tape: FixedTape<u16, 8> = FixedTape::new();
+            let (mut expected, mut output) = (Vec::new(), Vec::new());
+            let result = machine.run_bytecode(&bytecode, &mut &b"ab"[..], &mut expected);
+            let fixed = tape.run(&prog, &bytecode, &mut &b"ab"[..], &mut output);
+            assert_eq!(output, expected, "{code}");
+            assert_eq!(&tape.cells()[..], machine.cells(), "{code}");
+            assert_eq!(tape.head(), machine.head(), "{code}");
+            assert_eq!(tape.steps(), machine.steps(), "{code}");
+            assert_eq!(
+                fixed.map_err(|e| e.to_string()),
+                result.map_err(|e| e.to_string()),
+                "{code}"
+            );
+        }
+    }
+}
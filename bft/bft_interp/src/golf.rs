@@ -0,0 +1,230 @@
+//! Code golf assistance
+//!
+//! Shrinks a program as far as can be done automatically, and points out places where a human
+//! could shrink it further.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use bft_types::{DecoratedProgram, PositionedInstruction, RawInstruction};
+
+use crate::analysis::{Analysis, Finding};
+use crate::superopt;
+
+/// The fewest repeated `+` or `-` worth suggesting a multiplication loop for
+const MIN_CONSTANT: usize = 16;
+
+/// A way to shrink the program that can't be applied automatically
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    /// The first instruction of the code to replace
+    at: PositionedInstruction,
+    /// How many instructions the original code takes
+    before: usize,
+    /// The suggested code
+    replacement: String,
+}
+
+impl Suggestion {
+    /// The first instruction of the code to replace
+    pub fn at(&self) -> PositionedInstruction {
+        self.at
+    }
+
+    /// How many instructions the original code takes
+    pub fn before(&self) -> usize {
+        self.before
+    }
+
+    /// The suggested code
+    pub fn replacement(&self) -> &str {
+        &self.replacement
+    }
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: this constant can be built in {} instructions instead of {} as `{}`, if the cell to its right is zero",
+            self.at.line(),
+            self.at.character(),
+            self.replacement.len(),
+            self.before,
+            self.replacement
+        )
+    }
+}
+
+/// The result of golfing a program
+pub struct Golfed {
+    code: String,
+    suggestions: Vec<Suggestion>,
+}
+
+impl Golfed {
+    /// Shrinks a program without changing what it does
+    ///
+    /// Comments are stripped, loops that can never run are removed, straight-line code is
+    /// superoptimised, and code after the last loop or I/O instruction is dropped as it can't
+    /// affect anything.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::golf::Golfed;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "[a comment loop] +++-- > < . done >>+")
+    /// ).unwrap();
+    /// assert_eq!(Golfed::from_program(&prog).code(), "+.");
+    /// ```
+    pub fn from_program(prog: &DecoratedProgram) -> Golfed {
+        let analysis = Analysis::from_program(prog);
+        let partners = crate::matching_brackets(prog);
+        let instructions = prog.decorated_instructions();
+
+        let mut live = Vec::new();
+        let mut index = 0;
+        while index < instructions.len() {
+            let instruction = instructions[index].instruction();
+            let dead = analysis.findings().iter().any(|finding| {
+                matches!(finding, Finding::DeadLoop(opener)
+                    if opener.line() == instruction.line()
+                        && opener.character() == instruction.character())
+            });
+            if dead {
+                index = partners[index] + 1;
+            } else {
+                live.push(*instruction.instruction());
+                index += 1;
+            }
+        }
+
+        let mut golfed = superopt::superoptimise(&live);
+        while golfed
+            .last()
+            .is_some_and(|last| superopt::Fragment::from_instructions(&[*last]).is_some())
+        {
+            golfed.pop();
+        }
+
+        Golfed {
+            code: golfed.iter().map(|i| i.to_byte() as char).collect(),
+            suggestions: suggest_constants(prog),
+        }
+    }
+
+    /// The golfed code
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// Further changes a human could make to shrink the program
+    pub fn suggestions(&self) -> &[Suggestion] {
+        &self.suggestions
+    }
+}
+
+/// Finds long runs of `+` or `-` that a multiplication loop could build more cheaply
+fn suggest_constants(prog: &DecoratedProgram) -> Vec<Suggestion> {
+    let instructions: Vec<PositionedInstruction> = prog
+        .decorated_instructions()
+        .iter()
+        .map(|i| i.instruction())
+        .collect();
+    let mut suggestions = Vec::new();
+    for run in instructions.chunk_by(|a, b| a.instruction() == b.instruction()) {
+        let kind = *run[0].instruction();
+        if run.len() < MIN_CONSTANT
+            || !matches!(
+                kind,
+                RawInstruction::IncrementByte | RawInstruction::DecrementByte
+            )
+        {
+            continue;
+        }
+        // Wrapping means a long enough run is cheaper to build in the other direction
+        let delta = run.len() % 256;
+        let (amount, kind) = if delta > 128 {
+            (256 - delta, opposite(kind))
+        } else {
+            (delta, kind)
+        };
+        if let Some(replacement) = multiplication_loop(amount, kind.to_byte() as char) {
+            suggestions.push(Suggestion {
+                at: run[0],
+                before: run.len(),
+                replacement,
+            });
+        }
+    }
+    suggestions
+}
+
+/// Finds the shortest `>a[<b>-]<c` loop adding `amount` to a cell, using the cell to its right
+///
+/// Returns None if no such loop is shorter than repeating `symbol` `amount` times.
+fn multiplication_loop(amount: usize, symbol: char) -> Option<String> {
+    let mut best: Option<(usize, usize, usize, isize)> = None;
+    for outer in 2..amount {
+        let inner = (amount + outer / 2) / outer;
+        let correction = amount as isize - (outer * inner) as isize;
+        let length = 7 + outer + inner + correction.unsigned_abs();
+        if length < amount && best.is_none_or(|(best_length, ..)| length < best_length) {
+            best = Some((length, outer, inner, correction));
+        }
+    }
+    let (_, outer, inner, correction) = best?;
+    let fix = match (correction >= 0, symbol) {
+        (true, _) => symbol,
+        (false, '+') => '-',
+        (false, _) => '+',
+    };
+    Some(format!(
+        ">{}[<{}>-]<{}",
+        "+".repeat(outer),
+        symbol.to_string().repeat(inner),
+        fix.to_string().repeat(correction.unsigned_abs())
+    ))
+}
+
+/// The instruction that undoes `+` or `-`
+fn opposite(instruction: RawInstruction) -> RawInstruction {
+    if instruction == RawInstruction::IncrementByte {
+        RawInstruction::DecrementByte
+    } else {
+        RawInstruction::IncrementByte
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multiplication_loops_are_shorter() {
+        let code = multiplication_loop(72, '+').unwrap();
+        assert_eq!(code, ">++++++++[<+++++++++>-]<");
+        assert!(multiplication_loop(10, '+').is_none());
+        assert_eq!(multiplication_loop(23, '-').unwrap(), ">++++[<------>-]<+");
+    }
+
+    #[test]
+    fn suggestions_point_at_long_runs() {
+        let code = format!("{}.\n {}.", "+".repeat(72), "-".repeat(5));
+        let prog = DecoratedProgram::from_program(&bft_types::Program::new("<test>", &code));
+        let golfed = Golfed::from_program(&prog.unwrap());
+        assert_eq!(golfed.suggestions().len(), 1);
+        assert_eq!(golfed.suggestions()[0].at().line(), 1);
+        assert_eq!(golfed.suggestions()[0].before(), 72);
+
+        let code = "+".repeat(200);
+        let prog = DecoratedProgram::from_program(&bft_types::Program::new("<test>", &code));
+        let golfed = Golfed::from_program(&prog.unwrap());
+        assert!(golfed.suggestions()[0]
+            .replacement()
+            .contains("[<-------->-]"));
+    }
+}
@@ -0,0 +1,139 @@
+//! The input and output a [Machine](crate::Machine) reads and writes
+//!
+//! With the `std` feature, which is on by default, these are std's own I/O traits, so any reader
+//! or writer can be used. Without it they're minimal stand-ins with the same names and methods,
+//! which embedded code implements for its own devices. Byte slices read and vectors write either
+//! way.
+
+#[cfg(feature = "std")]
+pub use std::io::{Error, ErrorKind, Read, Write};
+
+#[cfg(not(feature = "std"))]
+pub use bare::{Error, ErrorKind, Read, Write};
+
+#[cfg(not(feature = "std"))]
+mod bare {
+    use alloc::vec::Vec;
+    use core::fmt;
+
+    /// The general category of an [Error]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum ErrorKind {
+        /// The input ran out before a whole byte could be read
+        UnexpectedEof,
+        /// The output stopped accepting bytes
+        WriteZero,
+        /// The device failed in some other way
+        Other,
+    }
+
+    /// A failure reading input or writing output
+    #[derive(Debug)]
+    pub struct Error {
+        kind: ErrorKind,
+        message: &'static str,
+    }
+
+    impl Error {
+        pub const fn new(kind: ErrorKind, message: &'static str) -> Error {
+            Error { kind, message }
+        }
+
+        pub fn kind(&self) -> ErrorKind {
+            self.kind
+        }
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.message)
+        }
+    }
+
+    impl core::error::Error for Error {}
+
+    /// Somewhere a program's input comes from
+    pub trait Read {
+        /// Reads some bytes into `buf`, returning how many, or 0 at the end of the input
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
+
+        /// Fills `buf`, failing with [ErrorKind::UnexpectedEof] if the input runs out first
+        fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<(), Error> {
+            while !buf.is_empty() {
+                match self.read(buf)? {
+                    0 => {
+                        return Err(Error::new(
+                            ErrorKind::UnexpectedEof,
+                            "failed to fill whole buffer",
+                        ))
+                    }
+                    read => buf = &mut buf[read..],
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Somewhere a program's output goes
+    pub trait Write {
+        /// Writes some of `buf`, returning how many bytes were taken
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error>;
+
+        /// Makes sure everything written so far has reached its destination
+        fn flush(&mut self) -> Result<(), Error>;
+
+        /// Writes all of `buf`, failing with [ErrorKind::WriteZero] if the output stops taking it
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+            while !buf.is_empty() {
+                match self.write(buf)? {
+                    0 => {
+                        return Err(Error::new(
+                            ErrorKind::WriteZero,
+                            "failed to write whole buffer",
+                        ))
+                    }
+                    written => buf = &buf[written..],
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Read for &[u8] {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            let length = buf.len().min(self.len());
+            let (read, rest) = self.split_at(length);
+            buf[..length].copy_from_slice(read);
+            *self = rest;
+            Ok(length)
+        }
+    }
+
+    impl<R: Read + ?Sized> Read for &mut R {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            (**self).read(buf)
+        }
+    }
+
+    impl Write for Vec<u8> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            self.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl<W: Write + ?Sized> Write for &mut W {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            (**self).write(buf)
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            (**self).flush()
+        }
+    }
+}
@@ -1,41 +1,255 @@
 //! Brainfuck interpreter library
 //! An implementation of the brainfuck virtual machine
+//!
+//! Turn off the default `std` feature to run programs with just `alloc`, reading and writing
+//! through the traits in [io]. The modules that need threads or clocks are left out.
 
-use std::io::{Read, Write};
-use std::num::NonZeroUsize;
+#![cfg_attr(not(feature = "std"), no_std)]
 
-use bft_types::{DecoratedInstruction, DecoratedProgram, PositionedInstruction};
+extern crate alloc;
+
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::num::NonZeroUsize;
+use core::ops::{Range, RangeInclusive};
+
+use bft_types::span::SourceSpan;
+use bft_types::{
+    DecoratedInstruction, DecoratedProgram, FileName, PositionedInstruction, RawInstruction,
+};
+
+use bytecode::{Bytecode, Op};
+use io::{Read, Write};
+use limits::{Limit, Limits, RunawayLoop};
+use messages::{Lang, Localize};
+use policy::{Eof, Policies, Seek};
+use trace::{Io, Step, Tracer};
+use value::ValueFormat;
 
 use thiserror::Error;
 
-pub trait CellKind: std::clone::Clone + Default {
+pub mod analysis;
+#[cfg(feature = "async")]
+mod asynchronous;
+pub mod bytecode;
+pub mod cycle;
+#[cfg(feature = "std")]
+pub mod diff;
+pub mod disasm;
+pub mod dump;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod events;
+#[cfg(feature = "std")]
+pub mod expect;
+pub mod explain;
+pub mod fixed;
+pub mod golf;
+pub mod io;
+pub mod limits;
+pub mod messages;
+pub mod minimise;
+pub mod narrate;
+#[cfg(feature = "std")]
+pub mod parallel;
+pub mod policy;
+#[cfg(feature = "std")]
+pub mod pool;
+pub mod profile;
+mod render;
+#[cfg(feature = "std")]
+pub mod sample;
+pub mod state;
+pub mod stream;
+pub mod superopt;
+pub mod suspend;
+pub mod symbolic;
+pub mod symbols;
+mod threaded;
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod transcript;
+pub mod value;
+#[cfg(feature = "std")]
+pub mod view;
+
+/// For each bracket in a program, finds the index of the bracket it pairs with
+///
+/// Every other instruction maps to 0, as would a bracket with no partner, though a
+/// DecoratedProgram's brackets are always balanced.
+pub(crate) fn matching_brackets(prog: &DecoratedProgram) -> Vec<usize> {
+    let instructions = prog.decorated_instructions();
+    let mut partners = vec![0; instructions.len()];
+    let mut openers = Vec::new();
+    for (index, instruction) in instructions.iter().enumerate() {
+        match instruction {
+            DecoratedInstruction::OpenLoop { .. } => openers.push(index),
+            DecoratedInstruction::CloseLoop { .. } => {
+                if let Some(opener) = openers.pop() {
+                    partners[opener] = index;
+                    partners[index] = opener;
+                }
+            }
+            _ => {}
+        }
+    }
+    partners
+}
+
+pub trait CellKind: Clone + Default + PartialEq {
     /// Increase the value of the cell by 1
     fn increment(&mut self);
     /// Decrease the value of the cell by 1
     fn decrement(&mut self);
+    /// Add `amount` to the value of the cell, which may be negative
+    ///
+    /// The default implementation increments or decrements one step at a time.
+    fn add(&mut self, amount: isize) {
+        for _ in 0..amount.unsigned_abs() {
+            if amount > 0 {
+                self.increment();
+            } else {
+                self.decrement();
+            }
+        }
+    }
+    /// Returns whether the cell holds zero, i.e. its default value
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
     /// Sets the cell's value to the given value
     ///
     /// Note that the value is a u8 because brainfuck only reads single bytes from stdin
     fn set_value(&mut self, value: u8);
     /// Gets the cell's value as a single byte
     fn get_value(&self) -> u8;
-}
-
-impl CellKind for u8 {
-    fn increment(&mut self) {
-        *self = self.wrapping_add(1)
+    /// Gets the cell's whole value, however wide the cell is, for reporting
+    ///
+    /// The default implementation returns [CellKind::get_value], so wider cells should override it.
+    fn get_full_value(&self) -> u64 {
+        self.get_value().into()
     }
-    fn decrement(&mut self) {
-        *self = self.wrapping_sub(1)
+    /// Sets the cell's whole value, keeping as many of the lowest bits of `value` as the cell has
+    ///
+    /// The default implementation calls [CellKind::set_value] with the lowest byte, so wider cells
+    /// should override it.
+    fn set_full_value(&mut self, value: u64) {
+        self.set_value(value as u8)
     }
-
-    fn set_value(&mut self, value: u8) {
-        *self = value
+    /// Returns the index of the first zero cell in `cells`, if there is one
+    ///
+    /// Scanning for a zero, as `[>]` does, is often where a program spends its time, so the
+    /// integer cells override this to compare whole chunks of cells at once.
+    fn position_zero(cells: &[Self]) -> Option<usize> {
+        cells.iter().position(Self::is_zero)
     }
-    fn get_value(&self) -> u8 {
-        *self
+    /// Returns the index of the last zero cell in `cells`, if there is one
+    fn rposition_zero(cells: &[Self]) -> Option<usize> {
+        cells.iter().rposition(Self::is_zero)
     }
 }
+
+/// Finds the zero cell a loop like `[>]` starting at `head` stops on, or if it would move off the
+/// end of `cells` first, the last cell it reaches
+pub(crate) fn scan_for_zero<T: CellKind>(
+    cells: &[T],
+    head: usize,
+    stride: isize,
+) -> Result<usize, usize> {
+    let distance = stride.unsigned_abs();
+    match stride {
+        1 => match T::position_zero(&cells[head..]) {
+            Some(index) => Ok(head + index),
+            None => Err(cells.len() - 1),
+        },
+        -1 => T::rposition_zero(&cells[..=head]).ok_or(0),
+        _ => {
+            let mut index = head;
+            loop {
+                if cells[index].is_zero() {
+                    return Ok(index);
+                }
+                let next = if stride > 0 {
+                    index
+                        .checked_add(distance)
+                        .filter(|next| *next < cells.len())
+                } else {
+                    index.checked_sub(distance)
+                };
+                match next {
+                    Some(next) => index = next,
+                    None => return Err(index),
+                }
+            }
+        }
+    }
+}
+
+/// How many cells the integer cells' scans compare at once
+///
+/// Comparing a whole chunk without stopping at the first zero lets the compiler use SIMD.
+const SCAN_LANES: usize = 32;
+
+/// Implements CellKind for unsigned integer types, which wrap around like a u8 does
+macro_rules! impl_cell_kind {
+    ($($cell:ty),*) => {
+        $(
+            impl CellKind for $cell {
+                fn increment(&mut self) {
+                    *self = self.wrapping_add(1)
+                }
+                fn decrement(&mut self) {
+                    *self = self.wrapping_sub(1)
+                }
+                fn add(&mut self, amount: isize) {
+                    // Truncating is the same as reducing modulo the cell size, even for negative
+                    // amounts
+                    *self = self.wrapping_add(amount as $cell)
+                }
+
+                fn set_value(&mut self, value: u8) {
+                    *self = value.into()
+                }
+                fn get_value(&self) -> u8 {
+                    // Output is a single byte, so only the lowest byte is kept
+                    *self as u8
+                }
+                fn get_full_value(&self) -> u64 {
+                    (*self).into()
+                }
+                fn set_full_value(&mut self, value: u64) {
+                    *self = value as $cell
+                }
+                fn position_zero(cells: &[Self]) -> Option<usize> {
+                    let skipped = cells
+                        .chunks_exact(SCAN_LANES)
+                        .take_while(|chunk| !chunk.iter().fold(false, |zero, cell| zero | (*cell == 0)))
+                        .count()
+                        * SCAN_LANES;
+                    cells[skipped..]
+                        .iter()
+                        .position(|cell| *cell == 0)
+                        .map(|index| skipped + index)
+                }
+                fn rposition_zero(cells: &[Self]) -> Option<usize> {
+                    let skipped = cells
+                        .rchunks_exact(SCAN_LANES)
+                        .take_while(|chunk| !chunk.iter().fold(false, |zero, cell| zero | (*cell == 0)))
+                        .count()
+                        * SCAN_LANES;
+                    cells[..cells.len() - skipped].iter().rposition(|cell| *cell == 0)
+                }
+            }
+        )*
+    };
+}
+
+impl_cell_kind!(u8, u16, u32);
+
 /// A brainfuck virtual machine
 ///
 /// The type T is the type that all brainfuck cells will be.
@@ -43,6 +257,28 @@ impl CellKind for u8 {
 /// The machine is initialised with a specific number of cells, and may
 /// allocate more cells when the head extends beyond the end of memory if
 /// configured to do so.
+///
+/// Cloning a Machine forks it: the clone has its own tape, head and place in the program, and
+/// shares the program if the Machine borrows it. This lets a search try several continuations
+/// from a common prefix without running the prefix again.
+///
+/// # Examples
+/// ```
+/// # use bft_interp;
+/// # use bft_types;
+/// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+///     &bft_types::Program::new("<None>", "++>,[<]<+.")
+/// ).unwrap();
+/// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+/// for _ in 0..2 {
+///     interp.step(&mut &[][..], &mut Vec::new()).unwrap();
+/// }
+/// let mut fork = interp.clone();
+/// assert_eq!(interp.run_captured(&[0]).unwrap(), [3]);
+/// assert!(fork.run_captured(&[1]).is_err());
+/// assert_eq!(fork.cells()[..2], [2, 1]);
+/// ```
+#[derive(Clone)]
 pub struct Machine<'a, T> {
     /// The Machine's internal memory
     cells: Vec<T>,
@@ -56,14 +292,57 @@ pub struct Machine<'a, T> {
     instruction_pointer: usize,
     /// Whether the cells can be extended if the memory pointer extends past the end
     may_grow: bool,
-    /// The program the Machine will run
-    prog: &'a DecoratedProgram,
+    /// The program the Machine will run, which it may own
+    prog: Cow<'a, DecoratedProgram>,
+    /// For each bracket in the program, the index of the bracket it pairs with
+    partners: Vec<usize>,
+    /// The index of the instruction [Machine::step] executes next
+    next: usize,
+    /// The resources the Machine may use
+    limits: Limits,
+    /// What the Machine does when the program strays off the tape or runs out of input
+    policies: Policies,
+    /// How many operations the Machine has executed
+    steps: u64,
+    /// How many bytes the Machine has written
+    output_bytes: u64,
+    /// When the Machine flushes its output
+    flush: Flush,
+    /// Whether output has been written since the Machine last flushed
+    unflushed: bool,
+    /// How the Machine's Display writes cell values
+    value_format: ValueFormat,
+    /// What's needed to spot the Machine repeating itself, if it's looking
+    cycles: Option<cycle::CycleDetector<T>>,
+    /// The step count at which to next look for a repeated state
+    next_cycle_check: u64,
+    /// How many times each loop has gone round, if there's a limit on it
+    loop_counter: Option<limits::LoopCounter>,
+    /// How many cells the Machine started with
+    size: usize,
+}
+
+/// When a [Machine] flushes its output
+///
+/// Whatever the setting, output is also flushed before reading input, so that prompts are
+/// visible, and when the program finishes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Flush {
+    /// After every byte, so that interactive programs' output appears immediately
+    #[default]
+    EachByte,
+    /// After every newline
+    Line,
+    /// Only when the writer decides to, such as when its buffer fills up
+    Buffered,
 }
 
 impl<'a, T> Machine<'a, T> {
-    /// Writes the program this Machine was initialised with to standard output
+    /// Writes the code of the program this Machine was initialised with to standard output,
+    /// without comments
+    #[cfg(feature = "std")]
     pub fn print_program(&self) {
-        print!("{}", self.prog)
+        print!("{:#}", self.prog)
     }
 
     /// Returns a reference to the Machine's cells
@@ -81,14 +360,178 @@ impl<'a, T> Machine<'a, T> {
         self.may_grow
     }
 
+    /// Returns the resources the Machine may use
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Limits the resources the Machine may use from now on
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::{limits::Limits, Machine, VMError};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+[]")
+    /// ).unwrap();
+    /// let bytecode = bft_interp::bytecode::Bytecode::compile(&prog);
+    /// let mut interp: Machine<u8> = Machine::new(None, false, &prog);
+    /// interp.set_limits(Limits { max_steps: Some(1000), ..Limits::default() });
+    /// let result = interp.run_bytecode(&bytecode, &mut std::io::empty(), &mut std::io::sink());
+    /// assert!(matches!(result, Err(VMError::LimitExceeded { .. })));
+    /// ```
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        let instructions = self.prog.decorated_instructions().len();
+        self.loop_counter = limits
+            .max_loop_iterations
+            .map(|max| limits::LoopCounter::new(max, instructions));
+    }
+
+    /// Returns what the Machine does when the program strays off the tape or runs out of input
+    pub fn policies(&self) -> Policies {
+        self.policies
+    }
+
+    /// Sets what the Machine does from now on when the program strays off the tape or runs out of
+    /// input
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::policy::{Eof, Policies, Seek};
+    /// # use bft_interp::Machine;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "<+<,")
+    /// ).unwrap();
+    /// let mut interp: Machine<u8> = Machine::new(Some(3.try_into().unwrap()), false, &prog);
+    /// interp.set_policies(Policies {
+    ///     seek_too_low: Seek::Wrap,
+    ///     eof: Eof::Max,
+    ///     ..Policies::default()
+    /// });
+    /// interp.run(&mut std::io::empty(), &mut std::io::sink()).unwrap();
+    /// assert_eq!(interp.cells(), [0, 255, 1]);
+    /// ```
+    pub fn set_policies(&mut self, policies: Policies) {
+        self.policies = policies;
+    }
+
+    /// Looks for the Machine coming back to the same state every `interval` steps from now on,
+    /// stopping with [VMError::InfiniteLoop] if it does, or stops looking if None
+    ///
+    /// A repeated state, without any input read in between, means the program can never finish.
+    /// Checking costs a copy of the tape, and a hash of it every `interval` steps or every so many
+    /// steps as there are cells, whichever is more. See [cycle] for how it works.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::{Machine, VMError};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+[>+<]")
+    /// ).unwrap();
+    /// let mut interp: Machine<u8> = Machine::new(Some(4.try_into().unwrap()), false, &prog);
+    /// interp.set_loop_detection(Some(bft_interp::cycle::DEFAULT_INTERVAL));
+    /// let result = interp.run(&mut std::io::empty(), &mut std::io::sink());
+    /// assert!(matches!(result, Err(VMError::InfiniteLoop { .. })));
+    /// ```
+    pub fn set_loop_detection(&mut self, interval: Option<u64>) {
+        self.cycles = interval.map(cycle::CycleDetector::new);
+        self.next_cycle_check = if interval.is_some() {
+            self.steps
+        } else {
+            u64::MAX
+        };
+    }
+
+    /// Returns when the Machine flushes its output
+    pub fn flush(&self) -> Flush {
+        self.flush
+    }
+
+    /// Sets when the Machine flushes its output from now on
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::{Flush, Machine};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "++++++++++.")
+    /// ).unwrap();
+    /// let mut interp: Machine<u8> = Machine::new(None, false, &prog);
+    /// interp.set_flush(Flush::Buffered);
+    /// let mut output = std::io::BufWriter::new(Vec::new());
+    /// interp.run(&mut std::io::empty(), &mut output).unwrap();
+    /// // Buffered output is still flushed when the program finishes
+    /// assert!(output.buffer().is_empty());
+    /// assert_eq!(output.get_ref(), b"\n");
+    /// ```
+    pub fn set_flush(&mut self, flush: Flush) {
+        self.flush = flush;
+    }
+
+    /// Returns how the Machine's Display and [Machine::render_state] write cell values
+    pub fn value_format(&self) -> ValueFormat {
+        self.value_format
+    }
+
+    /// Sets how the Machine's Display and [Machine::render_state] write cell values from now on
+    pub fn set_value_format(&mut self, format: ValueFormat) {
+        self.value_format = format;
+    }
+
+    /// Returns how many operations the Machine has executed
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Returns how many bytes the program has written to its output
+    pub fn output_bytes(&self) -> u64 {
+        self.output_bytes
+    }
+
     /// Returns a reference to the program inside the Machine
-    pub fn prog(&self) -> &'a DecoratedProgram {
-        self.prog
+    pub fn prog(&self) -> &DecoratedProgram {
+        &self.prog
+    }
+
+    /// Returns the instruction at the instruction pointer, or None if the program is empty
+    pub fn current_instruction(&self) -> Option<DecoratedInstruction> {
+        self.prog()
+            .decorated_instructions()
+            .get(self.instruction_pointer)
+            .copied()
     }
 
-    /// Returns the instruction at the instruction pointer
-    pub fn current_instruction(&self) -> DecoratedInstruction {
-        self.prog().decorated_instructions()[self.instruction_pointer]
+    /// Makes the error for something that went wrong at the current instruction, or a
+    /// [VMError::EmptyProgram] if there isn't one
+    pub(crate) fn at_instruction(
+        &self,
+        error: impl FnOnce(PositionedInstruction) -> VMError,
+    ) -> VMError {
+        match self.current_instruction() {
+            Some(instruction) => error(instruction.instruction()),
+            None => VMError::EmptyProgram,
+        }
+    }
+
+    /// Returns the instruction the next [Machine::step] will execute, or None once the program
+    /// has finished
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp;
+    /// # use bft_types;
+    /// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+,")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// interp.step(&mut &[][..], &mut Vec::new()).unwrap();
+    /// assert_eq!(interp.next_instruction().unwrap().instruction().character(), 2);
+    /// ```
+    pub fn next_instruction(&self) -> Option<DecoratedInstruction> {
+        self.prog().decorated_instructions().get(self.next).copied()
     }
 
     /// Decrements the memory pointer
@@ -119,23 +562,30 @@ impl<'a, T> Machine<'a, T> {
     /// TODO! Come back here when moving the head is more useful
     /// TODO! Once I can run programs, decide whether I want to allow external mutation of program state
     pub fn seek_left(&mut self) -> Result<(), VMError> {
-        if self.head == 0 {
-            Err(VMError::SeekTooLow(
-                self.current_instruction().instruction(),
-            ))
-        } else {
+        if self.head > 0 {
             self.head -= 1;
-            Ok(())
+            return Ok(());
+        }
+        match self.policies.seek_too_low {
+            Seek::Error => Err(self.at_instruction(VMError::SeekTooLow)),
+            Seek::Clamp => Ok(()),
+            Seek::Wrap => {
+                self.head = self.cells.len() - 1;
+                Ok(())
+            }
         }
     }
 }
 
+/// How many cells a Machine's tape starts with when no size is given
+pub const DEFAULT_CELLS: usize = 30000;
+
 impl<'a, T> Machine<'a, T>
 where
     T: CellKind,
 {
     /// Creates a new virtual machine of the specified size, type, and whether it can grow.
-    /// If `size` is set to 0, it will choose the default, 30000.
+    /// If `size` is None, it will choose the default, [DEFAULT_CELLS].
     /// # Examples
     /// ```
     /// # use bft_interp;
@@ -149,9 +599,38 @@ where
         size: Option<NonZeroUsize>,
         may_grow: bool,
         prog: &'a DecoratedProgram,
+    ) -> Machine<'a, T> {
+        Self::with_program(size, may_grow, Cow::Borrowed(prog))
+    }
+
+    /// Creates a virtual machine like [Machine::new], but which owns its program
+    ///
+    /// This is useful where the Machine must be kept somewhere the program can't be borrowed
+    /// from, such as in bindings for other languages.
+    /// # Examples
+    /// ```
+    /// # use bft_interp;
+    /// # use bft_types;
+    /// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+.")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<'static, u8> = bft_interp::Machine::owning(None, false, prog);
+    /// ```
+    pub fn owning(
+        size: Option<NonZeroUsize>,
+        may_grow: bool,
+        prog: DecoratedProgram,
+    ) -> Machine<'static, T> {
+        Machine::with_program(size, may_grow, Cow::Owned(prog))
+    }
+
+    fn with_program(
+        size: Option<NonZeroUsize>,
+        may_grow: bool,
+        prog: Cow<'a, DecoratedProgram>,
     ) -> Machine<'a, T> {
         let size = match size {
-            None => 30000,
+            None => DEFAULT_CELLS,
             Some(sz) => sz.into(),
         };
         let cells = vec![Default::default(); size];
@@ -160,7 +639,57 @@ where
             instruction_pointer: 0,
             cells,
             may_grow,
+            partners: matching_brackets(&prog),
+            next: 0,
             prog,
+            limits: Limits::default(),
+            policies: Policies::default(),
+            steps: 0,
+            output_bytes: 0,
+            flush: Flush::default(),
+            unflushed: false,
+            value_format: ValueFormat::default(),
+            cycles: None,
+            next_cycle_check: u64::MAX,
+            loop_counter: None,
+            size,
+        }
+    }
+
+    /// Puts the Machine back as it was created, ready to run the program again
+    ///
+    /// The limits and other settings are kept, and so is the memory for the tape, so a Machine
+    /// can run the same program many times without allocating.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp;
+    /// # use bft_types;
+    /// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",.>,.")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// assert_eq!(interp.run_to_string(b"hi").unwrap(), "hi");
+    /// interp.reset();
+    /// assert_eq!((interp.head(), interp.steps()), (0, 0));
+    /// assert!(interp.cells().iter().all(|cell| *cell == 0));
+    /// assert_eq!(interp.run_to_string(b"yo").unwrap(), "yo");
+    /// ```
+    pub fn reset(&mut self) {
+        self.cells.truncate(self.size);
+        self.cells.fill(Default::default());
+        self.head = 0;
+        self.instruction_pointer = 0;
+        self.next = 0;
+        self.steps = 0;
+        self.output_bytes = 0;
+        self.unflushed = false;
+        if let Some(cycles) = &mut self.cycles {
+            cycles.reset();
+            self.next_cycle_check = 0;
+        }
+        if let Some(counter) = &mut self.loop_counter {
+            counter.reset();
         }
     }
 
@@ -206,10 +735,14 @@ where
     pub fn seek_right(&mut self) -> Result<(), VMError> {
         if self.head + 1 == self.cells.len() {
             if !self.may_grow {
-                return Err(VMError::SeekTooHigh(
-                    self.current_instruction().instruction(),
-                ));
+                match self.policies.seek_too_high {
+                    Seek::Error => return Err(self.at_instruction(VMError::SeekTooHigh)),
+                    Seek::Clamp => {}
+                    Seek::Wrap => self.head = 0,
+                }
+                return Ok(());
             } else {
+                self.check_tape(self.cells.len() + 1)?;
                 self.cells.push(Default::default());
             }
         }
@@ -253,9 +786,557 @@ where
         self.cells[self.head].decrement()
     }
 
+    /// Moves the memory pointer by `offset` cells in either direction
+    ///
+    /// If the pointer would leave the cells, it stops at the edge and returns the same errors
+    /// as [Machine::seek_left] and [Machine::seek_right] would, unless the Machine's policies say
+    /// to clamp or wrap it instead. The move is taken to come from `offset` separate instructions
+    /// starting at the instruction pointer, so the error points at the one that went too far.
+    fn move_head(&mut self, offset: isize) -> Result<(), VMError> {
+        let distance = offset.unsigned_abs();
+        let length = self.cells.len();
+        if offset < 0 {
+            if distance > self.head {
+                match self.policies.seek_too_low {
+                    Seek::Error => {}
+                    Seek::Clamp => {
+                        self.head = 0;
+                        return Ok(());
+                    }
+                    Seek::Wrap => {
+                        self.head = (self.head + length - distance % length) % length;
+                        return Ok(());
+                    }
+                }
+                self.instruction_pointer += self.head;
+                self.head = 0;
+                return Err(self.at_instruction(VMError::SeekTooLow));
+            }
+            self.head -= distance;
+        } else {
+            let target = self.head + distance;
+            if target >= self.cells.len() {
+                let last = length - 1;
+                if !self.may_grow {
+                    match self.policies.seek_too_high {
+                        Seek::Error => {}
+                        Seek::Clamp => {
+                            self.head = last;
+                            return Ok(());
+                        }
+                        Seek::Wrap => {
+                            self.head = target % length;
+                            return Ok(());
+                        }
+                    }
+                    self.instruction_pointer += last - self.head;
+                    self.head = last;
+                    return Err(self.at_instruction(VMError::SeekTooHigh));
+                }
+                if let Some(max) = self.limits.max_tape.filter(|max| target + 1 > *max) {
+                    let last = max.max(self.cells.len()) - 1;
+                    self.cells.resize(last + 1, Default::default());
+                    self.instruction_pointer += last - self.head;
+                    self.head = last;
+                    return Err(self.limit_exceeded(Limit::Tape(max)));
+                }
+                self.cells.resize(target + 1, Default::default());
+            }
+            self.head = target;
+        }
+        Ok(())
+    }
+
+    /// Moves the head `stride` cells at a time until it's on a zero cell, for [Op::Scan]
+    ///
+    /// The instruction pointer is on the loop's opening bracket. Running off the tape fails, or
+    /// grows it, at the same instruction of the loop as running the loop itself would. If the
+    /// policies clamp or wrap the head instead, the scan carries on from there with
+    /// [Machine::scan_wrapped].
+    fn scan(&mut self, stride: isize) -> Result<(), VMError> {
+        if self.loop_counter.is_some() {
+            return self.scan_counted(stride);
+        }
+        match scan_for_zero(&self.cells, self.head, stride) {
+            Ok(zero) => self.head = zero,
+            Err(last) => {
+                let start = self.instruction_pointer;
+                self.head = last;
+                self.instruction_pointer += 1;
+                self.move_head(stride)?;
+                self.instruction_pointer = start;
+                self.scan_wrapped(stride)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Carries on a scan that was clamped or wrapped at the edge of the tape, once round the loop
+    /// at a time
+    ///
+    /// Each time round counts as the two steps the loop would take if it weren't a scan, so that a
+    /// scan that can never find a zero still runs into the step limit.
+    fn scan_wrapped(&mut self, stride: isize) -> Result<(), VMError> {
+        let start = self.instruction_pointer;
+        while !self.cells[self.head].is_zero() {
+            self.steps += 2;
+            if let Some(max) = self.limits.max_steps.filter(|max| self.steps > *max) {
+                return Err(self.limit_exceeded(Limit::Steps(max)));
+            }
+            self.check_cycle()?;
+            self.instruction_pointer = start + 1;
+            self.move_head(stride)?;
+            self.instruction_pointer = start;
+        }
+        Ok(())
+    }
+
+    /// Scans like [Machine::scan] a move at a time, so that each time round the loop counts
+    /// towards the loop limit
+    fn scan_counted(&mut self, stride: isize) -> Result<(), VMError> {
+        let start = self.instruction_pointer;
+        let end = start + stride.unsigned_abs() + 1;
+        if self.cells[self.head].is_zero() {
+            return Ok(());
+        }
+        self.enter_loop(start);
+        loop {
+            self.instruction_pointer = start + 1;
+            self.move_head(stride)?;
+            if self.cells[self.head].is_zero() {
+                break;
+            }
+            self.instruction_pointer = end;
+            self.repeat_loop(start)?;
+        }
+        self.instruction_pointer = start;
+        Ok(())
+    }
+
+    /// Returns whether the head can move anywhere from `low` to `high` cells away without leaving
+    /// the tape, for [Op::Guard]
+    ///
+    /// If the tape may grow to reach `high`, it does, just as moving there would have grown it.
+    fn reaches(&mut self, low: isize, high: isize) -> bool {
+        let (Some(_), Some(highest)) = (
+            self.head.checked_add_signed(low),
+            self.head.checked_add_signed(high),
+        ) else {
+            return false;
+        };
+        if highest < self.cells.len() {
+            return true;
+        }
+        if !self.may_grow || self.limits.max_tape.is_some_and(|max| highest >= max) {
+            return false;
+        }
+        self.cells.resize(highest + 1, Default::default());
+        true
+    }
+
+    /// Runs straight-line instructions one at a time, for an [Op::Guard] whose operations would
+    /// take the head off the tape
+    fn run_straight(&mut self, instructions: Range<usize>) -> Result<(), VMError> {
+        for index in instructions {
+            self.instruction_pointer = index;
+            let Some(instruction) = self.current_instruction() else {
+                return Err(VMError::BytecodeMismatch);
+            };
+            match instruction.instruction().instruction() {
+                RawInstruction::IncrementByte => self.increment_cell(),
+                RawInstruction::DecrementByte => self.decrement_cell(),
+                RawInstruction::IncrementDataPointer => self.move_head(1)?,
+                RawInstruction::DecrementDataPointer => self.move_head(-1)?,
+                _ => return Err(VMError::BytecodeMismatch),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns an error if growing the tape to `length` cells would break the tape limit
+    fn check_tape(&self, length: usize) -> Result<(), VMError> {
+        match self.limits.max_tape {
+            Some(max) if length > max => Err(self.limit_exceeded(Limit::Tape(max))),
+            _ => Ok(()),
+        }
+    }
+
+    fn limit_exceeded(&self, limit: Limit) -> VMError {
+        self.at_instruction(|instruction| VMError::LimitExceeded { limit, instruction })
+    }
+
+    /// Runs the program one instruction at a time until it finishes, carrying on from wherever
+    /// the last [Machine::step] stopped
+    ///
+    /// This is the reference interpreter: it's much slower than [Machine::run_bytecode], but
+    /// simple enough to check the optimised bytecode against.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp;
+    /// # use bft_types;
+    /// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",[.-]")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// let mut output = Vec::new();
+    /// interp.run(&mut std::io::Cursor::new(vec![3]), &mut output).unwrap();
+    /// assert_eq!(output, [3, 2, 1]);
+    /// assert_eq!(interp.steps(), 11);
+    /// ```
+    pub fn run(&mut self, input: &mut impl Read, output: &mut impl Write) -> Result<(), VMError> {
+        self.run_traced(input, output, &mut ())
+    }
+
+    /// Runs the program on `input`, returning everything it wrote
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp;
+    /// # use bft_types;
+    /// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",[.-]")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// assert_eq!(interp.run_captured(&[3]).unwrap(), [3, 2, 1]);
+    /// ```
+    pub fn run_captured(&mut self, input: &[u8]) -> Result<Vec<u8>, VMError> {
+        let mut output = Vec::new();
+        self.run(&mut &input[..], &mut output)?;
+        Ok(output)
+    }
+
+    /// Runs the program on `input` like [Machine::run_captured], returning its output as text
+    ///
+    /// Bytes that aren't valid UTF-8 are replaced with U+FFFD.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp;
+    /// # use bft_types;
+    /// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",.,.")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// assert_eq!(interp.run_to_string(b"h\xff").unwrap(), "h\u{fffd}");
+    /// ```
+    pub fn run_to_string(&mut self, input: &[u8]) -> Result<String, VMError> {
+        let output = self.run_captured(input)?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    /// Runs the program like [Machine::run], telling `tracer` about every instruction
+    ///
+    /// Each instruction is described as the operation
+    /// [Bytecode::compile_unoptimised] would have compiled it to.
+    pub fn run_traced(
+        &mut self,
+        input: &mut impl Read,
+        output: &mut impl Write,
+        tracer: &mut impl Tracer<T>,
+    ) -> Result<(), VMError> {
+        let _span = tracing::debug_span!("run", engine = "interp").entered();
+        self.run_threaded(input, output, tracer)?;
+        self.flush_output(output)?;
+        tracing::debug!(steps = self.steps, "Program finished");
+        Ok(())
+    }
+
+    /// Executes one instruction, carrying on from wherever the last step stopped
+    ///
+    /// Returns false, without executing anything, once the program has finished. After an error,
+    /// the next step tries the failing instruction again.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp;
+    /// # use bft_types;
+    /// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",+.")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// let mut input = std::io::Cursor::new(vec![4]);
+    /// let mut output = Vec::new();
+    /// assert!(interp.step(&mut input, &mut output).unwrap());
+    /// assert_eq!(interp.cells()[0], 4);
+    /// while interp.step(&mut input, &mut output).unwrap() {}
+    /// assert_eq!(output, [5]);
+    /// ```
+    pub fn step(
+        &mut self,
+        input: &mut impl Read,
+        output: &mut impl Write,
+    ) -> Result<bool, VMError> {
+        self.step_traced(input, output, &mut ())
+    }
+
+    /// Executes one instruction like [Machine::step], telling `tracer` about it
+    pub fn step_traced(
+        &mut self,
+        input: &mut impl Read,
+        output: &mut impl Write,
+        tracer: &mut impl Tracer<T>,
+    ) -> Result<bool, VMError> {
+        let stepped = self.step_threaded(input, output, tracer)?;
+        if !stepped {
+            self.flush_output(output)?;
+        }
+        Ok(stepped)
+    }
+
+    /// Executes one instruction like [Machine::step], except that a `[` runs its whole loop
+    ///
+    /// Returns false, without executing anything, once the program has finished.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp;
+    /// # use bft_types;
+    /// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+++[>++<-]>.")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// let (mut input, mut output) = (std::io::empty(), Vec::new());
+    /// for _ in 0..4 {
+    ///     interp.step_over(&mut input, &mut output).unwrap();
+    /// }
+    /// assert_eq!(interp.cells()[..2], [0, 6]);
+    /// assert_eq!(interp.next_instruction().unwrap().instruction().character(), 11);
+    /// ```
+    pub fn step_over(
+        &mut self,
+        input: &mut impl Read,
+        output: &mut impl Write,
+    ) -> Result<bool, VMError> {
+        match self.next_instruction() {
+            Some(DecoratedInstruction::OpenLoop { .. }) => {
+                let closer = self.partners[self.next];
+                self.step_until_outside(self.next..=closer, input, output)
+            }
+            _ => self.step(input, output),
+        }
+    }
+
+    /// Executes instructions until the innermost loop the next instruction is in has finished,
+    /// or until the program finishes if it isn't in one
+    ///
+    /// Returns false, without executing anything, once the program has finished.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp;
+    /// # use bft_types;
+    /// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "++[>+++<-]>.")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// let (mut input, mut output) = (std::io::empty(), Vec::new());
+    /// for _ in 0..4 {
+    ///     interp.step(&mut input, &mut output).unwrap();
+    /// }
+    /// interp.step_out(&mut input, &mut output).unwrap();
+    /// assert_eq!(interp.cells()[..2], [0, 6]);
+    /// assert!(interp.step_out(&mut input, &mut output).unwrap());
+    /// assert_eq!(output, [6]);
+    /// assert!(!interp.step_out(&mut input, &mut output).unwrap());
+    /// ```
+    pub fn step_out(
+        &mut self,
+        input: &mut impl Read,
+        output: &mut impl Write,
+    ) -> Result<bool, VMError> {
+        let instructions = self.prog().decorated_instructions();
+        // Look back for the nearest `[` whose loop hasn't closed yet, skipping whole loops
+        let mut index = self.next.min(instructions.len());
+        while index > 0 {
+            index -= 1;
+            match instructions[index] {
+                DecoratedInstruction::CloseLoop { .. } => index = self.partners[index],
+                DecoratedInstruction::OpenLoop { .. } => {
+                    let closer = self.partners[index];
+                    return self.step_until_outside(index..=closer, input, output);
+                }
+                DecoratedInstruction::Instruction(_) => {}
+            }
+        }
+        self.step_until_outside(0..=usize::MAX, input, output)
+    }
+
+    /// Runs just the instructions at the indices in `range`, from the Machine's current state
+    ///
+    /// This evaluates a piece of the program, such as one loop, against the tape as it stands.
+    /// The range must hold whole loops, so that it finishes at its end. Where [Machine::step]
+    /// carries on from is left as it was.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::{Machine, VMError};
+    /// # use bft_types;
+    /// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+++[>++<-]>.")
+    /// ).unwrap();
+    /// let mut interp: Machine<u8> = Machine::new(None, false, &prog);
+    /// let (mut input, mut output) = (std::io::empty(), Vec::new());
+    /// interp.run_range(0..3, &mut input, &mut output).unwrap();
+    /// assert_eq!(interp.cells()[0], 3);
+    /// interp.run_range(3..10, &mut input, &mut output).unwrap();
+    /// assert_eq!(interp.cells()[..2], [0, 6]);
+    /// assert!(matches!(
+    ///     interp.run_range(4..10, &mut input, &mut output),
+    ///     Err(VMError::SplitLoop { .. })
+    /// ));
+    /// // Stepping still starts from the beginning
+    /// interp.step(&mut input, &mut output).unwrap();
+    /// assert_eq!(interp.cells()[..2], [1, 6]);
+    /// ```
+    pub fn run_range(
+        &mut self,
+        range: Range<usize>,
+        input: &mut impl Read,
+        output: &mut impl Write,
+    ) -> Result<(), VMError> {
+        let instructions = self.prog.decorated_instructions();
+        let whole = range.start <= range.end
+            && range.end <= instructions.len()
+            && range.clone().all(|index| match instructions[index] {
+                DecoratedInstruction::Instruction(_) => true,
+                _ => range.contains(&self.partners[index]),
+            });
+        if !whole {
+            return Err(VMError::SplitLoop {
+                start: range.start,
+                end: range.end,
+            });
+        }
+        if range.is_empty() {
+            return Ok(());
+        }
+        let resume = self.next;
+        self.next = range.start;
+        let result = self
+            .step_until_outside(range.start..=range.end - 1, input, output)
+            .and_then(|_| self.flush_output(output));
+        self.next = resume;
+        result
+    }
+
+    /// Steps until the next instruction is outside `instructions`, returning whether any
+    /// instruction was executed
+    fn step_until_outside(
+        &mut self,
+        instructions: RangeInclusive<usize>,
+        input: &mut impl Read,
+        output: &mut impl Write,
+    ) -> Result<bool, VMError> {
+        let mut stepped = false;
+        while self.step(input, output)? {
+            stepped = true;
+            if !instructions.contains(&self.next) {
+                break;
+            }
+        }
+        Ok(stepped)
+    }
+
+    /// Runs bytecode compiled from this Machine's program until it finishes
+    ///
+    /// `,` reads from `input` and `.` writes to `output`. Errors report the position of the
+    /// instruction in the original program that the failing operation was compiled from.
+    /// Jumps aren't checked as they're taken: [Bytecode] is [verified](Bytecode::verify) when
+    /// it's loaded.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp;
+    /// # use bft_types;
+    /// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",[.-]")
+    /// ).unwrap();
+    /// let bytecode = bft_interp::bytecode::Bytecode::compile(&prog);
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// let mut output = Vec::new();
+    /// interp.run_bytecode(&bytecode, &mut std::io::Cursor::new(vec![3]), &mut output).unwrap();
+    /// assert_eq!(output, [3, 2, 1]);
+    /// ```
+    pub fn run_bytecode(
+        &mut self,
+        bytecode: &Bytecode,
+        input: &mut impl Read,
+        output: &mut impl Write,
+    ) -> Result<(), VMError> {
+        self.run_bytecode_traced(bytecode, input, output, &mut ())
+    }
+
+    /// Runs bytecode like [Machine::run_bytecode], telling `tracer` about every operation
+    pub fn run_bytecode_traced(
+        &mut self,
+        bytecode: &Bytecode,
+        input: &mut impl Read,
+        output: &mut impl Write,
+        tracer: &mut impl Tracer<T>,
+    ) -> Result<(), VMError> {
+        let _span = tracing::debug_span!("run", engine = "opt").entered();
+        if bytecode.fingerprint() != self.prog.fingerprint() || !bytecode.fits(&self.prog) {
+            return Err(VMError::BytecodeMismatch);
+        }
+        let ops = bytecode.ops();
+        let mut pc = 0;
+        while let Some(op) = ops.get(pc) {
+            self.instruction_pointer = bytecode.origins()[pc];
+            self.steps += 1;
+            if let Some(max) = self.limits.max_steps.filter(|max| self.steps > *max) {
+                return Err(self.limit_exceeded(Limit::Steps(max)));
+            }
+            self.check_cycle()?;
+            let Some(instruction) = self.current_instruction() else {
+                return Err(VMError::BytecodeMismatch);
+            };
+            tracer.step(&Step {
+                step: self.steps,
+                pc,
+                op: *op,
+                instruction: instruction.instruction(),
+                head: self.head,
+                cells: &self.cells,
+            });
+            match *op {
+                Op::Add(amount) => self.cells[self.head].add(amount),
+                Op::Move(offset) => self.move_head(offset)?,
+                Op::Clear => self.cells[self.head] = Default::default(),
+                Op::Scan(stride) => self.scan(stride)?,
+                Op::JumpIfZero(target) => {
+                    if self.cells[self.head].is_zero() {
+                        pc = target;
+                    } else {
+                        self.enter_loop(self.instruction_pointer);
+                    }
+                }
+                Op::JumpIfNonZero(target) => {
+                    if !self.cells[self.head].is_zero() {
+                        self.repeat_loop(bytecode.origins()[target])?;
+                        pc = target;
+                    }
+                }
+                Op::Input => self.input_traced(input, output, tracer)?,
+                Op::Output => self.output_traced(output, tracer)?,
+                Op::Guard { low, high, ops } => {
+                    if !self.reaches(low, high) {
+                        self.run_straight(bytecode.provenance(pc))?;
+                        pc += ops;
+                    }
+                }
+            }
+            pc += 1;
+        }
+        self.flush_output(output)?;
+        tracing::debug!(steps = self.steps, "Program finished");
+        Ok(())
+    }
+
     /// Read a value from `file` into memory at the memory pointer
     ///
-    /// If an I/O Error occurs while trying to read the file, it returns that error wrapped inside a [VMError].
+    /// If an I/O Error occurs while trying to read the file, it returns that error wrapped inside a [VMError],
+    /// unless the file has just run out and the Machine's [Eof] policy says what to do instead.
     ///
     /// # Examples
     /// ```
@@ -277,14 +1358,32 @@ where
                 self.cells[self.head].set_value(buffer[0]);
                 Ok(())
             }
-            Err(ioerror) => Err(VMError::IOError {
-                instruction: self.current_instruction().instruction(),
+            Err(ioerror) if ioerror.kind() == io::ErrorKind::UnexpectedEof => {
+                let cell = &mut self.cells[self.head];
+                match self.policies.eof {
+                    Eof::Error => {
+                        return Err(self.at_instruction(|instruction| VMError::IOError {
+                            instruction,
+                            source: ioerror,
+                        }))
+                    }
+                    Eof::Zero => *cell = T::default(),
+                    Eof::Max => {
+                        *cell = T::default();
+                        cell.decrement();
+                    }
+                    Eof::Unchanged => {}
+                }
+                Ok(())
+            }
+            Err(ioerror) => Err(self.at_instruction(|instruction| VMError::IOError {
+                instruction,
                 source: ioerror,
-            }),
+            })),
         }
     }
 
-    /// Writes the value at the memory pointer into `file`
+    /// Writes the value at the memory pointer into `file`, flushing it as [Machine::flush] says
     ///
     /// If an I/O Error occurs while trying to write the file, it returns that error wrapped inside a [VMError].
     ///
@@ -306,25 +1405,281 @@ where
     /// assert_eq!(data.get_ref()[1], 7);
     /// ```
     pub fn write_value(&mut self, file: &mut impl Write) -> Result<(), VMError> {
+        self.output_bytes += 1;
+        if let Some(max) = self
+            .limits
+            .max_output_bytes
+            .filter(|max| self.output_bytes > *max)
+        {
+            return Err(self.limit_exceeded(Limit::OutputBytes(max)));
+        }
         let mut buffer: [u8; 1] = [0; 1];
         buffer[0] = self.cells[self.head].get_value();
-        file.write_all(&buffer).map_err(|e| VMError::IOError {
-            instruction: self.current_instruction().instruction(),
-            source: e,
+        self.unflushed = match self.flush {
+            Flush::EachByte => false,
+            Flush::Line => buffer[0] != b'\n',
+            Flush::Buffered => true,
+        };
+        file.write_all(&buffer)
+            .and_then(|()| if self.unflushed { Ok(()) } else { file.flush() })
+            .map_err(|e| {
+                self.at_instruction(|instruction| VMError::IOError {
+                    instruction,
+                    source: e,
+                })
+            })
+    }
+
+    /// Reads a byte for `,`, showing any pending output first and telling `tracer` what was read
+    fn input_traced(
+        &mut self,
+        input: &mut impl Read,
+        output: &mut impl Write,
+        tracer: &mut impl Tracer<T>,
+    ) -> Result<(), VMError> {
+        self.flush_output(output)?;
+        self.read_value(input)?;
+        if let Some(cycles) = &mut self.cycles {
+            cycles.forget();
+        }
+        if let Some(io) = self.io() {
+            tracer.input(&io);
+        }
+        Ok(())
+    }
+
+    /// Writes a byte for `.`, telling `tracer` what was written
+    fn output_traced(
+        &mut self,
+        output: &mut impl Write,
+        tracer: &mut impl Tracer<T>,
+    ) -> Result<(), VMError> {
+        self.write_value(output)?;
+        if let Some(io) = self.io() {
+            tracer.output(&io);
+        }
+        Ok(())
+    }
+
+    /// Describes the byte at the memory pointer as just having been read or written, if there's
+    /// an instruction that did it
+    fn io(&self) -> Option<Io> {
+        Some(Io {
+            step: self.steps,
+            instruction: self.current_instruction()?.instruction(),
+            byte: self.cells[self.head].get_value(),
+        })
+    }
+
+    /// Flushes `file` if anything has been written to it without being flushed
+    fn flush_output(&mut self, file: &mut impl Write) -> Result<(), VMError> {
+        if !self.unflushed {
+            return Ok(());
+        }
+        self.unflushed = false;
+        file.flush().map_err(|e| {
+            self.at_instruction(|instruction| VMError::IOError {
+                instruction,
+                source: e,
+            })
         })
     }
 }
 
 /// Runtime errors in the interpreter
+///
+/// The wording of each is in [messages], which writes it in English for Display and in any other
+/// [Lang] through [Localize].
 #[derive(Error, Debug)]
 pub enum VMError {
-    #[error("Instruction {0} tried to seek to a negative head position")]
+    /// The head was moved below the first cell
     SeekTooLow(PositionedInstruction),
-    #[error("Instruction {0} tried to seek beyond the end of the cells and the cells aren't permitted to grow")]
+    /// The head was moved past the last cell and the tape can't grow
     SeekTooHigh(PositionedInstruction),
-    #[error("An I/O Error occurred while processing instruction {instruction}")]
+    /// Reading input or writing output failed
     IOError {
         instruction: PositionedInstruction,
-        source: std::io::Error,
+        source: io::Error,
     },
+    /// The program went over one of its [Limits]
+    LimitExceeded {
+        limit: Limit,
+        instruction: PositionedInstruction,
+    },
+    /// The bytecode was compiled from a different program
+    BytecodeMismatch,
+    /// The program came back to a state it had been in without reading any input
+    InfiniteLoop {
+        instruction: PositionedInstruction,
+        /// How many steps apart the repeated states were
+        period: u64,
+    },
+    /// A loop went round more times than [Limits::max_loop_iterations] allows
+    LoopLimitExceeded(Box<RunawayLoop>),
+    /// A range of instructions starts or ends part way through a loop
+    SplitLoop { start: usize, end: usize },
+    /// Something that needs an instruction to report went wrong, such as the head being moved off
+    /// the tape with [Machine::seek_left], but the program has none
+    EmptyProgram,
+}
+
+impl fmt::Display for VMError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_in(Lang::English, f)
+    }
+}
+
+impl VMError {
+    /// Returns the instruction that caused the error, if there was one
+    pub fn instruction(&self) -> Option<PositionedInstruction> {
+        match self {
+            Self::SeekTooLow(instruction) | Self::SeekTooHigh(instruction) => Some(*instruction),
+            Self::IOError { instruction, .. }
+            | Self::LimitExceeded { instruction, .. }
+            | Self::InfiniteLoop { instruction, .. } => Some(*instruction),
+            Self::LoopLimitExceeded(runaway) => Some(runaway.start),
+            Self::BytecodeMismatch | Self::SplitLoop { .. } | Self::EmptyProgram => None,
+        }
+    }
+
+    /// Returns where the instruction that caused the error is, given the file the program came
+    /// from, if there was one
+    ///
+    /// A loop that went round too many times spans the whole loop.
+    pub fn span(&self, file: &FileName) -> Option<SourceSpan> {
+        if let Self::LoopLimitExceeded(runaway) = self {
+            return Some(runaway.span(file));
+        }
+        Some(self.instruction()?.span(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::generate::seeded_bytes;
+    use bft_types::Program;
+
+    #[test]
+    fn nothing_panics_on_random_programs() {
+        const ALPHABET: &[u8] = b"+-<>,.[]x\n";
+        let limits = Limits {
+            max_steps: Some(2000),
+            max_output_bytes: Some(64),
+            max_tape: Some(64),
+            max_loop_iterations: Some(100),
+        };
+        let mut seed = 1;
+        for round in 0..500 {
+            // The first program is empty, so has no instruction for errors to be reported at
+            let length = match round {
+                0 => 0,
+                _ => seeded_bytes(&mut seed, 1)[0] as usize % 48,
+            };
+            let code: String = seeded_bytes(&mut seed, length)
+                .into_iter()
+                .map(|byte| ALPHABET[byte as usize % ALPHABET.len()] as char)
+                .collect();
+            let input = seeded_bytes(&mut seed, round % 8);
+            let prog = match DecoratedProgram::from_program(&Program::new("<fuzz>", &code)) {
+                Ok(prog) => prog,
+                Err(error) => {
+                    let _ = error.to_string();
+                    continue;
+                }
+            };
+            let _ = prog.to_string();
+            let partners = matching_brackets(&prog);
+            for (index, instruction) in prog.decorated_instructions().iter().enumerate() {
+                if let DecoratedInstruction::CloseLoop { .. } = instruction {
+                    assert_eq!(partners[partners[index]], index);
+                }
+            }
+            analysis::Analysis::from_program(&prog);
+            golf::Golfed::from_program(&prog);
+            let bounds = symbolic::Bounds {
+                max_steps: 500,
+                max_branches: 4,
+                max_paths: 16,
+            };
+            symbolic::Exploration::from_program(&prog, bounds);
+
+            for bytecode in [
+                Bytecode::compile(&prog),
+                Bytecode::compile_unoptimised(&prog),
+            ] {
+                let loaded = Bytecode::from_bytes(&bytecode.to_bytes()).unwrap();
+                for may_grow in [false, true] {
+                    let mut machine: Machine<u8> =
+                        Machine::new(NonZeroUsize::new(4), may_grow, &prog);
+                    machine.set_limits(limits);
+                    let _ = machine.run_bytecode(&loaded, &mut &input[..], &mut Vec::new());
+                }
+            }
+            for may_grow in [false, true] {
+                let mut machine: Machine<u16> = Machine::new(NonZeroUsize::new(4), may_grow, &prog);
+                machine.set_limits(limits);
+                if let Err(error) = machine.run(&mut &input[..], &mut Vec::new()) {
+                    let _ = (error.to_string(), error.instruction());
+                }
+                // Errors from outside a run are reported at whatever instruction it stopped on
+                let _ = machine.current_instruction();
+                for _ in 0..8 {
+                    let _ = (
+                        machine.seek_left(),
+                        machine.seek_right(),
+                        machine.seek_right(),
+                    );
+                }
+                let _ = machine.read_value(&mut &[][..]);
+            }
+        }
+    }
+
+    #[test]
+    fn policies_agree_between_engines() {
+        let programs = [
+            "+>>+>>+[>>]+",
+            "+>+<<[<]+",
+            "<<<<<<+>>>>>>>>+",
+            ",>,>,>-,",
+            "<+>+<+>+",
+            ">>>>+>+<+>+<+",
+        ];
+        let seeks = [Seek::Error, Seek::Clamp, Seek::Wrap];
+        let eofs = [Eof::Error, Eof::Zero, Eof::Max, Eof::Unchanged];
+        for code in programs {
+            let prog = DecoratedProgram::from_program(&Program::new("<test>", code)).unwrap();
+            let bytecode = Bytecode::compile(&prog);
+            for (seek, eof) in seeks
+                .into_iter()
+                .flat_map(|seek| eofs.map(|eof| (seek, eof)))
+            {
+                let policies = Policies {
+                    seek_too_low: seek,
+                    seek_too_high: seek,
+                    eof,
+                };
+                let mut machines: [Machine<u16>; 2] =
+                    core::array::from_fn(|_| Machine::new(NonZeroUsize::new(5), false, &prog));
+                for machine in &mut machines {
+                    machine.set_policies(policies);
+                    machine.set_limits(Limits {
+                        max_steps: Some(1000),
+                        ..Limits::default()
+                    });
+                }
+                let [interp, opt] = &mut machines;
+                let expected = interp.run(&mut &b"a"[..], &mut Vec::new());
+                let result = opt.run_bytecode(&bytecode, &mut &b"a"[..], &mut Vec::new());
+                assert_eq!(
+                    result.map_err(|e| core::mem::discriminant(&e)),
+                    expected.map_err(|e| core::mem::discriminant(&e)),
+                    "{code} {policies:?}"
+                );
+                assert_eq!(opt.cells(), interp.cells(), "{code} {policies:?}");
+                assert_eq!(opt.head(), interp.head(), "{code} {policies:?}");
+            }
+        }
+    }
 }
@@ -0,0 +1,258 @@
+//! Limits on the resources a running program may use
+//!
+//! Brainfuck programs can easily loop forever, print endlessly or walk off across memory. Limits
+//! stop a [Machine] with an error instead, so untrusted programs can be run safely.
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+use bft_types::span::SourceSpan;
+use bft_types::{FileName, PositionedInstruction};
+
+use crate::messages::{Lang, Localize};
+use crate::{CellKind, Machine, VMError};
+
+/// The resources a Machine may use before it stops with [VMError::LimitExceeded]
+///
+/// Every limit is off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// The most operations executed, counting every run of the Machine
+    ///
+    /// Bytecode fuses runs of instructions, so one step may cover several instructions.
+    pub max_steps: Option<u64>,
+    /// The most bytes written as output
+    pub max_output_bytes: Option<u64>,
+    /// The most cells the tape may grow to, if it's allowed to grow
+    pub max_tape: Option<usize>,
+    /// The most times any one loop may go round each time the program enters it
+    ///
+    /// Going over stops the Machine with [VMError::LoopLimitExceeded], which says which loop it
+    /// was, rather than with [VMError::LimitExceeded].
+    pub max_loop_iterations: Option<u64>,
+}
+
+/// One of the [Limits]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Steps(u64),
+    OutputBytes(u64),
+    Tape(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_in(Lang::English, f)
+    }
+}
+
+/// How many of a runaway loop's latest condition checks are kept to report
+const RECENT_CHECKS: usize = 8;
+
+/// A loop that went round more times than [Limits::max_loop_iterations] allows
+#[derive(Debug, Clone)]
+pub struct RunawayLoop {
+    /// The bracket that opens the loop
+    pub start: PositionedInstruction,
+    /// The bracket that closes the loop
+    pub end: PositionedInstruction,
+    /// The most iterations allowed
+    pub limit: u64,
+    /// The checks that kept the loop going: the one entering it, then the latest ones
+    pub checks: Vec<ConditionCheck>,
+}
+
+impl RunawayLoop {
+    /// The bytes of the source the loop covers, from its opening bracket to its closing one
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::{limits::Limits, Machine, VMError};
+    /// # use bft_types;
+    /// let text = "+[>+<]";
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", text)
+    /// ).unwrap();
+    /// let mut interp: Machine<u8> = Machine::new(None, false, &prog);
+    /// interp.set_limits(Limits { max_loop_iterations: Some(100), ..Limits::default() });
+    /// let Err(VMError::LoopLimitExceeded(runaway)) = interp.run_to_string(b"") else {
+    ///     panic!("the loop should have gone round too many times");
+    /// };
+    /// assert_eq!(&text[runaway.range()], "[>+<]");
+    /// assert_eq!(runaway.checks.last().unwrap().iteration, 101);
+    /// ```
+    pub fn range(&self) -> Range<usize> {
+        self.start.offset()..self.end.offset() + 1
+    }
+
+    /// Where the loop is, given the file it came from
+    pub fn span(&self, file: &FileName) -> SourceSpan {
+        SourceSpan {
+            len: self.range().len(),
+            ..self.start.span(file)
+        }
+    }
+}
+
+impl fmt::Display for RunawayLoop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_in(Lang::English, f)
+    }
+}
+
+/// The value of the cell a loop's condition was checked against, found non-zero
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConditionCheck {
+    /// The iteration the check started, counting the one entering the loop as 1
+    pub iteration: u64,
+    /// The cell the head was on
+    pub cell: usize,
+    pub value: u64,
+}
+
+impl fmt::Display for ConditionCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_in(Lang::English, f)
+    }
+}
+
+/// Counts how many times each loop has gone round since it was entered
+#[derive(Clone)]
+pub(crate) struct LoopCounter {
+    max: u64,
+    /// For each loop's opening bracket, the check that entered the loop and how many iterations
+    /// it's started since
+    loops: Vec<(ConditionCheck, u64)>,
+    /// The latest checks of each loop close to the limit
+    recent: BTreeMap<usize, VecDeque<ConditionCheck>>,
+}
+
+impl LoopCounter {
+    pub(crate) fn new(max: u64, instructions: usize) -> LoopCounter {
+        let unentered = ConditionCheck {
+            iteration: 0,
+            cell: 0,
+            value: 0,
+        };
+        LoopCounter {
+            max,
+            loops: vec![(unentered, 0); instructions],
+            recent: BTreeMap::new(),
+        }
+    }
+
+    /// Forgets every loop, as if the Machine had just been created
+    pub(crate) fn reset(&mut self) {
+        self.recent.clear();
+    }
+}
+
+impl<T: CellKind> Machine<'_, T> {
+    /// Starts counting the iterations of the loop opened at instruction `start`, which the
+    /// program has just entered
+    #[inline]
+    pub(crate) fn enter_loop(&mut self, start: usize) {
+        let Some(counter) = &mut self.loop_counter else {
+            return;
+        };
+        let check = ConditionCheck {
+            iteration: 1,
+            cell: self.head,
+            value: self.cells[self.head].get_full_value(),
+        };
+        counter.loops[start] = (check, 1);
+        if !counter.recent.is_empty() {
+            counter.recent.remove(&start);
+        }
+    }
+
+    /// Counts another iteration of the loop opened at instruction `start`, stopping if that's
+    /// more than the limit
+    #[inline]
+    pub(crate) fn repeat_loop(&mut self, start: usize) -> Result<(), VMError> {
+        let Some(counter) = &mut self.loop_counter else {
+            return Ok(());
+        };
+        let (entry, iterations) = &mut counter.loops[start];
+        *iterations += 1;
+        if *iterations + RECENT_CHECKS as u64 <= counter.max + 1 {
+            return Ok(());
+        }
+        let check = ConditionCheck {
+            iteration: *iterations,
+            cell: self.head,
+            value: self.cells[self.head].get_full_value(),
+        };
+        let entry = *entry;
+        let recent = counter.recent.entry(start).or_default();
+        if recent.len() == RECENT_CHECKS {
+            recent.pop_front();
+        }
+        recent.push_back(check);
+        if check.iteration <= counter.max {
+            return Ok(());
+        }
+        let limit = counter.max;
+        let mut checks = vec![entry];
+        checks.extend(counter.recent.remove(&start).unwrap_or_default());
+        let start = self.prog.decorated_instructions()[start].instruction();
+        Err(self.at_instruction(|end| {
+            VMError::LoopLimitExceeded(Box::new(RunawayLoop {
+                start,
+                end,
+                limit,
+                checks,
+            }))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::Bytecode;
+    use bft_types::{DecoratedProgram, Program};
+
+    #[test]
+    fn runaway_loops_are_reported_by_every_engine() {
+        // The inner loop goes round three times each time; the outer one never stops
+        let text = "+[>+++[->+<]<]";
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", text)).unwrap();
+        let bytecode = Bytecode::compile(&prog);
+        for engine in 0..3 {
+            let mut machine: Machine<u16> = Machine::new(None, false, &prog);
+            machine.set_limits(Limits {
+                max_loop_iterations: Some(5),
+                ..Limits::default()
+            });
+            let (mut input, mut output) = (std::io::empty(), std::io::sink());
+            let result = match engine {
+                0 => machine.run(&mut input, &mut output),
+                1 => machine.run_bytecode(&bytecode, &mut input, &mut output),
+                _ => loop {
+                    match machine.step(&mut input, &mut output) {
+                        Ok(true) => {}
+                        Ok(false) => break Ok(()),
+                        Err(e) => break Err(e),
+                    }
+                },
+            };
+            let Err(VMError::LoopLimitExceeded(runaway)) = result else {
+                panic!("{result:?}");
+            };
+            assert_eq!(&text[runaway.range()], &text[1..]);
+            let iterations: Vec<_> = runaway.checks.iter().map(|c| c.iteration).collect();
+            assert_eq!(iterations, [1, 2, 3, 4, 5, 6]);
+            assert!(runaway.checks.iter().all(|c| (c.cell, c.value) == (0, 1)));
+            assert_eq!(
+                runaway.to_string(),
+                "The loop from 1:2 to 1:14 went round more than 5 times: the cell it checks was \
+                 1 in cell 0 on entry, then 1, 1, 1, 1, 1 in cell 0 before iterations 2 to 6"
+            );
+        }
+    }
+}
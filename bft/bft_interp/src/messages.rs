@@ -0,0 +1,797 @@
+//! Diagnostics in more than one language
+//!
+//! Every message has a [MessageId], and each [Lang] has one table holding a template for every id.
+//! Templates name the details they show, such as `{instruction}`, and [fill] puts the details in.
+//! Errors, lint findings and the pieces they're built from implement [Localize], so they can be
+//! written in any language with the same details as their English Display. [Lang::detect] picks
+//! the language from the locale, the way other command line tools do.
+
+use core::fmt;
+
+use bft_types::{display_file, ParseError, ParseErrorKind, PositionedInstruction, RawInstruction};
+
+use crate::analysis::Finding;
+use crate::error::BftError;
+use crate::limits::{ConditionCheck, Limit, RunawayLoop};
+use crate::VMError;
+
+/// A language diagnostics can be written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Lang {
+    #[default]
+    English,
+    French,
+}
+
+/// The words bft puts in front of its diagnostics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Label {
+    Error,
+    Warning,
+}
+
+/// A message, the same in every language, which each language's table has a template for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageId {
+    ErrorLabel,
+    WarningLabel,
+    /// `{line}`, `{column}` and the `{meaning}` of an instruction
+    Instruction,
+    IncrementDataPointer,
+    DecrementDataPointer,
+    IncrementByte,
+    DecrementByte,
+    PutByte,
+    GetByte,
+    OpenLoop,
+    CloseLoop,
+    /// `{error}` from the operating system
+    Io,
+    /// The `{problem}` in `{file}` at `{line}` and `{column}`
+    Parse,
+    UnopenedBracket,
+    UnclosedBracket,
+    /// An `{error}` in `{file}`
+    InFile,
+    SeekTooLow,
+    SeekTooHigh,
+    VmIo,
+    /// The `{instruction}` that went over the `{limit}`
+    LimitExceeded,
+    StepLimit,
+    OutputLimit,
+    TapeLimit,
+    BytecodeMismatch,
+    InfiniteLoop,
+    RunawayLoop,
+    /// What the runaway loop's cell was on entry, written as a `{check}`
+    RunawayEntry,
+    /// Comes before the runaway loop's latest checks
+    RunawayThen,
+    RunawayCheck,
+    /// Comes after the latest checks when they were all of the same cell
+    RunawaySameCell,
+    RunawayIterations,
+    SplitLoop,
+    EmptyProgram,
+    DeadLoop,
+    NeverFinishes,
+    PossibleWrap,
+    AlwaysSeeksTooLow,
+    /// An embedder asked to run before giving a program
+    NoProgram,
+}
+
+/// How many messages there are, which is how long each table is
+const MESSAGES: usize = MessageId::NoProgram as usize + 1;
+
+/// A template for every message in one language, in the order of [MessageId]
+type Table = [(MessageId, &'static str); MESSAGES];
+
+const ENGLISH: Table = [
+    (MessageId::ErrorLabel, "Error"),
+    (MessageId::WarningLabel, "warning"),
+    (MessageId::Instruction, "{line}:{column} {meaning}"),
+    (
+        MessageId::IncrementDataPointer,
+        "Increment current location",
+    ),
+    (
+        MessageId::DecrementDataPointer,
+        "Decrement current location",
+    ),
+    (
+        MessageId::IncrementByte,
+        "Increment the byte at the current location",
+    ),
+    (
+        MessageId::DecrementByte,
+        "Decrement the byte at the current location",
+    ),
+    (
+        MessageId::PutByte,
+        "Output the byte at the current location",
+    ),
+    (
+        MessageId::GetByte,
+        "Store a byte of input at the current location",
+    ),
+    (MessageId::OpenLoop, "Start looping"),
+    (MessageId::CloseLoop, "Stop looping"),
+    (MessageId::Io, "{error}"),
+    (
+        MessageId::Parse,
+        "In input file {file}, {problem} at line {line}, column {column}",
+    ),
+    (
+        MessageId::UnopenedBracket,
+        "closed a loop with no matching opener",
+    ),
+    (
+        MessageId::UnclosedBracket,
+        "opened a loop that wasn't closed",
+    ),
+    (MessageId::InFile, "{file}: {error}"),
+    (
+        MessageId::SeekTooLow,
+        "Instruction {instruction} tried to seek to a negative head position",
+    ),
+    (
+        MessageId::SeekTooHigh,
+        "Instruction {instruction} tried to seek beyond the end of the cells and the cells \
+        aren't permitted to grow",
+    ),
+    (
+        MessageId::VmIo,
+        "An I/O Error occurred while processing instruction {instruction}",
+    ),
+    (
+        MessageId::LimitExceeded,
+        "Instruction {instruction} went over the {limit}",
+    ),
+    (MessageId::StepLimit, "limit of {max} steps"),
+    (MessageId::OutputLimit, "limit of {max} bytes of output"),
+    (MessageId::TapeLimit, "limit of {max} cells"),
+    (
+        MessageId::BytecodeMismatch,
+        "The bytecode was compiled from a different program",
+    ),
+    (
+        MessageId::InfiniteLoop,
+        "Instruction {instruction} is in a loop that will never end: the program came back to \
+        the same state after {period} steps without reading any input",
+    ),
+    (
+        MessageId::RunawayLoop,
+        "The loop from {start} to {end} went round more than {limit} times",
+    ),
+    (
+        MessageId::RunawayEntry,
+        ": the cell it checks was {check} on entry",
+    ),
+    (MessageId::RunawayThen, ", then "),
+    (MessageId::RunawayCheck, "{value} in cell {cell}"),
+    (MessageId::RunawaySameCell, " in cell {cell}"),
+    (
+        MessageId::RunawayIterations,
+        " before iterations {first} to {last}",
+    ),
+    (
+        MessageId::SplitLoop,
+        "Instructions {start} to {end} aren't whole loops within the program",
+    ),
+    (
+        MessageId::EmptyProgram,
+        "The program is empty, so there's no instruction where this went wrong",
+    ),
+    (
+        MessageId::DeadLoop,
+        "Loop at {instruction} is never entered because its cell is always zero",
+    ),
+    (
+        MessageId::NeverFinishes,
+        "Loop at {instruction} never finishes because its cell can never be zero",
+    ),
+    (
+        MessageId::PossibleWrap,
+        "Instruction {instruction} may wrap its cell's value",
+    ),
+    (
+        MessageId::AlwaysSeeksTooLow,
+        "Instruction {instruction} always seeks to a negative head position",
+    ),
+    (MessageId::NoProgram, "no program has been loaded"),
+];
+
+const FRENCH: Table = [
+    (MessageId::ErrorLabel, "Erreur"),
+    (MessageId::WarningLabel, "avertissement"),
+    (MessageId::Instruction, "{line}:{column} ({meaning})"),
+    (
+        MessageId::IncrementDataPointer,
+        "avancer la position courante",
+    ),
+    (
+        MessageId::DecrementDataPointer,
+        "reculer la position courante",
+    ),
+    (
+        MessageId::IncrementByte,
+        "incrémenter l'octet à la position courante",
+    ),
+    (
+        MessageId::DecrementByte,
+        "décrémenter l'octet à la position courante",
+    ),
+    (MessageId::PutByte, "écrire l'octet à la position courante"),
+    (
+        MessageId::GetByte,
+        "lire un octet d'entrée à la position courante",
+    ),
+    (MessageId::OpenLoop, "commencer la boucle"),
+    (MessageId::CloseLoop, "terminer la boucle"),
+    (MessageId::Io, "Erreur d'entrée-sortie : {error}"),
+    (
+        MessageId::Parse,
+        "Dans le fichier {file}, {problem}, ligne {line}, colonne {column}",
+    ),
+    (
+        MessageId::UnopenedBracket,
+        "une boucle est fermée sans avoir été ouverte",
+    ),
+    (
+        MessageId::UnclosedBracket,
+        "une boucle est ouverte sans être fermée",
+    ),
+    (MessageId::InFile, "{file} : {error}"),
+    (
+        MessageId::SeekTooLow,
+        "L'instruction {instruction} a déplacé la tête avant la première cellule",
+    ),
+    (
+        MessageId::SeekTooHigh,
+        "L'instruction {instruction} a déplacé la tête après la dernière cellule, et la bande \
+        ne peut pas s'agrandir",
+    ),
+    (
+        MessageId::VmIo,
+        "Erreur d'entrée-sortie pendant l'instruction {instruction}",
+    ),
+    (
+        MessageId::LimitExceeded,
+        "L'instruction {instruction} a dépassé {limit}",
+    ),
+    (MessageId::StepLimit, "la limite de {max} pas"),
+    (
+        MessageId::OutputLimit,
+        "la limite de {max} octets de sortie",
+    ),
+    (MessageId::TapeLimit, "la limite de {max} cellules"),
+    (
+        MessageId::BytecodeMismatch,
+        "Le bytecode a été compilé à partir d'un autre programme",
+    ),
+    (
+        MessageId::InfiniteLoop,
+        "L'instruction {instruction} est dans une boucle sans fin : le programme est revenu au \
+        même état après {period} pas sans lire d'entrée",
+    ),
+    (
+        MessageId::RunawayLoop,
+        "La boucle de {start} à {end} a tourné plus de {limit} fois",
+    ),
+    (
+        MessageId::RunawayEntry,
+        " : la cellule testée valait {check} à l'entrée",
+    ),
+    (MessageId::RunawayThen, ", puis "),
+    (MessageId::RunawayCheck, "{value} (cellule {cell})"),
+    (MessageId::RunawaySameCell, " dans la cellule {cell}"),
+    (
+        MessageId::RunawayIterations,
+        " avant les itérations {first} à {last}",
+    ),
+    (
+        MessageId::SplitLoop,
+        "Les instructions {start} à {end} ne forment pas des boucles entières du programme",
+    ),
+    (
+        MessageId::EmptyProgram,
+        "Le programme est vide, donc aucune instruction n'est en cause",
+    ),
+    (
+        MessageId::DeadLoop,
+        "La boucle {instruction} n'est jamais exécutée car sa cellule vaut toujours zéro",
+    ),
+    (
+        MessageId::NeverFinishes,
+        "La boucle {instruction} ne se termine jamais car sa cellule ne peut jamais valoir zéro",
+    ),
+    (
+        MessageId::PossibleWrap,
+        "L'instruction {instruction} peut faire déborder la valeur de sa cellule",
+    ),
+    (
+        MessageId::AlwaysSeeksTooLow,
+        "L'instruction {instruction} déplace toujours la tête avant la première cellule",
+    ),
+    (MessageId::NoProgram, "aucun programme n'a été chargé"),
+];
+
+impl Lang {
+    /// Every language there's a table for
+    pub const ALL: [Lang; 2] = [Lang::English, Lang::French];
+
+    /// Finds the language for a language tag or locale name, such as `fr`, `fr-CA` or
+    /// `fr_FR.UTF-8`
+    ///
+    /// The C and POSIX locales are English. Returns None for a language there's no table for.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::messages::Lang;
+    /// assert_eq!(Lang::from_tag("fr_FR.UTF-8"), Some(Lang::French));
+    /// assert_eq!(Lang::from_tag("C"), Some(Lang::English));
+    /// assert_eq!(Lang::from_tag("de_DE"), None);
+    /// ```
+    pub fn from_tag(tag: &str) -> Option<Lang> {
+        let language = tag.split(['_', '-', '.', '@']).next().unwrap_or_default();
+        match language.to_ascii_lowercase().as_str() {
+            "en" | "c" | "posix" => Some(Lang::English),
+            "fr" => Some(Lang::French),
+            _ => None,
+        }
+    }
+
+    /// The language's tag, as accepted by [Lang::from_tag]
+    pub fn tag(self) -> &'static str {
+        match self {
+            Lang::English => "en",
+            Lang::French => "fr",
+        }
+    }
+
+    /// Picks the language from the first of `LC_ALL`, `LC_MESSAGES` and `LANG` that's set,
+    /// falling back to English if there's no table for it
+    #[cfg(feature = "std")]
+    pub fn detect() -> Lang {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|locale| Lang::from_tag(&locale))
+            .unwrap_or_default()
+    }
+
+    /// The word for a kind of diagnostic
+    pub fn label(self, label: Label) -> &'static str {
+        self.template(match label {
+            Label::Error => MessageId::ErrorLabel,
+            Label::Warning => MessageId::WarningLabel,
+        })
+    }
+
+    /// The language's template for a message
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::messages::{Lang, MessageId};
+    /// assert_eq!(Lang::French.template(MessageId::StepLimit), "la limite de {max} pas");
+    /// ```
+    pub fn template(self, id: MessageId) -> &'static str {
+        let table = match self {
+            Lang::English => &ENGLISH,
+            Lang::French => &FRENCH,
+        };
+        table[id as usize].1
+    }
+
+    /// Writes a message, with the details its template names
+    fn write(
+        self,
+        f: &mut fmt::Formatter<'_>,
+        id: MessageId,
+        details: &[(&str, &dyn fmt::Display)],
+    ) -> fmt::Result {
+        fill(f, self.template(id), details)
+    }
+}
+
+/// Writes a template, putting in the detail for each `{name}` it has
+///
+/// A name with no detail given is written as it is, braces and all.
+///
+/// # Examples
+/// ```
+/// # use bft_interp::messages::fill;
+/// let mut text = String::new();
+/// fill(&mut text, "{file} : {missing}", &[("file", &"a.bf")]).unwrap();
+/// assert_eq!(text, "a.bf : {missing}");
+/// ```
+pub fn fill(
+    out: &mut impl fmt::Write,
+    template: &str,
+    details: &[(&str, &dyn fmt::Display)],
+) -> fmt::Result {
+    let mut rest = template;
+    while let Some((before, after)) = rest.split_once('{') {
+        out.write_str(before)?;
+        let Some((name, after)) = after.split_once('}') else {
+            out.write_char('{')?;
+            rest = after;
+            continue;
+        };
+        match details.iter().find(|(detail, _)| *detail == name) {
+            Some((_, value)) => write!(out, "{value}")?,
+            None => write!(out, "{{{name}}}")?,
+        }
+        rest = after;
+    }
+    out.write_str(rest)
+}
+
+/// The names of the details a template puts in, in the order they're written
+///
+/// # Examples
+/// ```
+/// # use bft_interp::messages::details;
+/// let names: Vec<&str> = details("{file} : {error}").collect();
+/// assert_eq!(names, ["file", "error"]);
+/// ```
+pub fn details(template: &str) -> impl Iterator<Item = &str> {
+    template
+        .split('{')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}'))
+        .map(|(name, _)| name)
+}
+
+/// Something that can be written in any [Lang]
+pub trait Localize {
+    /// Writes this in `lang`, with the same details as in English
+    fn write_in(&self, lang: Lang, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+
+    /// This in `lang`, ready to display
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::error::BftError;
+    /// # use bft_interp::messages::{Lang, Localize};
+    /// # use bft_types;
+    /// let prog = bft_types::Program::new("a.bf", "+]");
+    /// let error = BftError::from(bft_types::DecoratedProgram::from_program(&prog).err().unwrap());
+    /// assert_eq!(
+    ///     error.localized(Lang::French).to_string(),
+    ///     "Dans le fichier a.bf, une boucle est fermée sans avoir été ouverte, ligne 1, colonne 2"
+    /// );
+    /// assert_eq!(error.localized(Lang::English).to_string(), error.to_string());
+    /// ```
+    fn localized(&self, lang: Lang) -> Localized<'_, Self> {
+        Localized { item: self, lang }
+    }
+}
+
+/// Something written in a chosen language, made by [Localize::localized]
+pub struct Localized<'a, T: ?Sized> {
+    item: &'a T,
+    lang: Lang,
+}
+
+impl<T: Localize + ?Sized> fmt::Display for Localized<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.item.write_in(self.lang, f)
+    }
+}
+
+impl Localize for BftError {
+    fn write_in(&self, lang: Lang, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BftError::Io(error) => lang.write(f, MessageId::Io, &[("error", error)]),
+            BftError::Parse(error) => error.write_in(lang, f),
+            BftError::Vm {
+                file: Some(file),
+                source,
+            } => lang.write(
+                f,
+                MessageId::InFile,
+                &[
+                    ("file", &display_file(file)),
+                    ("error", &source.localized(lang)),
+                ],
+            ),
+            BftError::Vm { file: None, source } => source.write_in(lang, f),
+            // The command line writes these in the user's language before making the error
+            BftError::Config(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Localize for ParseError {
+    fn write_in(&self, lang: Lang, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let problem = match self.kind() {
+            ParseErrorKind::UnopenedBracket => MessageId::UnopenedBracket,
+            ParseErrorKind::UnclosedBracket => MessageId::UnclosedBracket,
+        };
+        lang.write(
+            f,
+            MessageId::Parse,
+            &[
+                ("file", &display_file(self.file())),
+                ("problem", &lang.template(problem)),
+                ("line", &self.position().line()),
+                ("column", &self.position().character()),
+            ],
+        )
+    }
+}
+
+impl Localize for PositionedInstruction {
+    fn write_in(&self, lang: Lang, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let meaning = match self.instruction() {
+            RawInstruction::IncrementDataPointer => MessageId::IncrementDataPointer,
+            RawInstruction::DecrementDataPointer => MessageId::DecrementDataPointer,
+            RawInstruction::IncrementByte => MessageId::IncrementByte,
+            RawInstruction::DecrementByte => MessageId::DecrementByte,
+            RawInstruction::PutByte => MessageId::PutByte,
+            RawInstruction::GetByte => MessageId::GetByte,
+            RawInstruction::OpenLoop => MessageId::OpenLoop,
+            RawInstruction::CloseLoop => MessageId::CloseLoop,
+        };
+        lang.write(
+            f,
+            MessageId::Instruction,
+            &[
+                ("line", &self.line()),
+                ("column", &self.character()),
+                ("meaning", &lang.template(meaning)),
+            ],
+        )
+    }
+}
+
+impl Localize for VMError {
+    fn write_in(&self, lang: Lang, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (id, instruction) = match self {
+            VMError::SeekTooLow(instruction) => (MessageId::SeekTooLow, instruction),
+            VMError::SeekTooHigh(instruction) => (MessageId::SeekTooHigh, instruction),
+            VMError::IOError { instruction, .. } => (MessageId::VmIo, instruction),
+            VMError::LimitExceeded { limit, instruction } => {
+                return lang.write(
+                    f,
+                    MessageId::LimitExceeded,
+                    &[
+                        ("instruction", &instruction.localized(lang)),
+                        ("limit", &limit.localized(lang)),
+                    ],
+                )
+            }
+            VMError::BytecodeMismatch => return lang.write(f, MessageId::BytecodeMismatch, &[]),
+            VMError::EmptyProgram => return lang.write(f, MessageId::EmptyProgram, &[]),
+            VMError::InfiniteLoop {
+                instruction,
+                period,
+            } => {
+                return lang.write(
+                    f,
+                    MessageId::InfiniteLoop,
+                    &[
+                        ("instruction", &instruction.localized(lang)),
+                        ("period", period),
+                    ],
+                )
+            }
+            VMError::LoopLimitExceeded(runaway) => return runaway.write_in(lang, f),
+            VMError::SplitLoop { start, end } => {
+                return lang.write(f, MessageId::SplitLoop, &[("start", start), ("end", end)])
+            }
+        };
+        lang.write(f, id, &[("instruction", &instruction.localized(lang))])
+    }
+}
+
+impl Localize for Limit {
+    fn write_in(&self, lang: Lang, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (id, max): (_, &dyn fmt::Display) = match self {
+            Limit::Steps(max) => (MessageId::StepLimit, max),
+            Limit::OutputBytes(max) => (MessageId::OutputLimit, max),
+            Limit::Tape(max) => (MessageId::TapeLimit, max),
+        };
+        lang.write(f, id, &[("max", max)])
+    }
+}
+
+impl Localize for RunawayLoop {
+    fn write_in(&self, lang: Lang, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        lang.write(
+            f,
+            MessageId::RunawayLoop,
+            &[
+                ("start", &Position(&self.start)),
+                ("end", &Position(&self.end)),
+                ("limit", &self.limit),
+            ],
+        )?;
+        let Some((entry, recent)) = self.checks.split_first() else {
+            return Ok(());
+        };
+        lang.write(
+            f,
+            MessageId::RunawayEntry,
+            &[("check", &entry.localized(lang))],
+        )?;
+        let (Some(first), Some(last)) = (recent.first(), recent.last()) else {
+            return Ok(());
+        };
+        lang.write(f, MessageId::RunawayThen, &[])?;
+        let same_cell = recent.iter().all(|check| check.cell == first.cell);
+        for (index, check) in recent.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            if same_cell {
+                write!(f, "{}", check.value)?;
+            } else {
+                check.write_in(lang, f)?;
+            }
+        }
+        if same_cell {
+            lang.write(f, MessageId::RunawaySameCell, &[("cell", &first.cell)])?;
+        }
+        lang.write(
+            f,
+            MessageId::RunawayIterations,
+            &[("first", &first.iteration), ("last", &last.iteration)],
+        )
+    }
+}
+
+impl Localize for ConditionCheck {
+    fn write_in(&self, lang: Lang, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        lang.write(
+            f,
+            MessageId::RunawayCheck,
+            &[("value", &self.value), ("cell", &self.cell)],
+        )
+    }
+}
+
+impl Localize for Finding {
+    fn write_in(&self, lang: Lang, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let id = match self {
+            Finding::DeadLoop(_) => MessageId::DeadLoop,
+            Finding::InfiniteLoop(_) => MessageId::NeverFinishes,
+            Finding::PossibleWrap(_) => MessageId::PossibleWrap,
+            Finding::SeekTooLow(_) => MessageId::AlwaysSeeksTooLow,
+        };
+        lang.write(
+            f,
+            id,
+            &[("instruction", &self.instruction().localized(lang))],
+        )
+    }
+}
+
+/// An instruction's line and column, which are written the same in every language
+struct Position<'i>(&'i PositionedInstruction);
+
+impl fmt::Display for Position<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.0.line(), self.0.character())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+    use bft_types::{DecoratedProgram, Program};
+    use core::num::NonZeroUsize;
+
+    use crate::analysis::Analysis;
+    use crate::limits::Limits;
+    use crate::Machine;
+
+    /// The names of a template's details, sorted
+    fn names(template: &str) -> Vec<&str> {
+        let mut names: Vec<&str> = details(template).collect();
+        names.sort_unstable();
+        names
+    }
+
+    #[test]
+    fn every_table_has_every_message_with_the_same_details() {
+        for table in [&ENGLISH, &FRENCH] {
+            for (index, (id, template)) in table.iter().enumerate() {
+                assert_eq!(*id as usize, index, "{id:?} is out of order");
+                assert_eq!(
+                    names(template),
+                    names(ENGLISH[index].1),
+                    "{id:?} names different details"
+                );
+            }
+        }
+    }
+
+    /// Runs a program until it fails, with small limits and the errors' file set
+    fn failure(source: &str, limits: Limits, input: &[u8]) -> BftError {
+        let prog = DecoratedProgram::from_program(&Program::new("a.bf", source)).unwrap();
+        let mut machine: Machine<u8> = Machine::new(NonZeroUsize::new(4), false, &prog);
+        machine.set_limits(limits);
+        BftError::Vm {
+            file: Some(prog.file().into()),
+            source: machine.run_to_string(input).unwrap_err(),
+        }
+    }
+
+    #[test]
+    fn english_is_the_same_as_display() {
+        let mut errors: Vec<BftError> = ["[", "]"]
+            .iter()
+            .map(|source| {
+                DecoratedProgram::from_program(&Program::new("a.bf", source))
+                    .err()
+                    .unwrap()
+                    .into()
+            })
+            .collect();
+        errors.extend([
+            failure("<", Limits::default(), b""),
+            failure(">>>>", Limits::default(), b""),
+            failure(
+                "+[]",
+                Limits {
+                    max_steps: Some(10),
+                    ..Limits::default()
+                },
+                b"",
+            ),
+            failure(
+                "+[>+<+]",
+                Limits {
+                    max_loop_iterations: Some(5),
+                    ..Limits::default()
+                },
+                b"",
+            ),
+            failure(
+                "+[>+]",
+                Limits {
+                    max_loop_iterations: Some(2),
+                    ..Limits::default()
+                },
+                b"",
+            ),
+            BftError::Config("bad option".to_string()),
+        ]);
+        for error in errors {
+            assert_eq!(
+                error.localized(Lang::English).to_string(),
+                error.to_string()
+            );
+        }
+        let prog = DecoratedProgram::from_program(&Program::new("a.bf", "<+[]")).unwrap();
+        let analysis = Analysis::from_program(&prog);
+        assert!(!analysis.findings().is_empty());
+        for finding in analysis.findings() {
+            let english: String = finding.localized(Lang::English).to_string();
+            assert_eq!(english, finding.to_string());
+        }
+    }
+
+    #[test]
+    fn vm_errors_keep_their_details_in_french() {
+        let error = failure(
+            "+[]",
+            Limits {
+                max_steps: Some(10),
+                ..Limits::default()
+            },
+            b"",
+        );
+        assert_eq!(
+            error.localized(Lang::French).to_string(),
+            "a.bf : L'instruction 1:3 (terminer la boucle) a dépassé la limite de 10 pas"
+        );
+    }
+}
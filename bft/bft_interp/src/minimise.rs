@@ -0,0 +1,160 @@
+//! Shrinks a program for as long as it keeps doing something interesting, usually failing in a
+//! particular way, so a huge program that shows a bug can be cut down to a small reproducer.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use bft_types::{DecoratedProgram, Program, RawInstruction};
+
+/// Removes instructions and loops from `prog` while `reproduces` still holds, returning the
+/// smallest code found
+///
+/// `reproduces` is given each candidate program along with its code.
+///
+/// Chunks of instructions are removed, halving in size down to single instructions, then whole
+/// loops are removed or replaced by their bodies, until nothing more can go. Comments are always
+/// dropped. Returns None if `prog` doesn't reproduce without its comments to begin with.
+///
+/// # Examples
+/// ```
+/// # use bft_interp::minimise::minimise;
+/// # use bft_interp::{limits::Limits, Machine, VMError};
+/// # use bft_types;
+/// let prog = bft_types::DecoratedProgram::from_program(
+///     &bft_types::Program::new("<None>", "+++[>+<-]>.<<")
+/// ).unwrap();
+/// let seeks_too_low = |prog: &bft_types::DecoratedProgram, _: &str| {
+///     let mut machine: Machine<u8> = Machine::new(None, false, prog);
+///     // Removing instructions can easily make a loop that never ends
+///     machine.set_limits(Limits { max_steps: Some(1000), ..Limits::default() });
+///     let result = machine.run(&mut std::io::empty(), &mut std::io::sink());
+///     matches!(result, Err(VMError::SeekTooLow(_)))
+/// };
+/// assert_eq!(minimise(&prog, seeks_too_low).unwrap(), "<");
+/// ```
+pub fn minimise(
+    prog: &DecoratedProgram,
+    mut reproduces: impl FnMut(&DecoratedProgram, &str) -> bool,
+) -> Option<String> {
+    let mut test = |code: &[RawInstruction]| {
+        let text: String = code
+            .iter()
+            .map(|instruction| instruction.to_byte() as char)
+            .collect();
+        DecoratedProgram::from_program(&Program::new(prog.file(), &text))
+            .is_ok_and(|candidate| reproduces(&candidate, &text))
+    };
+    let mut code: Vec<RawInstruction> = prog
+        .decorated_instructions()
+        .iter()
+        .map(|instruction| *instruction.instruction().instruction())
+        .collect();
+    if !test(&code) {
+        return None;
+    }
+
+    loop {
+        let before = code.len();
+
+        let mut chunk = code.len() / 2;
+        while chunk > 0 {
+            let mut start = 0;
+            while start < code.len() {
+                let end = (start + chunk).min(code.len());
+                let candidate = [&code[..start], &code[end..]].concat();
+                if test(&candidate) {
+                    code = candidate;
+                } else {
+                    start += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+
+        let mut index = 0;
+        while index < code.len() {
+            if let Some(closer) = closer(&code, index) {
+                let removed = [&code[..index], &code[closer + 1..]].concat();
+                let unwrapped = [
+                    &code[..index],
+                    &code[index + 1..closer],
+                    &code[closer + 1..],
+                ]
+                .concat();
+                if test(&removed) {
+                    code = removed;
+                    continue;
+                }
+                if test(&unwrapped) {
+                    code = unwrapped;
+                    continue;
+                }
+            }
+            index += 1;
+        }
+
+        tracing::debug!(before, after = code.len(), "Minimised");
+        if code.len() == before {
+            break;
+        }
+    }
+    Some(
+        code.iter()
+            .map(|instruction| instruction.to_byte() as char)
+            .collect(),
+    )
+}
+
+/// The index of the bracket closing the loop opened at `index`, if a loop is opened there
+fn closer(code: &[RawInstruction], index: usize) -> Option<usize> {
+    if code[index] != RawInstruction::OpenLoop {
+        return None;
+    }
+    let mut depth = 0;
+    for (offset, instruction) in code[index..].iter().enumerate() {
+        match instruction {
+            RawInstruction::OpenLoop => depth += 1,
+            RawInstruction::CloseLoop => depth -= 1,
+            _ => continue,
+        }
+        if depth == 0 {
+            return Some(index + offset);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+
+    fn writes(expected: &'static [u8]) -> impl FnMut(&DecoratedProgram, &str) -> bool {
+        move |prog, _| {
+            let mut machine: Machine<u8> = Machine::new(None, false, prog);
+            machine.set_limits(crate::limits::Limits {
+                max_steps: Some(1000),
+                ..Default::default()
+            });
+            let mut output = Vec::new();
+            let _ = machine.run(&mut std::io::empty(), &mut output);
+            output == expected
+        }
+    }
+
+    #[test]
+    fn loops_are_removed_and_unwrapped() {
+        let prog = DecoratedProgram::from_program(&Program::new(
+            "<test>",
+            "a comment [-]>++[>+++<-]>[-]+.[[-]>]",
+        ))
+        .unwrap();
+        assert_eq!(minimise(&prog, writes(b"\x01")).unwrap(), "+.");
+    }
+
+    #[test]
+    fn programs_that_never_reproduce_are_rejected() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", "+.")).unwrap();
+        assert!(minimise(&prog, writes(b"\x02")).is_none());
+    }
+}
@@ -0,0 +1,154 @@
+//! Steps described in plain English, for people learning what each instruction does
+//!
+//! [Machine::step_narrated] executes one instruction and returns a [Narration] of it: what the
+//! instruction means, and how it changed the head or the cell under it.
+
+use bft_types::{PositionedInstruction, RawInstruction};
+use core::fmt;
+
+use crate::io::{Read, Write};
+use crate::value::ValueFormat;
+use crate::{CellKind, Machine, VMError};
+
+/// What one instruction did
+#[derive(Debug, Clone)]
+pub struct Narration<T> {
+    pub instruction: PositionedInstruction,
+    /// The head before the instruction, which is also the cell it worked on
+    pub head: usize,
+    /// The head after the instruction
+    pub moved_to: usize,
+    /// The cell under the head before the instruction
+    pub before: T,
+    /// The same cell after the instruction
+    pub after: T,
+    /// How to write the cell's values
+    pub values: ValueFormat,
+}
+
+/// Writes the instruction's position, code and meaning, then what it did
+impl<T: CellKind> fmt::Display for Narration<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let instruction = self.instruction.instruction();
+        let position = alloc::format!(
+            "{}:{}",
+            self.instruction.line(),
+            self.instruction.character()
+        );
+        write!(f, "{position:<8} {instruction:#}  {instruction}: ")?;
+        let (cell, before, after) = (
+            self.head,
+            self.values.display(&self.before),
+            self.values.display(&self.after),
+        );
+        match instruction {
+            RawInstruction::IncrementDataPointer | RawInstruction::DecrementDataPointer => {
+                write!(
+                    f,
+                    "the head moves from cell {cell} to cell {}",
+                    self.moved_to
+                )
+            }
+            RawInstruction::IncrementByte
+            | RawInstruction::DecrementByte
+            | RawInstruction::GetByte => write!(f, "cell {cell} goes from {before} to {after}"),
+            RawInstruction::PutByte => write!(f, "cell {cell} holds {before}"),
+            RawInstruction::OpenLoop if self.before.is_zero() => {
+                write!(f, "cell {cell} is {before}, so the loop is skipped")
+            }
+            RawInstruction::OpenLoop => write!(f, "cell {cell} is {before}, so the loop runs"),
+            RawInstruction::CloseLoop if self.before.is_zero() => {
+                write!(f, "cell {cell} is {before}, so the loop ends")
+            }
+            RawInstruction::CloseLoop => {
+                write!(f, "cell {cell} is {before}, so the loop goes round again")
+            }
+        }
+    }
+}
+
+impl<T: CellKind> Machine<'_, T> {
+    /// Executes one instruction like [Machine::step], describing what it did, with values in
+    /// the Machine's [value format](Machine::value_format)
+    ///
+    /// Returns None, without executing anything, once the program has finished.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp;
+    /// # use bft_types;
+    /// use bft_interp::value::{Notation, ValueFormat};
+    /// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+>[-]")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// interp.set_value_format(ValueFormat { notation: Notation::Decimal, width: None });
+    /// let (mut input, mut output) = (std::io::empty(), Vec::new());
+    /// let mut narrations = Vec::new();
+    /// while let Some(narration) = interp.step_narrated(&mut input, &mut output).unwrap() {
+    ///     narrations.push(narration.to_string());
+    /// }
+    /// assert_eq!(narrations, [
+    ///     "1:1      +  Increment the byte at the current location: cell 0 goes from 0 to 1",
+    ///     "1:2      >  Increment current location: the head moves from cell 0 to cell 1",
+    ///     "1:3      [  Start looping: cell 1 is 0, so the loop is skipped",
+    /// ]);
+    /// ```
+    pub fn step_narrated(
+        &mut self,
+        input: &mut impl Read,
+        output: &mut impl Write,
+    ) -> Result<Option<Narration<T>>, VMError> {
+        let Some(next) = self.next_instruction() else {
+            self.step(input, output)?;
+            return Ok(None);
+        };
+        let (head, before) = (self.head, self.cells[self.head].clone());
+        self.step(input, output)?;
+        Ok(Some(Narration {
+            instruction: next.instruction(),
+            head,
+            moved_to: self.head,
+            before,
+            after: self.cells[head].clone(),
+            values: self.value_format,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Notation;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+    use bft_types::{DecoratedProgram, Program};
+
+    #[test]
+    fn loops_say_which_way_they_go() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", "++[-]")).unwrap();
+        let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+        machine.set_value_format(ValueFormat {
+            notation: Notation::Decimal,
+            width: None,
+        });
+        let mut narrations = Vec::new();
+        while let Some(narration) = machine
+            .step_narrated(&mut &[][..], &mut Vec::new())
+            .unwrap()
+        {
+            narrations.push(narration.to_string());
+        }
+        let explained = |narration: &String| narration.split_once(": ").unwrap().1.to_string();
+        assert_eq!(
+            narrations[2..].iter().map(explained).collect::<Vec<_>>(),
+            [
+                "cell 0 is 2, so the loop runs",
+                "cell 0 goes from 2 to 1",
+                "cell 0 is 1, so the loop goes round again",
+                "cell 0 goes from 1 to 0",
+                "cell 0 is 0, so the loop ends",
+            ]
+        );
+    }
+}
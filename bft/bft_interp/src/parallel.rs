@@ -0,0 +1,190 @@
+//! Running many programs at once across threads
+//!
+//! An [Executor] takes a list of [Job]s, each a program, its input and the limits to run it
+//! under, and runs them spread across a number of threads, handing back an [Outcome] for each.
+//! Jobs that share a program share its compiled bytecode and reuse each other's Machines through a
+//! [Pool]. This needs the `std` feature, for the threads.
+
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bft_types::DecoratedProgram;
+
+use crate::limits::Limits;
+use crate::pool::Pool;
+use crate::{CellKind, VMError};
+
+/// A program to run on one input
+#[derive(Clone)]
+pub struct Job<'a> {
+    pub prog: &'a DecoratedProgram,
+    pub input: Vec<u8>,
+    /// How many cells the tape starts with, as for [Machine::new](crate::Machine::new)
+    pub cells: Option<NonZeroUsize>,
+    pub may_grow: bool,
+    pub limits: Limits,
+}
+
+impl<'a> Job<'a> {
+    /// Makes a job that runs `prog` on `input` with the default tape and no limits
+    pub fn new(prog: &'a DecoratedProgram, input: impl Into<Vec<u8>>) -> Job<'a> {
+        Job {
+            prog,
+            input: input.into(),
+            cells: None,
+            may_grow: false,
+            limits: Limits::default(),
+        }
+    }
+}
+
+/// What happened when a [Job] ran
+#[derive(Debug)]
+pub struct Outcome {
+    /// Everything the program wrote, up to any error
+    pub output: Vec<u8>,
+    pub steps: u64,
+    /// How long the program ran for
+    pub time: Duration,
+    pub result: Result<(), VMError>,
+}
+
+/// Runs jobs on a fixed number of threads
+#[derive(Debug, Clone, Copy)]
+pub struct Executor {
+    threads: NonZeroUsize,
+}
+
+impl Executor {
+    /// Makes an executor with a thread for each core
+    pub fn new() -> Executor {
+        let threads = thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+        Executor::with_threads(threads)
+    }
+
+    pub fn with_threads(threads: NonZeroUsize) -> Executor {
+        Executor { threads }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads.get()
+    }
+
+    /// Runs every job, returning their outcomes in the same order as the jobs
+    ///
+    /// Each job runs on its own Machine with cells of type `T`, so one failing or going over its
+    /// limits doesn't affect the others.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::parallel::{Executor, Job};
+    /// # use bft_interp::limits::Limits;
+    /// # use bft_types;
+    /// let shout = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("shout", ",[--------------------------------.,]")
+    /// ).unwrap();
+    /// let forever = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("forever", "+[]")
+    /// ).unwrap();
+    /// let mut jobs = vec![Job::new(&shout, "hey\0"), Job::new(&forever, "")];
+    /// jobs[1].limits = Limits { max_steps: Some(1000), ..Limits::default() };
+    /// let outcomes = Executor::new().run::<u8>(&jobs);
+    /// assert_eq!(outcomes[0].output, b"HEY");
+    /// assert!(outcomes[1].result.is_err());
+    /// ```
+    pub fn run<T: CellKind + Send>(&self, jobs: &[Job<'_>]) -> Vec<Outcome> {
+        // One pool for each program and tape, so jobs that share them share bytecode and Machines
+        let mut keys = HashMap::new();
+        let mut pools: Vec<Pool<T>> = Vec::new();
+        let job_pools: Vec<usize> = jobs
+            .iter()
+            .map(|job| {
+                let key = (job.prog as *const DecoratedProgram, job.cells, job.may_grow);
+                *keys.entry(key).or_insert_with(|| {
+                    pools.push(Pool::new(job.cells, job.may_grow, job.prog));
+                    pools.len() - 1
+                })
+            })
+            .collect();
+
+        let next = AtomicUsize::new(0);
+        let outcomes: Mutex<Vec<Option<Outcome>>> = Mutex::new(jobs.iter().map(|_| None).collect());
+        thread::scope(|scope| {
+            for _ in 0..self.threads().min(jobs.len()) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(job) = jobs.get(index) else {
+                        break;
+                    };
+                    let outcome = run_job(&pools[job_pools[index]], job);
+                    outcomes.lock().expect("No thread panics holding the lock")[index] =
+                        Some(outcome);
+                });
+            }
+        });
+        outcomes
+            .into_inner()
+            .expect("No thread panics holding the lock")
+            .into_iter()
+            .map(|outcome| outcome.expect("Every job is run"))
+            .collect()
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn run_job<T: CellKind>(pool: &Pool<T>, job: &Job<'_>) -> Outcome {
+    let mut machine = pool.get();
+    machine.set_limits(job.limits);
+    let mut output = Vec::new();
+    let start = Instant::now();
+    let result = machine.run_bytecode(pool.bytecode(), &mut &job.input[..], &mut output);
+    Outcome {
+        output,
+        steps: machine.steps(),
+        time: start.elapsed(),
+        result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::Program;
+
+    #[test]
+    fn outcomes_keep_the_order_of_the_jobs() {
+        let add = DecoratedProgram::from_program(&Program::new("add", ",>,[<+>-]<.")).unwrap();
+        let echo = DecoratedProgram::from_program(&Program::new("echo", ",.")).unwrap();
+        let mut jobs: Vec<Job> = (0..100u8)
+            .map(|n| {
+                if n.is_multiple_of(3) {
+                    Job::new(&echo, [n])
+                } else {
+                    Job::new(&add, [n, n])
+                }
+            })
+            .collect();
+        // A job's limits and tape are its own, even when it shares a program with others
+        jobs[4].limits.max_steps = Some(3);
+        jobs[5].cells = NonZeroUsize::new(1);
+        let outcomes = Executor::with_threads(NonZeroUsize::new(4).unwrap()).run::<u8>(&jobs);
+        for (n, outcome) in outcomes.iter().enumerate() {
+            let n = n as u8;
+            match n {
+                4 => assert!(matches!(outcome.result, Err(VMError::LimitExceeded { .. }))),
+                5 => assert!(matches!(outcome.result, Err(VMError::SeekTooHigh(_)))),
+                _ if n.is_multiple_of(3) => assert_eq!(outcome.output, [n]),
+                _ => assert_eq!(outcome.output, [n * 2]),
+            }
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! What a Machine does when a program strays outside the tape or reads past the end of its input
+//!
+//! The original interpreters disagree here: some stop, some keep the head at the edge of the tape
+//! or wrap it round to the other end, and at the end of the input some store 0, some -1 and some
+//! leave the cell alone. Programs written for one of them often rely on its choices, so a
+//! [Machine](crate::Machine) can be set to follow them with [Policies]. By default it stops with
+//! an error, as it always has.
+
+/// How a Machine handles each of the things the language leaves undefined
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Policies {
+    /// Moving left from the first cell
+    pub seek_too_low: Seek,
+    /// Moving right from the last cell of a tape that can't grow
+    pub seek_too_high: Seek,
+    /// Reading when the input has run out
+    pub eof: Eof,
+}
+
+/// What happens when the head moves off one end of the tape
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Seek {
+    /// Stop with [VMError::SeekTooLow](crate::VMError::SeekTooLow) or
+    /// [VMError::SeekTooHigh](crate::VMError::SeekTooHigh)
+    #[default]
+    Error,
+    /// Leave the head on the cell at the end
+    Clamp,
+    /// Carry on from the other end of the tape
+    Wrap,
+}
+
+/// What `,` stores when there's no input left
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Eof {
+    /// Stop with [VMError::IOError](crate::VMError::IOError)
+    #[default]
+    Error,
+    Zero,
+    /// Store the cell's largest value, which is -1 to programs that treat cells as signed
+    Max,
+    /// Leave the cell as it was
+    Unchanged,
+}
@@ -0,0 +1,196 @@
+//! Running one program many times without allocating for each run
+//!
+//! Fuzzers and graders run the same program over thousands of inputs. A [Pool] compiles the
+//! program once and keeps the Machines that have finished, resetting one for the next run instead
+//! of allocating a new tape. Once there are as many Machines as runs happening at once, running
+//! allocates nothing. The pool can be shared between threads. This needs the `std` feature, for
+//! the lock.
+
+use std::num::NonZeroUsize;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+use bft_types::DecoratedProgram;
+
+use crate::bytecode::Bytecode;
+use crate::io::{Read, Write};
+use crate::limits::Limits;
+use crate::{CellKind, Machine, VMError};
+
+/// Machines for one program, ready to run it again
+pub struct Pool<'a, T> {
+    prog: &'a DecoratedProgram,
+    bytecode: Bytecode,
+    size: Option<NonZeroUsize>,
+    may_grow: bool,
+    limits: Limits,
+    /// The Machines not being used, already reset
+    idle: Mutex<Vec<Machine<'a, T>>>,
+}
+
+impl<'a, T: CellKind> Pool<'a, T> {
+    /// Makes an empty pool of Machines like [Machine::new] would make, compiling the program
+    pub fn new(
+        size: Option<NonZeroUsize>,
+        may_grow: bool,
+        prog: &'a DecoratedProgram,
+    ) -> Pool<'a, T> {
+        Self::with_bytecode(size, may_grow, prog, Bytecode::compile(prog))
+    }
+
+    /// Makes an empty pool like [Pool::new], running bytecode already compiled from the program
+    pub fn with_bytecode(
+        size: Option<NonZeroUsize>,
+        may_grow: bool,
+        prog: &'a DecoratedProgram,
+        bytecode: Bytecode,
+    ) -> Pool<'a, T> {
+        Pool {
+            prog,
+            bytecode,
+            size,
+            may_grow,
+            limits: Limits::default(),
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Sets the limits every Machine from now on runs with
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        for machine in self
+            .idle
+            .get_mut()
+            .expect("No thread panics holding the lock")
+        {
+            machine.set_limits(limits);
+        }
+    }
+
+    /// The bytecode the pool's Machines run
+    pub fn bytecode(&self) -> &Bytecode {
+        &self.bytecode
+    }
+
+    /// How many Machines are waiting to be used again
+    pub fn idle(&self) -> usize {
+        self.idle
+            .lock()
+            .expect("No thread panics holding the lock")
+            .len()
+    }
+
+    /// Takes a Machine from the pool, ready to run the program from the start
+    ///
+    /// The Machine goes back in the pool when it's dropped.
+    pub fn get(&self) -> Pooled<'_, 'a, T> {
+        let idle = self
+            .idle
+            .lock()
+            .expect("No thread panics holding the lock")
+            .pop();
+        let machine = idle.unwrap_or_else(|| {
+            let mut machine = Machine::new(self.size, self.may_grow, self.prog);
+            machine.set_limits(self.limits);
+            machine
+        });
+        Pooled {
+            pool: self,
+            machine: Some(machine),
+        }
+    }
+
+    /// Runs the compiled program on a Machine from the pool, returning how many steps it took
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::pool::Pool;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",+.")
+    /// ).unwrap();
+    /// let pool: Pool<u8> = Pool::new(None, false, &prog);
+    /// let mut output = Vec::new();
+    /// for input in b"HAL" {
+    ///     pool.run(&mut std::io::Cursor::new([*input]), &mut output).unwrap();
+    /// }
+    /// assert_eq!(output, b"IBM");
+    /// assert_eq!(pool.idle(), 1);
+    /// ```
+    pub fn run(&self, input: &mut impl Read, output: &mut impl Write) -> Result<u64, VMError> {
+        let mut machine = self.get();
+        machine.run_bytecode(&self.bytecode, input, output)?;
+        Ok(machine.steps())
+    }
+}
+
+/// A Machine borrowed from a [Pool], which goes back when it's dropped
+pub struct Pooled<'p, 'a, T: CellKind> {
+    pool: &'p Pool<'a, T>,
+    /// Always Some until the Machine goes back in the pool
+    machine: Option<Machine<'a, T>>,
+}
+
+impl<'a, T: CellKind> Deref for Pooled<'_, 'a, T> {
+    type Target = Machine<'a, T>;
+
+    fn deref(&self) -> &Machine<'a, T> {
+        self.machine
+            .as_ref()
+            .expect("The Machine is only taken on drop")
+    }
+}
+
+impl<T: CellKind> DerefMut for Pooled<'_, '_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.machine
+            .as_mut()
+            .expect("The Machine is only taken on drop")
+    }
+}
+
+impl<T: CellKind> Drop for Pooled<'_, '_, T> {
+    fn drop(&mut self) {
+        let Some(mut machine) = self.machine.take() else {
+            return;
+        };
+        machine.reset();
+        machine.set_limits(self.pool.limits);
+        if let Ok(mut idle) = self.pool.idle.lock() {
+            idle.push(machine);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::Program;
+
+    #[test]
+    fn machines_are_reused_clean() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", ",[>+<-]>[.>]")).unwrap();
+        let mut pool: Pool<u8> = Pool::new(Some(NonZeroUsize::new(2).unwrap()), true, &prog);
+        pool.set_limits(Limits {
+            max_steps: Some(50),
+            ..Limits::default()
+        });
+        // Growing the tape, then failing part way, mustn't leave anything for the next run
+        let mut output = Vec::new();
+        assert!(pool.run(&mut std::io::repeat(200), &mut output).is_err());
+        assert_eq!(pool.idle(), 1);
+        {
+            let machine = pool.get();
+            assert_eq!(pool.idle(), 0);
+            assert_eq!((machine.head(), machine.steps()), (0, 0));
+            assert_eq!(machine.cells(), [0, 0]);
+            let other = pool.get();
+            assert_eq!(other.limits().max_steps, Some(50));
+        }
+        assert_eq!(pool.idle(), 2);
+        output.clear();
+        let steps = pool.run(&mut std::io::repeat(3), &mut output).unwrap();
+        assert_eq!(output, [3]);
+        assert!(steps < 50);
+    }
+}
@@ -0,0 +1,180 @@
+//! Finding where a program spends its time
+//!
+//! A [Profiler] is a [Tracer] that counts how often each operation runs. Its [Report] sums those
+//! counts up by kind of operation and by loop.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::vec::Vec;
+use core::fmt;
+
+use bft_types::PositionedInstruction;
+
+use crate::bytecode::{Bytecode, Op};
+use crate::trace::{Step, Tracer};
+
+/// The most loops shown by a Report's Display
+const HOTTEST_SHOWN: usize = 10;
+
+/// Counts how many times each operation of some bytecode is executed
+#[derive(Debug, Default, Clone)]
+pub struct Profiler {
+    /// Executions of each operation, by index in the bytecode
+    counts: Vec<u64>,
+    /// The instruction each operation was compiled from, once it has run
+    instructions: Vec<Option<PositionedInstruction>>,
+}
+
+impl<T> Tracer<T> for Profiler {
+    fn step(&mut self, step: &Step<'_, T>) {
+        if step.pc >= self.counts.len() {
+            self.counts.resize(step.pc + 1, 0);
+            self.instructions.resize(step.pc + 1, None);
+        }
+        self.counts[step.pc] += 1;
+        self.instructions[step.pc] = Some(step.instruction);
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    /// Executions of each operation so far, by index in the bytecode
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Summarises the counts, given the bytecode that was run
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::profile::Profiler;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+++[>++<-]")
+    /// ).unwrap();
+    /// let bytecode = bft_interp::bytecode::Bytecode::compile(&prog);
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// let mut profiler = Profiler::new();
+    /// interp
+    ///     .run_bytecode_traced(&bytecode, &mut std::io::empty(), &mut std::io::sink(), &mut profiler)
+    ///     .unwrap();
+    /// let report = profiler.report(&bytecode);
+    /// assert_eq!(report.loops()[0].iterations(), 3);
+    /// assert_eq!(report.ops()["add"], 7);
+    /// ```
+    pub fn report(&self, bytecode: &Bytecode) -> Report {
+        let count = |pc: usize| self.counts.get(pc).copied().unwrap_or(0);
+        let mut ops = BTreeMap::new();
+        for (pc, op) in bytecode.ops().iter().enumerate() {
+            if count(pc) > 0 {
+                *ops.entry(op.mnemonic()).or_insert(0) += count(pc);
+            }
+        }
+
+        let mut loops = Vec::new();
+        for (pc, op) in bytecode.ops().iter().enumerate() {
+            let (Op::JumpIfZero(closer), Some(Some(opener))) = (op, self.instructions.get(pc))
+            else {
+                continue;
+            };
+            loops.push(LoopProfile {
+                opener: *opener,
+                entries: count(pc),
+                iterations: count(*closer),
+                steps: (pc..=*closer).map(count).sum(),
+            });
+        }
+        loops.sort_by_key(|profile| core::cmp::Reverse(profile.steps));
+
+        Report {
+            total_steps: self.counts.iter().sum(),
+            ops,
+            loops,
+        }
+    }
+}
+
+/// How much time a program spent in one loop
+#[derive(Debug, Clone, Copy)]
+pub struct LoopProfile {
+    opener: PositionedInstruction,
+    entries: u64,
+    iterations: u64,
+    steps: u64,
+}
+
+impl LoopProfile {
+    /// The `[` that starts the loop
+    pub fn opener(&self) -> PositionedInstruction {
+        self.opener
+    }
+
+    /// How many times the loop was reached, whether or not its body then ran
+    pub fn entries(&self) -> u64 {
+        self.entries
+    }
+
+    /// How many times the loop body ran
+    pub fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    /// How many steps were spent in the loop, including any loops inside it
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+}
+
+/// A summary of where a program spent its time
+#[derive(Debug, Clone)]
+pub struct Report {
+    total_steps: u64,
+    ops: BTreeMap<&'static str, u64>,
+    loops: Vec<LoopProfile>,
+}
+
+impl Report {
+    /// How many operations were executed in total
+    pub fn total_steps(&self) -> u64 {
+        self.total_steps
+    }
+
+    /// How many times each kind of operation was executed, keyed by [Op::mnemonic]
+    pub fn ops(&self) -> &BTreeMap<&'static str, u64> {
+        &self.ops
+    }
+
+    /// Every loop that was reached, hottest first
+    pub fn loops(&self) -> &[LoopProfile] {
+        &self.loops
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |steps: u64| 100.0 * steps as f64 / self.total_steps.max(1) as f64;
+        writeln!(f, "{} steps", self.total_steps)?;
+        writeln!(f, "Operations:")?;
+        for (name, count) in &self.ops {
+            writeln!(f, "  {name:<6}{count:>14} {:>6.2}%", percent(*count))?;
+        }
+        if !self.loops.is_empty() {
+            writeln!(f, "Hottest loops:")?;
+        }
+        for profile in self.loops.iter().take(HOTTEST_SHOWN) {
+            let position = format!("{}:{}", profile.opener.line(), profile.opener.character());
+            writeln!(
+                f,
+                "  {position:<10}{:>14} {:>6.2}%  entered {} times, {} iterations",
+                profile.steps,
+                percent(profile.steps),
+                profile.entries,
+                profile.iterations
+            )?;
+        }
+        Ok(())
+    }
+}
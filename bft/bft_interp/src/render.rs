@@ -0,0 +1,95 @@
+//! Printing a Machine's state for debugging: a window of cells around the head, with each value
+//! in the Machine's [ValueFormat](crate::value::ValueFormat)
+
+use alloc::string::String;
+use core::fmt::{self, Write};
+
+use crate::{CellKind, Machine};
+
+/// How many cells Display shows if the format doesn't give a width
+const DEFAULT_WIDTH: usize = 9;
+
+impl<T: CellKind> Machine<'_, T> {
+    /// Describes the step count, head and next instruction, then `width` cells around the head,
+    /// marking the one under it, with their values in the Machine's
+    /// [value format](Machine::value_format)
+    ///
+    /// `format!("{machine}")` does the same with 9 cells, and `format!("{machine:5}")` with 5.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp;
+    /// # use bft_types;
+    /// let prog: bft_types::DecoratedProgram = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",>++")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// let (mut input, mut output) = (&b"A"[..], Vec::new());
+    /// for _ in 0..3 {
+    ///     interp.step(&mut input, &mut output).unwrap();
+    /// }
+    /// assert_eq!(
+    ///     interp.render_state(3),
+    ///     "step 3, head 1, next 1:4 Increment the byte at the current location\n\
+    ///     \x20     0: 0x41   65 'A'\n\
+    ///     >     1: 0x01    1\n\
+    ///     \x20     2: 0x00    0\n"
+    /// );
+    /// ```
+    pub fn render_state(&self, width: usize) -> String {
+        let mut rendered = String::new();
+        // Writing to a String can't fail
+        let _ = self.write_state(&mut rendered, width);
+        rendered
+    }
+
+    fn write_state(&self, out: &mut impl Write, width: usize) -> fmt::Result {
+        let (cells, head) = (self.cells(), self.head());
+        write!(out, "step {}, head {head}, next ", self.steps())?;
+        match self.next_instruction() {
+            Some(next) => writeln!(out, "{}", next.instruction())?,
+            None => writeln!(out, "none, the program has finished")?,
+        }
+        let start = head
+            .saturating_sub(width / 2)
+            .min(cells.len().saturating_sub(width));
+        for (index, cell) in cells.iter().enumerate().skip(start).take(width) {
+            let marker = if index == head { '>' } else { ' ' };
+            writeln!(
+                out,
+                "{marker}{index:>6}: {}",
+                self.value_format().display(cell)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Shows the step count, head, next instruction and the cells around the head, as
+/// [Machine::render_state] does. The width, if given, is how many cells to show
+impl<T: CellKind> fmt::Display for Machine<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_state(f, f.width().unwrap_or(DEFAULT_WIDTH))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+    use bft_types::{DecoratedProgram, Program};
+
+    #[test]
+    fn windows_stay_on_the_tape() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", "")).unwrap();
+        let machine: Machine<u16> = Machine::new(core::num::NonZeroUsize::new(4), false, &prog);
+        let rendered = format!("{machine:2}");
+        assert_eq!(
+            rendered,
+            "step 0, head 0, next none, the program has finished\n\
+             >     0: 0x0000    0\n\
+             \x20     1: 0x0000    0\n"
+        );
+        assert_eq!(format!("{machine}").lines().count(), 5);
+    }
+}
@@ -0,0 +1,218 @@
+//! Time series of how a program runs, for plotting
+//!
+//! A [Sampler] is a [Tracer] that records the step count, head position and how many cells have
+//! been written every so many steps, along with how often each cell was written. The samples can
+//! be written as CSV or JSON. This needs the `std` feature, for the clock.
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::bytecode::Op;
+use crate::trace::{Io, Step, Tracer};
+use crate::Machine;
+
+/// The state of a run at one moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    /// How long the run had been going
+    pub time: Duration,
+    /// How many operations had been executed
+    pub step: u64,
+    pub head: usize,
+    /// How many times a cell had been written, counting every cell
+    pub writes: u64,
+    pub output_bytes: u64,
+}
+
+/// Takes a [Sample] every so many steps
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    interval: u64,
+    start: Instant,
+    samples: Vec<Sample>,
+    /// How many times each cell has been written, by index
+    cell_writes: Vec<u64>,
+    writes: u64,
+    output_bytes: u64,
+}
+
+impl Sampler {
+    /// Makes a sampler that takes a sample every `interval` steps, starting with the state before
+    /// the first, and starts the clock
+    pub fn new(interval: u64) -> Sampler {
+        Sampler {
+            interval: interval.max(1),
+            start: Instant::now(),
+            samples: Vec::new(),
+            cell_writes: Vec::new(),
+            writes: 0,
+            output_bytes: 0,
+        }
+    }
+
+    /// Every sample so far, oldest first
+    pub fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    /// How many times each cell has been written, by index. Cells past the end were never written
+    pub fn cell_writes(&self) -> &[u64] {
+        &self.cell_writes
+    }
+
+    /// Takes a last sample of the Machine once it has stopped, unless one was just taken
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::sample::Sampler;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "++>+")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// let mut sampler = Sampler::new(2);
+    /// interp.run_traced(&mut std::io::empty(), &mut std::io::sink(), &mut sampler).unwrap();
+    /// sampler.finish(&interp);
+    /// let steps: Vec<u64> = sampler.samples().iter().map(|sample| sample.step).collect();
+    /// assert_eq!(steps, [0, 2, 4]);
+    /// assert_eq!(sampler.cell_writes(), [2, 1]);
+    /// ```
+    pub fn finish<T>(&mut self, machine: &Machine<'_, T>) {
+        if self.samples.last().map(|sample| sample.step) != Some(machine.steps()) {
+            self.samples.push(Sample {
+                time: self.start.elapsed(),
+                step: machine.steps(),
+                head: machine.head(),
+                writes: self.writes,
+                output_bytes: self.output_bytes,
+            });
+        }
+    }
+
+    /// Writes the samples as CSV, with a header row
+    pub fn write_csv(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "time,step,head,writes,output_bytes")?;
+        for sample in &self.samples {
+            writeln!(
+                out,
+                "{},{},{},{},{}",
+                sample.time.as_secs_f64(),
+                sample.step,
+                sample.head,
+                sample.writes,
+                sample.output_bytes
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the number of times each written cell was written as CSV, with a header row
+    pub fn write_cell_writes_csv(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "cell,writes")?;
+        for (cell, writes) in self.written_cells() {
+            writeln!(out, "{cell},{writes}")?;
+        }
+        Ok(())
+    }
+
+    /// Writes the samples and the cell write counts as one JSON object
+    pub fn write_json(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, r#"{{"interval":{},"samples":["#, self.interval)?;
+        for (i, sample) in self.samples.iter().enumerate() {
+            write!(
+                out,
+                r#"{}{{"time":{},"step":{},"head":{},"writes":{},"output_bytes":{}}}"#,
+                if i == 0 { "" } else { "," },
+                sample.time.as_secs_f64(),
+                sample.step,
+                sample.head,
+                sample.writes,
+                sample.output_bytes
+            )?;
+        }
+        write!(out, r#"],"cell_writes":["#)?;
+        for (i, (cell, writes)) in self.written_cells().enumerate() {
+            write!(
+                out,
+                r#"{}{{"cell":{cell},"writes":{writes}}}"#,
+                if i == 0 { "" } else { "," }
+            )?;
+        }
+        writeln!(out, "]}}")
+    }
+
+    fn written_cells(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.cell_writes
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, writes)| writes > 0)
+    }
+}
+
+impl<T> Tracer<T> for Sampler {
+    fn step(&mut self, step: &Step<'_, T>) {
+        // A tracer sees each step before it runs, so this is the state after the one before
+        if (step.step - 1).is_multiple_of(self.interval) {
+            self.samples.push(Sample {
+                time: self.start.elapsed(),
+                step: step.step - 1,
+                head: step.head,
+                writes: self.writes,
+                output_bytes: self.output_bytes,
+            });
+        }
+        if matches!(step.op, Op::Add(_) | Op::Clear | Op::Input) {
+            if step.head >= self.cell_writes.len() {
+                self.cell_writes.resize(step.head + 1, 0);
+            }
+            self.cell_writes[step.head] += 1;
+            self.writes += 1;
+        }
+    }
+
+    fn output(&mut self, _io: &Io) {
+        self.output_bytes += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::{DecoratedProgram, Program};
+
+    #[test]
+    fn samples_are_written_as_csv_and_json() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", "+++[>+.<-]")).unwrap();
+        let bytecode = crate::bytecode::Bytecode::compile(&prog);
+        let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+        let mut sampler = Sampler::new(5);
+        machine
+            .run_bytecode_traced(&bytecode, &mut io::empty(), &mut io::sink(), &mut sampler)
+            .unwrap();
+        sampler.finish(&machine);
+        assert_eq!(sampler.cell_writes(), [4, 3]);
+
+        let mut csv = Vec::new();
+        sampler.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let rows: Vec<Vec<&str>> = csv
+            .lines()
+            .map(|row| row.split(',').skip(1).collect())
+            .collect();
+        assert_eq!(rows[0], ["step", "head", "writes", "output_bytes"]);
+        assert_eq!(rows.last().unwrap(), &["20", "0", "7", "3"]);
+
+        let mut cells = Vec::new();
+        sampler.write_cell_writes_csv(&mut cells).unwrap();
+        assert_eq!(cells, b"cell,writes\n0,4\n1,3\n");
+        let mut json = Vec::new();
+        sampler.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with(r#"{"interval":5,"samples":[{"time":"#));
+        assert!(json.ends_with(
+            r#""cell_writes":[{"cell":0,"writes":4},{"cell":1,"writes":3}]}
+"#
+        ));
+    }
+}
@@ -0,0 +1,434 @@
+//! Saved Machine states, which let a long run stop and carry on later, even in another release
+//!
+//! A [SavedState] holds what a Machine needs to carry on from where it stopped: the tape, the
+//! head, the next instruction, and the counts its limits apply to, along with the fingerprint of
+//! the program and the width of its cells, so that it isn't loaded into the wrong Machine. To carry
+//! on with cells of another width, [convert](SavedState::convert) the state first.
+//!
+//! The bytes start with two versions: the one that wrote them, and the oldest one that can read
+//! them. A later release that only adds header fields leaves the second alone, so older releases
+//! skip the fields they don't know and still load the state; one that changes the meaning of
+//! anything raises it, so they refuse the state clearly instead of misreading it.
+//!
+//! ```text
+//! "BFS\0"                  magic
+//! u16                      the version that wrote the state
+//! u16                      the oldest version that can read it
+//! u32                      the length of the header fields that follow
+//!   u8                     the width of a cell, in bytes
+//!   u64                    the program's fingerprint
+//!   u64                    the head
+//!   u64                    the index of the next instruction
+//!   u64                    the steps taken
+//!   u64                    the bytes written
+//! u64                      the length of the tape
+//! u64                      how many cells are stored, the rest being zero
+//! [width bytes]            each stored cell
+//! ```
+//!
+//! Every number is little-endian.
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use bft_types::fingerprint::Fingerprint;
+
+use thiserror::Error;
+
+use crate::{CellKind, Machine};
+
+/// Identifies a file as a saved bft Machine state
+const MAGIC: &[u8; 4] = b"BFS\0";
+
+/// The version of the format written by [SavedState::to_bytes]
+///
+/// This changes whenever the format does. Readers of this version can load any state whose
+/// oldest readable version is this or earlier.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// How many bytes of header fields version 1 has
+const HEADER_LENGTH: usize = 1 + 5 * 8;
+
+/// Errors that may occur while loading a saved state
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum StateError {
+    #[error("Not a saved state")]
+    BadMagic,
+    #[error(
+        "The state was saved in format version {0} and needs version {1} to read it, \
+         but only version {FORMAT_VERSION} is supported"
+    )]
+    UnsupportedVersion(u16, u16),
+    #[error("The saved state ended unexpectedly")]
+    Truncated,
+    #[error("The state was saved with {saved}-bit cells, not {expected}-bit ones")]
+    CellWidth { saved: usize, expected: usize },
+    #[error("Cell {cell} holds {value}, which doesn't fit in {bits}-bit cells")]
+    TooWide {
+        cell: usize,
+        value: u64,
+        bits: usize,
+    },
+    #[error("The saved head, cell {0}, is past the end of the tape")]
+    BadHead(usize),
+    #[error("The state was saved from a different program")]
+    ProgramMismatch,
+    #[error("The saved next instruction, {0}, is past the end of the program")]
+    BadInstruction(usize),
+}
+
+/// Everything a Machine needs to carry on running from where it was saved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedState<T> {
+    /// The fingerprint of the program the Machine was running
+    pub fingerprint: Fingerprint,
+    pub cells: Vec<T>,
+    pub head: usize,
+    /// The index of the instruction to execute next
+    pub next: usize,
+    pub steps: u64,
+    pub output_bytes: u64,
+}
+
+impl<T: CellKind> SavedState<T> {
+    /// Writes the state out in the format described in the [module docs](self)
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::state::SavedState;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+>++>+++")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u16> = bft_interp::Machine::new(None, false, &prog);
+    /// for _ in 0..4 {
+    ///     interp.step(&mut &[][..], &mut Vec::new()).unwrap();
+    /// }
+    /// let bytes = interp.save_state().to_bytes();
+    /// let loaded: SavedState<u16> = SavedState::from_bytes(&bytes).unwrap();
+    /// assert_eq!((loaded.head, loaded.next, loaded.steps), (1, 4, 4));
+    /// assert_eq!(loaded.cells[..3], [1, 2, 0]);
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let width = size_of::<T>();
+        let stored = self.cells.len()
+            - self
+                .cells
+                .iter()
+                .rev()
+                .take_while(|cell| cell.is_zero())
+                .count();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(HEADER_LENGTH as u32).to_le_bytes());
+        bytes.push(width as u8);
+        for field in [
+            self.fingerprint.as_u64(),
+            self.head as u64,
+            self.next as u64,
+            self.steps,
+            self.output_bytes,
+            self.cells.len() as u64,
+            stored as u64,
+        ] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        for cell in &self.cells[..stored] {
+            bytes.extend_from_slice(&cell.get_full_value().to_le_bytes()[..width]);
+        }
+        bytes
+    }
+
+    /// Loads a state written by [SavedState::to_bytes], by this release or another
+    ///
+    /// The state must have been saved with cells as wide as `T`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<SavedState<T>, StateError> {
+        SavedState::read(bytes, false)
+    }
+
+    /// Loads a state like [SavedState::from_bytes], converting its cells from whatever width
+    /// they were saved with, as [SavedState::convert] does
+    pub fn from_bytes_converting(bytes: &[u8]) -> Result<SavedState<T>, StateError> {
+        SavedState::read(bytes, true)
+    }
+
+    /// Converts the state to one with cells of another width, so that a run can carry on with
+    /// wider or narrower cells than it started with
+    ///
+    /// Widening always succeeds. Narrowing fails if any cell holds a value too big for the new
+    /// cells, rather than losing its top bits.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::state::{SavedState, StateError};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "-[->+<]")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// interp.step(&mut &[][..], &mut Vec::new()).unwrap();
+    /// let wide: SavedState<u16> = interp.save_state().convert().unwrap();
+    /// assert_eq!(wide.cells[0], 255);
+    ///
+    /// let mut wider: bft_interp::Machine<u16> = bft_interp::Machine::new(None, false, &prog);
+    /// wider.restore_state(wide.clone()).unwrap();
+    /// wider.run(&mut &[][..], &mut Vec::new()).unwrap();
+    /// assert_eq!(wider.cells()[..2], [0, 255]);
+    ///
+    /// let mut big = wide;
+    /// big.cells[1] = 256;
+    /// assert_eq!(
+    ///     big.convert::<u8>(),
+    ///     Err(StateError::TooWide { cell: 1, value: 256, bits: 8 })
+    /// );
+    /// ```
+    pub fn convert<U: CellKind>(self) -> Result<SavedState<U>, StateError> {
+        let cells = self
+            .cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| cell_from(index, cell.get_full_value()))
+            .collect::<Result<_, _>>()?;
+        Ok(SavedState {
+            fingerprint: self.fingerprint,
+            cells,
+            head: self.head,
+            next: self.next,
+            steps: self.steps,
+            output_bytes: self.output_bytes,
+        })
+    }
+
+    /// Reads a state, with cells of any width if `converting`, otherwise only as wide as `T`
+    fn read(bytes: &[u8], converting: bool) -> Result<SavedState<T>, StateError> {
+        let mut reader = Reader { bytes, offset: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = u16::from_le_bytes(reader.array()?);
+        let readable_by = u16::from_le_bytes(reader.array()?);
+        if readable_by > FORMAT_VERSION {
+            return Err(StateError::UnsupportedVersion(version, readable_by));
+        }
+        let header_length = u32::from_le_bytes(reader.array()?) as usize;
+        let mut header = Reader {
+            bytes: reader.take(header_length)?,
+            offset: 0,
+        };
+        let [width] = header.array()?;
+        let width = width as usize;
+        if !(1..=size_of::<u64>()).contains(&width) || !converting && width != size_of::<T>() {
+            return Err(StateError::CellWidth {
+                saved: width * 8,
+                expected: size_of::<T>() * 8,
+            });
+        }
+        let mut field = || Ok::<_, StateError>(u64::from_le_bytes(header.array()?));
+        let fingerprint = Fingerprint::from(field()?);
+        let head = field()? as usize;
+        let next = field()? as usize;
+        let steps = field()?;
+        let output_bytes = field()?;
+        // Any fields after these were added by later versions, and are skipped
+
+        let length = u64::from_le_bytes(reader.array()?) as usize;
+        let stored = u64::from_le_bytes(reader.array()?) as usize;
+        if head >= length {
+            return Err(StateError::BadHead(head));
+        }
+        if stored > length {
+            return Err(StateError::Truncated);
+        }
+        let cells = reader.take(stored.checked_mul(width).ok_or(StateError::Truncated)?)?;
+        let mut tape = Vec::with_capacity(length);
+        for (index, bytes) in cells.chunks_exact(width).enumerate() {
+            let mut value = [0; 8];
+            value[..width].copy_from_slice(bytes);
+            tape.push(cell_from(index, u64::from_le_bytes(value))?);
+        }
+        tape.resize(length, T::default());
+        Ok(SavedState {
+            fingerprint,
+            cells: tape,
+            head,
+            next,
+            steps,
+            output_bytes,
+        })
+    }
+}
+
+/// Makes a cell holding `value`, which was at `index` on the tape, if it fits
+fn cell_from<T: CellKind>(index: usize, value: u64) -> Result<T, StateError> {
+    let mut cell = T::default();
+    cell.set_full_value(value);
+    if cell.get_full_value() != value {
+        return Err(StateError::TooWide {
+            cell: index,
+            value,
+            bits: size_of::<T>() * 8,
+        });
+    }
+    Ok(cell)
+}
+
+impl<T: CellKind> Machine<'_, T> {
+    /// Saves the tape, the head and where the program has got to, so that a Machine running the
+    /// same program can [carry on](Machine::restore_state) from here
+    pub fn save_state(&self) -> SavedState<T> {
+        SavedState {
+            fingerprint: self.prog.fingerprint(),
+            cells: self.cells.clone(),
+            head: self.head,
+            next: self.next,
+            steps: self.steps,
+            output_bytes: self.output_bytes,
+        }
+    }
+
+    /// Puts the Machine in a saved state, so that [Machine::step] and [Machine::run] carry on
+    /// from where it was saved
+    ///
+    /// The state must have been saved from a Machine running the same program. The Machine's
+    /// settings are kept, as they are by [Machine::reset].
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::state::{SavedState, StateError};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",[.,]")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// let (mut input, mut output) = (&b"ab"[..], Vec::new());
+    /// for _ in 0..3 {
+    ///     interp.step(&mut input, &mut output).unwrap();
+    /// }
+    /// assert_eq!(output, b"a");
+    /// let saved = interp.save_state().to_bytes();
+    ///
+    /// let mut resumed: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// resumed.restore_state(SavedState::from_bytes(&saved).unwrap()).unwrap();
+    /// resumed.run(&mut &b"bc\0"[..], &mut output).unwrap();
+    /// assert_eq!(output, b"abc");
+    ///
+    /// let other = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+")
+    /// ).unwrap();
+    /// let mut wrong: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &other);
+    /// assert_eq!(
+    ///     wrong.restore_state(SavedState::from_bytes(&saved).unwrap()),
+    ///     Err(StateError::ProgramMismatch)
+    /// );
+    /// ```
+    pub fn restore_state(&mut self, state: SavedState<T>) -> Result<(), StateError> {
+        if state.fingerprint != self.prog.fingerprint() {
+            return Err(StateError::ProgramMismatch);
+        }
+        if state.next > self.prog.decorated_instructions().len() {
+            return Err(StateError::BadInstruction(state.next));
+        }
+        if state.head >= state.cells.len() {
+            return Err(StateError::BadHead(state.head));
+        }
+        self.reset();
+        self.cells = state.cells;
+        self.head = state.head;
+        self.next = state.next;
+        self.steps = state.steps;
+        self.output_bytes = state.output_bytes;
+        Ok(())
+    }
+}
+
+/// Reads fixed-size pieces from a byte slice
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], StateError> {
+        let taken = self
+            .offset
+            .checked_add(length)
+            .and_then(|end| self.bytes.get(self.offset..end))
+            .ok_or(StateError::Truncated)?;
+        self.offset += length;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        Ok(self.take(N)?.try_into().expect("Took exactly N bytes"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> SavedState<u16> {
+        SavedState {
+            fingerprint: Fingerprint::from(0x1234),
+            cells: alloc::vec![300, 0, 7, 0, 0],
+            head: 3,
+            next: 2,
+            steps: 10,
+            output_bytes: 1,
+        }
+    }
+
+    #[test]
+    fn states_load_across_versions() {
+        let bytes = state().to_bytes();
+        // The trailing zero cells aren't stored
+        assert_eq!(bytes.len(), 4 + 2 + 2 + 4 + HEADER_LENGTH + 16 + 3 * 2);
+        assert_eq!(SavedState::from_bytes(&bytes), Ok(state()));
+        assert_eq!(
+            SavedState::<u8>::from_bytes(&bytes),
+            Err(StateError::CellWidth {
+                saved: 16,
+                expected: 8
+            })
+        );
+
+        let converted = SavedState::<u32>::from_bytes_converting(&bytes).unwrap();
+        assert_eq!(converted.cells, [300, 0, 7, 0, 0]);
+        assert_eq!(
+            SavedState::<u8>::from_bytes_converting(&bytes),
+            Err(StateError::TooWide {
+                cell: 0,
+                value: 300,
+                bits: 8
+            })
+        );
+
+        // A later version that adds a header field, but can still be read by this one
+        let mut later = bytes.clone();
+        later[4..6].copy_from_slice(&2u16.to_le_bytes());
+        later[8..12].copy_from_slice(&(HEADER_LENGTH as u32 + 4).to_le_bytes());
+        later.splice(12 + HEADER_LENGTH..12 + HEADER_LENGTH, [0xff; 4]);
+        assert_eq!(SavedState::from_bytes(&later), Ok(state()));
+
+        // A later version that this one can't read
+        let mut incompatible = bytes.clone();
+        incompatible[4..8].copy_from_slice(&[3, 0, 3, 0]);
+        assert_eq!(
+            SavedState::<u16>::from_bytes(&incompatible),
+            Err(StateError::UnsupportedVersion(3, 3))
+        );
+
+        assert_eq!(
+            SavedState::<u16>::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(StateError::Truncated)
+        );
+        let mut lost = bytes.clone();
+        lost[13 + 8..13 + 16].copy_from_slice(&5u64.to_le_bytes());
+        assert_eq!(
+            SavedState::<u16>::from_bytes(&lost),
+            Err(StateError::BadHead(5))
+        );
+    }
+}
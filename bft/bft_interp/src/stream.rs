@@ -0,0 +1,107 @@
+//! Output handed over in chunks as a program produces it
+//!
+//! [Machine::output_chunks] only runs the program while the next chunk is being asked for, so a
+//! slow consumer, such as a web page showing the output of a long-running program, pauses the
+//! program rather than letting output pile up.
+
+use alloc::vec::Vec;
+
+use crate::io::Read;
+use crate::{CellKind, Machine, VMError};
+
+/// The most output kept back before a chunk is handed over, however the Machine is set to flush
+pub const MAX_CHUNK: usize = 4096;
+
+/// An iterator over the output of a running program, made by [Machine::output_chunks]
+///
+/// A chunk ends wherever the Machine would flush its output, or after [MAX_CHUNK] bytes. If the
+/// program fails, the output before the failure comes first and then the error, after which the
+/// iterator ends.
+pub struct OutputChunks<'m, 'a, T, R> {
+    machine: &'m mut Machine<'a, T>,
+    input: R,
+    chunk: Vec<u8>,
+    error: Option<VMError>,
+    done: bool,
+}
+
+impl<'a, T: CellKind> Machine<'a, T> {
+    /// Runs the program a chunk of output at a time, reading from `input`
+    ///
+    /// Like [Machine::step], this carries on from wherever the program last stopped.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::{Flush, Machine};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ">++++++++++[<+++++>-]<.+.>++++++++++.<.")
+    /// ).unwrap();
+    /// let mut interp: Machine<u8> = Machine::new(None, false, &prog);
+    /// interp.set_flush(Flush::Line);
+    /// let chunks: Vec<Vec<u8>> = interp.output_chunks(&[][..]).map(Result::unwrap).collect();
+    /// assert_eq!(chunks, [b"23\n".to_vec(), b"3".to_vec()]);
+    /// ```
+    pub fn output_chunks<R: Read>(&mut self, input: R) -> OutputChunks<'_, 'a, T, R> {
+        OutputChunks {
+            machine: self,
+            input,
+            chunk: Vec::new(),
+            error: None,
+            done: false,
+        }
+    }
+}
+
+impl<T: CellKind, R: Read> Iterator for OutputChunks<'_, '_, T, R> {
+    type Item = Result<Vec<u8>, VMError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.error.take() {
+            return Some(Err(error));
+        }
+        while !self.done {
+            match self.machine.step(&mut self.input, &mut self.chunk) {
+                Ok(stepped) => self.done = !stepped,
+                Err(error) => {
+                    self.done = true;
+                    if self.chunk.is_empty() {
+                        return Some(Err(error));
+                    }
+                    self.error = Some(error);
+                }
+            }
+            let flushed = !self.machine.unflushed || self.chunk.len() >= MAX_CHUNK;
+            if !self.chunk.is_empty() && (flushed || self.done) {
+                self.machine.unflushed = false;
+                return Some(Ok(core::mem::take(&mut self.chunk)));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::{DecoratedProgram, Program};
+
+    #[test]
+    fn chunks_follow_flushing_and_errors() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", ",[.,]")).unwrap();
+        let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+        let mut chunks = machine.output_chunks(&b"ab"[..]);
+        assert_eq!(chunks.next().unwrap().unwrap(), b"a");
+        assert_eq!(chunks.next().unwrap().unwrap(), b"b");
+        assert!(matches!(chunks.next(), Some(Err(VMError::IOError { .. }))));
+        assert!(chunks.next().is_none());
+
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", "+++[.-]")).unwrap();
+        let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+        assert_eq!(machine.output_chunks(&[][..]).count(), 3);
+        let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+        machine.set_flush(crate::Flush::Buffered);
+        let chunks: Vec<_> = machine.output_chunks(&[][..]).map(Result::unwrap).collect();
+        assert_eq!(chunks, [vec![3, 2, 1]]);
+    }
+}
@@ -0,0 +1,210 @@
+//! Superoptimisation of straight-line code
+//!
+//! A run of `+`, `-`, `<` and `>` does nothing but add a constant to some cells and move the head.
+//! Given just that effect, this finds the shortest possible sequence of instructions with the same
+//! effect, which is often much shorter than the code a generator or a human wrote.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use bft_types::RawInstruction;
+
+/// The effect of a straight-line run of instructions
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fragment {
+    /// The amount (wrapping) added to each cell, keyed by offset from the starting head position
+    deltas: BTreeMap<isize, u8>,
+    /// How far the head moves overall
+    shift: isize,
+}
+
+impl Fragment {
+    /// Works out the effect of some instructions
+    ///
+    /// Returns None if any of them do I/O or loop, as those can't be summarised as a Fragment.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::superopt::Fragment;
+    /// # use bft_types::RawInstruction;
+    /// let code: Vec<RawInstruction> = "+>++<-".bytes().filter_map(RawInstruction::from_byte).collect();
+    /// let fragment = Fragment::from_instructions(&code).unwrap();
+    /// assert_eq!(fragment.delta(0), 0);
+    /// assert_eq!(fragment.delta(1), 2);
+    /// assert_eq!(fragment.shift(), 0);
+    /// ```
+    pub fn from_instructions(instructions: &[RawInstruction]) -> Option<Fragment> {
+        let mut fragment = Fragment::default();
+        let mut head = 0;
+        for instruction in instructions {
+            match instruction {
+                RawInstruction::IncrementDataPointer => head += 1,
+                RawInstruction::DecrementDataPointer => head -= 1,
+                RawInstruction::IncrementByte => fragment.add(head, 1),
+                RawInstruction::DecrementByte => fragment.add(head, u8::MAX),
+                _ => return None,
+            }
+        }
+        fragment.shift = head;
+        Some(fragment)
+    }
+
+    /// Returns the amount (wrapping) added to the cell at `offset` from the starting head position
+    pub fn delta(&self, offset: isize) -> u8 {
+        self.deltas.get(&offset).copied().unwrap_or(0)
+    }
+
+    /// Returns how far the head moves overall
+    pub fn shift(&self) -> isize {
+        self.shift
+    }
+
+    fn add(&mut self, offset: isize, amount: u8) {
+        let delta = self.deltas.entry(offset).or_insert(0);
+        *delta = delta.wrapping_add(amount);
+        if *delta == 0 {
+            self.deltas.remove(&offset);
+        }
+    }
+
+    /// Finds the shortest sequence of instructions with this effect
+    ///
+    /// Each changed cell is adjusted by whichever of `+` or `-` needs fewer instructions, and the
+    /// head takes the shortest route that visits every changed cell and ends in the right place.
+    /// Because the route only covers cells between the start, the end and the changed cells, the
+    /// result never moves the head anywhere the original code didn't.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::superopt::Fragment;
+    /// # use bft_types::RawInstruction;
+    /// let parse = |code: &str| -> Vec<RawInstruction> {
+    ///     code.bytes().filter_map(RawInstruction::from_byte).collect()
+    /// };
+    /// let fragment = Fragment::from_instructions(&parse(">+<<+++>>>>")).unwrap();
+    /// assert_eq!(fragment.shortest(), parse("<+++>>+>>"));
+    /// ```
+    pub fn shortest(&self) -> Vec<RawInstruction> {
+        let lowest = self.deltas.keys().copied().chain([0, self.shift]).min();
+        let highest = self.deltas.keys().copied().chain([0, self.shift]).max();
+        let (lowest, highest) = (lowest.unwrap_or(0), highest.unwrap_or(0));
+
+        // Either sweep left first and then right, or right first and then left
+        let left_first = -lowest + (highest - lowest) + (highest - self.shift);
+        let right_first = highest + (highest - lowest) + (self.shift - lowest);
+        let route = if left_first <= right_first {
+            [lowest, highest, self.shift]
+        } else {
+            [highest, lowest, self.shift]
+        };
+
+        let mut code = Vec::new();
+        let mut head = 0;
+        let mut pending = self.deltas.clone();
+        adjust(&mut pending, head, &mut code);
+        for target in route {
+            while head != target {
+                if head < target {
+                    head += 1;
+                    code.push(RawInstruction::IncrementDataPointer);
+                } else {
+                    head -= 1;
+                    code.push(RawInstruction::DecrementDataPointer);
+                }
+                adjust(&mut pending, head, &mut code);
+            }
+        }
+        code
+    }
+}
+
+/// Emits the changes for the cell at `head`, if it still needs any
+fn adjust(pending: &mut BTreeMap<isize, u8>, head: isize, code: &mut Vec<RawInstruction>) {
+    if let Some(delta) = pending.remove(&head) {
+        if delta <= 128 {
+            code.extend((0..delta).map(|_| RawInstruction::IncrementByte));
+        } else {
+            code.extend((delta..=u8::MAX).map(|_| RawInstruction::DecrementByte));
+        }
+    }
+}
+
+/// Replaces every straight-line run of instructions with the shortest equivalent
+///
+/// Loops and I/O are left exactly as they were, so the result behaves the same as the original
+/// on any machine with 8-bit wrapping cells.
+///
+/// # Examples
+/// ```
+/// # use bft_interp::superopt;
+/// # use bft_types::RawInstruction;
+/// let parse = |code: &str| -> Vec<RawInstruction> {
+///     code.bytes().filter_map(RawInstruction::from_byte).collect()
+/// };
+/// assert_eq!(superopt::superoptimise(&parse("+-+>><[<+->-]")), parse("+>[-]"));
+/// ```
+pub fn superoptimise(instructions: &[RawInstruction]) -> Vec<RawInstruction> {
+    let mut optimised = Vec::new();
+    for run in instructions.split_inclusive(|i| Fragment::from_instructions(&[*i]).is_none()) {
+        let (straight, rest) = match run.split_last() {
+            Some((last, straight)) if Fragment::from_instructions(&[*last]).is_none() => {
+                (straight, Some(last))
+            }
+            _ => (run, None),
+        };
+        let fragment = Fragment::from_instructions(straight).expect("Runs are straight-line");
+        optimised.extend(fragment.shortest());
+        optimised.extend(rest);
+    }
+    optimised
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRAIGHT_LINE: [RawInstruction; 4] = [
+        RawInstruction::IncrementDataPointer,
+        RawInstruction::DecrementDataPointer,
+        RawInstruction::IncrementByte,
+        RawInstruction::DecrementByte,
+    ];
+
+    /// Every straight-line sequence of exactly `length` instructions
+    fn every_sequence(length: usize) -> Vec<Vec<RawInstruction>> {
+        let mut sequences = vec![Vec::new()];
+        for _ in 0..length {
+            sequences = sequences
+                .into_iter()
+                .flat_map(|sequence| {
+                    STRAIGHT_LINE.iter().map(move |instruction| {
+                        let mut longer = sequence.clone();
+                        longer.push(*instruction);
+                        longer
+                    })
+                })
+                .collect();
+        }
+        sequences
+    }
+
+    #[test]
+    fn shortest_is_minimal() {
+        // Compare against an exhaustive search over every short program
+        for length in 0..=6 {
+            for sequence in every_sequence(length) {
+                let fragment = Fragment::from_instructions(&sequence).unwrap();
+                let shortest = fragment.shortest();
+                assert!(shortest.len() <= length, "{fragment:?} gave {shortest:?}");
+                assert_eq!(Fragment::from_instructions(&shortest).unwrap(), fragment);
+            }
+        }
+    }
+
+    #[test]
+    fn large_deltas_wrap() {
+        let code = vec![RawInstruction::IncrementByte; 250];
+        let fragment = Fragment::from_instructions(&code).unwrap();
+        assert_eq!(fragment.shortest(), vec![RawInstruction::DecrementByte; 6]);
+    }
+}
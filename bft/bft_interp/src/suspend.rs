@@ -0,0 +1,218 @@
+//! Running a program whose input arrives a piece at a time
+//!
+//! A GUI or a server can't hand the Machine a reader that blocks until the user types something.
+//! A [Suspendable] Machine instead stops when the program reaches a `,` and no input has been
+//! provided, returning [Status::NeedsInput]. The host gives it input with
+//! [Suspendable::provide_input] whenever some arrives, and resumes the program from there.
+//!
+//! Output can be pulled the same way: [Suspendable::pull] stops at each `.` with
+//! [Status::ProducedOutput], so the host takes output when it's ready for it rather than
+//! providing somewhere to write it.
+
+use alloc::collections::VecDeque;
+
+use bft_types::RawInstruction;
+
+use crate::io::{Error, Write};
+use crate::{CellKind, Machine, VMError};
+
+/// Where a [Suspendable] program stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// The program has run to the end
+    Finished,
+    /// The program is at a `,`, and all the input provided so far has been read
+    NeedsInput,
+    /// The program took as many steps as it was allowed, and has more to do
+    Running,
+    /// The program wrote this byte, when its output is being pulled
+    ProducedOutput(u8),
+}
+
+/// A Machine that waits for input to be provided rather than reading it
+pub struct Suspendable<'a, T> {
+    machine: Machine<'a, T>,
+    /// Input provided but not read yet
+    input: VecDeque<u8>,
+    /// Whether the host has said no more input is coming
+    ended: bool,
+}
+
+impl<'a, T: CellKind> Suspendable<'a, T> {
+    /// Takes over `machine`, which carries on from wherever it last stopped
+    pub fn new(machine: Machine<'a, T>) -> Suspendable<'a, T> {
+        Suspendable {
+            machine,
+            input: VecDeque::new(),
+            ended: false,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine<'a, T> {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine<'a, T> {
+        &mut self.machine
+    }
+
+    /// Gives back the Machine, dropping any input it hasn't read
+    pub fn into_machine(self) -> Machine<'a, T> {
+        self.machine
+    }
+
+    /// Adds `bytes` to the end of the input
+    pub fn provide_input(&mut self, bytes: &[u8]) {
+        self.input.extend(bytes);
+    }
+
+    /// Says no more input is coming, so that a `,` after the last byte follows the Machine's
+    /// [Eof](crate::policy::Eof) policy rather than waiting
+    pub fn end_input(&mut self) {
+        self.ended = true;
+    }
+
+    /// How many bytes of input have been provided but not read yet
+    pub fn input_left(&self) -> usize {
+        self.input.len()
+    }
+
+    /// Whether the program can't go on until it's given more input
+    pub fn needs_input(&self) -> bool {
+        self.input.is_empty()
+            && !self.ended
+            && self
+                .machine
+                .next_instruction()
+                .is_some_and(|next| *next.instruction().instruction() == RawInstruction::GetByte)
+    }
+
+    /// Runs the program until it finishes or needs input, writing its output to `output`
+    ///
+    /// Output is flushed before the program stops for input, so any prompt can be shown.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::suspend::{Status, Suspendable};
+    /// # use bft_interp::Machine;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",[+.,]")
+    /// ).unwrap();
+    /// let mut program = Suspendable::new(Machine::<u8>::new(None, false, &prog));
+    /// let mut output = Vec::new();
+    /// assert_eq!(program.resume(&mut output).unwrap(), Status::NeedsInput);
+    /// program.provide_input(b"HAL");
+    /// assert_eq!(program.resume(&mut output).unwrap(), Status::NeedsInput);
+    /// assert_eq!(output, b"IBM");
+    /// program.provide_input(&[0]);
+    /// assert_eq!(program.resume(&mut output).unwrap(), Status::Finished);
+    /// ```
+    pub fn resume(&mut self, output: &mut impl Write) -> Result<Status, VMError> {
+        self.resume_for(u64::MAX, output)
+    }
+
+    /// Runs the program like [Suspendable::resume], but for at most `steps` steps, so that a host
+    /// can get on with other things while a long program runs
+    pub fn resume_for(&mut self, steps: u64, output: &mut impl Write) -> Result<Status, VMError> {
+        for _ in 0..steps {
+            if self.needs_input() {
+                self.machine.flush_output(output)?;
+                return Ok(Status::NeedsInput);
+            }
+            let (front, _) = self.input.as_slices();
+            let mut input = front;
+            let stepped = self.machine.step(&mut input, output);
+            let read = front.len() - input.len();
+            self.input.drain(..read);
+            if !stepped? {
+                return Ok(Status::Finished);
+            }
+        }
+        Ok(Status::Running)
+    }
+
+    /// Runs the program until it writes a byte, finishes or needs input
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::suspend::{Status, Suspendable};
+    /// # use bft_interp::Machine;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "++++++[>++++++++<-]>.+.,")
+    /// ).unwrap();
+    /// let mut program = Suspendable::new(Machine::<u8>::new(None, false, &prog));
+    /// assert_eq!(program.pull().unwrap(), Status::ProducedOutput(b'0'));
+    /// assert_eq!(program.pull().unwrap(), Status::ProducedOutput(b'1'));
+    /// assert_eq!(program.pull().unwrap(), Status::NeedsInput);
+    /// ```
+    pub fn pull(&mut self) -> Result<Status, VMError> {
+        self.pull_for(u64::MAX)
+    }
+
+    /// Runs the program like [Suspendable::pull], but for at most `steps` steps
+    pub fn pull_for(&mut self, steps: u64) -> Result<Status, VMError> {
+        for _ in 0..steps {
+            let mut produced = Produced(None);
+            match self.resume_for(1, &mut produced)? {
+                Status::Running => {}
+                status => return Ok(status),
+            }
+            if let Some(byte) = produced.0 {
+                return Ok(Status::ProducedOutput(byte));
+            }
+        }
+        Ok(Status::Running)
+    }
+}
+
+/// Somewhere to put the byte a single step writes
+struct Produced(Option<u8>);
+
+impl Write for Produced {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let Some(&byte) = buf.first() else {
+            return Ok(0);
+        };
+        self.0 = Some(byte);
+        Ok(1)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::{DecoratedProgram, Program};
+
+    #[test]
+    fn input_is_read_across_resumes_until_it_ends() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", ",[.,]")).unwrap();
+        let mut program = Suspendable::new(Machine::<u8>::new(None, false, &prog));
+        let mut output = Vec::new();
+        assert_eq!(
+            program.resume_for(3, &mut output).unwrap(),
+            Status::NeedsInput
+        );
+        assert_eq!(program.machine().steps(), 0);
+
+        // Input that wraps around the end of the queue is read in order
+        program.provide_input(b"abc");
+        assert_eq!(program.resume_for(4, &mut output).unwrap(), Status::Running);
+        program.provide_input(b"de");
+        assert_eq!(program.resume(&mut output).unwrap(), Status::NeedsInput);
+        assert_eq!(output, b"abcde");
+        assert_eq!(program.input_left(), 0);
+
+        program.end_input();
+        assert!(!program.needs_input());
+        assert!(matches!(
+            program.resume(&mut output),
+            Err(VMError::IOError { .. })
+        ));
+    }
+}
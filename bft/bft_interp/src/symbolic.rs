@@ -0,0 +1,425 @@
+//! Symbolic execution of Brainfuck programs
+//!
+//! Instead of reading real input, every `,` produces a symbolic byte. Whenever a loop's cell
+//! depends on input, execution forks into a path where the cell was zero and one where it wasn't,
+//! recording the choice as a constraint on the input. The result is a set of paths that can be
+//! asked questions like "can this program ever seek below zero?".
+//!
+//! The instruction semantics match a growable `Machine<u8>`.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use bft_types::{DecoratedProgram, PositionedInstruction, RawInstruction};
+
+/// The value of a cell during symbolic execution
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SymbolicValue {
+    /// A value that doesn't depend on input
+    Concrete(u8),
+    /// The `index`th byte of input, plus `offset` (wrapping)
+    Input { index: usize, offset: u8 },
+}
+
+impl SymbolicValue {
+    fn add(self, amount: u8) -> SymbolicValue {
+        match self {
+            Self::Concrete(value) => Self::Concrete(value.wrapping_add(amount)),
+            Self::Input { index, offset } => Self::Input {
+                index,
+                offset: offset.wrapping_add(amount),
+            },
+        }
+    }
+}
+
+impl Default for SymbolicValue {
+    fn default() -> SymbolicValue {
+        SymbolicValue::Concrete(0)
+    }
+}
+
+impl fmt::Display for SymbolicValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Concrete(value) => write!(f, "{value}"),
+            Self::Input { index, offset: 0 } => write!(f, "in[{index}]"),
+            Self::Input { index, offset } => write!(f, "in[{index}] + {offset}"),
+        }
+    }
+}
+
+/// Limits on how far the program is explored
+#[derive(Debug, Clone, Copy)]
+pub struct Bounds {
+    /// The most instructions executed along any one path
+    pub max_steps: usize,
+    /// The most times any one path may fork on an input-dependent loop
+    pub max_branches: usize,
+    /// The most paths explored in total
+    pub max_paths: usize,
+}
+
+impl Default for Bounds {
+    fn default() -> Bounds {
+        Bounds {
+            max_steps: 100_000,
+            max_branches: 16,
+            max_paths: 1024,
+        }
+    }
+}
+
+/// How a path through the program ended
+#[derive(Debug, Clone, Copy)]
+pub enum Outcome {
+    /// The program ran off the end of its instructions
+    Halted,
+    /// The instruction tried to seek to a negative head position
+    SeekTooLow(PositionedInstruction),
+    /// The path was abandoned after reaching one of the [Bounds]
+    BoundReached,
+}
+
+/// What's known about the input bytes along one path
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Constraints {
+    /// Input bytes that must hold one exact value
+    exact: BTreeMap<usize, u8>,
+    /// Values that input bytes must not hold
+    excluded: BTreeMap<usize, BTreeSet<u8>>,
+}
+
+impl Constraints {
+    /// Returns whether input byte `index` may hold `value`
+    pub fn allows(&self, index: usize, value: u8) -> bool {
+        match self.exact.get(&index) {
+            Some(exact) => *exact == value,
+            None => !self
+                .excluded
+                .get(&index)
+                .is_some_and(|excluded| excluded.contains(&value)),
+        }
+    }
+
+    /// Returns the smallest value input byte `index` may hold
+    fn example(&self, index: usize) -> u8 {
+        (u8::MIN..=u8::MAX)
+            .find(|value| self.allows(index, *value))
+            .expect("Constraints are only kept while satisfiable")
+    }
+
+    /// Produces one concrete input of `length` bytes satisfying every constraint
+    pub fn example_input(&self, length: usize) -> Vec<u8> {
+        (0..length).map(|index| self.example(index)).collect()
+    }
+
+    fn exclude(&mut self, index: usize, value: u8) -> bool {
+        let excluded = self.excluded.entry(index).or_default();
+        excluded.insert(value);
+        excluded.len() <= u8::MAX as usize
+    }
+}
+
+/// One explored route through the program
+#[derive(Debug, Clone)]
+pub struct Path {
+    outcome: Outcome,
+    constraints: Constraints,
+    output: Vec<SymbolicValue>,
+    inputs_read: usize,
+}
+
+impl Path {
+    /// How the path ended
+    pub fn outcome(&self) -> Outcome {
+        self.outcome
+    }
+
+    /// The conditions the input must meet for the program to follow this path
+    pub fn constraints(&self) -> &Constraints {
+        &self.constraints
+    }
+
+    /// Every byte the program wrote along this path
+    pub fn output(&self) -> &[SymbolicValue] {
+        &self.output
+    }
+
+    /// How many bytes of input the program read along this path
+    pub fn inputs_read(&self) -> usize {
+        self.inputs_read
+    }
+
+    /// Produces an input that leads the program down this path
+    pub fn example_input(&self) -> Vec<u8> {
+        self.constraints.example_input(self.inputs_read)
+    }
+
+    /// Finds an input that makes this path write exactly `expected`, if there is one
+    fn input_producing(&self, expected: &[u8]) -> Option<Vec<u8>> {
+        if self.output.len() != expected.len() {
+            return None;
+        }
+        let mut constraints = self.constraints.clone();
+        for (value, byte) in self.output.iter().zip(expected) {
+            match *value {
+                SymbolicValue::Concrete(value) if value == *byte => {}
+                SymbolicValue::Concrete(_) => return None,
+                SymbolicValue::Input { index, offset } => {
+                    let needed = byte.wrapping_sub(offset);
+                    if !constraints.allows(index, needed) {
+                        return None;
+                    }
+                    constraints.exact.insert(index, needed);
+                }
+            }
+        }
+        Some(constraints.example_input(self.inputs_read))
+    }
+}
+
+/// Every path found by symbolically executing a program
+pub struct Exploration {
+    paths: Vec<Path>,
+    complete: bool,
+}
+
+impl Exploration {
+    /// Symbolically executes `prog`, exploring every path within `bounds`
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::symbolic::{Bounds, Exploration};
+    /// # use bft_types;
+    /// // Seeks left if the first byte of input is non-zero
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",[<]")
+    /// ).unwrap();
+    /// let exploration = Exploration::from_program(&prog, Bounds::default());
+    /// assert_eq!(exploration.paths().len(), 2);
+    /// assert!(exploration.input_seeking_too_low().is_some());
+    /// ```
+    pub fn from_program(prog: &DecoratedProgram, bounds: Bounds) -> Exploration {
+        let instructions: Vec<PositionedInstruction> = prog
+            .decorated_instructions()
+            .iter()
+            .map(|i| i.instruction())
+            .collect();
+        let partners = crate::matching_brackets(prog);
+
+        let mut paths = Vec::new();
+        let mut complete = true;
+        let mut pending = vec![PathState::default()];
+        while let Some(mut state) = pending.pop() {
+            if paths.len() == bounds.max_paths {
+                complete = false;
+                break;
+            }
+            let outcome = loop {
+                let Some(instruction) = instructions.get(state.ip) else {
+                    break Outcome::Halted;
+                };
+                if state.steps == bounds.max_steps {
+                    break Outcome::BoundReached;
+                }
+                state.steps += 1;
+                match instruction.instruction() {
+                    RawInstruction::IncrementDataPointer => state.head += 1,
+                    RawInstruction::DecrementDataPointer => {
+                        if state.head == 0 {
+                            break Outcome::SeekTooLow(*instruction);
+                        }
+                        state.head -= 1;
+                    }
+                    RawInstruction::IncrementByte => state.modify(|value| value.add(1)),
+                    RawInstruction::DecrementByte => state.modify(|value| value.add(u8::MAX)),
+                    RawInstruction::PutByte => state.output.push(state.current()),
+                    RawInstruction::GetByte => {
+                        let index = state.inputs_read;
+                        state.inputs_read += 1;
+                        state.modify(|_| SymbolicValue::Input { index, offset: 0 });
+                    }
+                    RawInstruction::OpenLoop | RawInstruction::CloseLoop => {
+                        let jump_if_zero = *instruction.instruction() == RawInstruction::OpenLoop;
+                        let is_zero = match state.current() {
+                            SymbolicValue::Concrete(value) => value == 0,
+                            SymbolicValue::Input { .. }
+                                if state.branches == bounds.max_branches =>
+                            {
+                                break Outcome::BoundReached;
+                            }
+                            SymbolicValue::Input { index, offset } => {
+                                // Follow the non-zero branch, leaving the zero branch for later
+                                state.branches += 1;
+                                let zero = offset.wrapping_neg();
+                                if state.constraints.allows(index, zero) {
+                                    let mut forked = state.clone();
+                                    forked.assume(index, zero);
+                                    forked.jump(jump_if_zero, true, &partners);
+                                    pending.push(forked);
+                                }
+                                if !state.constraints.exclude(index, zero) {
+                                    state.infeasible = true;
+                                    break Outcome::BoundReached;
+                                }
+                                false
+                            }
+                        };
+                        state.jump(jump_if_zero, is_zero, &partners);
+                        continue;
+                    }
+                }
+                state.ip += 1;
+            };
+            if !state.infeasible {
+                paths.push(Path {
+                    outcome,
+                    constraints: state.constraints,
+                    output: state.output,
+                    inputs_read: state.inputs_read,
+                });
+            }
+        }
+        Exploration {
+            paths,
+            complete: complete && pending.is_empty(),
+        }
+    }
+
+    /// Every path explored, in the order they finished
+    pub fn paths(&self) -> &[Path] {
+        &self.paths
+    }
+
+    /// Returns whether every path was explored to the end without hitting a bound
+    ///
+    /// If this is false, answers drawn from the exploration only cover part of the program.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+            && self
+                .paths
+                .iter()
+                .all(|path| !matches!(path.outcome, Outcome::BoundReached))
+    }
+
+    /// Finds an input that makes the program seek below zero, if any explored path does so
+    pub fn input_seeking_too_low(&self) -> Option<Vec<u8>> {
+        self.paths
+            .iter()
+            .find(|path| matches!(path.outcome, Outcome::SeekTooLow(_)))
+            .map(Path::example_input)
+    }
+
+    /// Finds an input that makes the program halt having written exactly `expected`
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::symbolic::{Bounds, Exploration};
+    /// # use bft_types;
+    /// // Prints its input plus one
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",+.")
+    /// ).unwrap();
+    /// let exploration = Exploration::from_program(&prog, Bounds::default());
+    /// assert_eq!(exploration.input_producing(b"B"), Some(b"A".to_vec()));
+    /// ```
+    pub fn input_producing(&self, expected: &[u8]) -> Option<Vec<u8>> {
+        self.paths
+            .iter()
+            .filter(|path| matches!(path.outcome, Outcome::Halted))
+            .find_map(|path| path.input_producing(expected))
+    }
+}
+
+/// Everything about one path that's still being executed
+#[derive(Clone, Default)]
+struct PathState {
+    cells: BTreeMap<usize, SymbolicValue>,
+    head: usize,
+    ip: usize,
+    steps: usize,
+    branches: usize,
+    inputs_read: usize,
+    output: Vec<SymbolicValue>,
+    constraints: Constraints,
+    /// Set when no input could lead down this path
+    infeasible: bool,
+}
+
+impl PathState {
+    fn current(&self) -> SymbolicValue {
+        self.cells.get(&self.head).copied().unwrap_or_default()
+    }
+
+    fn modify(&mut self, change: impl FnOnce(SymbolicValue) -> SymbolicValue) {
+        let value = change(self.current());
+        self.cells.insert(self.head, value);
+    }
+
+    /// Fixes input byte `index` to `value`, making every cell that depends on it concrete
+    fn assume(&mut self, index: usize, value: u8) {
+        self.constraints.exact.insert(index, value);
+        let resolve = |cell: &mut SymbolicValue| {
+            if let SymbolicValue::Input { index: i, offset } = *cell {
+                if i == index {
+                    *cell = SymbolicValue::Concrete(value.wrapping_add(offset));
+                }
+            }
+        };
+        self.cells.values_mut().for_each(resolve);
+        self.output.iter_mut().for_each(resolve);
+    }
+
+    /// Moves past a bracket, jumping to its partner if the loop condition says to
+    fn jump(&mut self, jump_if_zero: bool, is_zero: bool, partners: &[usize]) {
+        if jump_if_zero == is_zero {
+            self.ip = partners[self.ip];
+        }
+        self.ip += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::Program;
+
+    fn explore(code: &str) -> Exploration {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", code)).unwrap();
+        Exploration::from_program(&prog, Bounds::default())
+    }
+
+    #[test]
+    fn concrete_programs_have_one_path() {
+        let exploration = explore("++[>+++<-]>.");
+        assert_eq!(exploration.paths().len(), 1);
+        assert!(exploration.is_complete());
+        assert_eq!(
+            exploration.paths()[0].output(),
+            [SymbolicValue::Concrete(6)]
+        );
+    }
+
+    #[test]
+    fn safe_programs_never_seek_too_low() {
+        let exploration = explore(">,[>,]<[.<]");
+        assert!(exploration.input_seeking_too_low().is_none());
+    }
+
+    #[test]
+    fn finds_input_for_output() {
+        // Echoes the first byte, but only if the second is zero
+        let exploration = explore(",>,[<[-]>[-]]<.");
+        assert_eq!(exploration.input_producing(b"x"), Some(b"x\0".to_vec()));
+        assert_eq!(exploration.input_producing(b"xy"), None);
+    }
+
+    #[test]
+    fn branching_is_bounded() {
+        let exploration = explore("+[,]");
+        assert!(!exploration.is_complete());
+    }
+}
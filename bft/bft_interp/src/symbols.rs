@@ -0,0 +1,215 @@
+//! Symbol files, which map compiled bytecode back to the source it came from
+//!
+//! Bytecode only knows each operation's instructions by their index in the program, so pointing
+//! at the source needs the program too. [Symbols] record the line and character of each
+//! operation's first and last instruction instead, so they can be kept next to a compiled program
+//! and used to place a crash or a profile of it without the source:
+//!
+//! ```text
+//! bft-symbols 1
+//! fingerprint 8a3f0c1d2e4b5a69
+//! file hello.b
+//! 0 1:1 1:3 add 3
+//! 1 1:4 1:4 jz 4
+//! ```
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use bft_types::fingerprint::Fingerprint;
+use bft_types::DecoratedProgram;
+
+use thiserror::Error;
+
+use crate::bytecode::Bytecode;
+
+/// The first line of every symbol file, which changes if the format does
+const MAGIC: &str = "bft-symbols 1";
+
+/// Errors that may occur while reading a symbol file
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SymbolsError {
+    #[error("Not a symbol file")]
+    BadMagic,
+    #[error("Line {0} of the symbol file isn't understood")]
+    BadLine(usize),
+    #[error("The symbol file has no {0} line")]
+    Missing(&'static str),
+}
+
+/// Where an operation came from in the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// The index of the operation in the bytecode
+    pub op: usize,
+    /// The line and character of the first instruction it was compiled from
+    pub start: (usize, usize),
+    /// The line and character of the last instruction it was compiled from
+    pub end: (usize, usize),
+    /// The operation as the disassembly shows it, for reading the file by eye
+    pub text: String,
+}
+
+/// The source positions of every operation of some bytecode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbols {
+    /// The fingerprint of the program the bytecode was compiled from
+    pub fingerprint: Fingerprint,
+    /// The name of the program's file
+    pub file: String,
+    /// A symbol for each operation, in order
+    pub symbols: Vec<Symbol>,
+}
+
+impl Symbols {
+    /// Finds where each operation of `bytecode` came from in `prog`
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::{bytecode::Bytecode, symbols::Symbols};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("hello.b", "+++\n[-]>.")
+    /// ).unwrap();
+    /// let symbols = Symbols::new(&Bytecode::compile(&prog), &prog);
+    /// let clear = symbols.find(1).unwrap();
+    /// assert_eq!((clear.start, clear.end), ((2, 1), (2, 3)));
+    /// assert_eq!(symbols.to_string().lines().nth(4), Some("1 2:1 2:3 clear"));
+    /// ```
+    pub fn new(bytecode: &Bytecode, prog: &DecoratedProgram) -> Symbols {
+        let instructions = prog.decorated_instructions();
+        let position = |index: usize| {
+            let instruction = instructions[index].instruction();
+            (instruction.line(), instruction.character())
+        };
+        let symbols = bytecode
+            .ops()
+            .iter()
+            .enumerate()
+            .map(|(op, text)| {
+                let provenance = bytecode.provenance(op);
+                Symbol {
+                    op,
+                    start: position(provenance.start),
+                    end: position(provenance.end - 1),
+                    text: text.to_string(),
+                }
+            })
+            .collect();
+        Symbols {
+            fingerprint: bytecode.fingerprint(),
+            file: bft_types::display_file(prog.file()).to_string(),
+            symbols,
+        }
+    }
+
+    /// The symbol for the operation at index `op`
+    pub fn find(&self, op: usize) -> Option<&Symbol> {
+        self.symbols.get(op)
+    }
+
+    /// Reads a symbol file written by Display
+    pub fn parse(text: &str) -> Result<Symbols, SymbolsError> {
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, line)| line) != Some(MAGIC) {
+            return Err(SymbolsError::BadMagic);
+        }
+        let mut header = |key: &'static str| {
+            let (number, line) = lines.next().ok_or(SymbolsError::Missing(key))?;
+            line.strip_prefix(key)
+                .and_then(|rest| rest.strip_prefix(' '))
+                .ok_or(SymbolsError::BadLine(number + 1))
+                .map(|value| (number, value))
+        };
+        let (number, fingerprint) = header("fingerprint")?;
+        let fingerprint = fingerprint
+            .parse()
+            .map_err(|_| SymbolsError::BadLine(number + 1))?;
+        let file = header("file")?.1.to_string();
+        let symbols = lines
+            .map(|(number, line)| parse_symbol(line).ok_or(SymbolsError::BadLine(number + 1)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Symbols {
+            fingerprint,
+            file,
+            symbols,
+        })
+    }
+}
+
+/// Reads an `OP LINE:CHARACTER LINE:CHARACTER TEXT` line
+fn parse_symbol(line: &str) -> Option<Symbol> {
+    let mut fields = line.splitn(4, ' ');
+    let op = fields.next()?.parse().ok()?;
+    let mut position = || -> Option<(usize, usize)> {
+        let (line, character) = fields.next()?.split_once(':')?;
+        Some((line.parse().ok()?, character.parse().ok()?))
+    };
+    let (start, end) = (position()?, position()?);
+    Some(Symbol {
+        op,
+        start,
+        end,
+        text: fields.next().unwrap_or_default().to_string(),
+    })
+}
+
+/// Writes the symbol file
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{MAGIC}")?;
+        writeln!(f, "fingerprint {}", self.fingerprint)?;
+        writeln!(f, "file {}", self.file)?;
+        for symbol in &self.symbols {
+            let ((line, character), (end_line, end_character)) = (symbol.start, symbol.end);
+            writeln!(
+                f,
+                "{} {line}:{character} {end_line}:{end_character} {}",
+                symbol.op, symbol.text
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::Op;
+    use bft_types::Program;
+
+    #[test]
+    fn symbol_files_round_trip() {
+        let prog =
+            DecoratedProgram::from_program(&Program::new("my program.b", "+[>+\n<-] scan [>>]\n."))
+                .unwrap();
+        let bytecode = Bytecode::compile(&prog);
+        let symbols = Symbols::new(&bytecode, &prog);
+        assert_eq!(symbols.symbols.len(), bytecode.ops().len());
+        let read = Symbols::parse(&symbols.to_string()).unwrap();
+        assert_eq!(read, symbols);
+        assert_eq!(read.file, "my program.b");
+        let scan = bytecode
+            .ops()
+            .iter()
+            .position(|op| matches!(op, Op::Scan(2)))
+            .unwrap();
+        assert_eq!(
+            (read.symbols[scan].start, read.symbols[scan].end),
+            ((2, 10), (2, 13))
+        );
+
+        assert_eq!(
+            Symbols::parse("bft-record 1\n"),
+            Err(SymbolsError::BadMagic)
+        );
+        let truncated = "bft-symbols 1\nfingerprint 0000000000000001\n";
+        assert_eq!(
+            Symbols::parse(truncated),
+            Err(SymbolsError::Missing("file"))
+        );
+        let garbled = format!("{truncated}file x.b\n0 1:1 add 1\n");
+        assert_eq!(Symbols::parse(&garbled), Err(SymbolsError::BadLine(4)));
+    }
+}
@@ -0,0 +1,320 @@
+//! The reference interpreter's dispatch loop
+//!
+//! Before a program runs, each of its instructions is looked up once to find the function that
+//! executes it. Each step then just calls through that table, rather than matching on the
+//! instruction again.
+
+use alloc::vec::Vec;
+
+use bft_types::{PositionedInstruction, RawInstruction};
+
+use crate::bytecode::{self, Op};
+use crate::io::{Read, Write};
+use crate::limits::Limit;
+use crate::trace::{Step, Tracer};
+use crate::{CellKind, Machine, VMError};
+
+/// Executes an instruction, given the index of its partner bracket, returning the index of the
+/// next one
+type Handler<'a, T, R, W, Tr> =
+    fn(&mut Machine<'a, T>, &mut R, &mut W, &mut Tr, usize) -> Result<usize, VMError>;
+
+/// An instruction ready for the dispatch loop
+struct Threaded<'a, T, R, W, Tr> {
+    execute: Handler<'a, T, R, W, Tr>,
+    /// The index of the bracket this one pairs with, or 0 if it isn't a bracket
+    partner: usize,
+    /// The operation a tracer is told the instruction is
+    op: Op,
+    instruction: PositionedInstruction,
+}
+
+impl<'a, T: CellKind> Machine<'a, T> {
+    /// Runs the program through a table of handlers, carrying on from wherever it last stopped
+    pub(crate) fn run_threaded<R: Read, W: Write, Tr: Tracer<T>>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+        tracer: &mut Tr,
+    ) -> Result<(), VMError> {
+        let table = self.thread();
+        let mut next = self.next;
+        while let Some(threaded) = table.get(next) {
+            // Kept up to date so that, if this instruction fails, running again resumes from it
+            self.next = next;
+            self.instruction_pointer = next;
+            self.steps += 1;
+            if let Some(max) = self.limits.max_steps.filter(|max| self.steps > *max) {
+                return Err(self.limit_exceeded(Limit::Steps(max)));
+            }
+            self.check_cycle()?;
+            tracer.step(&Step {
+                step: self.steps,
+                pc: next,
+                op: threaded.op,
+                instruction: threaded.instruction,
+                head: self.head,
+                cells: &self.cells,
+            });
+            next = (threaded.execute)(self, input, output, tracer, threaded.partner)?;
+        }
+        self.next = next;
+        Ok(())
+    }
+
+    /// Executes the instruction [Machine::step] is up to, returning false if there are none left
+    pub(crate) fn step_threaded<R: Read, W: Write, Tr: Tracer<T>>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+        tracer: &mut Tr,
+    ) -> Result<bool, VMError> {
+        let Some(instruction) = self.prog.decorated_instructions().get(self.next) else {
+            return Ok(false);
+        };
+        let instruction = *instruction;
+        let partner = self.partners[self.next];
+        self.instruction_pointer = self.next;
+        self.steps += 1;
+        if let Some(max) = self.limits.max_steps.filter(|max| self.steps > *max) {
+            return Err(self.limit_exceeded(Limit::Steps(max)));
+        }
+        self.check_cycle()?;
+        tracer.step(&Step {
+            step: self.steps,
+            pc: self.next,
+            op: bytecode::instruction_op(&instruction, partner),
+            instruction: instruction.instruction(),
+            head: self.head,
+            cells: &self.cells,
+        });
+        let execute: Handler<'a, T, R, W, Tr> = handler(*instruction.instruction().instruction());
+        self.next = execute(self, input, output, tracer, partner)?;
+        Ok(true)
+    }
+
+    /// Finds the handler for each instruction of the program
+    fn thread<R: Read, W: Write, Tr: Tracer<T>>(&self) -> Vec<Threaded<'a, T, R, W, Tr>> {
+        self.prog
+            .decorated_instructions()
+            .iter()
+            .zip(self.partners.iter().copied())
+            .map(|(instruction, partner)| Threaded {
+                execute: handler(*instruction.instruction().instruction()),
+                partner,
+                op: bytecode::instruction_op(instruction, partner),
+                instruction: instruction.instruction(),
+            })
+            .collect()
+    }
+
+    fn increment<R, W, Tr>(
+        &mut self,
+        _: &mut R,
+        _: &mut W,
+        _: &mut Tr,
+        _: usize,
+    ) -> Result<usize, VMError> {
+        self.increment_cell();
+        Ok(self.instruction_pointer + 1)
+    }
+
+    fn decrement<R, W, Tr>(
+        &mut self,
+        _: &mut R,
+        _: &mut W,
+        _: &mut Tr,
+        _: usize,
+    ) -> Result<usize, VMError> {
+        self.decrement_cell();
+        Ok(self.instruction_pointer + 1)
+    }
+
+    fn right<R, W, Tr>(
+        &mut self,
+        _: &mut R,
+        _: &mut W,
+        _: &mut Tr,
+        _: usize,
+    ) -> Result<usize, VMError> {
+        self.seek_right()?;
+        Ok(self.instruction_pointer + 1)
+    }
+
+    fn left<R, W, Tr>(
+        &mut self,
+        _: &mut R,
+        _: &mut W,
+        _: &mut Tr,
+        _: usize,
+    ) -> Result<usize, VMError> {
+        self.seek_left()?;
+        Ok(self.instruction_pointer + 1)
+    }
+
+    fn get<R: Read, W: Write, Tr: Tracer<T>>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+        tracer: &mut Tr,
+        _: usize,
+    ) -> Result<usize, VMError> {
+        self.input_traced(input, output, tracer)?;
+        Ok(self.instruction_pointer + 1)
+    }
+
+    fn put<R, W: Write, Tr: Tracer<T>>(
+        &mut self,
+        _: &mut R,
+        output: &mut W,
+        tracer: &mut Tr,
+        _: usize,
+    ) -> Result<usize, VMError> {
+        self.output_traced(output, tracer)?;
+        Ok(self.instruction_pointer + 1)
+    }
+
+    fn open_loop<R, W, Tr>(
+        &mut self,
+        _: &mut R,
+        _: &mut W,
+        _: &mut Tr,
+        partner: usize,
+    ) -> Result<usize, VMError> {
+        if self.cells[self.head].is_zero() {
+            Ok(partner + 1)
+        } else {
+            self.enter_loop(self.instruction_pointer);
+            Ok(self.instruction_pointer + 1)
+        }
+    }
+
+    fn close_loop<R, W, Tr>(
+        &mut self,
+        _: &mut R,
+        _: &mut W,
+        _: &mut Tr,
+        partner: usize,
+    ) -> Result<usize, VMError> {
+        if self.cells[self.head].is_zero() {
+            Ok(self.instruction_pointer + 1)
+        } else {
+            self.repeat_loop(partner)?;
+            Ok(partner + 1)
+        }
+    }
+}
+
+/// The function that executes an instruction
+fn handler<'a, T: CellKind, R: Read, W: Write, Tr: Tracer<T>>(
+    instruction: RawInstruction,
+) -> Handler<'a, T, R, W, Tr> {
+    match instruction {
+        RawInstruction::IncrementByte => Machine::increment,
+        RawInstruction::DecrementByte => Machine::decrement,
+        RawInstruction::IncrementDataPointer => Machine::right,
+        RawInstruction::DecrementDataPointer => Machine::left,
+        RawInstruction::GetByte => Machine::get,
+        RawInstruction::PutByte => Machine::put,
+        RawInstruction::OpenLoop => Machine::open_loop,
+        RawInstruction::CloseLoop => Machine::close_loop,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+    use bft_types::{DecoratedProgram, Program};
+
+    use crate::bytecode::Bytecode;
+    use crate::limits::Limits;
+
+    /// Runs a clone of `machine` through the table, and another a step at a time, returning each
+    /// one's output and result
+    fn both_ways(machine: &Machine<u8>, input: &[u8]) -> [(Vec<u8>, Result<(), String>); 2] {
+        let mut threaded = machine.clone();
+        let mut output = Vec::new();
+        let result = threaded.run(&mut &input[..], &mut output);
+        let run = (output, result.map_err(|e| e.to_string()));
+        let mut stepped = machine.clone();
+        let (mut input, mut output) = (input, Vec::new());
+        let result = loop {
+            match stepped.step(&mut input, &mut output) {
+                Ok(true) => {}
+                Ok(false) => break Ok(()),
+                Err(e) => break Err(e.to_string()),
+            }
+        };
+        assert_eq!(threaded.cells(), stepped.cells());
+        assert_eq!(threaded.head(), stepped.head());
+        assert_eq!(threaded.steps(), stepped.steps());
+        [run, (output, result)]
+    }
+
+    #[test]
+    fn runs_like_stepping_and_like_the_bytecode() {
+        let programs: [(&str, &[u8]); 4] = [
+            (include_str!("../../programs/hello.b"), b""),
+            (include_str!("../../programs/sierpinski.b"), b""),
+            (",[.-]", b"\x05"),
+            ("++[>+++[>++<-]<-]>>[<+<+>>-]<.<.", b""),
+        ];
+        for (text, input) in programs {
+            let prog = DecoratedProgram::from_program(&Program::new("<test>", text)).unwrap();
+            let machine: Machine<u8> = Machine::new(None, false, &prog);
+            let [run, stepped] = both_ways(&machine, input);
+            assert_eq!(run, stepped, "{text}");
+            let mut compiled = machine.clone();
+            let mut output = Vec::new();
+            compiled
+                .run_bytecode(&Bytecode::compile(&prog), &mut &input[..], &mut output)
+                .unwrap();
+            assert_eq!(run, (output, Ok(())), "{text}");
+        }
+    }
+
+    #[test]
+    fn errors_are_the_same_as_stepping() {
+        let limited = Limits {
+            max_steps: Some(7),
+            ..Limits::default()
+        };
+        let cases: [(&str, Option<usize>, Limits, bool); 4] = [
+            ("+.<", None, Limits::default(), false),
+            ("+[>+]", Some(4), Limits::default(), false),
+            ("+[.]", None, limited, false),
+            ("+[>+<]>>", Some(4), Limits::default(), true),
+        ];
+        for (text, cells, limits, detect) in cases {
+            let prog = DecoratedProgram::from_program(&Program::new("<test>", text)).unwrap();
+            let cells = cells.map(|cells| cells.try_into().unwrap());
+            let mut machine: Machine<u8> = Machine::new(cells, false, &prog);
+            machine.set_limits(limits);
+            machine.set_loop_detection(detect.then_some(16));
+            let [run, stepped] = both_ways(&machine, b"");
+            assert!(run.1.is_err(), "{text}");
+            assert_eq!(run, stepped, "{text}");
+        }
+    }
+
+    #[test]
+    fn running_again_after_an_error_resumes_at_the_failed_instruction() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", "+.,+.")).unwrap();
+        let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+        let mut output = Vec::new();
+        assert!(machine.run(&mut &[][..], &mut output).is_err());
+        assert_eq!(output, [1]);
+        assert_eq!(
+            machine
+                .next_instruction()
+                .unwrap()
+                .instruction()
+                .character(),
+            3
+        );
+        machine.run(&mut &b"a"[..], &mut output).unwrap();
+        assert_eq!(output, [1, 98]);
+        assert_eq!(machine.cells()[..2], [98, 0]);
+    }
+}
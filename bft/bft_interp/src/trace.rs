@@ -0,0 +1,201 @@
+//! Watching a program as it runs
+//!
+//! A [Tracer] is told about every operation a [Machine](crate::Machine) executes, just before it
+//! runs, and about every byte the program reads or writes. [WriteTracer] records each operation as
+//! a line of text or JSON.
+
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+use bft_types::PositionedInstruction;
+
+use crate::bytecode::Op;
+#[cfg(feature = "std")]
+use crate::value::{Notation, ValueFormat};
+#[cfg(feature = "std")]
+use crate::CellKind;
+
+/// The state of a Machine just before it executes an operation
+pub struct Step<'a, T> {
+    /// How many operations have been executed, counting this one
+    pub step: u64,
+    /// The index of the operation in the bytecode
+    pub pc: usize,
+    /// The operation about to run
+    pub op: Op,
+    /// The instruction in the program that the operation was compiled from
+    pub instruction: PositionedInstruction,
+    /// The memory pointer
+    pub head: usize,
+    /// Every cell
+    pub cells: &'a [T],
+}
+
+/// A byte that a Machine read or wrote
+#[derive(Debug, Clone, Copy)]
+pub struct Io {
+    /// The step that read or wrote the byte
+    pub step: u64,
+    /// The instruction in the program that read or wrote the byte
+    pub instruction: PositionedInstruction,
+    pub byte: u8,
+}
+
+/// Something that watches a Machine run
+pub trait Tracer<T> {
+    /// Called just before each operation is executed
+    fn step(&mut self, step: &Step<'_, T>);
+
+    /// Called after the program reads a byte
+    fn input(&mut self, _io: &Io) {}
+
+    /// Called after the program writes a byte
+    fn output(&mut self, _io: &Io) {}
+}
+
+/// Ignores every step, so running without a tracer costs nothing
+impl<T> Tracer<T> for () {
+    fn step(&mut self, _step: &Step<'_, T>) {}
+}
+
+/// Only traces when there's a tracer
+impl<T, A: Tracer<T>> Tracer<T> for Option<A> {
+    fn step(&mut self, step: &Step<'_, T>) {
+        if let Some(tracer) = self {
+            tracer.step(step);
+        }
+    }
+
+    fn input(&mut self, io: &Io) {
+        if let Some(tracer) = self {
+            tracer.input(io);
+        }
+    }
+
+    fn output(&mut self, io: &Io) {
+        if let Some(tracer) = self {
+            tracer.output(io);
+        }
+    }
+}
+
+/// Passes everything to both tracers
+impl<T, A: Tracer<T>, B: Tracer<T>> Tracer<T> for (A, B) {
+    fn step(&mut self, step: &Step<'_, T>) {
+        self.0.step(step);
+        self.1.step(step);
+    }
+
+    fn input(&mut self, io: &Io) {
+        self.0.input(io);
+        self.1.input(io);
+    }
+
+    fn output(&mut self, io: &Io) {
+        self.0.output(io);
+        self.1.output(io);
+    }
+}
+
+/// How a [WriteTracer] formats each step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `step line:character op head=N cell=N`
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Writes a line for every step
+///
+/// Tracing carries on after a write fails, but nothing more is written and the error is returned
+/// by [WriteTracer::finish]. This needs the `std` feature.
+#[cfg(feature = "std")]
+pub struct WriteTracer<W> {
+    out: W,
+    format: Format,
+    /// How text lines write the cell under the head
+    values: ValueFormat,
+    error: Option<io::Error>,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> WriteTracer<W> {
+    pub fn new(out: W, format: Format) -> WriteTracer<W> {
+        WriteTracer {
+            out,
+            format,
+            values: ValueFormat {
+                notation: Notation::Decimal,
+                width: None,
+            },
+            error: None,
+        }
+    }
+
+    /// Sets how text lines write the cell under the head, which is in decimal unless set
+    ///
+    /// JSON lines always give the cell as a number.
+    pub fn set_value_format(&mut self, values: ValueFormat) {
+        self.values = values;
+    }
+
+    /// Flushes the trace, returning the writer or the first error writing it
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::trace::{Format, WriteTracer};
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "++>")
+    /// ).unwrap();
+    /// let bytecode = bft_interp::bytecode::Bytecode::compile(&prog);
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// let mut tracer = WriteTracer::new(Vec::new(), Format::Text);
+    /// interp
+    ///     .run_bytecode_traced(&bytecode, &mut std::io::empty(), &mut std::io::sink(), &mut tracer)
+    ///     .unwrap();
+    /// let trace = String::from_utf8(tracer.finish().unwrap()).unwrap();
+    /// assert_eq!(trace, "1 1:1 add 2 head=0 cell=0\n2 1:3 move 1 head=0 cell=2\n");
+    /// ```
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => self.out.flush().map(|()| self.out),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: CellKind, W: Write> Tracer<T> for WriteTracer<W> {
+    fn step(&mut self, step: &Step<'_, T>) {
+        if self.error.is_some() {
+            return;
+        }
+        let cell = &step.cells[step.head];
+        let result = match self.format {
+            Format::Text => writeln!(
+                self.out,
+                "{} {}:{} {} head={} cell={}",
+                step.step,
+                step.instruction.line(),
+                step.instruction.character(),
+                step.op,
+                step.head,
+                self.values.display(cell)
+            ),
+            Format::Json => writeln!(
+                self.out,
+                r#"{{"step":{},"pc":{},"line":{},"character":{},"op":"{}","head":{},"cell":{}}}"#,
+                step.step,
+                step.pc,
+                step.instruction.line(),
+                step.instruction.character(),
+                step.op,
+                step.head,
+                cell.get_full_value()
+            ),
+        };
+        self.error = result.err();
+    }
+}
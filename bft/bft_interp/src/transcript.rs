@@ -0,0 +1,301 @@
+//! Recording every byte a program reads and writes
+//!
+//! A [Transcript] is a [Tracer] that notes each byte, which step read or wrote it, and when.
+//! [Transcript::write_to] saves it as one line per byte: `seconds step in|out byte`, and
+//! [Transcript::read_from] loads it again. A [Replay] checks that a program run on a transcript's
+//! input writes the same output again.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, Instant};
+
+use bft_types::PositionedInstruction;
+use thiserror::Error;
+
+use crate::trace::{Io, Step, Tracer};
+
+/// Whether a byte was read or written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Direction::Input => "in",
+            Direction::Output => "out",
+        })
+    }
+}
+
+/// A byte read or written by a program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    /// How long after the transcript started the byte was read or written
+    pub time: Duration,
+    /// The step that read or wrote the byte
+    pub step: u64,
+    pub direction: Direction,
+    pub byte: u8,
+}
+
+/// Every byte a program read or wrote, in order
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    start: Instant,
+    events: Vec<Event>,
+}
+
+impl Default for Transcript {
+    fn default() -> Transcript {
+        Transcript {
+            start: Instant::now(),
+            events: Vec::new(),
+        }
+    }
+}
+
+impl<T> Tracer<T> for Transcript {
+    fn step(&mut self, _step: &Step<'_, T>) {}
+
+    fn input(&mut self, io: &Io) {
+        self.record(Direction::Input, io);
+    }
+
+    fn output(&mut self, io: &Io) {
+        self.record(Direction::Output, io);
+    }
+}
+
+impl Transcript {
+    /// Starts a transcript, timing events from now
+    pub fn new() -> Transcript {
+        Transcript::default()
+    }
+
+    /// Returns every byte read or written so far
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Returns every byte the program read, in order
+    pub fn input(&self) -> Vec<u8> {
+        self.bytes(Direction::Input)
+    }
+
+    /// Returns every byte the program wrote, in order
+    pub fn output(&self) -> Vec<u8> {
+        self.bytes(Direction::Output)
+    }
+
+    fn bytes(&self, direction: Direction) -> Vec<u8> {
+        self.events
+            .iter()
+            .filter(|event| event.direction == direction)
+            .map(|event| event.byte)
+            .collect()
+    }
+
+    fn record(&mut self, direction: Direction, io: &Io) {
+        self.events.push(Event {
+            time: self.start.elapsed(),
+            step: io.step,
+            direction,
+            byte: io.byte,
+        });
+    }
+
+    /// Writes the transcript as a line per byte
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::transcript::Transcript;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", ",+.")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// let mut transcript = Transcript::new();
+    /// interp
+    ///     .run_traced(&mut &b"a"[..], &mut std::io::sink(), &mut transcript)
+    ///     .unwrap();
+    /// let mut saved = Vec::new();
+    /// transcript.write_to(&mut saved).unwrap();
+    /// let lines: Vec<_> = std::str::from_utf8(&saved)
+    ///     .unwrap()
+    ///     .lines()
+    ///     .map(|line| line.split_once(' ').unwrap().1)
+    ///     .collect();
+    /// assert_eq!(lines, ["1 in 97", "3 out 98"]);
+    /// ```
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        for event in &self.events {
+            writeln!(
+                out,
+                "{:.6} {} {} {}",
+                event.time.as_secs_f64(),
+                event.step,
+                event.direction,
+                event.byte
+            )?;
+        }
+        out.flush()
+    }
+
+    /// Reads a transcript saved by [Transcript::write_to]
+    pub fn read_from(input: impl BufRead) -> Result<Transcript, TranscriptError> {
+        let mut transcript = Transcript::new();
+        for (index, line) in input.lines().enumerate() {
+            let line = line?;
+            let bad = |reason| TranscriptError::BadLine {
+                line: index + 1,
+                reason,
+            };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [time, step, direction, byte] = fields[..] else {
+                return Err(bad("expected `seconds step in|out byte`"));
+            };
+            let time = time
+                .parse()
+                .ok()
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                .ok_or(bad("bad time"))?;
+            transcript.events.push(Event {
+                time,
+                step: step.parse().map_err(|_| bad("bad step"))?,
+                direction: match direction {
+                    "in" => Direction::Input,
+                    "out" => Direction::Output,
+                    _ => return Err(bad("expected in or out")),
+                },
+                byte: byte.parse().map_err(|_| bad("bad byte"))?,
+            });
+        }
+        Ok(transcript)
+    }
+}
+
+/// Errors that may occur while reading a transcript
+#[derive(Error, Debug)]
+pub enum TranscriptError {
+    #[error("Line {line} of the transcript is invalid: {reason}")]
+    BadLine { line: usize, reason: &'static str },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Where a replayed program's output first differed from its transcript
+#[derive(Debug, Clone)]
+pub enum Divergence {
+    /// The program wrote a byte the transcript doesn't have, or a different one
+    Wrote {
+        /// How many bytes were written before this one
+        index: usize,
+        step: u64,
+        instruction: PositionedInstruction,
+        expected: Option<u8>,
+        actual: u8,
+    },
+    /// The program finished before writing everything the transcript has
+    Missing { index: usize, expected: u8 },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Divergence::Wrote {
+                index,
+                step,
+                instruction,
+                expected: Some(expected),
+                actual,
+            } => write!(
+                f,
+                "Output byte {index} was {actual} instead of {expected}, written at step {step} by instruction {instruction}"
+            ),
+            Divergence::Wrote {
+                index,
+                step,
+                instruction,
+                expected: None,
+                actual,
+            } => write!(
+                f,
+                "Output byte {index} ({actual}) is beyond the end of the transcript, written at step {step} by instruction {instruction}"
+            ),
+            Divergence::Missing { index, expected } => write!(
+                f,
+                "The program finished without writing output byte {index} ({expected})"
+            ),
+        }
+    }
+}
+
+/// Checks a program writes the same output as a transcript, as it runs
+///
+/// # Examples
+/// ```
+/// # use bft_interp::transcript::{Divergence, Replay, Transcript};
+/// # use bft_types;
+/// let transcript = Transcript::read_from(&b"0.1 1 in 97\n0.2 3 out 98\n"[..]).unwrap();
+/// let prog = bft_types::DecoratedProgram::from_program(
+///     &bft_types::Program::new("<None>", ",++.")
+/// ).unwrap();
+/// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+/// let mut replay = Replay::new(&transcript);
+/// interp
+///     .run_traced(&mut &transcript.input()[..], &mut std::io::sink(), &mut replay)
+///     .unwrap();
+/// let divergence = replay.finish().unwrap_err();
+/// assert!(matches!(divergence, Divergence::Wrote { index: 0, expected: Some(98), actual: 99, .. }));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Replay {
+    expected: Vec<u8>,
+    written: usize,
+    divergence: Option<Divergence>,
+}
+
+impl Replay {
+    pub fn new(transcript: &Transcript) -> Replay {
+        Replay {
+            expected: transcript.output(),
+            written: 0,
+            divergence: None,
+        }
+    }
+
+    /// Returns the first difference from the transcript, once the program has finished
+    pub fn finish(self) -> Result<(), Divergence> {
+        if let Some(divergence) = self.divergence {
+            return Err(divergence);
+        }
+        match self.expected.get(self.written) {
+            Some(&expected) => Err(Divergence::Missing {
+                index: self.written,
+                expected,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T> Tracer<T> for Replay {
+    fn step(&mut self, _step: &Step<'_, T>) {}
+
+    fn output(&mut self, io: &Io) {
+        let expected = self.expected.get(self.written).copied();
+        if self.divergence.is_none() && expected != Some(io.byte) {
+            self.divergence = Some(Divergence::Wrote {
+                index: self.written,
+                step: io.step,
+                instruction: io.instruction,
+                expected,
+                actual: io.byte,
+            });
+        }
+        self.written += 1;
+    }
+}
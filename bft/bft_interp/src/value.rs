@@ -0,0 +1,99 @@
+//! Writing cell values for people to read
+//!
+//! The debugger, state dumps, traces and a Machine's Display all write cells through a
+//! [ValueFormat], so the same setting makes them agree on how a value looks.
+
+use alloc::string::String;
+use core::fmt::{self, Write};
+use core::mem::size_of;
+
+use crate::CellKind;
+
+/// Which notation cell values are written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Notation {
+    /// `0x41`, with as many digits as the widest value of the cell
+    Hex,
+    /// `65`
+    Decimal,
+    /// `'A'`, with escapes for bytes that aren't printable, and values wider than a byte in
+    /// decimal
+    Char,
+    /// `0x41   65 'A'`: hex, decimal in a column four wide, and the character if it's printable
+    #[default]
+    Auto,
+}
+
+/// How a cell value is written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValueFormat {
+    pub notation: Notation,
+    /// The fewest characters to write, padding values on the left
+    pub width: Option<usize>,
+}
+
+impl ValueFormat {
+    /// Wraps `cell` so that displaying it writes it in this format
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::value::{Notation, ValueFormat};
+    /// let hex = ValueFormat { notation: Notation::Hex, width: None };
+    /// assert_eq!(hex.display(&10u16).to_string(), "0x000a");
+    /// let char = ValueFormat { notation: Notation::Char, width: Some(5) };
+    /// assert_eq!(char.display(&10u8).to_string(), " '\\n'");
+    /// assert_eq!(ValueFormat::default().display(&b'A').to_string(), "0x41   65 'A'");
+    /// ```
+    pub fn display<T: CellKind>(self, cell: &T) -> Value<'_, T> {
+        Value { format: self, cell }
+    }
+}
+
+/// A cell value to be written in a [ValueFormat], made by [ValueFormat::display]
+pub struct Value<'c, T> {
+    format: ValueFormat,
+    cell: &'c T,
+}
+
+impl<T: CellKind> fmt::Display for Value<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.cell.get_full_value();
+        let digits = size_of::<T>() * 2;
+        let mut text = String::new();
+        match self.format.notation {
+            Notation::Hex => write!(text, "0x{value:0digits$x}")?,
+            Notation::Decimal => write!(text, "{value}")?,
+            Notation::Char => match u8::try_from(value) {
+                Ok(byte) => {
+                    text.push('\'');
+                    write_escaped(byte, &mut text)?;
+                    text.push('\'');
+                }
+                Err(_) => write!(text, "{value}")?,
+            },
+            Notation::Auto => {
+                write!(text, "0x{value:0digits$x} {value:>4}")?;
+                if let Some(byte) = u8::try_from(value).ok().filter(u8::is_ascii_graphic) {
+                    write!(text, " '{}'", byte as char)?;
+                }
+            }
+        }
+        match self.format.width {
+            Some(width) => write!(f, "{text:>width$}"),
+            None => f.write_str(&text),
+        }
+    }
+}
+
+/// Writes a byte as it would be written inside quotes
+fn write_escaped(byte: u8, out: &mut impl Write) -> fmt::Result {
+    match byte {
+        b'\0' => out.write_str("\\0"),
+        b'\t' => out.write_str("\\t"),
+        b'\n' => out.write_str("\\n"),
+        b'\r' => out.write_str("\\r"),
+        b'\'' | b'\\' => write!(out, "\\{}", byte as char),
+        b' '..=b'~' => out.write_char(byte as char),
+        _ => write!(out, "\\x{byte:02x}"),
+    }
+}
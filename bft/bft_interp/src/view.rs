@@ -0,0 +1,219 @@
+//! Sampling a running program's state from another thread
+//!
+//! A [ViewPublisher] is a [Tracer] that, every so many steps, copies the head, the instruction
+//! and a window of cells into a spare snapshot and swaps it into the [StateView]. Readers take the
+//! latest snapshot whenever they like, such as once a frame, without slowing the program down
+//! much: the swap only takes a lock that is never held for longer than a pointer swap or a copy of
+//! the window, and the publisher skips a swap rather than waiting if a reader holds it. This needs
+//! the `std` feature.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use bft_types::PositionedInstruction;
+
+use crate::trace::{Io, Step, Tracer};
+use crate::Machine;
+
+/// How many steps a [ViewPublisher] waits between snapshots, unless told otherwise
+pub const DEFAULT_INTERVAL: u64 = 1024;
+
+/// The state of a Machine at one moment
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot<T> {
+    /// How many operations had been executed
+    pub steps: u64,
+    /// How many bytes had been written
+    pub output_bytes: u64,
+    pub head: usize,
+    /// The instruction about to be executed, or None before the program starts or once it stops
+    pub instruction: Option<PositionedInstruction>,
+    /// The index of the first cell in [Snapshot::cells]
+    pub start: usize,
+    /// The cells around the head
+    pub cells: Vec<T>,
+    /// How many cells the tape had
+    pub tape_len: usize,
+    /// Whether the run has stopped
+    pub finished: bool,
+}
+
+impl<T: Clone> Snapshot<T> {
+    /// Takes a snapshot of every cell of a Machine that isn't running, with the instruction it
+    /// will execute next
+    pub fn of(machine: &Machine<'_, T>) -> Snapshot<T> {
+        Snapshot {
+            steps: machine.steps(),
+            output_bytes: machine.output_bytes(),
+            head: machine.head(),
+            instruction: machine
+                .next_instruction()
+                .map(|instruction| instruction.instruction()),
+            start: 0,
+            cells: machine.cells().to_vec(),
+            tape_len: machine.cells().len(),
+            finished: machine.next_instruction().is_none(),
+        }
+    }
+}
+
+/// The latest snapshot of a running Machine, which any thread can read
+///
+/// Clones share the same snapshot.
+#[derive(Debug)]
+pub struct StateView<T> {
+    latest: Arc<Mutex<Snapshot<T>>>,
+}
+
+impl<T> Clone for StateView<T> {
+    fn clone(&self) -> StateView<T> {
+        StateView {
+            latest: Arc::clone(&self.latest),
+        }
+    }
+}
+
+impl<T: Clone + Default> StateView<T> {
+    /// Makes a view, and the publisher that updates it, with up to `window` cells either side of
+    /// the head in each snapshot
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_interp::view::StateView;
+    /// # use bft_types;
+    /// let prog = bft_types::DecoratedProgram::from_program(
+    ///     &bft_types::Program::new("<None>", "+>++")
+    /// ).unwrap();
+    /// let mut interp: bft_interp::Machine<u8> = bft_interp::Machine::new(None, false, &prog);
+    /// let (view, mut publisher) = StateView::new(1);
+    /// publisher.set_interval(1);
+    /// interp.run_traced(&mut std::io::empty(), &mut std::io::sink(), &mut publisher).unwrap();
+    /// publisher.finish(&interp);
+    /// let snapshot = view.snapshot();
+    /// assert_eq!((snapshot.steps, snapshot.head, snapshot.finished), (4, 1, true));
+    /// assert_eq!((snapshot.start, snapshot.cells), (0, vec![1, 2, 0]));
+    /// ```
+    pub fn new(window: usize) -> (StateView<T>, ViewPublisher<T>) {
+        let view = StateView {
+            latest: Arc::new(Mutex::new(Snapshot::default())),
+        };
+        let publisher = ViewPublisher {
+            view: view.clone(),
+            spare: Snapshot::default(),
+            window,
+            interval: DEFAULT_INTERVAL,
+            output_bytes: 0,
+        };
+        (view, publisher)
+    }
+
+    /// Returns a copy of the latest snapshot
+    pub fn snapshot(&self) -> Snapshot<T> {
+        self.latest
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+/// Updates a [StateView] while a Machine runs, made by [StateView::new]
+pub struct ViewPublisher<T> {
+    view: StateView<T>,
+    /// The snapshot being filled in, which is swapped with the view's
+    spare: Snapshot<T>,
+    window: usize,
+    interval: u64,
+    output_bytes: u64,
+}
+
+impl<T: Clone> ViewPublisher<T> {
+    /// Takes a snapshot every `interval` steps instead of every [DEFAULT_INTERVAL]
+    pub fn set_interval(&mut self, interval: u64) {
+        self.interval = interval.max(1);
+    }
+
+    /// Publishes the Machine's final state, marking the run as stopped
+    pub fn finish(&mut self, machine: &Machine<'_, T>) {
+        self.fill(machine.steps(), machine.head(), None, machine.cells());
+        self.spare.output_bytes = machine.output_bytes();
+        self.spare.finished = true;
+        // Unlike the snapshots taken while running, the last one mustn't be skipped
+        let mut latest = self
+            .view
+            .latest
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        std::mem::swap(&mut *latest, &mut self.spare);
+    }
+
+    fn fill(
+        &mut self,
+        steps: u64,
+        head: usize,
+        instruction: Option<PositionedInstruction>,
+        cells: &[T],
+    ) {
+        let start = head.saturating_sub(self.window).min(cells.len());
+        let end = head.saturating_add(self.window + 1).min(cells.len());
+        let spare = &mut self.spare;
+        spare.steps = steps;
+        spare.output_bytes = self.output_bytes;
+        spare.head = head;
+        spare.instruction = instruction;
+        spare.start = start;
+        spare.cells.clear();
+        spare.cells.extend_from_slice(&cells[start..end]);
+        spare.tape_len = cells.len();
+        spare.finished = false;
+    }
+}
+
+impl<T: Clone> Tracer<T> for ViewPublisher<T> {
+    fn step(&mut self, step: &Step<'_, T>) {
+        if !step.step.is_multiple_of(self.interval) {
+            return;
+        }
+        self.fill(step.step, step.head, Some(step.instruction), step.cells);
+        if let Ok(mut latest) = self.view.latest.try_lock() {
+            std::mem::swap(&mut *latest, &mut self.spare);
+        }
+    }
+
+    fn output(&mut self, _io: &Io) {
+        self.output_bytes += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::{DecoratedProgram, Program};
+
+    #[test]
+    fn snapshots_can_be_read_while_running() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", "+[>+]")).unwrap();
+        let (view, mut publisher) = StateView::new(2);
+        publisher.set_interval(10);
+        let reader = {
+            let view = view.clone();
+            std::thread::spawn(move || {
+                let mut last = 0;
+                while !view.snapshot().finished {
+                    let snapshot = view.snapshot();
+                    assert!(snapshot.steps >= last);
+                    assert!(snapshot.cells.len() <= 5);
+                    last = snapshot.steps;
+                }
+            })
+        };
+        let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+        let result =
+            machine.run_traced(&mut std::io::empty(), &mut std::io::sink(), &mut publisher);
+        assert!(result.is_err());
+        publisher.finish(&machine);
+        reader.join().unwrap();
+        let snapshot = view.snapshot();
+        assert_eq!(snapshot.head, 29999);
+        assert_eq!((snapshot.start, snapshot.cells.len()), (29997, 3));
+        assert!(snapshot.instruction.is_none());
+    }
+}
@@ -0,0 +1,208 @@
+//! Python bindings, for scripting test harnesses around the interpreter
+//!
+//! Build the module with `maturin develop` from this directory.
+//!
+//! ```python
+//! import bft_py
+//!
+//! program = bft_py.Program(",[.,]")
+//! machine = bft_py.Machine(program, input=b"hi", max_steps=10_000)
+//! try:
+//!     machine.run()
+//! except bft_py.BftError as error:
+//!     print(error)  # E0203: An I/O Error occurred ...
+//! assert machine.output == b"hi"
+//! tape = numpy.frombuffer(machine.tape, dtype=numpy.uint8)
+//! ```
+
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+
+use bft_interp::error;
+use bft_interp::limits::Limits;
+use bft_types::DecoratedProgram;
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+
+create_exception!(
+    bft_py,
+    BftError,
+    PyException,
+    "A program couldn't be parsed or failed while running, with a message starting with its code"
+);
+
+/// Raises `error` in Python as a [BftError]
+fn raise(error: impl Into<error::BftError>) -> PyErr {
+    let error = error.into();
+    BftError::new_err(format!("{}: {error}", error.code()))
+}
+
+/// A parsed program whose brackets all match
+#[pyclass(frozen)]
+struct Program {
+    decorated: DecoratedProgram,
+}
+
+#[pymethods]
+impl Program {
+    /// Parses `source`, raising BftError if its brackets don't match
+    #[new]
+    #[pyo3(signature = (source, file = "<string>"))]
+    fn new(source: &str, file: &str) -> PyResult<Program> {
+        let prog = bft_types::Program::new(file, source);
+        let decorated = DecoratedProgram::from_program(&prog).map_err(raise)?;
+        Ok(Program { decorated })
+    }
+
+    /// How many instructions the program has, leaving out comments
+    fn __len__(&self) -> usize {
+        self.decorated.decorated_instructions().len()
+    }
+}
+
+/// A program being run, with the input it has still to read and everything it has written
+#[pyclass]
+struct Machine {
+    machine: bft_interp::Machine<'static, u8>,
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+#[pymethods]
+impl Machine {
+    /// Prepares to run `program` with `input`, on a tape of `cells` cells that may grow if it's
+    /// `extensible`
+    ///
+    /// The limits stop the program with a BftError once it goes over them.
+    #[new]
+    #[pyo3(signature = (
+        program,
+        input = Vec::new(),
+        cells = None,
+        extensible = false,
+        max_steps = None,
+        max_output_bytes = None,
+        max_tape = None,
+    ))]
+    fn new(
+        program: &Program,
+        input: Vec<u8>,
+        cells: Option<NonZeroUsize>,
+        extensible: bool,
+        max_steps: Option<u64>,
+        max_output_bytes: Option<u64>,
+        max_tape: Option<usize>,
+    ) -> Machine {
+        let mut machine = bft_interp::Machine::owning(cells, extensible, program.decorated.clone());
+        machine.set_limits(Limits {
+            max_steps,
+            max_output_bytes,
+            max_tape,
+            max_loop_iterations: None,
+        });
+        Machine {
+            machine,
+            input: input.into(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Adds `data` to the end of the input still to be read
+    fn feed(&mut self, data: Vec<u8>) {
+        self.input.extend(data);
+    }
+
+    /// Copies the machine, with its tape, the input it has still to read and everything it has
+    /// written, so that the copy can carry on differently
+    fn fork(&self) -> Machine {
+        Machine {
+            machine: self.machine.clone(),
+            input: self.input.clone(),
+            output: self.output.clone(),
+        }
+    }
+
+    /// Executes one instruction, returning False once the program has finished
+    fn step(&mut self) -> PyResult<bool> {
+        self.machine
+            .step(&mut self.input, &mut self.output)
+            .map_err(raise)
+    }
+
+    /// Runs the program until it finishes, carrying on from wherever stepping stopped
+    fn run(&mut self) -> PyResult<()> {
+        if self.machine.steps() == 0 {
+            return self
+                .machine
+                .run(&mut self.input, &mut self.output)
+                .map_err(raise);
+        }
+        while self.step()? {}
+        Ok(())
+    }
+
+    /// Everything the program has written
+    #[getter]
+    fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// The cells of the tape, as bytes
+    #[getter]
+    fn tape(&self) -> &[u8] {
+        self.machine.cells()
+    }
+
+    /// The cells of the tape, as a numpy array of uint8
+    #[cfg(feature = "numpy")]
+    fn tape_array<'py>(&self, py: Python<'py>) -> Bound<'py, numpy::PyArray1<u8>> {
+        numpy::PyArray1::from_slice(py, self.machine.cells())
+    }
+
+    /// Which cell the head is on
+    #[getter]
+    fn head(&self) -> usize {
+        self.machine.head()
+    }
+
+    /// How many instructions have been executed
+    #[getter]
+    fn steps(&self) -> u64 {
+        self.machine.steps()
+    }
+}
+
+#[pymodule]
+fn bft_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Program>()?;
+    m.add_class::<Machine>()?;
+    m.add("BftError", m.py().get_type::<BftError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn programs_run_until_they_fail() {
+        Python::attach(|py| {
+            let program = Program::new(",[.,]", "<test>").unwrap();
+            let mut machine = Machine::new(&program, b"hi".to_vec(), None, false, None, None, None);
+            assert!(machine.step().unwrap());
+            let mut fork = machine.fork();
+            fork.feed(b"!".to_vec());
+            let error = machine.run().unwrap_err();
+            assert!(error.is_instance_of::<BftError>(py));
+            assert!(error.to_string().contains("E0203"));
+            assert_eq!(machine.output(), b"hi");
+            assert_eq!(machine.tape()[0], b'i');
+            assert!(fork.run().is_err());
+            assert_eq!(fork.output(), b"hi!");
+
+            let error = Program::new("+]", "<test>").err().unwrap();
+            assert!(error.to_string().contains("E0101"));
+        });
+    }
+}
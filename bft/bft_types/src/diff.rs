@@ -0,0 +1,274 @@
+//! Instruction-level differences between two programs
+//!
+//! Comments and whitespace are ignored, so only changes that affect what a program does show up.
+
+use alloc::borrow::ToOwned;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{display_file, FileName, FileNameBuf, PositionedInstruction, Program, RawInstruction};
+
+/// A run of instructions that differ between two programs
+#[derive(Debug, Clone)]
+pub struct Hunk {
+    removed: Vec<PositionedInstruction>,
+    added: Vec<PositionedInstruction>,
+}
+
+impl Hunk {
+    fn empty() -> Hunk {
+        Hunk {
+            removed: Vec::new(),
+            added: Vec::new(),
+        }
+    }
+
+    /// Instructions only present in the old program
+    pub fn removed(&self) -> &[PositionedInstruction] {
+        &self.removed
+    }
+
+    /// Instructions only present in the new program
+    pub fn added(&self) -> &[PositionedInstruction] {
+        &self.added
+    }
+}
+
+/// Every difference between an old and a new program
+#[derive(Debug)]
+pub struct Diff {
+    old_file: FileNameBuf,
+    new_file: FileNameBuf,
+    hunks: Vec<Hunk>,
+}
+
+impl Diff {
+    /// Finds the smallest set of instructions to remove from `old` and add to it to get `new`
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_types::{diff::Diff, Program};
+    /// let old = Program::new("old.bf", "+++[>+<-] a comment");
+    /// let new = Program::new("new.bf", "+++\n[>++<-]");
+    /// let diff = Diff::new(&old, &new);
+    /// assert_eq!(diff.hunks().len(), 1);
+    /// assert_eq!(diff.hunks()[0].added()[0].line(), 2);
+    /// ```
+    pub fn new(old: &Program, new: &Program) -> Diff {
+        let old_instructions: Vec<&RawInstruction> =
+            old.instructions().iter().map(|i| i.instruction()).collect();
+        let new_instructions: Vec<&RawInstruction> =
+            new.instructions().iter().map(|i| i.instruction()).collect();
+
+        let mut hunks = Vec::new();
+        let mut current: Option<Hunk> = None;
+        for edit in shortest_edit(&old_instructions, &new_instructions) {
+            match edit {
+                Edit::Keep => hunks.extend(current.take()),
+                Edit::Remove(index) => current
+                    .get_or_insert_with(Hunk::empty)
+                    .removed
+                    .push(old.instructions()[index]),
+                Edit::Add(index) => current
+                    .get_or_insert_with(Hunk::empty)
+                    .added
+                    .push(new.instructions()[index]),
+            }
+        }
+        hunks.extend(current);
+
+        Diff {
+            old_file: old.file().to_owned(),
+            new_file: new.file().to_owned(),
+            hunks,
+        }
+    }
+
+    /// Returns whether the two programs contain the same instructions
+    pub fn is_empty(&self) -> bool {
+        self.hunks.is_empty()
+    }
+
+    /// Every run of differing instructions, in program order
+    pub fn hunks(&self) -> &[Hunk] {
+        &self.hunks
+    }
+
+    pub fn old_file(&self) -> &FileName {
+        &self.old_file
+    }
+
+    pub fn new_file(&self) -> &FileName {
+        &self.new_file
+    }
+}
+
+/// Formats the span of a run of instructions, e.g. `foo.bf:1:4-2:7`
+fn write_span(
+    f: &mut fmt::Formatter<'_>,
+    file: &FileName,
+    instructions: &[PositionedInstruction],
+) -> fmt::Result {
+    match (instructions.first(), instructions.last()) {
+        (Some(first), Some(last)) => write!(
+            f,
+            "{}:{}:{}-{}:{}",
+            display_file(file),
+            first.line(),
+            first.character(),
+            last.line(),
+            last.character()
+        ),
+        _ => write!(f, "{}:(none)", display_file(file)),
+    }
+}
+
+/// Formats instructions as the Brainfuck code they came from
+fn write_code(f: &mut fmt::Formatter<'_>, instructions: &[PositionedInstruction]) -> fmt::Result {
+    for instruction in instructions {
+        write!(f, "{:#}", instruction.instruction())?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for hunk in &self.hunks {
+            write!(f, "@@ ")?;
+            write_span(f, &self.old_file, &hunk.removed)?;
+            write!(f, " ")?;
+            write_span(f, &self.new_file, &hunk.added)?;
+            writeln!(f, " @@")?;
+            if !hunk.removed.is_empty() {
+                write!(f, "- ")?;
+                write_code(f, &hunk.removed)?;
+                writeln!(f)?;
+            }
+            if !hunk.added.is_empty() {
+                write!(f, "+ ")?;
+                write_code(f, &hunk.added)?;
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One step in turning the old sequence into the new one
+#[derive(Debug, PartialEq)]
+pub enum Edit {
+    /// The next item is in both sequences
+    Keep,
+    /// The item at this index of the old sequence is removed
+    Remove(usize),
+    /// The item at this index of the new sequence is added
+    Add(usize),
+}
+
+/// Finds a shortest edit script from `old` to `new` using Myers' algorithm
+///
+/// This works on any sequences, not just instructions, so it can also compare program output.
+///
+/// # Examples
+/// ```
+/// # use bft_types::diff::{shortest_edit, Edit};
+/// assert_eq!(
+///     shortest_edit(b"ab", b"b"),
+///     [Edit::Remove(0), Edit::Keep]
+/// );
+/// ```
+pub fn shortest_edit<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    // trace[d][k + d] is how far along `old` the furthest path with d edits reaches on diagonal k
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    'search: for d in 0..=(n + m) {
+        let mut row = vec![0; 2 * d as usize + 1];
+        for k in (-d..=d).step_by(2) {
+            let previous = |k: isize| trace[d as usize - 1][(k + d - 1) as usize];
+            let mut x = if d == 0 {
+                0
+            } else if k == -d || (k != d && previous(k - 1) < previous(k + 1)) {
+                previous(k + 1)
+            } else {
+                previous(k - 1) + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            row[(k + d) as usize] = x;
+            if x >= n && y >= m {
+                trace.push(row);
+                break 'search;
+            }
+        }
+        trace.push(row);
+    }
+
+    // Walk back from the end to recover which edits were made
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let previous = |k: isize| trace[d as usize - 1][(k + d - 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && previous(k - 1) < previous(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = previous(previous_k);
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            edits.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        if x == previous_x {
+            edits.push(Edit::Add(previous_y as usize));
+        } else {
+            edits.push(Edit::Remove(previous_x as usize));
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    edits.extend((0..x).map(|_| Edit::Keep));
+    edits.reverse();
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortest_edits() {
+        let edits = shortest_edit(b"abcabba", b"cbabac");
+        let changes = edits.iter().filter(|e| **e != Edit::Keep).count();
+        assert_eq!(changes, 5);
+        assert_eq!(shortest_edit(b"", b"ab"), [Edit::Add(0), Edit::Add(1)]);
+        assert_eq!(
+            shortest_edit(b"ab", b""),
+            [Edit::Remove(0), Edit::Remove(1)]
+        );
+        assert_eq!(shortest_edit(b"ab", b"ab"), [Edit::Keep, Edit::Keep]);
+    }
+
+    #[test]
+    fn comments_are_ignored() {
+        let old = Program::new("old.bf", "+ add one\n.");
+        let new = Program::new("new.bf", "+. print it");
+        assert!(Diff::new(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn display_shows_positions_and_code() {
+        let old = Program::new("old.bf", "+++.");
+        let new = Program::new("new.bf", "+\n--.");
+        let diff = Diff::new(&old, &new);
+        assert_eq!(
+            diff.to_string(),
+            "@@ old.bf:1:2-1:3 new.bf:2:1-2:2 @@\n- ++\n+ --\n"
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! Stable identities for programs
+//!
+//! A fingerprint only depends on a program's instructions, so reformatting or re-commenting a
+//! program doesn't change it. The hash (64-bit FNV-1a over the instruction characters) is fixed,
+//! so fingerprints can be stored and compared across runs and releases.
+
+use core::fmt;
+use core::str::FromStr;
+
+use crate::PositionedInstruction;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A stable hash of a program's instructions, ignoring comments and whitespace
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Fingerprint(u64);
+
+impl Fingerprint {
+    /// Hashes a sequence of instructions
+    pub fn of<'a>(
+        instructions: impl IntoIterator<Item = &'a PositionedInstruction>,
+    ) -> Fingerprint {
+        let mut hash = FNV_OFFSET_BASIS;
+        for instruction in instructions {
+            hash ^= u64::from(instruction.instruction().to_byte());
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+        Fingerprint(hash)
+    }
+
+    /// The fingerprint as a plain number
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for Fingerprint {
+    fn from(hash: u64) -> Fingerprint {
+        Fingerprint(hash)
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = core::num::ParseIntError;
+
+    /// Parses the hexadecimal form written by Display
+    fn from_str(s: &str) -> Result<Fingerprint, Self::Err> {
+        u64::from_str_radix(s, 16).map(Fingerprint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DecoratedProgram, Program};
+
+    #[test]
+    fn comments_dont_matter() {
+        let plain = Program::new("a.bf", "+[>.<-]");
+        let commented = Program::new("b.bf", "add one +\n[ > print . < - ] done");
+        assert_eq!(plain.fingerprint(), commented.fingerprint());
+        assert_ne!(
+            plain.fingerprint(),
+            Program::new("c.bf", "+[>.<+]").fingerprint()
+        );
+    }
+
+    #[test]
+    fn fingerprints_are_stable() {
+        // FNV-1a of the empty string and of "+"
+        assert_eq!(
+            Program::new("a.bf", "").fingerprint().as_u64(),
+            0xcbf29ce484222325
+        );
+        let prog = Program::new("a.bf", "+");
+        assert_eq!(prog.fingerprint().to_string(), "af63a64c860190ca");
+        let decorated = DecoratedProgram::from_program(&prog).unwrap();
+        assert_eq!(decorated.fingerprint(), prog.fingerprint());
+        assert_eq!(
+            prog.fingerprint().to_string().parse(),
+            Ok(prog.fingerprint())
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! Generating random valid programs, for property tests and fuzzing
+//!
+//! With the `arbitrary` feature, [Program] and [DecoratedProgram] implement
+//! [Arbitrary](arbitrary::Arbitrary). Generated programs always have balanced brackets, so
+//! decorating one never fails. [seeded_bytes] makes reproducible data to generate them from.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{DecoratedProgram, Program};
+
+/// What generated programs are made of, besides brackets: the other instructions, newlines so
+/// that positions span several lines, and a comment character
+const BODY: &[u8] = b"+-<>,.\n#";
+
+/// Bytes that look random, but come out the same for the same seed, which moves on past them
+///
+/// # Examples
+/// ```
+/// # use bft_types::generate::seeded_bytes;
+/// let (mut first, mut second) = (1, 1);
+/// assert_eq!(seeded_bytes(&mut first, 8), seeded_bytes(&mut second, 8));
+/// assert_ne!(seeded_bytes(&mut first, 8), seeded_bytes(&mut 1, 8));
+/// ```
+pub fn seeded_bytes(seed: &mut u32, length: usize) -> Vec<u8> {
+    (0..length)
+        .map(|_| {
+            *seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (*seed >> 16) as u8
+        })
+        .collect()
+}
+
+impl<'a> Arbitrary<'a> for Program {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Program> {
+        let mut text = String::new();
+        let mut depth = 0;
+        for _ in 0..u.arbitrary_len::<u8>()? {
+            match u.int_in_range(0..=9)? {
+                0 => {
+                    text.push('[');
+                    depth += 1;
+                }
+                1 if depth > 0 => {
+                    text.push(']');
+                    depth -= 1;
+                }
+                _ => text.push(char::from(*u.choose(BODY)?)),
+            }
+        }
+        text.push_str(&"]".repeat(depth));
+        Ok(Program::new("<arbitrary>", &text))
+    }
+}
+
+impl<'a> Arbitrary<'a> for DecoratedProgram {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<DecoratedProgram> {
+        let prog = Program::arbitrary(u)?;
+        DecoratedProgram::from_program(&prog).map_err(|_| arbitrary::Error::IncorrectFormat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_programs_always_decorate() {
+        let mut seed = 1;
+        for _ in 0..200 {
+            let data = seeded_bytes(&mut seed, 256);
+            let prog = Program::arbitrary(&mut Unstructured::new(&data)).unwrap();
+            assert!(DecoratedProgram::from_program(&prog).is_ok());
+        }
+    }
+}
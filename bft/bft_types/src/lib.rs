@@ -1,13 +1,56 @@
 //! Brainfuck types library
 //! A description of the brainfuck language model, translated from text into rust data structures.
+//!
+//! Everything but reading programs from files works without the standard library: turn off the
+//! default `std` feature to use this crate with just `alloc`.
 
-use std::fmt;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::borrow::ToOwned;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
 use std::fs::File;
+#[cfg(feature = "std")]
 use std::io::{BufReader, Read};
-use std::path::{Path, PathBuf};
-use std::string::String;
+#[cfg(feature = "std")]
+use std::path::Path;
 use thiserror::Error;
 
+pub mod diff;
+pub mod fingerprint;
+#[cfg(feature = "arbitrary")]
+pub mod generate;
+pub mod span;
+
+use fingerprint::Fingerprint;
+use span::SourceSpan;
+
+/// The name of the file a program came from
+///
+/// This is a [Path] with the `std` feature, and plain text without it.
+#[cfg(feature = "std")]
+pub type FileName = std::path::Path;
+/// The name of the file a program came from
+///
+/// This is a `Path` with the `std` feature, and plain text without it.
+#[cfg(not(feature = "std"))]
+pub type FileName = str;
+
+/// An owned [FileName]
+pub type FileNameBuf = <FileName as ToOwned>::Owned;
+
+/// Shows a file name the same way with or without the `std` feature
+pub fn display_file(file: &FileName) -> impl fmt::Display + '_ {
+    #[cfg(feature = "std")]
+    return file.display();
+    #[cfg(not(feature = "std"))]
+    return file;
+}
+
 /// An enum of every possible instruction Brainfuck can execute
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum RawInstruction {
@@ -46,10 +89,41 @@ impl RawInstruction {
             _ => None,
         }
     }
+
+    /// The character that represents the instruction in Brainfuck code, the inverse of
+    /// [RawInstruction::from_byte]
+    /// # Examples
+    /// ```
+    /// # use bft_types::RawInstruction;
+    /// assert_eq!(RawInstruction::CloseLoop.to_byte(), b']');
+    /// assert_eq!(RawInstruction::from_byte(b',').unwrap().to_byte(), b',');
+    /// ```
+    pub fn to_byte(self) -> u8 {
+        match self {
+            RawInstruction::IncrementDataPointer => b'>',
+            RawInstruction::DecrementDataPointer => b'<',
+            RawInstruction::IncrementByte => b'+',
+            RawInstruction::DecrementByte => b'-',
+            RawInstruction::PutByte => b'.',
+            RawInstruction::GetByte => b',',
+            RawInstruction::OpenLoop => b'[',
+            RawInstruction::CloseLoop => b']',
+        }
+    }
 }
 
+/// Describes the instruction in English, or with `{:#}`, writes it as Brainfuck code
+/// # Examples
+/// ```
+/// # use bft_types::RawInstruction;
+/// assert_eq!(format!("{}", RawInstruction::OpenLoop), "Start looping");
+/// assert_eq!(format!("{:#}", RawInstruction::OpenLoop), "[");
+/// ```
 impl fmt::Display for RawInstruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return write!(f, "{}", self.to_byte() as char);
+        }
         f.write_str(match self {
             Self::IncrementDataPointer => "Increment current location",
             Self::DecrementDataPointer => "Decrement current location",
@@ -69,6 +143,8 @@ pub struct PositionedInstruction {
     instruction: RawInstruction,
     line: usize,
     character: usize,
+    /// How many bytes into the source the instruction is
+    offset: usize,
 }
 
 impl PositionedInstruction {
@@ -83,6 +159,22 @@ impl PositionedInstruction {
     pub fn character(&self) -> usize {
         self.character
     }
+
+    /// How many bytes into the source the instruction is
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Where the instruction is, given the file it came from
+    pub fn span(&self, file: &FileName) -> SourceSpan {
+        SourceSpan {
+            file: file.to_owned(),
+            line: self.line,
+            column: self.character,
+            offset: self.offset,
+            len: 1,
+        }
+    }
 }
 
 impl fmt::Display for PositionedInstruction {
@@ -105,44 +197,41 @@ pub enum DecoratedInstruction {
     },
     /// An ordinary instruction that can be used as-is
     Instruction(PositionedInstruction),
-    /// An open-bracket instruction that gets replaced when the loop is closed
-    ///
-    /// This is expected to only exist as a temporary implementation detail.
-    PlaceholderOpenBracket,
 }
 
 impl DecoratedInstruction {
     pub fn instruction(&self) -> PositionedInstruction {
-        // :-/ That placeholder variant sure is a nuisance!
-        assert!(!matches!(self, Self::PlaceholderOpenBracket));
-
         match self {
             Self::OpenLoop { instruction, .. } => *instruction,
             Self::CloseLoop { instruction, .. } => *instruction,
             Self::Instruction(instruction) => *instruction,
-            Self::PlaceholderOpenBracket => unreachable!(),
         }
     }
 }
 
 impl fmt::Display for DecoratedInstruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        assert!(!matches!(self, Self::PlaceholderOpenBracket));
         write!(f, "{}", self.instruction())
     }
 }
 
 /// A program that's been processed into a form useful to an interpreter
 /// Compared to a Program, this has the additional constraint that the code must be valid Brainfuck.
+#[derive(Clone)]
 pub struct DecoratedProgram {
-    file: PathBuf,
+    file: FileNameBuf,
     decorated_instructions: Vec<DecoratedInstruction>,
 }
 
+/// Describes each instruction on its own line, or with `{:#}`, writes the code as Program does
 impl fmt::Display for DecoratedProgram {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            let instructions = self.decorated_instructions().iter();
+            return write_code(f, instructions.map(|i| *i.instruction().instruction()));
+        }
         for instruction in self.decorated_instructions() {
-            writeln!(f, "{}:{}", self.file().display(), instruction,)?
+            writeln!(f, "{}:{}", display_file(self.file()), instruction,)?
         }
         Ok(())
     }
@@ -151,47 +240,80 @@ impl fmt::Display for DecoratedProgram {
 /// Errors that may occur while parsing a Brainfuck program.
 #[derive(Debug, Error)]
 pub enum ParseError {
-    /// A bracket was opened, but never closed
+    /// A closing bracket was found before an opening bracket
     UnopenedBracket {
         closer: PositionedInstruction,
-        source_file: PathBuf,
+        source_file: FileNameBuf,
     },
-    /// A closing bracket was found before an opening bracket
+    /// A bracket was opened, but never closed
     UnclosedBracket {
         opener: PositionedInstruction,
-        source_file: PathBuf,
+        source_file: FileNameBuf,
     },
 }
 
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+/// What kind of mistake a [ParseError] is, without the details of where it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnopenedBracket,
+    UnclosedBracket,
+}
+
+impl ParseError {
+    /// The bracket that has no partner
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_types::{DecoratedProgram, ParseErrorKind, Program};
+    /// let prog = Program::new("<Test program>", "+\n+]");
+    /// let error = DecoratedProgram::from_program(&prog).err().unwrap();
+    /// assert_eq!(error.kind(), ParseErrorKind::UnopenedBracket);
+    /// assert_eq!((error.position().line(), error.position().character()), (2, 2));
+    /// assert_eq!(error.file(), std::path::Path::new("<Test program>"));
+    /// ```
+    pub fn position(&self) -> PositionedInstruction {
         match self {
-            Self::UnopenedBracket {
-                closer,
-                source_file,
-            } => {
-                write!(
-                    f,
-                    "In input file {}, closed a loop with no matching opener at line {}, column {}",
-                    source_file.to_string_lossy(),
-                    closer.line(),
-                    closer.character()
-                )
-            }
-            Self::UnclosedBracket {
-                opener,
-                source_file,
-            } => {
-                write!(
-                    f,
-                    "In input file {}, opened a loop that wasn't closed at line {}, column {}",
-                    source_file.to_string_lossy(),
-                    opener.line(),
-                    opener.character()
-                )
-            }
+            Self::UnopenedBracket { closer, .. } => *closer,
+            Self::UnclosedBracket { opener, .. } => *opener,
         }
     }
+
+    /// Where the bracket that has no partner is
+    pub fn span(&self) -> SourceSpan {
+        self.position().span(self.file())
+    }
+
+    /// The file the program was read from
+    pub fn file(&self) -> &FileName {
+        match self {
+            Self::UnopenedBracket { source_file, .. } => source_file,
+            Self::UnclosedBracket { source_file, .. } => source_file,
+        }
+    }
+
+    /// What kind of mistake this is
+    pub fn kind(&self) -> ParseErrorKind {
+        match self {
+            Self::UnopenedBracket { .. } => ParseErrorKind::UnopenedBracket,
+            Self::UnclosedBracket { .. } => ParseErrorKind::UnclosedBracket,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let problem = match self.kind() {
+            ParseErrorKind::UnopenedBracket => "closed a loop with no matching opener",
+            ParseErrorKind::UnclosedBracket => "opened a loop that wasn't closed",
+        };
+        write!(
+            f,
+            "In input file {}, {problem} at line {}, column {}",
+            display_file(self.file()),
+            self.position().line(),
+            self.position().character()
+        )
+    }
 }
 
 impl DecoratedProgram {
@@ -216,68 +338,123 @@ impl DecoratedProgram {
     /// assert!(bft_types::DecoratedProgram::from_program(&raw_prog).is_err());
     /// ```
     pub fn from_program(prog: &Program) -> Result<DecoratedProgram, ParseError> {
+        let _span =
+            tracing::debug_span!("match_brackets", file = %display_file(prog.file())).entered();
+        let instructions = prog.instructions();
+
+        // First pair up the brackets, so every instruction can be decorated in one go afterwards
+        let mut partners = vec![0; instructions.len()];
         let mut bracket_stack = Vec::new();
-        let mut decorated_instructions: Vec<DecoratedInstruction> = Vec::new();
-        for (index, instruction) in prog.instructions().iter().enumerate() {
+        for (index, instruction) in instructions.iter().enumerate() {
             match instruction.instruction() {
-                RawInstruction::OpenLoop => {
-                    bracket_stack.push((index, instruction));
-
-                    decorated_instructions.push(DecoratedInstruction::PlaceholderOpenBracket);
-                }
+                RawInstruction::OpenLoop => bracket_stack.push(index),
                 RawInstruction::CloseLoop => {
-                    let opener = bracket_stack.pop();
-                    if opener.is_none() {
+                    let Some(opener) = bracket_stack.pop() else {
                         return Err(ParseError::UnopenedBracket {
                             closer: *instruction,
-                            source_file: prog.file().to_path_buf(),
+                            source_file: prog.file().to_owned(),
                         });
                     };
-                    // Now that we've closed the loop, go back and decorate the opener.
-                    decorated_instructions[opener.unwrap().0] = DecoratedInstruction::OpenLoop {
-                        instruction: *(opener.unwrap().1),
-                        closer: *instruction,
-                    };
-
-                    decorated_instructions.push(DecoratedInstruction::CloseLoop {
-                        instruction: *instruction,
-                        opener: *(opener.unwrap().1),
-                    });
+                    partners[opener] = index;
+                    partners[index] = opener;
                 }
-                _ => decorated_instructions.push(DecoratedInstruction::Instruction(*instruction)),
-            };
+                _ => (),
+            }
         }
-        if !bracket_stack.is_empty() {
+        if let Some(opener) = bracket_stack.pop() {
             return Err(ParseError::UnclosedBracket {
-                opener: *(bracket_stack.pop().unwrap().1),
-                source_file: prog.file().to_path_buf(),
+                opener: instructions[opener],
+                source_file: prog.file().to_owned(),
             });
-        };
+        }
 
-        // Double-check I haven't left placeholders lying around
-        assert!(decorated_instructions
+        let decorated_instructions = instructions
             .iter()
-            .all(|i| !matches!(i, DecoratedInstruction::PlaceholderOpenBracket)));
+            .zip(partners)
+            .map(|(instruction, partner)| match instruction.instruction() {
+                RawInstruction::OpenLoop => DecoratedInstruction::OpenLoop {
+                    instruction: *instruction,
+                    closer: instructions[partner],
+                },
+                RawInstruction::CloseLoop => DecoratedInstruction::CloseLoop {
+                    instruction: *instruction,
+                    opener: instructions[partner],
+                },
+                _ => DecoratedInstruction::Instruction(*instruction),
+            })
+            .collect();
 
         Ok(DecoratedProgram {
-            file: prog.file().to_path_buf(),
+            file: prog.file().to_owned(),
             decorated_instructions,
         })
     }
 
-    pub fn file(&self) -> &Path {
+    pub fn file(&self) -> &FileName {
         &self.file
     }
 
     pub fn decorated_instructions(&self) -> &[DecoratedInstruction] {
         self.decorated_instructions.as_ref()
     }
+
+    /// Returns where the instruction at an index is, or None past the end
+    pub fn span(&self, index: usize) -> Option<SourceSpan> {
+        let instruction = self.decorated_instructions.get(index)?.instruction();
+        Some(instruction.span(&self.file))
+    }
+
+    /// Returns the line and character of the instruction at an index, or None past the end
+    /// # Examples
+    /// ```
+    /// # use bft_types;
+    /// let raw_prog = bft_types::Program::new("<Test program>", "+\n [-]");
+    /// let prog = bft_types::DecoratedProgram::from_program(&raw_prog).unwrap();
+    /// assert_eq!(prog.index_to_position(2), Some((2, 3)));
+    /// assert_eq!(prog.index_to_position(4), None);
+    /// ```
+    pub fn index_to_position(&self, index: usize) -> Option<(usize, usize)> {
+        let instruction = self.decorated_instructions.get(index)?.instruction();
+        Some((instruction.line(), instruction.character()))
+    }
+
+    /// Returns the index of the instruction at a line and character, or None if there isn't one
+    /// there, such as in a comment
+    /// # Examples
+    /// ```
+    /// # use bft_types;
+    /// let raw_prog = bft_types::Program::new("<Test program>", "+\n [-]");
+    /// let prog = bft_types::DecoratedProgram::from_program(&raw_prog).unwrap();
+    /// assert_eq!(prog.position_to_index(2, 3), Some(2));
+    /// assert_eq!(prog.position_to_index(2, 1), None);
+    /// ```
+    pub fn position_to_index(&self, line: usize, character: usize) -> Option<usize> {
+        // Instructions are in the order they appear in the source
+        self.decorated_instructions
+            .binary_search_by_key(&(line, character), |i| {
+                let instruction = i.instruction();
+                (instruction.line(), instruction.character())
+            })
+            .ok()
+    }
+
+    /// Returns a stable hash of the program's instructions
+    ///
+    /// This is the same as the fingerprint of the Program it was decorated from.
+    pub fn fingerprint(&self) -> Fingerprint {
+        let instructions: Vec<PositionedInstruction> = self
+            .decorated_instructions
+            .iter()
+            .map(|i| i.instruction())
+            .collect();
+        Fingerprint::of(&instructions)
+    }
 }
 
 /// A collection of all the brainfuck instructions within a single source file
 #[derive(Debug)]
 pub struct Program {
-    file: PathBuf,
+    file: FileNameBuf,
     instructions: Vec<PositionedInstruction>,
 }
 
@@ -290,12 +467,29 @@ impl Program {
     /// let filepath = "my_file.bf";
     /// let prog: std::io::Result<bft_types::Program> = bft_types::Program::from_file(&filepath);
     /// ```
+    #[cfg(feature = "std")]
     pub fn from_file<T: AsRef<Path>>(file: T) -> std::io::Result<Program> {
-        let file: PathBuf = file.as_ref().to_path_buf();
-        // Load the text from the path, pass it into new.
+        Self::from_reader(&file, BufReader::new(File::open(file.as_ref())?))
+    }
+
+    /// Reads all the text from a reader and converts it into a brainfuck program.
+    ///
+    /// `filename` is only used to describe where instructions came from.
+    /// # Examples
+    /// ```
+    /// # use bft_types;
+    /// let text = std::io::Cursor::new("[,.]");
+    /// let prog = bft_types::Program::from_reader("<stdin>", text).unwrap();
+    /// assert_eq!(prog.instructions().len(), 4);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn from_reader<T: AsRef<Path>>(
+        filename: T,
+        mut reader: impl Read,
+    ) -> std::io::Result<Program> {
         let mut text = String::new();
-        BufReader::new(File::open(&file)?).read_to_string(&mut text)?;
-        Ok(Self::new(file, &text))
+        reader.read_to_string(&mut text)?;
+        Ok(Self::new(filename, &text))
     }
 
     /// Converts a string into a brainfuck program.
@@ -306,38 +500,105 @@ impl Program {
     /// let text = "[,.]";
     /// let prog: bft_types::Program = bft_types::Program::new(&filename, &text);
     /// ```
-    pub fn new<T: AsRef<Path>>(filename: T, text: &str) -> Program {
+    pub fn new<T: AsRef<FileName>>(filename: T, text: &str) -> Program {
+        let _span =
+            tracing::debug_span!("parse", file = %display_file(filename.as_ref())).entered();
         let mut instructions: Vec<PositionedInstruction> = Vec::new();
         for (line_index, line) in text.lines().enumerate() {
+            // Lines are slices of the text, so this is how far into it the line starts
+            let line_offset = line.as_ptr() as usize - text.as_ptr() as usize;
             for (char_index, byte) in line.bytes().enumerate() {
                 if let Some(instruction) = RawInstruction::from_byte(byte) {
                     instructions.push(PositionedInstruction {
                         instruction,
                         line: line_index + 1,
                         character: char_index + 1,
+                        offset: line_offset + char_index,
                     });
                 }
             }
         }
+        tracing::debug!(
+            bytes = text.len(),
+            instructions = instructions.len(),
+            "Parsed program"
+        );
         Program {
-            file: filename.as_ref().to_path_buf(),
+            file: filename.as_ref().to_owned(),
             instructions,
         }
     }
 
-    pub fn file(&self) -> &Path {
+    pub fn file(&self) -> &FileName {
         &self.file
     }
 
     pub fn instructions(&self) -> &[PositionedInstruction] {
         &self.instructions
     }
+
+    /// Returns where the instruction at an index is, or None past the end
+    pub fn span(&self, index: usize) -> Option<SourceSpan> {
+        Some(self.instructions.get(index)?.span(&self.file))
+    }
+
+    /// Returns a stable hash of the program's instructions, ignoring comments and whitespace
+    /// # Examples
+    /// ```
+    /// # use bft_types;
+    /// let prog = bft_types::Program::new("a.bf", "+[-]");
+    /// let reformatted = bft_types::Program::new("b.bf", "+ [ - ] clear the cell");
+    /// assert_eq!(prog.fingerprint(), reformatted.fingerprint());
+    /// ```
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::of(&self.instructions)
+    }
 }
 
+/// How many characters of code `{:#}` puts on a line if the format doesn't give a width
+const CODE_WIDTH: usize = 80;
+
+/// Writes instructions as Brainfuck code, starting a new line after every so many
+fn write_code(
+    f: &mut fmt::Formatter<'_>,
+    instructions: impl Iterator<Item = RawInstruction>,
+) -> fmt::Result {
+    let width = f.width().unwrap_or(CODE_WIDTH).max(1);
+    let mut column = 0;
+    for instruction in instructions {
+        if column == width {
+            writeln!(f)?;
+            column = 0;
+        }
+        write!(f, "{instruction:#}")?;
+        column += 1;
+    }
+    if column > 0 {
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+/// Describes each instruction on its own line, with its file and position
+///
+/// With `{:#}`, writes the program's code instead, without comments, wrapped at the format's
+/// width or 80 characters.
+/// # Examples
+/// ```
+/// # use bft_types;
+/// let prog = bft_types::Program::new("a.bf", "+[->+<] add the cells");
+/// assert_eq!(format!("{prog:#}"), "+[->+<]\n");
+/// assert_eq!(format!("{prog:#4}"), "+[->\n+<]\n");
+/// assert!(format!("{prog}").starts_with("a.bf:1:1 Increment the byte"));
+/// ```
 impl fmt::Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            let instructions = self.instructions().iter();
+            return write_code(f, instructions.map(|i| *i.instruction()));
+        }
         for instruction in self.instructions() {
-            writeln!(f, "{}:{}", self.file().display(), instruction,)?
+            writeln!(f, "{}:{}", display_file(self.file()), instruction,)?
         }
         Ok(())
     }
@@ -0,0 +1,56 @@
+//! Where in a source file something is
+//!
+//! A [SourceSpan] names the file, the line and column, and the range of bytes, so tools can
+//! report a position or pick the text out of the file without working it out from an instruction
+//! themselves.
+
+use core::fmt;
+use core::ops::Range;
+
+use crate::{display_file, FileNameBuf};
+
+/// A run of bytes in a source file
+///
+/// Displays as `file:line:column`, the way compilers report positions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceSpan {
+    pub file: FileNameBuf,
+    /// The line the span starts on, counting from 1
+    pub line: usize,
+    /// The byte within the line the span starts at, counting from 1
+    pub column: usize,
+    /// How many bytes into the file the span starts
+    pub offset: usize,
+    /// How many bytes the span covers
+    pub len: usize,
+}
+
+impl SourceSpan {
+    /// The bytes of the file the span covers
+    ///
+    /// # Examples
+    /// ```
+    /// # use bft_types::Program;
+    /// let text = "read: ,\nwrite: .";
+    /// let prog = Program::new("echo.bf", text);
+    /// let span = prog.span(1).unwrap();
+    /// assert_eq!((span.line, span.column), (2, 8));
+    /// assert_eq!(&text[span.range()], ".");
+    /// assert_eq!(span.to_string(), "echo.bf:2:8");
+    /// ```
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.len
+    }
+}
+
+impl fmt::Display for SourceSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            display_file(&self.file),
+            self.line,
+            self.column
+        )
+    }
+}
@@ -0,0 +1,266 @@
+//! WebAssembly bindings, for building a browser playground or visualiser on the interpreter
+//!
+//! ```js
+//! import init, { Program, Session, check } from "./bft_wasm.js";
+//!
+//! await init();
+//! const program = new Program(",[.,]");
+//! const session = new Session(
+//!     program,
+//!     (byte) => console.log(String.fromCharCode(byte)),
+//!     () => nextKeyPress(), // a byte, or undefined once there's no more input
+//! );
+//! // Run in slices, so the page stays responsive while the program runs
+//! while (session.run(10000)) {
+//!     await new Promise(requestAnimationFrame);
+//! }
+//! ```
+
+use std::io::{self, Read, Write};
+
+use bft_interp::analysis::Analysis;
+use bft_interp::{Machine, VMError};
+use bft_types::DecoratedProgram;
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+
+/// A parsed program whose brackets all match
+#[wasm_bindgen]
+pub struct Program {
+    decorated: DecoratedProgram,
+}
+
+#[wasm_bindgen]
+impl Program {
+    /// Parses a program, failing with a message saying where the brackets don't match
+    #[wasm_bindgen(constructor)]
+    pub fn new(source: &str) -> Result<Program, JsError> {
+        let prog = bft_types::Program::new("<playground>", source);
+        let decorated = DecoratedProgram::from_program(&prog)?;
+        Ok(Program { decorated })
+    }
+
+    /// How many instructions the program has, leaving out comments
+    pub fn length(&self) -> usize {
+        self.decorated.decorated_instructions().len()
+    }
+
+    /// The line instruction `index` is on, counting from 1
+    pub fn line(&self, index: usize) -> Option<usize> {
+        let instruction = self.decorated.decorated_instructions().get(index)?;
+        Some(instruction.instruction().line())
+    }
+
+    /// The column instruction `index` is in, counting from 1
+    pub fn column(&self, index: usize) -> Option<usize> {
+        let instruction = self.decorated.decorated_instructions().get(index)?;
+        Some(instruction.instruction().character())
+    }
+}
+
+/// Checks a program without running it, returning a message for each mistake or suspicious piece
+/// of code
+#[wasm_bindgen]
+pub fn check(source: &str) -> Vec<String> {
+    let prog = bft_types::Program::new("<playground>", source);
+    match DecoratedProgram::from_program(&prog) {
+        Ok(decorated) => Analysis::from_program(&decorated)
+            .findings()
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        Err(error) => vec![error.to_string()],
+    }
+}
+
+/// A program being run, which asks JavaScript for its input and hands it its output
+#[wasm_bindgen]
+pub struct Session {
+    machine: Machine<'static, u8>,
+    output: Box<dyn Write>,
+    input: Box<dyn Read>,
+}
+
+#[wasm_bindgen]
+impl Session {
+    /// Starts running `program`
+    ///
+    /// `on_output` is called with each byte the program writes. `on_input` is called whenever the
+    /// program reads, and returns the byte to read, or undefined if there's no more input.
+    #[wasm_bindgen(constructor)]
+    pub fn new(program: &Program, on_output: Function, on_input: Function) -> Session {
+        let input = Input(move || {
+            let value = on_input.call0(&JsValue::NULL).map_err(js_failure)?;
+            Ok(value.as_f64().map(|byte| byte as u8))
+        });
+        let output = Output(move |byte| {
+            on_output
+                .call1(&JsValue::NULL, &JsValue::from(byte))
+                .map(|_| ())
+                .map_err(js_failure)
+        });
+        Session::with_io(program, input, output)
+    }
+
+    /// Executes one instruction, returning false once the program has finished
+    pub fn step(&mut self) -> Result<bool, JsError> {
+        Ok(self.step_machine()?)
+    }
+
+    /// Executes up to `steps` instructions, returning false once the program has finished
+    pub fn run(&mut self, steps: u32) -> Result<bool, JsError> {
+        Ok(self.run_machine(steps)?)
+    }
+
+    /// The cells of the tape
+    pub fn cells(&self) -> Vec<u8> {
+        self.machine.cells().to_vec()
+    }
+
+    /// Which cell the head is on
+    pub fn head(&self) -> usize {
+        self.machine.head()
+    }
+
+    /// How many instructions have been executed
+    pub fn steps(&self) -> f64 {
+        self.machine.steps() as f64
+    }
+}
+
+impl Session {
+    /// Starts running `program` with Rust input and output, which is what the JavaScript
+    /// callbacks are wrapped in
+    fn with_io(
+        program: &Program,
+        input: impl Read + 'static,
+        output: impl Write + 'static,
+    ) -> Session {
+        Session {
+            machine: Machine::owning(None, false, program.decorated.clone()),
+            output: Box::new(output),
+            input: Box::new(input),
+        }
+    }
+
+    fn step_machine(&mut self) -> Result<bool, VMError> {
+        self.machine.step(&mut self.input, &mut self.output)
+    }
+
+    fn run_machine(&mut self, steps: u32) -> Result<bool, VMError> {
+        for _ in 0..steps {
+            if !self.step_machine()? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Turns an exception thrown by a callback into an I/O error
+fn js_failure(exception: JsValue) -> io::Error {
+    io::Error::other(format!("{exception:?}"))
+}
+
+/// Input that asks a function for each byte, which returns None once there's no more
+struct Input<F>(F);
+
+impl<F: FnMut() -> io::Result<Option<u8>>> Read for Input<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(first) = buf.first_mut() else {
+            return Ok(0);
+        };
+        match (self.0)()? {
+            Some(byte) => {
+                *first = byte;
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+/// Output that hands each byte to a function
+struct Output<F>(F);
+
+impl<F: FnMut(u8) -> io::Result<()>> Write for Output<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for byte in buf {
+            (self.0)(*byte)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    #[test]
+    fn check_reports_mistakes() {
+        assert!(check("+[-].").is_empty());
+        assert_eq!(check("+[-").len(), 1);
+        assert_eq!(check("+[]").len(), 1);
+    }
+
+    #[test]
+    fn programs_know_where_their_instructions_are() {
+        let program = Program::new("+ comment\n  [-]").unwrap();
+        assert_eq!(program.length(), 4);
+        assert_eq!((program.line(0), program.column(0)), (Some(1), Some(1)));
+        assert_eq!((program.line(2), program.column(2)), (Some(2), Some(4)));
+        assert_eq!((program.line(4), program.column(4)), (None, None));
+    }
+
+    /// A session on `source`, reading `input` then running out, and what it has written so far
+    fn session(source: &str, input: &[u8]) -> (Session, Rc<RefCell<Vec<u8>>>) {
+        let mut input: VecDeque<u8> = input.iter().copied().collect();
+        let written = Rc::new(RefCell::new(Vec::new()));
+        let output = Rc::clone(&written);
+        let session = Session::with_io(
+            &Program::new(source).unwrap(),
+            Input(move || Ok(input.pop_front())),
+            Output(move |byte| {
+                output.borrow_mut().push(byte);
+                Ok(())
+            }),
+        );
+        (session, written)
+    }
+
+    #[test]
+    fn sessions_run_in_slices() {
+        let (mut session, written) = session(",[.,]", b"hi");
+        assert!(session.step_machine().unwrap());
+        assert_eq!((session.cells()[0], session.steps()), (b'h', 1.0));
+        assert!(session.run_machine(3).unwrap());
+        assert_eq!(*written.borrow(), b"h");
+        // Running out of input is the end of file, which is an error by default
+        let Err(VMError::IOError { source, .. }) = session.run_machine(100) else {
+            panic!("reading past the end of the input should fail");
+        };
+        assert_eq!(source.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(*written.borrow(), b"hi");
+        assert_eq!((session.head(), session.steps()), (0, 7.0));
+    }
+
+    #[test]
+    fn callback_failures_stop_the_session() {
+        let mut session = Session::with_io(
+            &Program::new("+.").unwrap(),
+            Input(|| Ok(None)),
+            Output(|_| Err(io::Error::other("thrown"))),
+        );
+        assert!(matches!(
+            session.run_machine(10),
+            Err(VMError::IOError { .. })
+        ));
+    }
+}
@@ -0,0 +1,8 @@
+[nested.b -- counts through three nested loops of 255 iterations each
+then prints an exclamation mark
+
+Almost all of the time is spent moving a value between two cells
+in the innermost loop; about sixteen million times]
+
+-[>-[>-[>+<-]<-]<-]
+++++++++++[>+++<-]>+++.[-]++++++++++.
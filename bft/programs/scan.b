@@ -0,0 +1,10 @@
+[scan.b -- lays out a run of 255 non-zero cells then walks the head
+from one end of it to the other and back 65025 times before printing
+an exclamation mark
+
+Almost all of the time is spent in the two scan loops looking for the
+zero cells at each end of the run]
+
+>>>-[[->+<]+>-]<[<]<<
+-[>-[>>[>]<[<]<-]<-]
+++++++++++[>+++<-]>+++.[-]++++++++++.
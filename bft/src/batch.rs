@@ -0,0 +1,171 @@
+//! Running one program over many inputs at once
+
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bft_interp::messages::{Lang, Localize};
+use bft_interp::{bytecode::Bytecode, limits::Limits, pool::Pool};
+use bft_types::DecoratedProgram;
+
+use crate::cli::{run_engine, Engine};
+use crate::json;
+use crate::messages::{Message, MessageId};
+
+/// What happened when the program ran on one input
+pub(crate) struct Outcome {
+    pub(crate) input: PathBuf,
+    /// The error the program stopped with, if it didn't finish normally
+    pub(crate) error: Option<String>,
+    pub(crate) output: Vec<u8>,
+    pub(crate) steps: u64,
+    pub(crate) time: Duration,
+}
+
+/// Runs the program once for each input file, spread across every available core
+///
+/// The outcomes are in the same order as the inputs, with errors written in `lang`. Each core
+/// reuses the same Machine from one input to the next.
+pub(crate) fn run_all(
+    prog: &DecoratedProgram,
+    bytecode: &Bytecode,
+    engine: Engine,
+    limits: Limits,
+    inputs: &[PathBuf],
+    lang: Lang,
+) -> Vec<Outcome> {
+    let mut pool = Pool::with_bytecode(None, false, prog, bytecode.clone());
+    pool.set_limits(limits);
+    let pool = &pool;
+    let next = AtomicUsize::new(0);
+    let outcomes: Mutex<Vec<Option<Outcome>>> = Mutex::new(inputs.iter().map(|_| None).collect());
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    thread::scope(|scope| {
+        for _ in 0..workers.min(inputs.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(input) = inputs.get(index) else {
+                    break;
+                };
+                let outcome = run_one(pool, engine, input, lang);
+                outcomes
+                    .lock()
+                    .expect("No worker panics while holding the lock")[index] = Some(outcome);
+            });
+        }
+    });
+    outcomes
+        .into_inner()
+        .expect("No worker panics while holding the lock")
+        .into_iter()
+        .map(|outcome| outcome.expect("Every input is run"))
+        .collect()
+}
+
+fn run_one(pool: &Pool<u8>, engine: Engine, input: &Path, lang: Lang) -> Outcome {
+    let mut output = Vec::new();
+    let start = Instant::now();
+    let mut machine = pool.get();
+    let error = match fs::read(input) {
+        Ok(data) => run_engine(
+            engine,
+            &mut machine,
+            pool.bytecode(),
+            &mut Cursor::new(data),
+            &mut output,
+            &mut (),
+        )
+        .err()
+        .map(|e| e.localized(lang).to_string()),
+        Err(e) => Some(
+            Message::new(MessageId::UnreadableFile)
+                .with("file", input.display())
+                .with("error", e)
+                .localized(lang)
+                .to_string(),
+        ),
+    };
+    Outcome {
+        input: input.to_path_buf(),
+        error,
+        output,
+        steps: machine.steps(),
+        time: start.elapsed(),
+    }
+}
+
+/// Describes the outcomes as a JSON object
+pub(crate) fn report_json(program: &Path, outcomes: &[Outcome]) -> String {
+    let results: Vec<String> = outcomes
+        .iter()
+        .map(|outcome| {
+            format!(
+                r#"{{"input":{},"status":"{}","error":{},"output_bytes":{},"steps":{},"seconds":{}}}"#,
+                json::string(&outcome.input.display().to_string()),
+                if outcome.error.is_none() { "ok" } else { "error" },
+                outcome.error.as_deref().map_or("null".to_string(), json::string),
+                outcome.output.len(),
+                outcome.steps,
+                outcome.time.as_secs_f64()
+            )
+        })
+        .collect();
+    format!(
+        "{{\"program\":{},\"results\":[{}]}}\n",
+        json::string(&program.display().to_string()),
+        results.join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::Program;
+
+    #[test]
+    fn every_input_is_run_and_reported_in_order() {
+        let dir = std::env::temp_dir().join(format!("bft-batch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let inputs: Vec<PathBuf> = ["ab", "cd", "missing"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect();
+        fs::write(&inputs[0], "ab").unwrap();
+        fs::write(&inputs[1], "cd").unwrap();
+
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", ",.,.")).unwrap();
+        let bytecode = Bytecode::compile(&prog);
+        let outcomes = run_all(
+            &prog,
+            &bytecode,
+            Engine::Opt,
+            Limits::default(),
+            &inputs,
+            Lang::English,
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        let outputs: Vec<&[u8]> = outcomes.iter().map(|o| &o.output[..]).collect();
+        assert_eq!(outputs, [&b"ab"[..], b"cd", b""]);
+        assert!(outcomes[0].error.is_none());
+        assert!(outcomes[2]
+            .error
+            .as_deref()
+            .unwrap()
+            .starts_with(&format!("couldn't read {}: ", inputs[2].display())));
+
+        let report = json::parse(&report_json(Path::new("echo.b"), &outcomes)).unwrap();
+        assert_eq!(report.get("program").unwrap().as_str(), Some("echo.b"));
+        let Some(json::Value::Array(results)) = report.get("results") else {
+            panic!("The results are an array");
+        };
+        let field = |index: usize, key| results[index].get(key).unwrap();
+        assert_eq!(field(1, "status").as_str(), Some("ok"));
+        assert_eq!(field(1, "output_bytes").as_u64(), Some(2));
+        assert_eq!(field(1, "error"), &json::Value::Null);
+        assert_eq!(field(2, "status").as_str(), Some("error"));
+    }
+}
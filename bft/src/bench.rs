@@ -0,0 +1,101 @@
+//! Benchmark programs built into bft, and timing them on each engine
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use bft_interp::{bytecode::Bytecode, Machine};
+use bft_types::{DecoratedProgram, Program};
+
+use crate::cli::{run_engine, Engine};
+
+/// A benchmark program built into bft
+pub(crate) struct Benchmark {
+    pub(crate) name: &'static str,
+    pub(crate) source: &'static str,
+    /// What the program reads
+    pub(crate) input: &'static [u8],
+}
+
+/// Every benchmark program
+pub(crate) const PROGRAMS: &[Benchmark] = &[
+    Benchmark {
+        name: "hello",
+        source: include_str!("../programs/hello.b"),
+        input: b"",
+    },
+    Benchmark {
+        name: "sierpinski",
+        source: include_str!("../programs/sierpinski.b"),
+        input: b"",
+    },
+    Benchmark {
+        name: "mandelbrot",
+        source: include_str!("../programs/mandelbrot.b"),
+        input: b"",
+    },
+    // dbfi interpreting sierpinski
+    Benchmark {
+        name: "dbfi",
+        source: include_str!("../programs/dbfi.b"),
+        input: concat!(include_str!("../programs/sierpinski.b"), "!").as_bytes(),
+    },
+    Benchmark {
+        name: "nested",
+        source: include_str!("../programs/nested.b"),
+        input: b"",
+    },
+    Benchmark {
+        name: "scan",
+        source: include_str!("../programs/scan.b"),
+        input: b"",
+    },
+];
+
+/// The timing of one benchmark program on one engine
+pub(crate) struct Measurement {
+    /// The fastest of the runs
+    pub(crate) time: Duration,
+    pub(crate) steps: u64,
+}
+
+impl Measurement {
+    pub(crate) fn steps_per_second(&self) -> f64 {
+        self.steps as f64 / self.time.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+/// Runs a benchmark program `runs` times on an engine, keeping the fastest time
+///
+/// Compiling the program isn't timed, and its output is thrown away.
+pub(crate) fn measure(
+    benchmark: &Benchmark,
+    engine: Engine,
+    runs: usize,
+) -> Result<Measurement, Box<dyn std::error::Error>> {
+    let name = benchmark.name;
+    let prog = Program::new(name, benchmark.source);
+    let decorated = DecoratedProgram::from_program(&prog)?;
+    let bytecode = Bytecode::compile(&decorated);
+    let mut best: Option<Measurement> = None;
+    for _ in 0..runs {
+        let mut machine: Machine<u8> = Machine::new(None, false, &decorated);
+        let start = Instant::now();
+        run_engine(
+            engine,
+            &mut machine,
+            &bytecode,
+            &mut &benchmark.input[..],
+            &mut io::sink(),
+            &mut (),
+        )
+        .map_err(|e| format!("{name}: {e}"))?;
+        let time = start.elapsed();
+        if best.as_ref().is_none_or(|best| time < best.time) {
+            best = Some(Measurement {
+                time,
+                steps: machine.steps(),
+            });
+        }
+    }
+    Ok(best.expect("There's always at least one run"))
+}
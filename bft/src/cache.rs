@@ -0,0 +1,188 @@
+//! An on-disk cache of compiled bytecode
+//!
+//! Entries are keyed by program fingerprint and bytecode format version, so a program is only
+//! compiled again if its instructions change or bft starts writing a different format. The cache
+//! lives in `$XDG_CACHE_HOME/bft`, falling back to `~/.cache/bft`.
+//!
+//! Bytecode can also be kept next to the program's source, as `.hello.b.bfc` for `hello.b`, so it
+//! travels with the program and is found without a cache directory. These files start with the
+//! version of bft that wrote them, and are only used by the same version.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bft_interp::bytecode::{Bytecode, FORMAT_VERSION};
+use bft_types::{fingerprint::Fingerprint, DecoratedProgram};
+
+/// The extension given to cached bytecode files
+const EXTENSION: &str = "bfc";
+
+/// A single compiled program in the cache
+pub(crate) struct Entry {
+    pub(crate) fingerprint: Fingerprint,
+    pub(crate) size: u64,
+    pub(crate) path: PathBuf,
+}
+
+/// Returns the directory the cache lives in, if one can be found
+pub(crate) fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir).join("bft"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache").join("bft"))
+}
+
+fn entry_path(fingerprint: Fingerprint) -> Option<PathBuf> {
+    cache_dir().map(|dir| dir.join(format!("{fingerprint}.v{FORMAT_VERSION}.{EXTENSION}")))
+}
+
+/// Identifies the bft that wrote bytecode kept next to a source file
+const BESIDE_HEADER: &str = concat!("bft ", env!("CARGO_PKG_VERSION"), "\n");
+
+/// Returns where bytecode for the program in `source` is kept next to it
+fn beside_path(source: &Path) -> Option<PathBuf> {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(source.file_name()?);
+    name.push(format!(".{EXTENSION}"));
+    Some(source.with_file_name(name))
+}
+
+/// Loads the program's bytecode from next to `source` if it's there, or else from the cache,
+/// compiling and caching it if it isn't there either
+///
+/// If `keep_beside` is true, bytecode that wasn't next to `source` is written there for next
+/// time. Problems with the cache itself are never fatal: the program is just compiled again.
+pub(crate) fn load_or_compile_beside(
+    prog: &DecoratedProgram,
+    source: &Path,
+    keep_beside: bool,
+) -> Bytecode {
+    let Some(path) = beside_path(source).filter(|_| source.is_file()) else {
+        return load_or_compile(prog);
+    };
+    let kept = fs::read(&path)
+        .ok()
+        .and_then(|bytes| {
+            bytes
+                .strip_prefix(BESIDE_HEADER.as_bytes())
+                .and_then(|bytes| Bytecode::from_bytes(bytes).ok())
+        })
+        .filter(|bytecode| bytecode.fingerprint() == prog.fingerprint());
+    if let Some(bytecode) = kept {
+        tracing::info!(path = %path.display(), "Loaded bytecode from beside the program");
+        return bytecode;
+    }
+
+    let bytecode = load_or_compile(prog);
+    if keep_beside {
+        let mut bytes = BESIDE_HEADER.as_bytes().to_vec();
+        bytes.extend(bytecode.to_bytes());
+        if let Err(e) = fs::write(&path, bytes) {
+            tracing::warn!(path = %path.display(), "Couldn't keep bytecode beside the program: {e}");
+        }
+    }
+    bytecode
+}
+
+/// Loads the program's bytecode from the cache, compiling and caching it if it isn't there
+///
+/// Problems with the cache itself are never fatal: the program is just compiled again.
+pub(crate) fn load_or_compile(prog: &DecoratedProgram) -> Bytecode {
+    let path = entry_path(prog.fingerprint());
+    let cached = path
+        .as_ref()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|bytes| Bytecode::from_bytes(&bytes).ok())
+        .filter(|bytecode| bytecode.fingerprint() == prog.fingerprint());
+    if let Some(bytecode) = cached {
+        tracing::info!(fingerprint = %prog.fingerprint(), "Loaded bytecode from the cache");
+        return bytecode;
+    }
+
+    tracing::info!(fingerprint = %prog.fingerprint(), "Bytecode isn't cached, compiling it");
+    let bytecode = Bytecode::compile(prog);
+    if let Some(path) = path {
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&path, bytecode.to_bytes()));
+        if let Err(e) = written {
+            tracing::warn!(path = %path.display(), "Couldn't cache bytecode: {e}");
+        }
+    }
+    bytecode
+}
+
+/// Lists everything in the cache
+pub(crate) fn list() -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let Some(dir) = cache_dir().filter(|dir| dir.is_dir()) else {
+        return Ok(entries);
+    };
+    for file in fs::read_dir(dir)? {
+        let path = file?.path();
+        if path
+            .extension()
+            .is_none_or(|extension| extension != EXTENSION)
+        {
+            continue;
+        }
+        let fingerprint = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.split('.').next())
+            .and_then(|hash| hash.parse().ok());
+        if let Some(fingerprint) = fingerprint {
+            entries.push(Entry {
+                fingerprint,
+                size: fs::metadata(&path)?.len(),
+                path,
+            });
+        }
+    }
+    entries.sort_by_key(|entry| entry.fingerprint);
+    Ok(entries)
+}
+
+/// Removes everything from the cache, returning how many entries were removed
+pub(crate) fn clear() -> io::Result<usize> {
+    let entries = list()?;
+    for entry in &entries {
+        fs::remove_file(&entry.path)?;
+    }
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::Program;
+
+    #[test]
+    fn bytecode_is_kept_beside_the_program() {
+        let dir = std::env::temp_dir().join(format!("bft-beside-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("hello.b");
+        fs::write(&source, "+[>]").unwrap();
+        let prog = DecoratedProgram::from_program(&Program::new(&source, "+[>]")).unwrap();
+        let kept = dir.join(".hello.b.bfc");
+
+        load_or_compile_beside(&prog, &source, false);
+        assert!(!kept.exists());
+        let bytecode = load_or_compile_beside(&prog, &source, true);
+        let bytes = fs::read(&kept).unwrap();
+        assert!(bytes.starts_with(BESIDE_HEADER.as_bytes()));
+        assert_eq!(load_or_compile_beside(&prog, &source, false), bytecode);
+
+        // Bytecode from another version of bft is compiled again and replaced
+        fs::write(
+            &kept,
+            [b"bft 0.0.0\n", &bytes[BESIDE_HEADER.len()..]].concat(),
+        )
+        .unwrap();
+        assert_eq!(load_or_compile_beside(&prog, &source, true), bytecode);
+        assert_eq!(fs::read(&kept).unwrap(), bytes);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
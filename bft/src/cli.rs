@@ -1,23 +1,2126 @@
-use clap::Parser;
-use std::{num::NonZeroUsize, path::PathBuf};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Cursor, IsTerminal, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
-use bft_interp::Machine;
-use bft_types::{DecoratedProgram, Program};
+use bft_interp::disasm::Disassembly;
+use bft_interp::dump::DumpFormat;
+use bft_interp::expect::{self, Script};
+use bft_interp::explain::Explanation;
+use bft_interp::messages::{Lang, Localize};
+use bft_interp::minimise::minimise;
+use bft_interp::policy::{self, Policies};
+use bft_interp::profile::{Profiler, Report};
+use bft_interp::sample::Sampler;
+use bft_interp::symbols::Symbols;
+use bft_interp::trace::{self, Tracer, WriteTracer};
+use bft_interp::transcript::{Replay, Transcript};
+use bft_interp::value::{Notation, ValueFormat};
+use bft_interp::{
+    bytecode::Bytecode, error::BftError, golf::Golfed, limits::Limits, CellKind, Flush, Machine,
+    VMError,
+};
+use bft_types::{diff::Diff, DecoratedProgram, Program};
+
+use crate::batch;
+use crate::bench;
+use crate::cache;
+use crate::debugger::{self, Debugger};
+use crate::demo;
+use crate::difftest;
+use crate::dump::{self, DumpRadix, Window};
+use crate::expected;
+use crate::golden;
+use crate::json;
+use crate::lint::LintArgs;
+use crate::messages::{Message, MessageId};
+use crate::minimise::Condition;
+use crate::record::Record;
+use crate::rpc::Service;
+use crate::selftest;
+use crate::serve;
+use crate::style::{Style, StyleArgs};
+use crate::terminal::RawMode;
+use crate::throttle::Throttle;
+use crate::utf8::{Utf8Mode, Utf8Reader, Utf8Writer};
+use crate::visualize;
+
+/// How often --watch checks whether the program has changed
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub(crate) struct Cli {
+    #[command(subcommand)]
+    pub(crate) command: Command,
+    #[command(flatten)]
+    pub(crate) style: StyleArgs,
+}
+
+#[derive(Subcommand)]
+pub(crate) enum Command {
+    /// Run a Brainfuck program
+    Run(Box<RunArgs>),
+    /// Check a program for errors and suspicious code without running it
+    Check(CheckArgs),
+    /// Compile a program to bytecode, and optionally a symbol file mapping it back to the source
+    Compile(CompileArgs),
+    /// Print compiled bytecode as a listing of its operations and where they came from
+    #[command(alias = "disassemble")]
+    Disasm(DisasmArgs),
+    /// Show the instructions that differ between two programs, ignoring comments
+    Diff(DiffArgs),
+    /// Print a program annotated with its loops, the idioms it uses and the cells it works on
+    Explain(ExplainArgs),
+    /// Shrink a program, and suggest ways to shrink it further
+    Golf(GolfArgs),
+    /// Shrink a program for as long as it keeps failing in the same way, to make a small
+    /// reproducer for a bug
+    #[command(alias = "minimise")]
+    Minimize(MinimizeArgs),
+    /// List the engines programs can be run with
+    Engines,
+    /// Run a program once for each file in a directory, in parallel, using the file as input
+    Batch(BatchArgs),
+    /// Run a program on several engines and report the first place they disagree
+    Difftest(DifftestArgs),
+    /// Time the built-in benchmark programs on each engine
+    Bench(BenchArgs),
+    /// Test an interactive program with a script of input to send and output to expect
+    Expect(ExpectArgs),
+    /// Run every program that has a .expected file next to it, and check it writes that output
+    Test(TestArgs),
+    /// Run a program again exactly as it ran when recorded with `bft run --record`, and fail if
+    /// its output differs
+    Replay(ReplayArgs),
+    /// Check the engines follow the documented semantics, with a built-in suite of programs
+    Selftest(SelftestArgs),
+    /// Animate a program in the terminal, showing the tape and the instruction being run
+    #[command(alias = "visualise")]
+    Visualize(VisualizeArgs),
+    /// Step through a program, stopping at breakpoints to look at the tape
+    Debug(DebugArgs),
+    /// Run programs sent over HTTP, so a shared server can run them safely for anyone
+    Serve(ServeArgs),
+    /// Answer JSON-RPC requests on standard input, one per line, so other tools can parse, check
+    /// and step through programs
+    Rpc,
+    /// Write man pages for bft and its subcommands
+    Man(ManArgs),
+    /// Manage the cache of compiled programs
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Subcommand)]
+pub(crate) enum CacheAction {
+    /// List every cached program
+    List,
+    /// Remove every cached program
+    Clear,
+}
+
+#[derive(Args)]
+pub(crate) struct RunArgs {
+    /// The program to run, or - to read it from standard input
+    ///
+    /// When the program is read from standard input, its own input must be given with --input or
+    /// --input-text, otherwise it reads nothing.
+    #[arg(required_unless_present = "execute")]
+    pub(crate) program: Option<PathBuf>,
+    /// Arguments for the program, given after --, which it reads as input with --args-as-input
+    #[arg(last = true, requires = "args_as_input")]
+    pub(crate) args: Vec<OsString>,
+    /// Run this code instead of reading a program from a file
+    #[arg(short = 'e', long, conflicts_with = "program")]
+    pub(crate) execute: Option<String>,
+    /// When the program finishes normally, exit with the value of this cell (0 if no cell is
+    /// given). Only the lowest byte of wider cells is used
+    #[arg(long, value_name = "CELL", num_args = 0..=1, default_missing_value = "0")]
+    pub(crate) exit_cell: Option<usize>,
+    /// Clear the screen and run the program again whenever its file changes
+    #[arg(long, conflicts_with = "execute")]
+    pub(crate) watch: bool,
+    #[arg(short, long, env = "BFT_CELLS")]
+    pub(crate) cells: Option<NonZeroUsize>,
+    #[arg(long, env = "BFT_EXTENSIBLE")]
+    pub(crate) extensible: bool,
+    /// How many bits each cell holds
+    #[arg(long, value_enum, default_value = "8", env = "BFT_CELL_SIZE")]
+    pub(crate) cell_size: CellSize,
+    /// Which engine runs the program. See `bft engines`
+    #[arg(long, value_enum, default_value = "opt", env = "BFT_ENGINE")]
+    pub(crate) engine: Engine,
+    /// Stop with exit status 3 after this many steps
+    #[arg(long, value_name = "STEPS", env = "BFT_MAX_STEPS")]
+    pub(crate) max_steps: Option<u64>,
+    /// Stop with exit status 3 after writing this many bytes of output
+    #[arg(long, value_name = "BYTES", env = "BFT_MAX_OUTPUT_BYTES")]
+    pub(crate) max_output_bytes: Option<u64>,
+    /// Stop with exit status 3 if an --extensible tape would grow beyond this many cells
+    #[arg(long, value_name = "CELLS", env = "BFT_MAX_TAPE")]
+    pub(crate) max_tape: Option<usize>,
+    /// Stop with exit status 3 if any loop goes round more than this many times once it's
+    /// entered, reporting which loop it was and the values its condition cell took
+    #[arg(long, value_name = "ITERATIONS", env = "BFT_MAX_LOOP_ITERATIONS")]
+    pub(crate) max_loop_iterations: Option<u64>,
+    /// What to do when the head moves left of the first cell
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value = "error",
+        env = "BFT_SEEK_TOO_LOW"
+    )]
+    pub(crate) seek_too_low: SeekPolicy,
+    /// What to do when the head moves right of the last cell, if the tape isn't --extensible
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value = "error",
+        env = "BFT_SEEK_TOO_HIGH"
+    )]
+    pub(crate) seek_too_high: SeekPolicy,
+    /// What `,` does once the input has run out
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value = "error",
+        env = "BFT_EOF"
+    )]
+    pub(crate) eof: EofPolicy,
+    #[command(flatten)]
+    pub(crate) lints: LintArgs,
+    #[command(flatten)]
+    pub(crate) values: ValueArgs,
+    /// Compile the program from scratch instead of using the cache
+    #[arg(long, env = "BFT_NO_CACHE")]
+    pub(crate) no_cache: bool,
+    /// Keep the compiled program next to its source, as .NAME.bfc, so later runs start without
+    /// compiling it. Bytecode kept there is always used if it's up to date
+    #[arg(long, env = "BFT_WARM_START", conflicts_with = "no_cache")]
+    pub(crate) warm_start: bool,
+    /// Read the program's input from this file instead of standard input
+    #[arg(short, long)]
+    pub(crate) input: Option<PathBuf>,
+    /// Use this text as the program's input. Supports \n, \t, \0, \\ and \xNN escapes
+    #[arg(long, value_parser = parse_escaped, conflicts_with = "input")]
+    pub(crate) input_text: Option<EscapedBytes>,
+    /// Use the arguments after -- as the program's input, each one followed by a newline or NUL
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SEPARATOR",
+        num_args = 0..=1,
+        default_missing_value = "newline",
+        conflicts_with_all = ["input", "input_text"]
+    )]
+    pub(crate) args_as_input: Option<ArgSeparator>,
+    /// Treat the program's input and output as UTF-8 text, replacing invalid sequences with
+    /// U+FFFD, or failing on them if strict
+    #[arg(
+        long,
+        value_enum,
+        value_name = "INVALID",
+        num_args = 0..=1,
+        default_missing_value = "replace"
+    )]
+    pub(crate) utf8: Option<Utf8Mode>,
+    /// Put the terminal into raw mode, so that `,` reads each key as soon as it's pressed,
+    /// without echoing it
+    #[arg(long, conflicts_with_all = ["input", "input_text", "args_as_input"])]
+    pub(crate) raw: bool,
+    /// Play an interactive program in the terminal: the same as --raw --buffering unbuffered
+    #[arg(
+        long,
+        conflicts_with_all = ["input", "input_text", "args_as_input", "output", "buffering"]
+    )]
+    pub(crate) interactive: bool,
+    /// Write the program's output to this file instead of standard output
+    #[arg(short, long)]
+    pub(crate) output: Option<PathBuf>,
+    /// Pause for this many milliseconds after each byte of output
+    #[arg(long, value_name = "MS")]
+    pub(crate) output_delay: Option<u64>,
+    /// Draw no more than this many screens a second, where a screen starts when the program clears
+    /// the terminal or moves the cursor home
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) fps: Option<u32>,
+    /// Run the program slowly, describing each instruction in plain English on standard error,
+    /// along with where the head is and how the cell under it changed
+    #[arg(
+        long,
+        conflicts_with_all = ["trace", "profile", "profile_out", "metrics_out", "record", "replay"]
+    )]
+    pub(crate) narrate: bool,
+    /// Run the program slowly, redrawing the first cells of the tape on standard error after each
+    /// step, for an audience to watch. The program's output is shown as it's written, and printed
+    /// when it finishes
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "narrate", "trace", "profile", "profile_out", "metrics_out", "record", "replay"
+        ]
+    )]
+    pub(crate) demo: bool,
+    /// How many cells, from the first, --demo shows
+    #[arg(long, value_name = "CELLS", default_value_t = 10, requires = "demo")]
+    pub(crate) demo_cells: usize,
+    /// Pause for this many milliseconds after each step of --narrate or --demo
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    pub(crate) step_delay: u64,
+    /// How to buffer the program's output
+    #[arg(long, value_enum, default_value = "auto", env = "BFT_BUFFERING")]
+    pub(crate) buffering: Buffering,
+    /// Stop the program if it comes back to exactly the same state without reading input, which
+    /// means it would loop forever. The state is checked every STEPS steps, 1024 if not given
+    #[arg(
+        long,
+        value_name = "STEPS",
+        num_args = 0..=1,
+        default_missing_value = "1024",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub(crate) detect_loops: Option<u64>,
+    /// Compare the program's output against this file instead of printing it, and fail if they differ
+    #[arg(long, value_name = "FILE", conflicts_with = "output")]
+    pub(crate) diff_expected: Option<PathBuf>,
+    /// After the run, even if it fails, write the head, step count and non-zero cells to this
+    /// file, or to standard error if no file is given
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-")]
+    pub(crate) dump_state: Option<PathBuf>,
+    /// How far either side of the head to include cells in --dump-state, or "all"
+    #[arg(long, value_name = "CELLS", default_value = "16")]
+    pub(crate) dump_window: Window,
+    /// Write --dump-state as a hex dump like xxd's, instead of a list of the non-zero cells
+    #[arg(long)]
+    pub(crate) dump_hex: bool,
+    /// The base to write cells in with --dump-hex
+    #[arg(long, value_enum, default_value = "hex", requires = "dump_hex")]
+    pub(crate) dump_radix: DumpRadix,
+    /// How many cells to write together, between spaces, with --dump-hex
+    #[arg(long, value_name = "CELLS", default_value_t = 2, requires = "dump_hex")]
+    pub(crate) dump_group: usize,
+    /// How many cells to put on each line with --dump-hex
+    #[arg(
+        long,
+        value_name = "CELLS",
+        default_value_t = 16,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        requires = "dump_hex"
+    )]
+    pub(crate) dump_columns: usize,
+    /// Leave out the cells as characters at the end of each line with --dump-hex
+    #[arg(long, requires = "dump_hex")]
+    pub(crate) no_dump_chars: bool,
+    /// Leave out lines of zero cells with --dump-hex, marking each run of them with a `*`
+    #[arg(long, requires = "dump_hex")]
+    pub(crate) dump_non_zero: bool,
+    /// Write a line for every operation executed to this file, or to standard error if no file is
+    /// given
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-")]
+    pub(crate) trace: Option<PathBuf>,
+    /// How to write each line of --trace
+    #[arg(long, value_enum, default_value = "text")]
+    pub(crate) trace_format: TraceFormat,
+    /// When the program finishes, report which operations and loops it spent its time in
+    #[arg(long)]
+    pub(crate) profile: bool,
+    /// Write the raw profile data to this file as JSON. Implies --profile
+    #[arg(long, value_name = "FILE")]
+    pub(crate) profile_out: Option<PathBuf>,
+    /// Write a time series of the step count, head position and cell writes to this file, for
+    /// plotting
+    #[arg(long, value_name = "FILE")]
+    pub(crate) metrics_out: Option<PathBuf>,
+    /// How often to take a sample for --metrics-out
+    #[arg(
+        long,
+        value_name = "STEPS",
+        default_value_t = 1000,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub(crate) metrics_interval: u64,
+    /// How to write --metrics-out. With csv, how often each cell was written goes in a second
+    /// file, named like the first but ending in .cells.csv
+    #[arg(long, value_enum, default_value = "json")]
+    pub(crate) metrics_format: MetricsFormat,
+    /// What to do if the program's output is closed early, such as by piping it into `head`
+    #[arg(long, value_enum, default_value = "stop")]
+    pub(crate) broken_pipe: BrokenPipe,
+    /// Record the program, the options that change how it runs, and every byte it reads and
+    /// writes to this file, so `bft replay` can run it again exactly
+    #[arg(long, value_name = "FILE")]
+    pub(crate) record: Option<PathBuf>,
+    /// Run the program on the input in this record, and fail if its output differs from what
+    /// was recorded
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["input", "input_text", "args_as_input", "raw", "interactive"]
+    )]
+    pub(crate) replay: Option<PathBuf>,
+    /// How to report the result of the run. With json, a single result record is printed to
+    /// standard output, and unless --output is given it includes the program's output as base64
+    #[arg(
+        long,
+        value_enum,
+        default_value = "text",
+        conflicts_with = "diff_expected"
+    )]
+    pub(crate) format: ResultFormat,
+}
+
+#[derive(Args)]
+pub(crate) struct CheckArgs {
+    pub(crate) program: PathBuf,
+    #[command(flatten)]
+    pub(crate) lints: LintArgs,
+}
+
+#[derive(Args)]
+pub(crate) struct CompileArgs {
+    pub(crate) program: PathBuf,
+    /// Where to write the bytecode, which is next to the program with a .bfc extension if not
+    /// given
+    #[arg(short, long)]
+    pub(crate) output: Option<PathBuf>,
+    /// Also write a symbol file giving the source position of each operation, to this file or
+    /// next to the bytecode with .sym added
+    #[arg(long, value_name = "FILE", num_args = 0..=1)]
+    pub(crate) symbols: Option<Option<PathBuf>>,
+    /// interp compiles each instruction to an operation of its own, opt fuses and optimises them
+    #[arg(long, value_enum, default_value = "opt", env = "BFT_ENGINE")]
+    pub(crate) engine: Engine,
+}
+
+#[derive(Args)]
+pub(crate) struct ExplainArgs {
+    pub(crate) program: PathBuf,
+}
+
+#[derive(Args)]
+pub(crate) struct DisasmArgs {
+    /// The bytecode, as written by compile
+    pub(crate) bytecode: PathBuf,
+    /// Give source positions from this symbol file, which is the bytecode's with .sym added if
+    /// not given and that exists
+    #[arg(long, value_name = "FILE")]
+    pub(crate) symbols: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub(crate) struct BatchArgs {
+    pub(crate) program: PathBuf,
+    /// The directory of input files
+    #[arg(long)]
+    pub(crate) inputs: PathBuf,
+    /// Write each run's output to a file in this directory, named after its input
+    #[arg(long, value_name = "DIR")]
+    pub(crate) outputs: Option<PathBuf>,
+    /// Write a JSON report of every run to this file
+    #[arg(long, value_name = "FILE")]
+    pub(crate) report: Option<PathBuf>,
+    #[arg(long, value_enum, default_value = "opt", env = "BFT_ENGINE")]
+    pub(crate) engine: Engine,
+    /// Stop any run after this many steps
+    #[arg(long, value_name = "STEPS", env = "BFT_MAX_STEPS")]
+    pub(crate) max_steps: Option<u64>,
+}
+
+#[derive(Args)]
+pub(crate) struct DifftestArgs {
     pub(crate) program: PathBuf,
+    /// The engines to compare, separated by commas. Each is compared against the first
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "interp,opt")]
+    pub(crate) engines: Vec<Engine>,
+    /// Read the program's input from this file. Without it the program gets no input
     #[arg(short, long)]
+    pub(crate) input: Option<PathBuf>,
+    /// Use this text as the program's input. Supports \n, \t, \0, \\ and \xNN escapes
+    #[arg(long, value_parser = parse_escaped, conflicts_with = "input")]
+    pub(crate) input_text: Option<EscapedBytes>,
+    /// Stop each run after this many steps
+    #[arg(long, value_name = "STEPS", env = "BFT_MAX_STEPS")]
+    pub(crate) max_steps: Option<u64>,
+}
+
+#[derive(Args)]
+pub(crate) struct ExpectArgs {
+    pub(crate) program: PathBuf,
+    /// The script, with a `send TEXT`, `expect TEXT`, `timeout SECONDS` or `close` on each line
+    pub(crate) script: PathBuf,
+    /// Stop the program after this many steps
+    #[arg(long, value_name = "STEPS", env = "BFT_MAX_STEPS")]
+    pub(crate) max_steps: Option<u64>,
+}
+
+#[derive(Args)]
+pub(crate) struct TestArgs {
+    /// The programs to test, and directories to search for them
+    #[arg(default_value = ".")]
+    pub(crate) paths: Vec<PathBuf>,
+    /// The engine to run the programs with
+    #[arg(long, value_enum, default_value = "opt", env = "BFT_ENGINE")]
+    pub(crate) engine: Engine,
+    /// Stop each program after this many steps
+    #[arg(long, value_name = "STEPS", env = "BFT_MAX_STEPS")]
+    pub(crate) max_steps: Option<u64>,
+    /// Write each program's output to its .expected file instead of checking it
+    #[arg(long)]
+    pub(crate) bless: bool,
+}
+
+#[derive(Args)]
+pub(crate) struct SelftestArgs {
+    /// The engines to check, separated by commas
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "interp,opt")]
+    pub(crate) engines: Vec<Engine>,
+}
+
+#[derive(Args)]
+pub(crate) struct BenchArgs {
+    /// The benchmarks to run. Runs all of them if none are given
+    #[arg(value_parser = bench::PROGRAMS.iter().map(|benchmark| benchmark.name).collect::<Vec<_>>())]
+    pub(crate) programs: Vec<String>,
+    /// The engines to compare, separated by commas
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "interp,opt")]
+    pub(crate) engines: Vec<Engine>,
+    /// How many times to run each benchmark, keeping the fastest
+    #[arg(long, default_value = "3", value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) runs: u64,
+}
+
+#[derive(Args)]
+pub(crate) struct ManArgs {
+    /// Write a page for bft and one for every subcommand into this directory, instead of
+    /// printing the page for bft
+    #[arg(long, value_name = "DIR")]
+    pub(crate) out_dir: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub(crate) struct DiffArgs {
+    pub(crate) old: PathBuf,
+    pub(crate) new: PathBuf,
+}
+
+#[derive(Args)]
+pub(crate) struct GolfArgs {
+    pub(crate) program: PathBuf,
+}
+
+#[derive(Args)]
+pub(crate) struct ReplayArgs {
+    /// A record written by `bft run --record`
+    pub(crate) record: PathBuf,
+}
+
+#[derive(Args)]
+pub(crate) struct MinimizeArgs {
+    pub(crate) program: PathBuf,
+    /// What the program must keep doing: error, error:CODE, panic, output=TEXT, output!=TEXT,
+    /// disagree, or sh:COMMAND, where the command is given the program's file as $1 and succeeds
+    /// if the program still reproduces
+    #[arg(long)]
+    pub(crate) check: Condition,
+    /// The engine to run each candidate program on
+    #[arg(long, value_enum, default_value = "interp")]
+    pub(crate) engine: Engine,
+    /// Read the program's input from this file. Without it the program gets no input
+    #[arg(short, long)]
+    pub(crate) input: Option<PathBuf>,
+    /// Use this text as the program's input. Supports \n, \t, \0, \\ and \xNN escapes
+    #[arg(long, value_parser = parse_escaped, conflicts_with = "input")]
+    pub(crate) input_text: Option<EscapedBytes>,
+    /// Stop each run after this many steps, as removing instructions easily makes endless loops
+    #[arg(
+        long,
+        value_name = "STEPS",
+        env = "BFT_MAX_STEPS",
+        default_value_t = 100_000
+    )]
+    pub(crate) max_steps: u64,
+    /// Write the minimised program to this file instead of standard output
+    #[arg(short, long)]
+    pub(crate) output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub(crate) struct VisualizeArgs {
+    pub(crate) program: PathBuf,
+    /// Read the program's input from this file. Without it the program gets no input, as
+    /// standard input is used for the keys that control the animation
+    #[arg(short, long)]
+    pub(crate) input: Option<PathBuf>,
+    /// Use this text as the program's input. Supports \n, \t, \0, \\ and \xNN escapes
+    #[arg(long, value_parser = parse_escaped, conflicts_with = "input")]
+    pub(crate) input_text: Option<EscapedBytes>,
+    /// How many instructions to run a second to begin with. + and - change it while running
+    #[arg(long, value_name = "STEPS", default_value_t = 10.0)]
+    pub(crate) speed: f64,
+    /// How many cells to show either side of the head
+    #[arg(long, value_name = "CELLS", default_value_t = 6)]
+    pub(crate) window: usize,
+    #[arg(short, long, env = "BFT_CELLS")]
     pub(crate) cells: Option<NonZeroUsize>,
+    #[arg(long, env = "BFT_EXTENSIBLE")]
+    pub(crate) extensible: bool,
+}
+
+#[derive(Args)]
+pub(crate) struct DebugArgs {
+    pub(crate) program: PathBuf,
+    /// Read the program's input from this file. Without it the program gets no input, as
+    /// standard input is used for debugger commands
     #[arg(short, long)]
+    pub(crate) input: Option<PathBuf>,
+    /// Use this text as the program's input. Supports \n, \t, \0, \\ and \xNN escapes
+    #[arg(long, value_parser = parse_escaped, conflicts_with = "input")]
+    pub(crate) input_text: Option<EscapedBytes>,
+    /// Run the debugger commands in this file instead of reading them from standard input, and
+    /// fail at the first one that can't be carried out
+    #[arg(long, value_name = "FILE")]
+    pub(crate) script: Option<PathBuf>,
+    /// Don't restore the breakpoints, watchpoints and settings saved next to the program when it
+    /// was last debugged, or save them when done. Scripts never use the saved session
+    #[arg(long)]
+    pub(crate) no_session: bool,
+    #[arg(short, long, env = "BFT_CELLS")]
+    pub(crate) cells: Option<NonZeroUsize>,
+    #[arg(long, env = "BFT_EXTENSIBLE")]
     pub(crate) extensible: bool,
+    #[command(flatten)]
+    pub(crate) values: ValueArgs,
+}
+
+#[derive(Args)]
+pub(crate) struct ServeArgs {
+    /// The address and port to listen on
+    #[arg(long, default_value = "127.0.0.1:8080", env = "BFT_SERVE_ADDRESS")]
+    pub(crate) address: String,
+    /// The most steps any run may take. Requests can ask for fewer
+    #[arg(long, value_name = "STEPS", default_value_t = 10_000_000)]
+    pub(crate) max_steps: u64,
+    /// The most bytes any run may write. Requests can ask for fewer
+    #[arg(long, value_name = "BYTES", default_value_t = 1 << 20)]
+    pub(crate) max_output_bytes: u64,
+    /// The most cells any run's tape may have
+    #[arg(long, value_name = "CELLS", default_value_t = 1 << 20)]
+    pub(crate) max_tape: usize,
+    /// The largest program accepted
+    #[arg(long, value_name = "BYTES", default_value_t = 1 << 20)]
+    pub(crate) max_program_bytes: usize,
+    /// How many requests to handle at once before replying that the server is busy
+    #[arg(long, value_name = "COUNT", default_value_t = 16)]
+    pub(crate) max_connections: usize,
+}
+
+/// The cell types a program can be run with
+#[derive(Clone, Copy, ValueEnum)]
+pub(crate) enum CellSize {
+    #[value(name = "8")]
+    Bits8,
+    #[value(name = "16")]
+    Bits16,
+    #[value(name = "32")]
+    Bits32,
+}
+
+/// The ways a program can be run
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum Engine {
+    /// The reference interpreter, which runs one instruction at a time
+    Interp,
+    /// Optimised bytecode, which may be cached. The fastest engine
+    Opt,
+}
+
+impl Engine {
+    /// The name the engine is chosen by on the command line
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Engine::Interp => "interp",
+            Engine::Opt => "opt",
+        }
+    }
+}
+
+/// The ways --trace can write each operation
+#[derive(Clone, Copy, ValueEnum)]
+pub(crate) enum TraceFormat {
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl From<TraceFormat> for trace::Format {
+    fn from(format: TraceFormat) -> trace::Format {
+        match format {
+            TraceFormat::Text => trace::Format::Text,
+            TraceFormat::Json => trace::Format::Json,
+        }
+    }
+}
+
+/// Command line options controlling how cell values are written in the debugger, traces and
+/// state dumps
+#[derive(Args)]
+pub(crate) struct ValueArgs {
+    /// How to write cell values: decimal by default in --trace and --dump-state, and auto in the
+    /// debugger
+    #[arg(long, value_enum, value_name = "NOTATION", env = "BFT_VALUE_FORMAT")]
+    pub(crate) value_format: Option<ValueNotation>,
+    /// Pad cell values on the left to at least this many characters
+    #[arg(long, value_name = "CHARS", env = "BFT_VALUE_WIDTH")]
+    pub(crate) value_width: Option<usize>,
+}
+
+impl ValueArgs {
+    /// The format chosen, in `default` notation if none was
+    pub(crate) fn format(&self, default: Notation) -> ValueFormat {
+        ValueFormat {
+            notation: self.value_format.map_or(default, Notation::from),
+            width: self.value_width,
+        }
+    }
+}
+
+/// The notations cell values can be written in
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub(crate) enum ValueNotation {
+    /// 0x41
+    Hex,
+    /// 65
+    Decimal,
+    /// 'A'
+    Char,
+    /// 0x41   65 'A', with the character only if it's printable
+    Auto,
+}
+
+impl From<ValueNotation> for Notation {
+    fn from(notation: ValueNotation) -> Notation {
+        match notation {
+            ValueNotation::Hex => Notation::Hex,
+            ValueNotation::Decimal => Notation::Decimal,
+            ValueNotation::Char => Notation::Char,
+            ValueNotation::Auto => Notation::Auto,
+        }
+    }
+}
+
+/// What happens when the head moves off the end of the tape
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum SeekPolicy {
+    /// Stop the program with an error
+    Error,
+    /// Leave the head on the last cell it reached
+    Clamp,
+    /// Carry on from the other end of the tape
+    Wrap,
+}
+
+impl From<SeekPolicy> for policy::Seek {
+    fn from(seek: SeekPolicy) -> policy::Seek {
+        match seek {
+            SeekPolicy::Error => policy::Seek::Error,
+            SeekPolicy::Clamp => policy::Seek::Clamp,
+            SeekPolicy::Wrap => policy::Seek::Wrap,
+        }
+    }
+}
+
+/// What `,` does at the end of the input
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum EofPolicy {
+    /// Stop the program with an error
+    Error,
+    /// Set the cell to 0
+    Zero,
+    /// Set the cell to its largest value, which is -1 to programs that treat cells as signed
+    Max,
+    /// Leave the cell as it was
+    Unchanged,
+}
+
+impl From<EofPolicy> for policy::Eof {
+    fn from(eof: EofPolicy) -> policy::Eof {
+        match eof {
+            EofPolicy::Error => policy::Eof::Error,
+            EofPolicy::Zero => policy::Eof::Zero,
+            EofPolicy::Max => policy::Eof::Max,
+            EofPolicy::Unchanged => policy::Eof::Unchanged,
+        }
+    }
+}
+
+/// How a program's output is buffered
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum Buffering {
+    /// Unbuffered if the output is a terminal, block-buffered otherwise
+    Auto,
+    /// Write each byte as soon as it's output, for interactive programs
+    Unbuffered,
+    /// Write the output a line at a time
+    Line,
+    /// Write the output in large blocks, for throughput
+    Block,
+}
+
+impl Buffering {
+    /// When a Machine should flush output going to a terminal, or somewhere else
+    fn flush(self, is_terminal: bool) -> Flush {
+        match self {
+            Buffering::Auto if is_terminal => Flush::EachByte,
+            Buffering::Auto => Flush::Buffered,
+            Buffering::Unbuffered => Flush::EachByte,
+            Buffering::Line => Flush::Line,
+            Buffering::Block => Flush::Buffered,
+        }
+    }
+}
+
+/// What follows each argument when they're given to a program as input
+#[derive(Clone, Copy, ValueEnum)]
+pub(crate) enum ArgSeparator {
+    Newline,
+    Nul,
+}
+
+impl ArgSeparator {
+    /// Joins arguments into a single input, ending each with the separator
+    fn join(self, args: &[OsString]) -> Vec<u8> {
+        let separator = match self {
+            ArgSeparator::Newline => b'\n',
+            ArgSeparator::Nul => 0,
+        };
+        let mut joined = Vec::new();
+        for arg in args {
+            joined.extend_from_slice(arg.as_encoded_bytes());
+            joined.push(separator);
+        }
+        joined
+    }
+}
+
+/// What to do when the reader of the program's output goes away
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum BrokenPipe {
+    /// Stop the program quietly and successfully, like other Unix filters
+    Stop,
+    /// Fail with an I/O error
+    Error,
+}
+
+/// The ways `run` can report how the run went
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum ResultFormat {
+    /// Errors on standard error, and the exit status
+    Text,
+    /// A JSON result record on standard output, for other tools to consume
+    Json,
+}
+
+/// The ways --metrics-out can be written
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum MetricsFormat {
+    Csv,
+    Json,
+}
+
+/// A program failed while it was running
+#[derive(Debug)]
+pub(crate) struct RunFailed {
+    file: PathBuf,
+    error: VMError,
+}
+
+impl RunFailed {
+    /// The exit status to report the failure with: 3 for going over a limit, 1 otherwise
+    fn exit_code(&self) -> u8 {
+        match self.error {
+            VMError::LimitExceeded { .. } | VMError::LoopLimitExceeded(_) => 3,
+            _ => 1,
+        }
+    }
+
+    /// A short name for the kind of failure, used as the status in result records
+    fn status(&self) -> &'static str {
+        match self.error {
+            VMError::SeekTooLow(_) => "seek_too_low",
+            VMError::SeekTooHigh(_) => "seek_too_high",
+            VMError::IOError { .. } => "io_error",
+            VMError::LimitExceeded { .. } => "limit_exceeded",
+            VMError::BytecodeMismatch => "bytecode_mismatch",
+            VMError::InfiniteLoop { .. } => "infinite_loop",
+            VMError::LoopLimitExceeded(_) => "loop_limit_exceeded",
+            VMError::SplitLoop { .. } => "split_loop",
+            VMError::EmptyProgram => "empty_program",
+        }
+    }
+}
+
+impl std::fmt::Display for RunFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.file.display(), self.error)
+    }
+}
+
+impl std::error::Error for RunFailed {}
+
+impl From<RunFailed> for BftError {
+    fn from(failed: RunFailed) -> BftError {
+        BftError::Vm {
+            file: Some(failed.file),
+            source: failed.error,
+        }
+    }
+}
+
+/// The exit status to report an error with: 3 for a program going over a limit, 1 otherwise
+pub(crate) fn exit_code(error: &BftError) -> u8 {
+    match error {
+        BftError::Vm {
+            source: VMError::LimitExceeded { .. } | VMError::LoopLimitExceeded(_),
+            ..
+        } => 3,
+        _ => 1,
+    }
+}
+
+/// Sorts an error from one of the commands into the category it belongs to, writing the command
+/// line's own errors in `lang`
+fn categorise(error: Box<dyn std::error::Error>, lang: Lang) -> BftError {
+    let error = match error.downcast::<BftError>() {
+        Ok(error) => return *error,
+        Err(error) => error,
+    };
+    let error = match error.downcast::<RunFailed>() {
+        Ok(failed) => return (*failed).into(),
+        Err(error) => error,
+    };
+    let error = match error.downcast::<VMError>() {
+        Ok(error) => return (*error).into(),
+        Err(error) => error,
+    };
+    let error = match error.downcast::<bft_types::ParseError>() {
+        Ok(error) => return (*error).into(),
+        Err(error) => error,
+    };
+    let error = match error.downcast::<io::Error>() {
+        Ok(error) => return (*error).into(),
+        Err(error) => error,
+    };
+    match error.downcast::<Message>() {
+        Ok(message) => BftError::Config(message.localized(lang).to_string()),
+        Err(error) => BftError::Config(error.to_string()),
+    }
+}
+
+/// What happened when a program was executed
+struct Finished {
+    steps: u64,
+    output_bytes: u64,
+    head: usize,
+    tape_len: usize,
+    exit_cell: Option<u8>,
+    failure: Option<RunFailed>,
+    /// Everything the program read and wrote, if the run is being recorded
+    transcript: Option<Transcript>,
+}
+
+/// Bytes given on the command line as text with escape sequences
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct EscapedBytes(pub(crate) Vec<u8>);
+
+/// Converts text with backslash escapes into the bytes it represents
+fn parse_escaped(text: &str) -> Result<EscapedBytes, String> {
+    expect::unescape(text).map(EscapedBytes)
+}
+
+/// Reads the input given by --input or --input-text, which is empty if neither was given
+fn load_input(path: Option<&Path>, text: Option<EscapedBytes>) -> io::Result<Vec<u8>> {
+    match (path, text) {
+        (Some(path), _) => std::fs::read(path),
+        (None, Some(text)) => Ok(text.0),
+        (None, None) => Ok(Vec::new()),
+    }
+}
+
+pub(crate) fn run_bft(command: Command, style: &Style) -> Result<ExitCode, BftError> {
+    let categorise = |error| categorise(error, style.lang());
+    match command {
+        Command::Run(args) => return run(*args, style).map_err(categorise),
+        Command::Check(args) => check(args, style),
+        Command::Compile(args) => compile(args, style),
+        Command::Disasm(args) => disasm(args),
+        Command::Diff(args) => diff(args, style),
+        Command::Explain(args) => explain(args),
+        Command::Golf(args) => golf(args, style),
+        Command::Minimize(args) => minimize(args, style),
+        Command::Engines => engines(),
+        Command::Batch(args) => batch(args, style),
+        Command::Difftest(args) => difftest(args, style),
+        Command::Bench(args) => bench(args, style),
+        Command::Expect(args) => expect(args, style),
+        Command::Test(args) => test(args, style),
+        Command::Replay(args) => return replay(args, style).map_err(categorise),
+        Command::Selftest(args) => selftest(args, style),
+        Command::Visualize(args) => visualize(args, style),
+        Command::Debug(args) => debug(args, style),
+        Command::Serve(args) => serve(args, style),
+        Command::Rpc => Ok(Service::default().serve(io::stdin().lock(), io::stdout().lock())?),
+        Command::Man(args) => man(args),
+        Command::Cache { action } => cache(action, style),
+    }
+    .map_err(categorise)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run(args: RunArgs, style: &Style) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    if !args.watch {
+        return run_once(&args, style);
+    }
+    let path = args
+        .program
+        .clone()
+        .expect("--watch conflicts with --execute");
+    if path.as_os_str() == "-" {
+        return Err(Message::new(MessageId::WatchNeedsFile).into());
+    }
+    let modified = || {
+        std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+    loop {
+        let last_run = modified();
+        if io::stdout().is_terminal() {
+            // Clear the screen and move the cursor to the top left
+            print!("\x1b[2J\x1b[H");
+        }
+        if let Err(e) = run_once(&args, style) {
+            style.error(
+                std::env::args().next().unwrap_or_default(),
+                &categorise(e, style.lang()),
+            );
+        }
+        style.note(&Message::new(MessageId::WaitingForChange).with("file", path.display()));
+        while modified() == last_run {
+            std::thread::sleep(WATCH_INTERVAL);
+        }
+    }
+}
+
+/// Runs the program once, as told by the command line, returning the status to exit with
+fn run_once(args: &RunArgs, style: &Style) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let (file, source) = match (&args.program, &args.execute) {
+        (_, Some(code)) => (PathBuf::from("<cmdline>"), code.clone()),
+        (Some(path), None) if path.as_os_str() == "-" => (
+            PathBuf::from("<stdin>"),
+            io::read_to_string(io::stdin().lock())?,
+        ),
+        (Some(path), None) => (path.clone(), std::fs::read_to_string(path)?),
+        (None, None) => unreachable!("clap requires a program or code"),
+    };
+    let replay = match &args.replay {
+        Some(path) => Some(Record::read_from(BufReader::new(File::open(path)?))?.transcript),
+        None => None,
+    };
+    run_source(args, style, file, source, replay)
+}
+
+fn replay(args: ReplayArgs, style: &Style) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let record = Record::read_from(BufReader::new(File::open(&args.record)?))?;
+    // Start from the defaults, leaving out anything from the environment that changes the run
+    let Command::Run(mut run_args) = Cli::parse_from(["bft", "run", "--execute", ""]).command
+    else {
+        unreachable!("a run command was parsed")
+    };
+    run_args.execute = None;
+    record.apply(&mut run_args)?;
+    run_source(
+        &run_args,
+        style,
+        record.file,
+        record.source,
+        Some(record.transcript),
+    )
+}
+
+/// Runs the program in `source`, read from `file`, on the input in `replay` if there is one
+fn run_source(
+    args: &RunArgs,
+    style: &Style,
+    file: PathBuf,
+    source: String,
+    replay: Option<Transcript>,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let program_from_stdin = args
+        .program
+        .as_ref()
+        .is_some_and(|path| path.as_os_str() == "-");
+    let prog = Program::new(&file, &source);
+    let decorated = DecoratedProgram::from_program(&prog)?;
+    args.lints.check(&decorated, style)?;
+    // The reference interpreter doesn't run bytecode, but a profile of it is described in terms
+    // of its unoptimised equivalent
+    let bytecode = match args.engine {
+        Engine::Interp => Bytecode::compile_unoptimised(&decorated),
+        Engine::Opt if args.no_cache => Bytecode::compile(&decorated),
+        Engine::Opt => cache::load_or_compile_beside(&decorated, &file, args.warm_start),
+    };
+    let given = (&args.input, args.input_text.clone(), args.args_as_input);
+    let mut input: Box<dyn Read> = match given {
+        (Some(path), _, _) => Box::new(BufReader::new(File::open(path)?)),
+        (None, Some(text), _) => Box::new(Cursor::new(text.0)),
+        (None, None, Some(separator)) => Box::new(Cursor::new(separator.join(&args.args))),
+        (None, None, None) if replay.is_some() => Box::new(Cursor::new(
+            replay.as_ref().map_or(Vec::new(), Transcript::input),
+        )),
+        // Standard input has already been used up by the program itself
+        (None, None, None) if program_from_stdin => Box::new(io::empty()),
+        (None, None, None) => Box::new(io::stdin().lock()),
+    };
+    let json = args.format == ResultFormat::Json;
+    let capture = args.output.is_none() && (args.diff_expected.is_some() || json);
+    let mut captured = Vec::new();
+    let (mut writer, flush): (Box<dyn Write>, _) = match &args.output {
+        Some(path) => (
+            Box::new(BufWriter::new(File::create(path)?)),
+            args.buffering.flush(false),
+        ),
+        None if capture => (Box::new(&mut captured), Flush::Buffered),
+        None => (
+            Box::new(BufWriter::new(io::stdout().lock())),
+            if args.interactive {
+                Flush::EachByte
+            } else {
+                args.buffering.flush(io::stdout().is_terminal())
+            },
+        ),
+    };
+    if args.output_delay.is_some() || args.fps.is_some() {
+        let delay = args.output_delay.map(Duration::from_millis);
+        writer = Box::new(Throttle::new(writer, delay, args.fps));
+    }
+    let mut utf8_writer = None;
+    let mut output: &mut dyn Write = match args.utf8 {
+        Some(mode) => {
+            input = Box::new(Utf8Reader::new(input, mode));
+            utf8_writer.insert(Utf8Writer::new(&mut writer, mode))
+        }
+        None => &mut writer,
+    };
+    let raw_mode = if args.raw || args.interactive {
+        RawMode::enable()?
+    } else {
+        None
+    };
+    let start = Instant::now();
+    let mut finished = match args.cell_size {
+        CellSize::Bits8 => execute::<u8>(
+            args,
+            &decorated,
+            &bytecode,
+            flush,
+            replay.as_ref(),
+            &mut input,
+            &mut output,
+        ),
+        CellSize::Bits16 => execute::<u16>(
+            args,
+            &decorated,
+            &bytecode,
+            flush,
+            replay.as_ref(),
+            &mut input,
+            &mut output,
+        ),
+        CellSize::Bits32 => execute::<u32>(
+            args,
+            &decorated,
+            &bytecode,
+            flush,
+            replay.as_ref(),
+            &mut input,
+            &mut output,
+        ),
+    }?;
+    let time = start.elapsed();
+    drop(raw_mode);
+    if let Some(utf8_writer) = utf8_writer {
+        utf8_writer
+            .finish()
+            .map_err(|e| Message::new(MessageId::OutputFailed).with("error", e))?;
+    }
+    drop(writer);
+    if let (Some(transcript), Some(path)) = (finished.transcript.take(), &args.record) {
+        let record = Record::new(file, source, args, transcript);
+        record.write_to(&mut BufWriter::new(File::create(path)?))?;
+    }
+
+    if json {
+        let output = capture.then_some(captured.as_slice());
+        println!("{}", result_json(args, &finished, time, output));
+        return Ok(match &finished.failure {
+            Some(failure) => ExitCode::from(failure.exit_code()),
+            None => finished.exit_cell.map_or(ExitCode::SUCCESS, ExitCode::from),
+        });
+    }
+    if let Some(failure) = finished.failure {
+        return Err(failure.into());
+    }
+
+    if let Some(path) = &args.diff_expected {
+        let expected = std::fs::read(path)?;
+        let name = path.display().to_string();
+        if let Some(diff) = expected::unified_diff(&name, &expected, &captured) {
+            style.diff(diff);
+            return Err(Message::new(MessageId::OutputDiffers)
+                .with("file", name)
+                .into());
+        }
+    }
+    Ok(finished.exit_cell.map_or(ExitCode::SUCCESS, ExitCode::from))
+}
+
+/// Describes a finished run as a single line of JSON
+fn result_json(
+    args: &RunArgs,
+    finished: &Finished,
+    time: Duration,
+    output: Option<&[u8]>,
+) -> String {
+    let (status, error) = match &finished.failure {
+        Some(failure) => {
+            let position = match failure.error.instruction() {
+                Some(instruction) => format!(
+                    r#","line":{},"character":{}"#,
+                    instruction.line(),
+                    instruction.character()
+                ),
+                None => String::new(),
+            };
+            let message = json::string(&failure.error.to_string());
+            (
+                failure.status(),
+                format!(r#"{{"message":{message}{position}}}"#),
+            )
+        }
+        None => ("ok", "null".to_string()),
+    };
+    let exit_cell = finished
+        .exit_cell
+        .map_or("null".to_string(), |value| value.to_string());
+    let output = match output {
+        Some(bytes) => format!(r#","output":{}"#, json::base64(bytes)),
+        None => String::new(),
+    };
+    format!(
+        r#"{{"status":"{status}","error":{error},"engine":"{}","steps":{},"output_bytes":{},"head":{},"cells":{},"exit_cell":{exit_cell},"time":{}{output}}}"#,
+        args.engine.name(),
+        finished.steps,
+        finished.output_bytes,
+        finished.head,
+        finished.tape_len,
+        time.as_secs_f64()
+    )
+}
+
+/// Runs the program on a Machine with cells of type T
+///
+/// Returns the lowest byte of the --exit-cell, if there is one.
+fn execute<T: CellKind + std::fmt::Display + Copy + Into<u64>>(
+    args: &RunArgs,
+    decorated: &DecoratedProgram,
+    bytecode: &Bytecode,
+    flush: Flush,
+    replay: Option<&Transcript>,
+    input: &mut impl Read,
+    output: &mut impl Write,
+) -> Result<Finished, Box<dyn std::error::Error>> {
+    let mut machine: Machine<T> = Machine::new(args.cells, args.extensible, decorated);
+    machine.set_flush(flush);
+    machine.set_loop_detection(args.detect_loops);
+    machine.set_policies(Policies {
+        seek_too_low: args.seek_too_low.into(),
+        seek_too_high: args.seek_too_high.into(),
+        eof: args.eof.into(),
+    });
+    machine.set_limits(Limits {
+        max_steps: args.max_steps,
+        max_output_bytes: args.max_output_bytes,
+        max_tape: args.max_tape,
+        max_loop_iterations: args.max_loop_iterations,
+    });
+    let tracer = match &args.trace {
+        Some(path) => {
+            let out: Box<dyn Write> = if path.as_os_str() == "-" {
+                Box::new(io::stderr().lock())
+            } else {
+                Box::new(File::create(path)?)
+            };
+            let mut tracer = WriteTracer::new(BufWriter::new(out), args.trace_format.into());
+            tracer.set_value_format(args.values.format(Notation::Decimal));
+            Some(tracer)
+        }
+        None => None,
+    };
+    let profiler = (args.profile || args.profile_out.is_some()).then(Profiler::new);
+    let transcript = args.record.is_some().then(Transcript::new);
+    let replay = replay.map(Replay::new);
+    let sampler = args
+        .metrics_out
+        .as_ref()
+        .map(|_| Sampler::new(args.metrics_interval));
+    let tracing = tracer.is_some() || profiler.is_some() || sampler.is_some();
+    let (result, transcript, divergence) = if args.narrate {
+        machine.set_value_format(args.values.format(Notation::Decimal));
+        let delay = Duration::from_millis(args.step_delay);
+        (narrate(&mut machine, input, output, delay), None, None)
+    } else if args.demo {
+        let settings = demo::Settings {
+            cells: args.demo_cells,
+            delay: Duration::from_millis(args.step_delay),
+        };
+        let mut shown = Vec::new();
+        let result = demo::demo(&mut machine, input, &mut shown, &settings);
+        output.write_all(&shown)?;
+        (result, None, None)
+    } else if !tracing && transcript.is_none() && replay.is_none() {
+        let result = run_engine(args.engine, &mut machine, bytecode, input, output, &mut ());
+        (result, None, None)
+    } else {
+        let mut tracers = (((tracer, profiler), sampler), (transcript, replay));
+        let result = run_engine(
+            args.engine,
+            &mut machine,
+            bytecode,
+            input,
+            output,
+            &mut tracers,
+        );
+        let (((tracer, profiler), sampler), (transcript, replay)) = tracers;
+        if let Some(tracer) = tracer {
+            tracer.finish()?;
+        }
+        if let (Some(mut sampler), Some(path)) = (sampler, &args.metrics_out) {
+            sampler.finish(&machine);
+            write_metrics(&sampler, path, args.metrics_format)?;
+        }
+        if let Some(profiler) = profiler {
+            let report = profiler.report(bytecode);
+            eprint!("{report}");
+            if let Some(path) = &args.profile_out {
+                std::fs::write(path, profile_json(&report, profiler.counts()))?;
+            }
+        }
+        (
+            result,
+            transcript,
+            replay.and_then(|replay| replay.finish().err()),
+        )
+    };
+    let format = args.dump_hex.then(|| DumpFormat {
+        radix: args.dump_radix.into(),
+        group: args.dump_group,
+        columns: args.dump_columns,
+        chars: !args.no_dump_chars,
+        non_zero: args.dump_non_zero,
+    });
+    let values = args.values.format(Notation::Decimal);
+    match &args.dump_state {
+        Some(path) if path.as_os_str() == "-" => dump::write_state(
+            &machine,
+            args.dump_window,
+            format,
+            values,
+            &mut io::stderr().lock(),
+        )?,
+        Some(path) => dump::write_state(
+            &machine,
+            args.dump_window,
+            format,
+            values,
+            &mut File::create(path)?,
+        )?,
+        None => {}
+    }
+    let result = match result {
+        Err(VMError::IOError { source, .. })
+            if source.kind() == io::ErrorKind::BrokenPipe
+                && args.broken_pipe == BrokenPipe::Stop =>
+        {
+            tracing::debug!("Output closed, stopping the program");
+            Ok(())
+        }
+        result => result,
+    };
+    let failure = result.err().map(|error| RunFailed {
+        file: decorated.file().to_path_buf(),
+        error,
+    });
+    // Where the output went wrong explains a replay better than how the program ended
+    if let Some(divergence) = divergence {
+        return Err(Message::new(MessageId::Diverged)
+            .with("file", decorated.file().display())
+            .with("divergence", divergence)
+            .into());
+    }
+    let exit_cell = match args.exit_cell {
+        Some(_) if failure.is_some() => None,
+        Some(cell) => match machine.cells().get(cell) {
+            Some(value) => Some(value.get_value()),
+            None => {
+                return Err(Message::new(MessageId::ExitCellOutOfRange)
+                    .with("cell", cell)
+                    .into())
+            }
+        },
+        None => None,
+    };
+    Ok(Finished {
+        steps: machine.steps(),
+        output_bytes: machine.output_bytes(),
+        head: machine.head(),
+        tape_len: machine.cells().len(),
+        exit_cell,
+        failure,
+        transcript,
+    })
+}
+
+/// Runs the program one step at a time for --narrate, describing each step on standard error
+fn narrate<T: CellKind>(
+    machine: &mut Machine<T>,
+    input: &mut impl Read,
+    output: &mut impl Write,
+    delay: Duration,
+) -> Result<(), VMError> {
+    while let Some(narration) = machine.step_narrated(input, output)? {
+        eprintln!("{narration}");
+        std::thread::sleep(delay);
+    }
+    Ok(())
+}
+
+/// Runs the program on the chosen engine
+pub(crate) fn run_engine<T: CellKind>(
+    engine: Engine,
+    machine: &mut Machine<T>,
+    bytecode: &Bytecode,
+    input: &mut impl Read,
+    output: &mut impl Write,
+    tracer: &mut impl Tracer<T>,
+) -> Result<(), VMError> {
+    match engine {
+        Engine::Interp => machine.run_traced(input, output, tracer),
+        Engine::Opt => machine.run_bytecode_traced(bytecode, input, output, tracer),
+    }
+}
+
+/// Writes the samples taken for --metrics-out
+fn write_metrics(sampler: &Sampler, path: &Path, format: MetricsFormat) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    match format {
+        MetricsFormat::Json => sampler.write_json(&mut out)?,
+        MetricsFormat::Csv => {
+            sampler.write_csv(&mut out)?;
+            let mut cells = BufWriter::new(File::create(path.with_extension("cells.csv"))?);
+            sampler.write_cell_writes_csv(&mut cells)?;
+            cells.flush()?;
+        }
+    }
+    out.flush()
+}
+
+/// Formats a profile as JSON, including the count for every bytecode operation
+fn profile_json(report: &Report, counts: &[u64]) -> String {
+    let ops: Vec<String> = report
+        .ops()
+        .iter()
+        .map(|(name, count)| format!("\"{name}\":{count}"))
+        .collect();
+    let loops: Vec<String> = report
+        .loops()
+        .iter()
+        .map(|profile| {
+            format!(
+                r#"{{"line":{},"character":{},"entries":{},"iterations":{},"steps":{}}}"#,
+                profile.opener().line(),
+                profile.opener().character(),
+                profile.entries(),
+                profile.iterations(),
+                profile.steps()
+            )
+        })
+        .collect();
+    let counts: Vec<String> = counts.iter().map(u64::to_string).collect();
+    format!(
+        "{{\"total_steps\":{},\"ops\":{{{}}},\"loops\":[{}],\"counts\":[{}]}}\n",
+        report.total_steps(),
+        ops.join(","),
+        loops.join(","),
+        counts.join(",")
+    )
+}
+
+fn check(args: CheckArgs, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
+    let prog = Program::from_file(&args.program)?;
+    let decorated = DecoratedProgram::from_program(&prog)?;
+    args.lints.check(&decorated, style)?;
+    Ok(())
+}
+
+fn compile(args: CompileArgs, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
+    let prog = Program::from_file(&args.program)?;
+    let decorated = DecoratedProgram::from_program(&prog)?;
+    let bytecode = match args.engine {
+        Engine::Interp => Bytecode::compile_unoptimised(&decorated),
+        Engine::Opt => Bytecode::compile(&decorated),
+    };
+    let output = args
+        .output
+        .unwrap_or_else(|| args.program.with_extension("bfc"));
+    std::fs::write(&output, bytecode.to_bytes())?;
+    style.note(
+        &Message::new(MessageId::WroteOperations)
+            .with("operations", bytecode.ops().len())
+            .with("file", output.display()),
+    );
+    if let Some(symbols) = args.symbols {
+        let path = symbols.unwrap_or_else(|| {
+            let mut path = output.into_os_string();
+            path.push(".sym");
+            PathBuf::from(path)
+        });
+        std::fs::write(&path, Symbols::new(&bytecode, &decorated).to_string())?;
+        style.note(&Message::new(MessageId::WroteSymbols).with("file", path.display()));
+    }
+    Ok(())
+}
+
+fn disasm(args: DisasmArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let bytecode = Bytecode::from_bytes(&std::fs::read(&args.bytecode)?)?;
+    let path = args.symbols.or_else(|| {
+        let mut path = args.bytecode.clone().into_os_string();
+        path.push(".sym");
+        Some(PathBuf::from(path)).filter(|path| path.exists())
+    });
+    let symbols = match path {
+        Some(path) => {
+            let symbols = Symbols::parse(&std::fs::read_to_string(&path)?)?;
+            if symbols.fingerprint != bytecode.fingerprint()
+                || symbols.symbols.len() != bytecode.ops().len()
+            {
+                return Err(Message::new(MessageId::SymbolsMismatch)
+                    .with("file", path.display())
+                    .into());
+            }
+            Some(symbols)
+        }
+        None => None,
+    };
+    let mut listing = Disassembly::new(&bytecode);
+    if let Some(symbols) = &symbols {
+        listing = listing.with_symbols(symbols);
+    }
+    print!("{listing}");
+    Ok(())
+}
+
+fn diff(args: DiffArgs, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
+    let old = Program::from_file(&args.old)?;
+    let new = Program::from_file(&args.new)?;
+    style.diff(Diff::new(&old, &new));
+    Ok(())
+}
+
+fn explain(args: ExplainArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let prog = Program::from_file(&args.program)?;
+    let decorated = DecoratedProgram::from_program(&prog)?;
+    print!("{}", Explanation::from_program(&decorated));
+    Ok(())
+}
+
+fn golf(args: GolfArgs, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
+    let original_size = std::fs::metadata(&args.program)?.len();
+    let prog = Program::from_file(&args.program)?;
+    let decorated = DecoratedProgram::from_program(&prog)?;
+    let golfed = Golfed::from_program(&decorated);
+    println!("{}", golfed.code());
+    style.note(
+        &Message::new(MessageId::GolfSizes)
+            .with("original", original_size)
+            .with("minified", prog.instructions().len())
+            .with("golfed", golfed.code().len()),
+    );
+    for suggestion in golfed.suggestions() {
+        style.note(
+            &Message::new(MessageId::Suggestion)
+                .with("file", args.program.display())
+                .with("suggestion", suggestion),
+        );
+    }
+    Ok(())
+}
+
+fn minimize(args: MinimizeArgs, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
+    let prog = Program::from_file(&args.program)?;
+    let decorated = DecoratedProgram::from_program(&prog)?;
+    let input = load_input(args.input.as_deref(), args.input_text)?;
+    let limits = Limits {
+        max_steps: Some(args.max_steps),
+        ..Limits::default()
+    };
+    if args.check == Condition::Panic {
+        // Every candidate that reproduces panics, so the messages would only be noise
+        std::panic::set_hook(Box::new(|_| {}));
+    }
+    let scratch = std::env::temp_dir().join(format!("bft-minimize-{}.b", std::process::id()));
+    let mut tries = 0;
+    let minimised = minimise(&decorated, |candidate, code| {
+        tries += 1;
+        args.check
+            .holds(candidate, code, args.engine, limits, &input, &scratch)
+    });
+    let _ = std::fs::remove_file(&scratch);
+    let Some(code) = minimised else {
+        return Err(Message::new(MessageId::NoCondition)
+            .with("file", args.program.display())
+            .into());
+    };
+    match &args.output {
+        Some(path) => std::fs::write(path, format!("{code}\n"))?,
+        None => println!("{code}"),
+    }
+    style.note(
+        &Message::new(MessageId::Minimised)
+            .with("from", prog.instructions().len())
+            .with("to", code.len())
+            .with("tries", tries),
+    );
+    Ok(())
+}
+
+fn engines() -> Result<(), Box<dyn std::error::Error>> {
+    for engine in Engine::value_variants() {
+        let value = engine.to_possible_value().expect("No engines are skipped");
+        let help = value
+            .get_help()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        println!("{:<8}{help}", value.get_name());
+    }
+    Ok(())
+}
+
+fn batch(args: BatchArgs, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
+    let prog = Program::from_file(&args.program)?;
+    let decorated = DecoratedProgram::from_program(&prog)?;
+    let bytecode = match args.engine {
+        Engine::Interp => Bytecode::compile_unoptimised(&decorated),
+        Engine::Opt => cache::load_or_compile(&decorated),
+    };
+    let mut inputs = Vec::new();
+    for entry in std::fs::read_dir(&args.inputs)? {
+        let path = entry?.path();
+        if path.is_file() {
+            inputs.push(path);
+        }
+    }
+    inputs.sort();
+
+    let limits = Limits {
+        max_steps: args.max_steps,
+        ..Limits::default()
+    };
+    let outcomes = batch::run_all(
+        &decorated,
+        &bytecode,
+        args.engine,
+        limits,
+        &inputs,
+        style.lang(),
+    );
+    if let Some(dir) = &args.outputs {
+        std::fs::create_dir_all(dir)?;
+        for outcome in &outcomes {
+            let name = outcome.input.file_name().expect("Inputs are files");
+            std::fs::write(dir.join(name), &outcome.output)?;
+        }
+    }
+    if let Some(path) = &args.report {
+        std::fs::write(path, batch::report_json(&args.program, &outcomes))?;
+    }
+
+    style.banner(Message::new(MessageId::BatchHeading).localized(style.lang()));
+    let mut failures = 0;
+    for outcome in &outcomes {
+        let status = match &outcome.error {
+            Some(e) => {
+                failures += 1;
+                Message::new(MessageId::RunError).with("error", e)
+            }
+            None => Message::new(MessageId::RunOk),
+        };
+        let row = Message::new(MessageId::BatchRow)
+            .with("input", outcome.input.display())
+            .with("time", format_args!("{:.2?}", outcome.time))
+            .with("steps", outcome.steps)
+            .with("bytes", outcome.output.len())
+            .with("status", status.localized(style.lang()));
+        style.result(&row);
+    }
+    style.note(
+        &Message::new(MessageId::RunsSucceeded)
+            .with("succeeded", outcomes.len() - failures)
+            .with("runs", outcomes.len()),
+    );
+    if failures > 0 {
+        return Err(Message::new(MessageId::RunsFailed)
+            .with("failed", failures)
+            .into());
+    }
+    Ok(())
+}
+
+fn difftest(args: DifftestArgs, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
+    let prog = Program::from_file(&args.program)?;
+    let decorated = DecoratedProgram::from_program(&prog)?;
+    let input = load_input(args.input.as_deref(), args.input_text)?;
+    let limits = Limits {
+        max_steps: args.max_steps,
+        ..Limits::default()
+    };
+    match difftest::compare(&decorated, &args.engines, limits, &input) {
+        Some(divergence) => Err(Message::new(MessageId::EnginesDisagree)
+            .with("divergence", divergence)
+            .into()),
+        None => {
+            style.note(&Message::new(MessageId::EnginesAgree).with("engines", args.engines.len()));
+            Ok(())
+        }
+    }
+}
+
+fn expect(args: ExpectArgs, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
+    let prog = Program::from_file(&args.program)?;
+    let decorated = DecoratedProgram::from_program(&prog)?;
+    let script = Script::parse(&std::fs::read_to_string(&args.script)?)?;
+    let limits = Limits {
+        max_steps: args.max_steps,
+        ..Limits::default()
+    };
+    script.run(&decorated, limits)?;
+    style.note(
+        &Message::new(MessageId::Followed)
+            .with("program", prog.file().display())
+            .with("script", args.script.display()),
+    );
+    Ok(())
+}
+
+fn test(args: TestArgs, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
+    let cases = golden::discover(&args.paths)?;
+    let limits = Limits {
+        max_steps: args.max_steps,
+        ..Limits::default()
+    };
+    style.banner(
+        Message::new(MessageId::RunningTests)
+            .with("count", cases.len())
+            .localized(style.lang()),
+    );
+    let mut failures = Vec::new();
+    for case in &cases {
+        let verdict = check_case(case, &args, limits, style.lang())?;
+        let id = match verdict {
+            Verdict::Passed => MessageId::CasePassed,
+            Verdict::Blessed => MessageId::CaseBlessed,
+            Verdict::Failed(failure) => {
+                failures.push(failure);
+                MessageId::CaseFailed
+            }
+        };
+        style.result(&Message::new(id).with("name", case.program.display()));
+    }
+    for failure in &failures {
+        // A blank line before each failure sets it apart from the one before
+        style.diff(format_args!("\n{failure}"));
+    }
+    style.note(
+        &Message::new(MessageId::TestSummary)
+            .with("passed", cases.len() - failures.len())
+            .with("failed", failures.len()),
+    );
+    match failures.len() {
+        0 => Ok(()),
+        1 => Err(Message::new(MessageId::TestFailed).into()),
+        failed => Err(Message::new(MessageId::TestsFailed)
+            .with("failed", failed)
+            .into()),
+    }
+}
+
+/// How one golden test went
+enum Verdict {
+    Passed,
+    Blessed,
+    /// Why it failed: a diff of the output, or the error that stopped it
+    Failed(String),
+}
+
+/// Runs one golden test, or blesses its output if --bless was given
+///
+/// Only failing to bless is an error; anything else that goes wrong fails the test.
+fn check_case(
+    case: &golden::Case,
+    args: &TestArgs,
+    limits: Limits,
+    lang: Lang,
+) -> io::Result<Verdict> {
+    let name = case.program.display();
+    let output = match case.run(args.engine, limits, lang) {
+        Ok(output) => output,
+        Err(e) => return Ok(Verdict::Failed(format!("{name}: {e}"))),
+    };
+    if args.bless {
+        std::fs::write(&case.expected, &output)?;
+        return Ok(Verdict::Blessed);
+    }
+    let expected_name = case.expected.display().to_string();
+    let expected = match std::fs::read(&case.expected) {
+        Ok(expected) => expected,
+        Err(e) => {
+            let message = Message::new(MessageId::UnreadableFile)
+                .with("file", &expected_name)
+                .with("error", e);
+            return Ok(Verdict::Failed(message.localized(lang).to_string()));
+        }
+    };
+    Ok(
+        match expected::unified_diff(&expected_name, &expected, &output) {
+            Some(diff) => Verdict::Failed(diff),
+            None => Verdict::Passed,
+        },
+    )
+}
+
+fn visualize(args: VisualizeArgs, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
+    let prog = Program::from_file(&args.program)?;
+    let decorated = DecoratedProgram::from_program(&prog)?;
+    let input = load_input(args.input.as_deref(), args.input_text)?;
+    if args.speed.is_nan() || args.speed < 1.0 {
+        return Err(Message::new(MessageId::SpeedTooLow).into());
+    }
+    let mut machine: Machine<u8> = Machine::new(args.cells, args.extensible, &decorated);
+    let settings = visualize::Settings {
+        speed: args.speed,
+        window: args.window,
+        color: style.stdout_color(),
+    };
+    visualize::visualize(&mut machine, &input, settings)
 }
 
-pub(crate) fn run_bft() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Cli::parse();
+fn debug(args: DebugArgs, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
     let prog = Program::from_file(&args.program)?;
     let decorated = DecoratedProgram::from_program(&prog)?;
-    let _machine: Machine<u8> = Machine::new(args.cells, args.extensible, &decorated);
+    let input = load_input(args.input.as_deref(), args.input_text)?;
+    let mut machine: Machine<u8> = Machine::new(args.cells, args.extensible, &decorated);
+    machine.set_value_format(args.values.format(Notation::Auto));
+    let mut debugger = Debugger::new(machine, input);
+    let mut stdout = io::stdout().lock();
+    match &args.script {
+        Some(script) => {
+            debugger.source(script, &mut stdout)?;
+        }
+        None => {
+            let session = (!args.no_session).then(|| debugger::session_path(&args.program));
+            if let Some(saved) = session.as_ref().filter(|path| path.exists()) {
+                debugger.restore(&std::fs::read_to_string(saved)?, &mut stdout)?;
+                style.note(&Message::new(MessageId::RestoredSession).with("file", saved.display()));
+            }
+            let stdin = io::stdin().lock();
+            let prompt = stdin.is_terminal();
+            debugger.interact(stdin, &mut stdout, prompt)?;
+            match session {
+                Some(path) if !debugger.session().is_empty() => {
+                    std::fs::write(path, debugger.session())?
+                }
+                Some(path) if path.exists() => std::fs::remove_file(path)?,
+                _ => {}
+            }
+        }
+    }
+    Ok(stdout.flush()?)
+}
+
+fn serve(args: ServeArgs, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = std::net::TcpListener::bind(&args.address)?;
+    style.note(&Message::new(MessageId::Listening).with("address", listener.local_addr()?));
+    let settings = serve::Settings {
+        limits: Limits {
+            max_steps: Some(args.max_steps),
+            max_output_bytes: Some(args.max_output_bytes),
+            max_tape: Some(args.max_tape),
+            max_loop_iterations: None,
+        },
+        max_program_bytes: args.max_program_bytes,
+        max_connections: args.max_connections,
+    };
+    Ok(serve::serve(listener, settings)?)
+}
+
+fn selftest(args: SelftestArgs, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
+    let checks = args.engines.len() * selftest::CHECKS.len();
+    style.banner(
+        Message::new(MessageId::RunningChecks)
+            .with("count", checks)
+            .localized(style.lang()),
+    );
+    let mut failed = 0;
+    for engine in &args.engines {
+        for check in selftest::CHECKS {
+            let name = format!("{:<8}{}", engine.name(), check.name);
+            let line = match check.run(*engine) {
+                Ok(()) => Message::new(MessageId::CasePassed).with("name", name),
+                Err(e) => {
+                    failed += 1;
+                    Message::new(MessageId::CaseFailedBecause)
+                        .with("name", name)
+                        .with("error", e)
+                }
+            };
+            style.result(&line);
+        }
+    }
+    style.note(
+        &Message::new(MessageId::TestSummary)
+            .with("passed", checks - failed)
+            .with("failed", failed),
+    );
+    match failed {
+        0 => Ok(()),
+        1 => Err(Message::new(MessageId::CheckFailed).into()),
+        failed => Err(Message::new(MessageId::ChecksFailed)
+            .with("failed", failed)
+            .into()),
+    }
+}
+
+fn bench(args: BenchArgs, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
+    style.banner(format_args!(
+        "{:<12}{:<8}{:>12}{:>14}{:>16}{:>10}",
+        "program", "engine", "time", "steps", "steps/sec", "speedup"
+    ));
+    for benchmark in bench::PROGRAMS {
+        let name = benchmark.name;
+        if !args.programs.is_empty() && !args.programs.iter().any(|wanted| wanted == name) {
+            continue;
+        }
+        // Speedups are relative to the first engine listed
+        let mut baseline = None;
+        for engine in &args.engines {
+            let measurement = bench::measure(benchmark, *engine, args.runs as usize)?;
+            let baseline = *baseline.get_or_insert(measurement.time);
+            println!(
+                "{:<12}{:<8}{:>12}{:>14}{:>16.0}{:>9.2}x",
+                name,
+                engine.name(),
+                format!("{:.2?}", measurement.time),
+                measurement.steps,
+                measurement.steps_per_second(),
+                baseline.as_secs_f64() / measurement.time.as_secs_f64().max(f64::MIN_POSITIVE)
+            );
+        }
+    }
     Ok(())
 }
+
+fn man(args: ManArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut command = Cli::command();
+    // Fills in the full names of subcommands, e.g. `bft cache list`, for their usage lines
+    command.build();
+    match args.out_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(&dir)?;
+            write_man_pages(&command, "bft", &dir)
+        }
+        None => Ok(clap_mangen::Man::new(command).render(&mut io::stdout().lock())?),
+    }
+}
+
+/// Writes the man page for a command and, recursively, every subcommand it has
+///
+/// Pages are named after the full path to the command, e.g. `bft-cache-list.1`.
+fn write_man_pages(
+    command: &clap::Command,
+    title: &str,
+    dir: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let man = clap_mangen::Man::new(command.clone()).title(title);
+    let mut page = File::create(dir.join(man.get_filename()))?;
+    man.render(&mut page)?;
+    for subcommand in command
+        .get_subcommands()
+        .filter(|sub| sub.get_name() != "help")
+    {
+        let title = format!("{title}-{}", subcommand.get_name());
+        write_man_pages(subcommand, &title, dir)?;
+    }
+    Ok(())
+}
+
+fn cache(action: CacheAction, style: &Style) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        CacheAction::List => {
+            for entry in cache::list()? {
+                println!(
+                    "{}\t{} bytes\t{}",
+                    entry.fingerprint,
+                    entry.size,
+                    entry.path.display()
+                );
+            }
+        }
+        CacheAction::Clear => {
+            style.note(&Message::new(MessageId::CacheCleared).with("count", cache::clear()?));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::{ColorChoice, Stream};
+
+    /// A style that writes in English, keeping what it prints
+    fn english(quiet: bool, no_banner: bool) -> Style {
+        Style::new(&StyleArgs {
+            color: ColorChoice::Never,
+            quiet,
+            no_banner,
+            verbose: 0,
+            lang: Some(Lang::English),
+        })
+    }
+
+    #[test]
+    fn escaped_text() {
+        assert_eq!(
+            parse_escaped(r"a\n\x41\0\\"),
+            Ok(EscapedBytes(b"a\nA\0\\".to_vec()))
+        );
+        assert!(parse_escaped(r"\q").is_err());
+        assert!(parse_escaped(r"\x4").is_err());
+        assert!(parse_escaped("trailing\\").is_err());
+    }
+
+    #[test]
+    fn input_comes_from_a_file_or_text() {
+        let text = || Some(EscapedBytes(b"text".to_vec()));
+        assert_eq!(load_input(None, text()).unwrap(), b"text");
+        assert_eq!(load_input(None, None).unwrap(), b"");
+        let path = std::env::temp_dir().join(format!("bft-input-{}", std::process::id()));
+        std::fs::write(&path, "file").unwrap();
+        assert_eq!(load_input(Some(&path), text()).unwrap(), b"file");
+        std::fs::remove_file(&path).unwrap();
+        assert!(load_input(Some(&path), None).is_err());
+    }
+
+    #[test]
+    fn unreadable_expected_output_fails_only_its_own_test() {
+        let dir = std::env::temp_dir().join(format!("bft-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("echo.b"), ",.").unwrap();
+        std::fs::write(dir.join("echo.in"), "x").unwrap();
+        std::fs::write(dir.join("dot.b"), ".").unwrap();
+        std::fs::write(dir.join("dot.expected"), "\0").unwrap();
+        let style = english(true, true);
+        let args = TestArgs {
+            paths: vec![dir.clone()],
+            engine: Engine::Opt,
+            max_steps: None,
+            bless: false,
+        };
+        let cases = golden::discover(&args.paths).unwrap();
+        let verdicts: Vec<_> = cases
+            .iter()
+            .map(|case| check_case(case, &args, Limits::default(), Lang::English).unwrap())
+            .collect();
+        let error = test(args, &style).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(verdicts[0], Verdict::Passed));
+        let Verdict::Failed(failure) = &verdicts[1] else {
+            panic!("A test with no expected output fails");
+        };
+        assert!(failure.starts_with(&format!(
+            "couldn't read {}: ",
+            dir.join("echo.expected").display()
+        )));
+        assert_eq!(error.to_string(), "1 test failed");
+    }
+
+    #[test]
+    fn quiet_and_no_banner_leave_only_the_results() {
+        let dir = std::env::temp_dir().join(format!("bft-quiet-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("dot.b"), ".").unwrap();
+        std::fs::write(dir.join("dot.expected"), "\0").unwrap();
+        let run = |style: Style| {
+            selftest(
+                SelftestArgs {
+                    engines: vec![Engine::Interp],
+                },
+                &style,
+            )
+            .unwrap();
+            let tests = TestArgs {
+                paths: vec![dir.clone()],
+                engine: Engine::Opt,
+                max_steps: None,
+                bless: false,
+            };
+            test(tests, &style).unwrap();
+            let difftest_args = DifftestArgs {
+                program: dir.join("dot.b"),
+                engines: vec![Engine::Interp, Engine::Opt],
+                input: None,
+                input_text: None,
+                max_steps: None,
+            };
+            difftest(difftest_args, &style).unwrap();
+            style.printed.take()
+        };
+        let loud = run(english(false, false));
+        let silent = run(english(true, true));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let printed = |stream, text: &str| loud.contains(&(stream, text.to_string()));
+        assert!(printed(Stream::Stdout, "running 1 test(s)"));
+        assert!(printed(Stream::Stderr, "1 passed, 0 failed"));
+        assert!(printed(Stream::Stderr, "All 2 engines agree"));
+        let results: Vec<_> = loud
+            .iter()
+            .filter(|(stream, line)| *stream == Stream::Stdout && !line.starts_with("running "))
+            .cloned()
+            .collect();
+        assert!(results.contains(&(
+            Stream::Stdout,
+            format!("{} ... ok", dir.join("dot.b").display())
+        )));
+        assert_eq!(silent, results);
+    }
+}
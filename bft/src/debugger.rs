@@ -0,0 +1,511 @@
+//! A line-at-a-time debugger: breakpoints, stepping, and looking at the tape
+//!
+//! Commands come from the terminal, or from a script so an investigation can be repeated and
+//! attached to a bug report. Blank lines and lines starting with `#` are ignored.
+//!
+//! A session's breakpoints, watchpoints and settings are saved as a script of the commands that
+//! set them up, next to the program, and run again when the program is next debugged.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, BufRead, Cursor, Write};
+use std::path::{Path, PathBuf};
+
+use bft_interp::dump::DumpFormat;
+use bft_interp::value::{Notation, ValueFormat};
+use bft_interp::Machine;
+
+/// How many cells `show` prints if it isn't told
+const SHOW_WIDTH: usize = 9;
+
+/// How deeply scripts may `source` each other, so one that sources itself stops
+const MAX_SOURCE_DEPTH: usize = 16;
+
+const HELP: &str = "\
+step [N]           run N instructions, 1 if not given, stopping at breakpoints
+next [N]           like step, but run each loop it comes to as one step
+finish             run until the loop the program is in has finished
+continue           run until a breakpoint or the end of the program
+break [LINE:COL]   stop before the instruction at LINE:COL, or list the breakpoints
+delete [LINE:COL]  remove the breakpoint at LINE:COL, or every breakpoint
+watch [CELL]       stop when CELL changes, or list the watchpoints
+unwatch [CELL]     stop watching CELL, or every cell
+show [CELLS]       print the cells around the head
+dump               print every non-zero line of the tape as a hex dump
+set width CELLS    how many cells show prints by default
+set autoshow on    show the cells every time the program stops, or not with off
+set format FORMAT  write cell values as hex, decimal, char or auto
+source FILE        run the commands in FILE
+help               print this
+quit               stop debugging
+";
+
+/// How much one step runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stride {
+    /// One instruction
+    Instruction,
+    /// One instruction, or a whole loop
+    Over,
+    /// Until the loop the program is in finishes
+    Out,
+}
+
+/// Whether to carry on reading commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Flow {
+    Continue,
+    Quit,
+}
+
+/// Where the session for a program is kept: next to it, with `.bftdbg` added to its name
+pub(crate) fn session_path(program: &Path) -> PathBuf {
+    let mut path = program.as_os_str().to_owned();
+    path.push(".bftdbg");
+    PathBuf::from(path)
+}
+
+/// A program being debugged, with its input, breakpoints and watchpoints
+pub(crate) struct Debugger<'a> {
+    machine: Machine<'a, u8>,
+    input: Cursor<Vec<u8>>,
+    /// The indices of the instructions to stop before
+    breakpoints: BTreeSet<usize>,
+    /// The cells to stop after a change to, with the values they had when last checked
+    watchpoints: BTreeMap<usize, u8>,
+    /// How many cells `show` prints if it isn't told
+    width: usize,
+    /// Whether to show the cells whenever the program stops
+    autoshow: bool,
+    depth: usize,
+}
+
+impl<'a> Debugger<'a> {
+    pub(crate) fn new(machine: Machine<'a, u8>, input: Vec<u8>) -> Debugger<'a> {
+        Debugger {
+            machine,
+            input: Cursor::new(input),
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            width: SHOW_WIDTH,
+            autoshow: false,
+            depth: 0,
+        }
+    }
+
+    /// The commands that set up this session's breakpoints, watchpoints and settings again
+    pub(crate) fn session(&self) -> String {
+        let mut session = String::new();
+        for &index in &self.breakpoints {
+            let instruction = self.machine.prog().decorated_instructions()[index].instruction();
+            session += &format!("break {}:{}\n", instruction.line(), instruction.character());
+        }
+        for cell in self.watchpoints.keys() {
+            session += &format!("watch {cell}\n");
+        }
+        if self.width != SHOW_WIDTH {
+            session += &format!("set width {}\n", self.width);
+        }
+        if self.autoshow {
+            session += "set autoshow on\n";
+        }
+        let notation = self.machine.value_format().notation;
+        if notation != Notation::Auto {
+            session += &format!("set format {}\n", notation_name(notation));
+        }
+        session
+    }
+
+    /// Runs the commands in a saved session, warning about any that no longer work, such as
+    /// breakpoints on instructions that have since been edited away
+    pub(crate) fn restore(&mut self, session: &str, out: &mut dyn Write) -> io::Result<()> {
+        for line in session.lines() {
+            if let Err(e) = self.execute(line, &mut io::sink()) {
+                writeln!(
+                    out,
+                    "warning: couldn't restore {line:?} from the session: {e}"
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads commands until `quit` or the end of the input, reporting mistakes and carrying on
+    pub(crate) fn interact(
+        &mut self,
+        commands: impl BufRead,
+        out: &mut dyn Write,
+        prompt: bool,
+    ) -> io::Result<()> {
+        let mut lines = commands.lines();
+        loop {
+            if prompt {
+                write!(out, "(bft) ")?;
+                out.flush()?;
+            }
+            let Some(line) = lines.next().transpose()? else {
+                return Ok(());
+            };
+            match self.execute(&line, out) {
+                Ok(Flow::Continue) => {}
+                Ok(Flow::Quit) => return Ok(()),
+                Err(e) => writeln!(out, "error: {e}")?,
+            }
+            out.flush()?;
+        }
+    }
+
+    /// Runs the commands in a file, stopping at the first mistake
+    pub(crate) fn source(&mut self, path: &Path, out: &mut dyn Write) -> Result<Flow, String> {
+        if self.depth == MAX_SOURCE_DEPTH {
+            return Err(format!(
+                "scripts are sourced more than {MAX_SOURCE_DEPTH} deep"
+            ));
+        }
+        let script = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        self.depth += 1;
+        let result = script
+            .lines()
+            .enumerate()
+            .try_fold(Flow::Continue, |_, (i, line)| {
+                match self.execute(line, out) {
+                    Ok(Flow::Continue) => Ok(Flow::Continue),
+                    // Not an error, but nothing after it should run
+                    Ok(Flow::Quit) => Err(None),
+                    Err(e) => Err(Some(format!("{}:{}: {e}", path.display(), i + 1))),
+                }
+            });
+        self.depth -= 1;
+        match result {
+            Ok(flow) => Ok(flow),
+            Err(None) => Ok(Flow::Quit),
+            Err(Some(e)) => Err(e),
+        }
+    }
+
+    /// Runs one command
+    pub(crate) fn execute(&mut self, line: &str, out: &mut dyn Write) -> Result<Flow, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(Flow::Continue);
+        }
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let argument = argument.trim();
+        let io = |e: io::Error| e.to_string();
+        match command {
+            "step" | "s" | "next" | "n" => {
+                let count = match argument {
+                    "" => 1,
+                    count => count
+                        .parse()
+                        .map_err(|_| format!("expected a number of steps, not {count:?}"))?,
+                };
+                let stride = match command {
+                    "step" | "s" => Stride::Instruction,
+                    _ => Stride::Over,
+                };
+                self.run(Some(count), stride, out).map_err(io)?;
+            }
+            "finish" | "f" => self.run(Some(1), Stride::Out, out).map_err(io)?,
+            "continue" | "c" => self.run(None, Stride::Instruction, out).map_err(io)?,
+            "break" | "b" if argument.is_empty() => {
+                for &index in &self.breakpoints {
+                    writeln!(out, "{}", self.describe(index)).map_err(io)?;
+                }
+            }
+            "break" | "b" => {
+                let index = self.find(argument)?;
+                self.breakpoints.insert(index);
+                writeln!(out, "breakpoint at {}", self.describe(index)).map_err(io)?;
+            }
+            "delete" | "d" if argument.is_empty() => self.breakpoints.clear(),
+            "delete" | "d" => {
+                let index = self.find(argument)?;
+                if !self.breakpoints.remove(&index) {
+                    return Err(format!("there is no breakpoint at {argument}"));
+                }
+            }
+            "watch" | "w" if argument.is_empty() => {
+                for cell in self.watchpoints.keys() {
+                    writeln!(out, "cell {cell}").map_err(io)?;
+                }
+            }
+            "watch" | "w" => {
+                let cell = parse_cell(argument)?;
+                self.watchpoints.insert(cell, self.value(cell));
+                writeln!(out, "watching cell {cell}").map_err(io)?;
+            }
+            "unwatch" if argument.is_empty() => self.watchpoints.clear(),
+            "unwatch" => {
+                if self.watchpoints.remove(&parse_cell(argument)?).is_none() {
+                    return Err(format!("cell {argument} isn't being watched"));
+                }
+            }
+            "set" => match argument.split_once(' ').map(|(a, b)| (a, b.trim())) {
+                Some(("width", width)) => {
+                    self.width = width
+                        .parse()
+                        .map_err(|_| format!("expected a number of cells, not {width:?}"))?
+                }
+                Some(("autoshow", "on")) => self.autoshow = true,
+                Some(("autoshow", "off")) => self.autoshow = false,
+                Some(("format", name)) => {
+                    let notation = [
+                        Notation::Hex,
+                        Notation::Decimal,
+                        Notation::Char,
+                        Notation::Auto,
+                    ]
+                    .into_iter()
+                    .find(|notation| notation_name(*notation) == name)
+                    .ok_or_else(|| format!("expected hex, decimal, char or auto, not {name:?}"))?;
+                    self.machine.set_value_format(ValueFormat {
+                        notation,
+                        ..self.machine.value_format()
+                    });
+                }
+                _ => {
+                    return Err(
+                        "expected set width CELLS, set autoshow on|off or set format FORMAT"
+                            .to_string(),
+                    )
+                }
+            },
+            "show" | "p" => {
+                let width = match argument {
+                    "" => self.width,
+                    width => width
+                        .parse()
+                        .map_err(|_| format!("expected a number of cells, not {width:?}"))?,
+                };
+                write!(out, "{}", self.machine.render_state(width)).map_err(io)?;
+            }
+            "dump" => {
+                let format = DumpFormat {
+                    non_zero: true,
+                    ..DumpFormat::default()
+                };
+                write!(out, "{}", format.dump(self.machine.cells(), 0)).map_err(io)?;
+            }
+            "source" if argument.is_empty() => return Err("source needs a file".to_string()),
+            "source" => return self.source(Path::new(argument), out),
+            "help" | "h" | "?" => write!(out, "{HELP}").map_err(io)?,
+            "quit" | "q" => return Ok(Flow::Quit),
+            _ => return Err(format!("unknown command {command:?}, try help")),
+        }
+        Ok(Flow::Continue)
+    }
+
+    /// Takes up to `count` steps, or carries on until the program stops if not given, stopping
+    /// early before a breakpoint or after a watched cell changes, and says where it stopped
+    ///
+    /// A step that runs a whole loop doesn't stop at the breakpoints inside it. The program's
+    /// output is written first, on a line of its own.
+    fn run(&mut self, count: Option<u64>, stride: Stride, out: &mut dyn Write) -> io::Result<()> {
+        let mut output = Vec::new();
+        let stopped = self.stop(count, stride, &mut output);
+        if !output.is_empty() {
+            out.write_all(&output)?;
+            if !output.ends_with(b"\n") {
+                writeln!(out)?;
+            }
+        }
+        writeln!(out, "{stopped}")?;
+        if self.autoshow {
+            write!(out, "{}", self.machine.render_state(self.width))?;
+        }
+        Ok(())
+    }
+
+    /// Executes instructions for [Debugger::run], returning why it stopped
+    fn stop(&mut self, count: Option<u64>, stride: Stride, output: &mut Vec<u8>) -> String {
+        let mut steps = 0;
+        loop {
+            let input = &mut self.input;
+            let stepped = match stride {
+                Stride::Instruction => self.machine.step(input, output),
+                Stride::Over => self.machine.step_over(input, output),
+                Stride::Out => self.machine.step_out(input, output),
+            };
+            match stepped {
+                Ok(true) => {}
+                Ok(false) if steps == 0 => return "the program has finished".to_string(),
+                Ok(false) => return format!("finished after {} steps", self.machine.steps()),
+                Err(e) => return format!("stopped: {e}"),
+            }
+            steps += 1;
+            if let Some(stopped) = self.check_watchpoints() {
+                return stopped;
+            }
+            let next = self.next();
+            if let Some(index) = next.filter(|index| self.breakpoints.contains(index)) {
+                return format!("breakpoint at {}", self.describe(index));
+            }
+            if count.is_some_and(|count| steps >= count) {
+                return match next {
+                    Some(index) => format!("at {}", self.describe(index)),
+                    None => format!("finished after {} steps", self.machine.steps()),
+                };
+            }
+        }
+    }
+
+    /// Notes the new values of any watched cells that have changed, describing the changes
+    fn check_watchpoints(&mut self) -> Option<String> {
+        let cells = self.machine.cells();
+        // Auto's columns are for show, so a change reads better in decimal unless asked otherwise
+        let mut format = self.machine.value_format();
+        if format.notation == Notation::Auto {
+            format.notation = Notation::Decimal;
+        }
+        let mut changes = Vec::new();
+        for (&cell, last) in &mut self.watchpoints {
+            let value = cells.get(cell).copied().unwrap_or(0);
+            if value != *last {
+                changes.push(format!(
+                    "cell {cell} changed from {} to {}",
+                    format.display(last),
+                    format.display(&value)
+                ));
+                *last = value;
+            }
+        }
+        (!changes.is_empty()).then(|| format!("watchpoint: {}", changes.join(", ")))
+    }
+
+    /// The value of a cell, which is zero if the tape hasn't grown that far yet
+    fn value(&self, cell: usize) -> u8 {
+        self.machine.cells().get(cell).copied().unwrap_or(0)
+    }
+
+    /// The index of the instruction about to run, or None once the program has finished
+    fn next(&self) -> Option<usize> {
+        let next = self.machine.next_instruction()?.instruction();
+        self.machine
+            .prog()
+            .position_to_index(next.line(), next.character())
+    }
+
+    /// Finds the instruction at a `LINE:COL` position
+    fn find(&self, position: &str) -> Result<usize, String> {
+        let parsed = position
+            .split_once(':')
+            .and_then(|(line, column)| Some((line.parse().ok()?, column.parse().ok()?)));
+        let Some((line, column)) = parsed else {
+            return Err(format!("expected a position like 3:14, not {position:?}"));
+        };
+        self.machine
+            .prog()
+            .position_to_index(line, column)
+            .ok_or_else(|| format!("there is no instruction at {position}"))
+    }
+
+    fn describe(&self, index: usize) -> String {
+        self.machine.prog().decorated_instructions()[index].to_string()
+    }
+}
+
+/// The name `set format` knows a notation by
+fn notation_name(notation: Notation) -> &'static str {
+    match notation {
+        Notation::Hex => "hex",
+        Notation::Decimal => "decimal",
+        Notation::Char => "char",
+        Notation::Auto => "auto",
+    }
+}
+
+fn parse_cell(cell: &str) -> Result<usize, String> {
+    cell.parse()
+        .map_err(|_| format!("expected a cell number, not {cell:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::{DecoratedProgram, Program};
+
+    #[test]
+    fn scripts_stop_at_breakpoints() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", "++\n[>+<-]>.")).unwrap();
+        let mut debugger = Debugger::new(Machine::new(None, false, &prog), Vec::new());
+        let mut out = Vec::new();
+        let script = "# Stop in the loop\nbreak 2:2\ncontinue\nstep 2\nshow 2\ndelete\ncontinue\n";
+        for line in script.lines() {
+            assert_eq!(debugger.execute(line, &mut out), Ok(Flow::Continue));
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "breakpoint at 2:2 Increment current location\n\
+             breakpoint at 2:2 Increment current location\n\
+             at 2:4 Decrement current location\n\
+             step 5, head 1, next 2:4 Decrement current location\n\
+             \x20     0: 0x02    2\n\
+             >     1: 0x01    1\n\
+             \x02\n\
+             finished after 15 steps\n"
+        );
+        let error = debugger.execute("break 1:3", &mut io::sink()).unwrap_err();
+        assert_eq!(error, "there is no instruction at 1:3");
+    }
+
+    #[test]
+    fn next_and_finish_run_whole_loops() {
+        let prog =
+            DecoratedProgram::from_program(&Program::new("<test>", "+++[>+[-]<-]>.")).unwrap();
+        let mut debugger = Debugger::new(Machine::new(None, false, &prog), Vec::new());
+        let mut out = Vec::new();
+        for line in ["next 3", "step 3", "next", "finish", "next"] {
+            assert_eq!(debugger.execute(line, &mut out), Ok(Flow::Continue));
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "at 1:4 Start looping\n\
+             at 1:7 Start looping\n\
+             at 1:10 Decrement current location\n\
+             at 1:13 Increment current location\n\
+             at 1:14 Output the byte at the current location\n"
+        );
+    }
+
+    #[test]
+    fn sessions_are_restored() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", "+>+++")).unwrap();
+        let mut debugger = Debugger::new(Machine::new(None, false, &prog), Vec::new());
+        let commands = [
+            "break 1:4",
+            "watch 1",
+            "set width 3",
+            "set autoshow on",
+            "set format hex",
+        ];
+        for line in commands {
+            assert_eq!(debugger.execute(line, &mut io::sink()), Ok(Flow::Continue));
+        }
+        let session = debugger.session();
+        assert_eq!(
+            session,
+            "break 1:4\nwatch 1\nset width 3\nset autoshow on\nset format hex\n"
+        );
+
+        let edited = DecoratedProgram::from_program(&Program::new("<test>", "+>+")).unwrap();
+        let mut debugger = Debugger::new(Machine::new(None, false, &edited), Vec::new());
+        let mut warnings = Vec::new();
+        debugger.restore(&session, &mut warnings).unwrap();
+        assert_eq!(
+            String::from_utf8(warnings).unwrap(),
+            "warning: couldn't restore \"break 1:4\" from the session: \
+             there is no instruction at 1:4\n"
+        );
+        let mut out = Vec::new();
+        debugger.execute("continue", &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "watchpoint: cell 1 changed from 0x00 to 0x01\n\
+             step 3, head 1, next none, the program has finished\n\
+             \x20     0: 0x01\n\
+             >     1: 0x01\n\
+             \x20     2: 0x00\n"
+        );
+    }
+}
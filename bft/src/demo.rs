@@ -0,0 +1,124 @@
+//! Showing a program run slowly, for a class to watch: the first cells of the tape redrawn in
+//! place after every step, with no keys to press
+//!
+//! Unlike `bft visualize`, the tape doesn't follow the head, so a value can be seen moving from
+//! one fixed cell to the next.
+
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+use bft_interp::value::{Notation, ValueFormat};
+use bft_interp::{CellKind, Machine, VMError};
+
+/// How each frame is drawn
+pub(crate) struct Settings {
+    /// How many cells, from the first, are shown
+    pub(crate) cells: usize,
+    /// How long each frame stays up before the next step
+    pub(crate) delay: Duration,
+}
+
+/// How many lines each frame takes, which the next one moves the cursor back up over
+const LINES: usize = 5;
+
+/// Runs the program, drawing a frame on standard error after each step
+///
+/// The program's output is collected in `output` and shown in the frame as it's written, so that
+/// it doesn't break up the frame; the caller writes it out once the program stops.
+pub(crate) fn demo<T: CellKind>(
+    machine: &mut Machine<T>,
+    input: &mut impl Read,
+    output: &mut Vec<u8>,
+    settings: &Settings,
+) -> Result<(), VMError> {
+    let mut stderr = io::stderr().lock();
+    let _ = write!(stderr, "{}", frame(machine, output, settings));
+    loop {
+        thread::sleep(settings.delay);
+        let stepped = machine.step(input, output);
+        let _ = write!(stderr, "\x1b[{LINES}A{}", frame(machine, output, settings));
+        let _ = stderr.flush();
+        match stepped {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Draws the step and head, the first cells with the one under the head marked, and the last line
+/// of the output
+fn frame<T: CellKind>(machine: &Machine<T>, output: &[u8], settings: &Settings) -> String {
+    let values = ValueFormat {
+        notation: Notation::Decimal,
+        width: None,
+    };
+    let next = match machine.next_instruction() {
+        Some(next) => next.instruction().to_string(),
+        None => "end of program".to_string(),
+    };
+    let status = format!("step {}  head {}  {next}", machine.steps(), machine.head());
+    let shown: Vec<String> = machine
+        .cells()
+        .iter()
+        .take(settings.cells)
+        .map(|cell| values.display(cell).to_string())
+        .collect();
+    let width = shown.iter().map(String::len).max().unwrap_or(0).max(3);
+    let (mut indices, mut cells, mut marks) = (String::new(), String::new(), String::new());
+    for (index, value) in shown.iter().enumerate() {
+        let head = index == machine.head();
+        indices.push_str(&format!(" {index:>width$}  "));
+        if head {
+            cells.push_str(&format!("[{value:>width$}] "));
+        } else {
+            cells.push_str(&format!(" {value:>width$}  "));
+        }
+        marks.push_str(&format!(" {:>width$}  ", if head { "^" } else { "" }));
+    }
+    let text = String::from_utf8_lossy(output);
+    let last = text.lines().last().unwrap_or_default();
+    let output = format!(
+        "output: {}",
+        last.chars().filter(|c| !c.is_control()).collect::<String>()
+    );
+    // Clearing to the end of each line wipes whatever the last frame left there
+    [status, indices, cells, marks, output]
+        .iter()
+        .map(|line| format!("{line}\x1b[K\n"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::{DecoratedProgram, Program};
+
+    #[test]
+    fn frames_show_the_first_cells_and_the_head() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", "++[->+<]")).unwrap();
+        let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+        for _ in 0..5 {
+            machine.step(&mut io::empty(), &mut io::sink()).unwrap();
+        }
+        let settings = Settings {
+            cells: 3,
+            delay: Duration::ZERO,
+        };
+        let frame = frame(&machine, b"ab\ncd", &settings);
+        let lines: Vec<&str> = frame
+            .lines()
+            .map(|line| line.trim_end_matches("\x1b[K"))
+            .collect();
+        assert_eq!(lines.len(), LINES);
+        assert_eq!(
+            lines[0],
+            "step 5  head 1  1:6 Increment the byte at the current location"
+        );
+        assert_eq!(lines[1], "   0     1     2  ");
+        assert_eq!(lines[2], "   1  [  0]    0  ");
+        assert_eq!(lines[3], "         ^        ");
+        assert_eq!(lines[4], "output: cd");
+    }
+}
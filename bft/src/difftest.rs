@@ -0,0 +1,212 @@
+//! Running a program on several engines and finding where they disagree
+
+use std::fmt;
+use std::io::Cursor;
+
+use bft_interp::bytecode::{Bytecode, Op};
+use bft_interp::limits::Limits;
+use bft_interp::trace::{Step, Tracer};
+use bft_interp::Machine;
+use bft_types::{DecoratedProgram, PositionedInstruction};
+
+use crate::cli::{run_engine, Engine};
+
+/// Where the machine was when it wrote a byte of output
+#[derive(Clone, Copy)]
+struct Write {
+    step: u64,
+    instruction: PositionedInstruction,
+    head: usize,
+}
+
+/// Remembers where every output operation happened
+#[derive(Default)]
+struct OutputTracer {
+    writes: Vec<Write>,
+}
+
+impl Tracer<u8> for OutputTracer {
+    fn step(&mut self, step: &Step<'_, u8>) {
+        if step.op == Op::Output {
+            self.writes.push(Write {
+                step: step.step,
+                instruction: step.instruction,
+                head: step.head,
+            });
+        }
+    }
+}
+
+/// Everything about one engine's run that's compared
+struct Run {
+    engine: Engine,
+    output: Vec<u8>,
+    writes: Vec<Write>,
+    error: Option<String>,
+    head: usize,
+    cells: Vec<u8>,
+    steps: u64,
+}
+
+impl Run {
+    fn new(engine: Engine, prog: &DecoratedProgram, limits: Limits, input: &[u8]) -> Run {
+        let bytecode = match engine {
+            Engine::Interp => Bytecode::compile_unoptimised(prog),
+            Engine::Opt => Bytecode::compile(prog),
+        };
+        let mut machine: Machine<u8> = Machine::new(None, false, prog);
+        machine.set_limits(limits);
+        let mut output = Vec::new();
+        let mut tracer = OutputTracer::default();
+        let result = run_engine(
+            engine,
+            &mut machine,
+            &bytecode,
+            &mut Cursor::new(input),
+            &mut output,
+            &mut tracer,
+        );
+        Run {
+            engine,
+            output,
+            writes: tracer.writes,
+            error: result.err().map(|e| e.to_string()),
+            head: machine.head(),
+            cells: machine.cells().to_vec(),
+            steps: machine.steps(),
+        }
+    }
+
+    /// Describes where this run was when it wrote output byte `index`, or that it never did
+    fn describe_write(&self, index: usize) -> String {
+        match (self.output.get(index), self.writes.get(index)) {
+            (Some(byte), Some(write)) => format!(
+                "wrote {byte} at step {} by {}:{} with the head at {}",
+                write.step,
+                write.instruction.line(),
+                write.instruction.character(),
+                write.head
+            ),
+            _ => match &self.error {
+                Some(e) => format!("stopped first, after {} steps: {e}", self.steps),
+                None => format!("finished first, after {} steps", self.steps),
+            },
+        }
+    }
+}
+
+/// The first way in which two engines disagreed
+pub(crate) struct Divergence {
+    description: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
+/// Runs the program on each engine with the same input, comparing each against the first
+///
+/// Output is compared first, byte by byte, then how each run ended, and finally the tape.
+pub(crate) fn compare(
+    prog: &DecoratedProgram,
+    engines: &[Engine],
+    limits: Limits,
+    input: &[u8],
+) -> Option<Divergence> {
+    let runs: Vec<Run> = engines
+        .iter()
+        .map(|engine| Run::new(*engine, prog, limits, input))
+        .collect();
+    let (reference, others) = runs.split_first()?;
+    for other in others {
+        let (a, b) = (reference.engine.name(), other.engine.name());
+        let same_prefix = reference
+            .output
+            .iter()
+            .zip(&other.output)
+            .take_while(|(x, y)| x == y)
+            .count();
+        if reference.output != other.output {
+            return Some(Divergence {
+                description: format!(
+                    "output byte {same_prefix} differs:\n  {a}: {}\n  {b}: {}",
+                    reference.describe_write(same_prefix),
+                    other.describe_write(same_prefix)
+                ),
+            });
+        }
+        if reference.error != other.error {
+            let ending = |run: &Run| run.error.clone().unwrap_or("finished".to_string());
+            return Some(Divergence {
+                description: format!(
+                    "the runs ended differently:\n  {a}: {}\n  {b}: {}",
+                    ending(reference),
+                    ending(other)
+                ),
+            });
+        }
+        if reference.head != other.head {
+            return Some(Divergence {
+                description: format!(
+                    "the head finished in different places:\n  {a}: {}\n  {b}: {}",
+                    reference.head, other.head
+                ),
+            });
+        }
+        let cell = (0..reference.cells.len().max(other.cells.len()))
+            .find(|i| reference.cells.get(*i) != other.cells.get(*i));
+        if let Some(cell) = cell {
+            let value = |run: &Run| {
+                run.cells
+                    .get(cell)
+                    .map_or("missing".to_string(), u8::to_string)
+            };
+            return Some(Divergence {
+                description: format!(
+                    "cell {cell} finished with different values:\n  {a}: {}\n  {b}: {}",
+                    value(reference),
+                    value(other)
+                ),
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::Program;
+
+    fn compare_source(source: &str, max_steps: Option<u64>) -> Option<String> {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", source)).unwrap();
+        let limits = Limits {
+            max_steps,
+            ..Limits::default()
+        };
+        compare(&prog, &[Engine::Interp, Engine::Opt], limits, b"ab")
+            .map(|divergence| divergence.to_string())
+    }
+
+    #[test]
+    fn engines_agree_on_a_whole_run() {
+        assert!(compare_source(",.>,+.[-]<[->+<]", None).is_none());
+    }
+
+    #[test]
+    fn the_first_difference_is_described() {
+        // Bytecode runs the three increments as one step, so only the interpreter hits the limit
+        let output = compare_source("+++.", Some(2)).unwrap();
+        assert_eq!(
+            output,
+            "output byte 0 differs:\n  interp: stopped first, after 3 steps: Instruction 1:3 Increment \
+             the byte at the current location went over the limit of 2 steps\n  \
+             opt: wrote 3 at step 2 by 1:4 with the head at 0"
+        );
+        let ending = compare_source("+++", Some(2)).unwrap();
+        assert!(ending.starts_with("the runs ended differently:\n  interp: "));
+        assert!(ending.ends_with("\n  opt: finished"));
+    }
+}
@@ -0,0 +1,102 @@
+//! Printing the state a Machine was left in after a run
+
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use bft_interp::dump::{DumpFormat, Radix};
+use bft_interp::value::ValueFormat;
+use bft_interp::{CellKind, Machine};
+use clap::ValueEnum;
+
+/// Which cells to include in a dump
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Window {
+    /// Every cell
+    All,
+    /// Only cells at most this far from the head
+    Around(usize),
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Window, String> {
+        match s {
+            "all" => Ok(Window::All),
+            _ => s
+                .parse()
+                .map(Window::Around)
+                .map_err(|_| format!("expected a number of cells or \"all\", not {s:?}")),
+        }
+    }
+}
+
+/// The bases --dump-radix accepts
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub(crate) enum DumpRadix {
+    Bin,
+    Oct,
+    Dec,
+    Hex,
+}
+
+impl From<DumpRadix> for Radix {
+    fn from(radix: DumpRadix) -> Radix {
+        match radix {
+            DumpRadix::Bin => Radix::Binary,
+            DumpRadix::Oct => Radix::Octal,
+            DumpRadix::Dec => Radix::Decimal,
+            DumpRadix::Hex => Radix::Hex,
+        }
+    }
+}
+
+/// Writes the head position and step count of a Machine, then either its non-zero cells, with
+/// their values written as `values` says, or, given a format, a hex dump of the cells
+///
+/// In the list of non-zero cells, the cell under the head is always included, even if it's zero.
+pub(crate) fn write_state<T: CellKind + Copy + Into<u64>>(
+    machine: &Machine<T>,
+    window: Window,
+    format: Option<DumpFormat>,
+    values: ValueFormat,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let head = machine.head();
+    writeln!(out, "head: {head}")?;
+    writeln!(out, "steps: {}", machine.steps())?;
+    let range = match window {
+        Window::All => 0..machine.cells().len(),
+        Window::Around(distance) => {
+            head.saturating_sub(distance)..(head + distance + 1).min(machine.cells().len())
+        }
+    };
+    if let Some(format) = format {
+        writeln!(
+            out,
+            "cells {}-{} of {}:",
+            range.start,
+            range.end - 1,
+            machine.cells().len()
+        )?;
+        let dump = format.dump(&machine.cells()[range.clone()], range.start);
+        return out.write_all(dump.as_bytes());
+    }
+    writeln!(
+        out,
+        "non-zero cells {}-{} of {}:",
+        range.start,
+        range.end - 1,
+        machine.cells().len()
+    )?;
+    for (index, cell) in machine.cells()[range.clone()].iter().enumerate() {
+        let index = index + range.start;
+        let value = values.display(cell);
+        if index == head {
+            writeln!(out, "{index:>8}: {value} <- head")?;
+        } else if !cell.is_zero() {
+            writeln!(out, "{index:>8}: {value}")?;
+        }
+    }
+    Ok(())
+}
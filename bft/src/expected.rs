@@ -0,0 +1,127 @@
+//! Comparing a program's output against the output it was expected to produce
+
+use std::fmt::Write;
+
+use bft_types::diff::{shortest_edit, Edit};
+
+/// Lines of unchanged output shown around each change
+const CONTEXT: usize = 3;
+
+/// One line of a unified diff, before it's grouped into hunks
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Makes a unified diff from the expected output to the actual output
+///
+/// Returns None if they're the same. Output that isn't UTF-8 is shown lossily, but still compared
+/// byte for byte.
+pub(crate) fn unified_diff(expected_name: &str, expected: &[u8], actual: &[u8]) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+    let expected = String::from_utf8_lossy(expected);
+    let actual = String::from_utf8_lossy(actual);
+    let old: Vec<&str> = expected.split_inclusive('\n').collect();
+    let new: Vec<&str> = actual.split_inclusive('\n').collect();
+
+    // How far along the expected output the edits have got
+    let mut position = 0;
+    let mut lines = Vec::new();
+    for edit in shortest_edit(&old, &new) {
+        lines.push(match edit {
+            Edit::Keep => {
+                position += 1;
+                Line::Same(old[position - 1])
+            }
+            Edit::Remove(index) => {
+                position = index + 1;
+                Line::Removed(old[index])
+            }
+            Edit::Add(index) => Line::Added(new[index]),
+        });
+    }
+
+    let mut diff = format!("--- {expected_name}\n+++ <program output>\n");
+    let changed: Vec<usize> = (0..lines.len())
+        .filter(|i| !matches!(lines[*i], Line::Same(_)))
+        .collect();
+    let mut start = 0;
+    while start < changed.len() {
+        // Changes close enough to share context go in the same hunk
+        let mut end = start;
+        while end + 1 < changed.len() && changed[end + 1] - changed[end] <= 2 * CONTEXT {
+            end += 1;
+        }
+        let first = changed[start].saturating_sub(CONTEXT);
+        let last = (changed[end] + CONTEXT).min(lines.len() - 1);
+        let hunk = &lines[first..=last];
+
+        let before = &lines[..first];
+        let old_start = before
+            .iter()
+            .filter(|l| !matches!(l, Line::Added(_)))
+            .count();
+        let new_start = before
+            .iter()
+            .filter(|l| !matches!(l, Line::Removed(_)))
+            .count();
+        let old_count = hunk.iter().filter(|l| !matches!(l, Line::Added(_))).count();
+        let new_count = hunk
+            .iter()
+            .filter(|l| !matches!(l, Line::Removed(_)))
+            .count();
+        // Empty ranges are numbered from the line before them
+        let number = |start: usize, count: usize| if count == 0 { start } else { start + 1 };
+        let _ = writeln!(
+            diff,
+            "@@ -{},{old_count} +{},{new_count} @@",
+            number(old_start, old_count),
+            number(new_start, new_count)
+        );
+        for line in hunk {
+            let (prefix, text) = match line {
+                Line::Same(text) => (' ', text),
+                Line::Removed(text) => ('-', text),
+                Line::Added(text) => ('+', text),
+            };
+            match text.strip_suffix('\n') {
+                Some(text) => {
+                    let _ = writeln!(diff, "{prefix}{text}");
+                }
+                None => {
+                    let _ = writeln!(diff, "{prefix}{text}\n\\ No newline at end of file");
+                }
+            }
+        }
+        start = end + 1;
+    }
+    Some(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_output_has_no_diff() {
+        assert!(unified_diff("e", b"a\nb\n", b"a\nb\n").is_none());
+    }
+
+    #[test]
+    fn changes_are_shown_with_context() {
+        let expected = b"1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let actual = b"1\n2\n3\n4\nfive\n6\n7\n8\n9";
+        assert_eq!(
+            unified_diff("e", expected, actual).unwrap(),
+            "--- e\n+++ <program output>\n\
+             @@ -2,8 +2,8 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n-9\n+9\n\\ No newline at end of file\n"
+        );
+        assert_eq!(
+            unified_diff("e", b"", b"hi\n").unwrap(),
+            "--- e\n+++ <program output>\n@@ -0,0 +1,1 @@\n+hi\n"
+        );
+    }
+}
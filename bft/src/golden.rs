@@ -0,0 +1,133 @@
+//! Golden tests: programs whose output is checked against a file of what it should be
+//!
+//! A test is a program, `foo.b` or `foo.bf`, next to a `foo.expected` file holding its output. If
+//! there's a `foo.in` file, the program reads it as input; otherwise it gets none.
+
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+
+use bft_interp::messages::{Lang, Localize};
+use bft_interp::{bytecode::Bytecode, limits::Limits, Machine};
+use bft_types::{DecoratedProgram, Program};
+
+use crate::cli::{run_engine, Engine};
+use crate::messages::{Message, MessageId};
+
+/// The extensions a program's file may have
+const PROGRAM_EXTENSIONS: [&str; 2] = ["b", "bf"];
+
+/// A program and the files that go with it
+pub(crate) struct Case {
+    pub(crate) program: PathBuf,
+    pub(crate) input: Option<PathBuf>,
+    pub(crate) expected: PathBuf,
+}
+
+impl Case {
+    /// Returns the test for a program, if it has an expected output or an input
+    ///
+    /// A program with only an input isn't a test yet, but blessing it makes it one. Testing it
+    /// before then fails, as there's no expected output to read.
+    fn for_program(program: &Path) -> Option<Case> {
+        let extension = program.extension()?.to_str()?;
+        if !PROGRAM_EXTENSIONS.contains(&extension) {
+            return None;
+        }
+        let input = program.with_extension("in");
+        let expected = program.with_extension("expected");
+        if !input.is_file() && !expected.is_file() {
+            return None;
+        }
+        Some(Case {
+            program: program.to_path_buf(),
+            input: input.is_file().then_some(input),
+            expected,
+        })
+    }
+
+    /// Runs the program, returning what it wrote, or why it failed in `lang`
+    pub(crate) fn run(
+        &self,
+        engine: Engine,
+        limits: Limits,
+        lang: Lang,
+    ) -> Result<Vec<u8>, String> {
+        let unreadable = |path: &Path, error: io::Error| {
+            Message::new(MessageId::UnreadableFile)
+                .with("file", path.display())
+                .with("error", error)
+                .localized(lang)
+                .to_string()
+        };
+        let prog = Program::from_file(&self.program).map_err(|e| unreadable(&self.program, e))?;
+        let decorated =
+            DecoratedProgram::from_program(&prog).map_err(|e| e.localized(lang).to_string())?;
+        let bytecode = match engine {
+            Engine::Interp => Bytecode::compile_unoptimised(&decorated),
+            Engine::Opt => Bytecode::compile(&decorated),
+        };
+        let input = match &self.input {
+            Some(path) => fs::read(path).map_err(|e| unreadable(path, e))?,
+            None => Vec::new(),
+        };
+        let mut output = Vec::new();
+        let mut machine: Machine<u8> = Machine::new(None, false, &decorated);
+        machine.set_limits(limits);
+        run_engine(
+            engine,
+            &mut machine,
+            &bytecode,
+            &mut Cursor::new(input),
+            &mut output,
+            &mut (),
+        )
+        .map_err(|e| e.localized(lang).to_string())?;
+        Ok(output)
+    }
+}
+
+/// Finds every test in the given files and directories, searching directories recursively
+pub(crate) fn discover(paths: &[PathBuf]) -> io::Result<Vec<Case>> {
+    let mut cases = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<_>>()?;
+            entries.sort();
+            cases.extend(discover(&entries)?);
+        } else {
+            cases.extend(Case::for_program(path));
+        }
+    }
+    Ok(cases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn programs_need_an_expected_output_or_an_input() {
+        let dir = std::env::temp_dir().join(format!("bft-golden-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("a.b"), ".").unwrap();
+        fs::write(dir.join("a.expected"), "\0").unwrap();
+        fs::write(dir.join("nested/b.bf"), ",.").unwrap();
+        fs::write(dir.join("nested/b.in"), "x").unwrap();
+        fs::write(dir.join("lonely.b"), ".").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let cases = discover(std::slice::from_ref(&dir)).unwrap();
+        let programs: Vec<_> = cases.iter().map(|case| case.program.clone()).collect();
+        assert_eq!(programs, [dir.join("a.b"), dir.join("nested/b.bf")]);
+        assert_eq!(
+            cases[1]
+                .run(Engine::Opt, Limits::default(), Lang::English)
+                .unwrap(),
+            b"x"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+//! Helpers for writing the JSON reports that bft's subcommands produce, and reading the JSON
+//! requests that `bft rpc` is sent
+
+use std::fmt;
+
+/// Quotes a string for JSON
+pub(crate) fn string(text: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Encodes bytes as a quoted base64 string, so binary output survives the trip through JSON
+pub(crate) fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::from('"');
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | u32::from(byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded.push('"');
+    encoded
+}
+
+/// A parsed JSON value
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// The members of an object, in the order they were written
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The member called `key`, if this is an object that has one
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The value as a whole number that isn't negative
+    pub(crate) fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 && *n <= u64::MAX as f64 => {
+                Some(*n as u64)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Writes the value back out as compact JSON
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Number(n) if n.is_finite() => write!(f, "{n}"),
+            Value::Number(_) => write!(f, "null"),
+            Value::String(text) => write!(f, "{}", string(text)),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    write!(f, "{}{item}", if i == 0 { "" } else { "," })?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    write!(
+                        f,
+                        "{}{}:{value}",
+                        if i == 0 { "" } else { "," },
+                        string(name)
+                    )?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Parses a JSON document, or says where it stops making sense
+pub(crate) fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        text: text.as_bytes(),
+        at: 0,
+    };
+    let value = parser.value(0)?;
+    parser.skip_space();
+    match parser.text.get(parser.at) {
+        None => Ok(value),
+        Some(_) => Err(parser.error("unexpected text after the value")),
+    }
+}
+
+/// How deeply arrays and objects may nest before parsing gives up
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    text: &'a [u8],
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{message} at byte {}", self.at)
+    }
+
+    fn skip_space(&mut self) {
+        while self
+            .text
+            .get(self.at)
+            .is_some_and(|byte| matches!(byte, b' ' | b'\t' | b'\n' | b'\r'))
+        {
+            self.at += 1;
+        }
+    }
+
+    /// Consumes `expected` if it comes next
+    fn eat(&mut self, expected: &[u8]) -> bool {
+        let found = self.text[self.at..].starts_with(expected);
+        if found {
+            self.at += expected.len();
+        }
+        found
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("too deeply nested"));
+        }
+        self.skip_space();
+        match self.text.get(self.at) {
+            Some(b'n') if self.eat(b"null") => Ok(Value::Null),
+            Some(b't') if self.eat(b"true") => Ok(Value::Bool(true)),
+            Some(b'f') if self.eat(b"false") => Ok(Value::Bool(false)),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b'[') => {
+                self.at += 1;
+                let mut items = Vec::new();
+                self.skip_space();
+                if !self.eat(b"]") {
+                    loop {
+                        items.push(self.value(depth + 1)?);
+                        self.skip_space();
+                        if self.eat(b"]") {
+                            break;
+                        }
+                        if !self.eat(b",") {
+                            return Err(self.error("expected , or ]"));
+                        }
+                    }
+                }
+                Ok(Value::Array(items))
+            }
+            Some(b'{') => {
+                self.at += 1;
+                let mut members = Vec::new();
+                self.skip_space();
+                if !self.eat(b"}") {
+                    loop {
+                        self.skip_space();
+                        if self.text.get(self.at) != Some(&b'"') {
+                            return Err(self.error("expected a member name"));
+                        }
+                        let name = self.string()?;
+                        self.skip_space();
+                        if !self.eat(b":") {
+                            return Err(self.error("expected :"));
+                        }
+                        members.push((name, self.value(depth + 1)?));
+                        self.skip_space();
+                        if self.eat(b"}") {
+                            break;
+                        }
+                        if !self.eat(b",") {
+                            return Err(self.error("expected , or }"));
+                        }
+                    }
+                }
+                Ok(Value::Object(members))
+            }
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.at;
+                while self.text.get(self.at).is_some_and(|byte| {
+                    matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+                }) {
+                    self.at += 1;
+                }
+                std::str::from_utf8(&self.text[start..self.at])
+                    .ok()
+                    .and_then(|number| number.parse().ok())
+                    .map(Value::Number)
+                    .ok_or_else(|| self.error("badly formed number"))
+            }
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    /// Reads a quoted string, with the parser at its opening quote
+    fn string(&mut self) -> Result<String, String> {
+        self.at += 1;
+        let mut text = Vec::new();
+        loop {
+            match self.text.get(self.at) {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.at += 1;
+                    let escaped = match self.text.get(self.at) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let mut unit = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&unit)
+                                && self.text[self.at + 1..].starts_with(b"\\u")
+                            {
+                                self.at += 2;
+                                let low = self.hex4()?;
+                                unit = 0x10000
+                                    + ((unit - 0xd800) << 10)
+                                    + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            char::from_u32(unit).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return Err(self.error("unknown escape")),
+                    };
+                    text.extend_from_slice(escaped.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                Some(&byte) => text.push(byte),
+            }
+            self.at += 1;
+        }
+        self.at += 1;
+        String::from_utf8(text).map_err(|_| self.error("a string isn't UTF-8"))
+    }
+
+    /// Reads the four hex digits of a `\\u` escape, leaving the parser on the last one
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.at + 1..self.at + 5)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("badly formed \\u escape"))?;
+        self.at += 4;
+        Ok(digits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_pads_partial_groups() {
+        assert_eq!(base64(b""), "\"\"");
+        assert_eq!(base64(b"f"), "\"Zg==\"");
+        assert_eq!(base64(b"fo"), "\"Zm8=\"");
+        assert_eq!(base64(b"foo"), "\"Zm9v\"");
+        assert_eq!(base64(b"Hello\n"), "\"SGVsbG8K\"");
+    }
+
+    #[test]
+    fn strings_escape_quotes_and_controls() {
+        assert_eq!(string("a\"b\\\n\u{1}"), r#""a\"b\\\n\u0001""#);
+    }
+
+    #[test]
+    fn parsing_round_trips() {
+        let text = r#"{"a":[1,-2.5,true,null],"b":"x\"\u00e9\ud83d\ude00"}"#;
+        let value = parse(text).unwrap();
+        assert_eq!(
+            value.get("a"),
+            Some(&Value::Array(vec![
+                Value::Number(1.0),
+                Value::Number(-2.5),
+                Value::Bool(true),
+                Value::Null,
+            ]))
+        );
+        assert_eq!(value.get("b").and_then(Value::as_str), Some("x\"é😀"));
+        assert_eq!(parse(&value.to_string()), Ok(value));
+        assert!(parse("{\"a\":}").is_err());
+        assert!(parse("[1] 2").is_err());
+        assert!(parse(&"[".repeat(100)).is_err());
+    }
+}
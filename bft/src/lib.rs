@@ -0,0 +1,102 @@
+//! Parse, check and run Brainfuck programs
+//!
+//! This crate gathers [bft_types] and [bft_interp] behind one dependency, so their versions always
+//! match. The [prelude] has the types most programs need, [load] and [parse] turn source into a
+//! program ready to run in one step, and [run] goes all the way from source to output.
+//!
+//! # Examples
+//! ```
+//! use bft::prelude::*;
+//!
+//! let prog = bft::parse("<None>", ",[.-]")?;
+//! let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+//! assert_eq!(machine.run_captured(&[3])?, [3, 2, 1]);
+//! # Ok::<(), BftError>(())
+//! ```
+
+use std::num::NonZeroUsize;
+use std::path::Path;
+
+pub use bft_interp as interp;
+pub use bft_types as types;
+
+use bft_interp::bytecode::Bytecode;
+use bft_interp::error::BftError;
+use bft_interp::limits::Limits;
+use bft_interp::Machine;
+use bft_types::{DecoratedProgram, Program};
+
+/// The types most programs that embed the interpreter need
+pub mod prelude {
+    pub use bft_interp::error::BftError;
+    pub use bft_interp::limits::Limits;
+    pub use bft_interp::{CellKind, Flush, Machine, VMError};
+    pub use bft_types::{DecoratedProgram, ParseError, Program};
+
+    pub use crate::Options;
+}
+
+/// How [run] runs a program
+///
+/// The default is the same as the command line's: 30000 byte cells that can't grow, no limits,
+/// and optimised bytecode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    /// How many cells the tape starts with, or the default of 30000
+    pub cells: Option<NonZeroUsize>,
+    /// Whether the tape grows when the head moves past its end
+    pub extensible: bool,
+    pub limits: Limits,
+    /// Whether to compile the program to optimised bytecode, rather than interpret it an
+    /// instruction at a time
+    pub optimise: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            cells: None,
+            extensible: false,
+            limits: Limits::default(),
+            optimise: true,
+        }
+    }
+}
+
+/// Parses `source` and checks its brackets match, naming it `file` in errors
+pub fn parse(file: impl AsRef<Path>, source: &str) -> Result<DecoratedProgram, BftError> {
+    Ok(DecoratedProgram::from_program(&Program::new(file, source))?)
+}
+
+/// Reads the program in `file` and checks its brackets match
+pub fn load(file: impl AsRef<Path>) -> Result<DecoratedProgram, BftError> {
+    Ok(DecoratedProgram::from_program(&Program::from_file(file)?)?)
+}
+
+/// Parses `source`, checks it and runs it on `input`, returning everything it wrote
+///
+/// # Examples
+/// ```
+/// let output = bft::run("++++++++[>++++++++<-]>+.", b"", Default::default()).unwrap();
+/// assert_eq!(output, b"A");
+///
+/// let options = bft::Options {
+///     limits: bft::interp::limits::Limits { max_steps: Some(1000), ..Default::default() },
+///     ..Default::default()
+/// };
+/// let error = bft::run("+[]", b"", options).unwrap_err();
+/// assert_eq!(error.code(), "E0204");
+/// ```
+pub fn run(source: &str, input: &[u8], options: Options) -> Result<Vec<u8>, BftError> {
+    let prog = parse("<None>", source)?;
+    let mut machine: Machine<u8> = Machine::new(options.cells, options.extensible, &prog);
+    machine.set_limits(options.limits);
+    let mut output = Vec::new();
+    let mut input = input;
+    if options.optimise {
+        machine.run_bytecode(&Bytecode::compile(&prog), &mut input, &mut output)?;
+    } else {
+        machine.run(&mut input, &mut output)?;
+    }
+    Ok(output)
+}
@@ -0,0 +1,91 @@
+//! Warnings about suspicious code, drawn from abstract interpretation of the program
+
+use clap::{Args, ValueEnum};
+
+use bft_interp::analysis::{Analysis, Finding};
+use bft_types::DecoratedProgram;
+
+use crate::messages::{Message, MessageId};
+use crate::style::Style;
+
+/// The kinds of suspicious code that can be warned about
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum Lint {
+    /// A loop that is never entered
+    #[value(name = "dead_loop")]
+    DeadLoop,
+    /// A loop that never finishes
+    #[value(name = "infinite_loop")]
+    InfiniteLoop,
+    /// Arithmetic that may wrap past 0 or 255. Allowed unless asked for, as wrapping is often deliberate
+    #[value(name = "possible_wrap")]
+    PossibleWrap,
+    /// A move that always goes below the first cell
+    #[value(name = "seek_too_low")]
+    SeekTooLow,
+}
+
+impl Lint {
+    pub(crate) fn of(finding: &Finding) -> Lint {
+        match finding {
+            Finding::DeadLoop(_) => Lint::DeadLoop,
+            Finding::InfiniteLoop(_) => Lint::InfiniteLoop,
+            Finding::PossibleWrap(_) => Lint::PossibleWrap,
+            Finding::SeekTooLow(_) => Lint::SeekTooLow,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Lint::DeadLoop => "dead_loop",
+            Lint::InfiniteLoop => "infinite_loop",
+            Lint::PossibleWrap => "possible_wrap",
+            Lint::SeekTooLow => "seek_too_low",
+        }
+    }
+
+    fn warns_by_default(self) -> bool {
+        self != Lint::PossibleWrap
+    }
+}
+
+/// Command line options controlling which lints are reported, and how seriously
+#[derive(Args)]
+pub(crate) struct LintArgs {
+    /// Treat warnings as errors
+    #[arg(long, env = "BFT_STRICT")]
+    pub(crate) strict: bool,
+    /// Warn about this lint, even if it's allowed by default
+    #[arg(short = 'W', long = "warn", value_name = "LINT")]
+    pub(crate) warn: Vec<Lint>,
+    /// Don't warn about this lint
+    #[arg(short = 'A', long = "allow", value_name = "LINT")]
+    pub(crate) allow: Vec<Lint>,
+}
+
+impl LintArgs {
+    fn enabled(&self, lint: Lint) -> bool {
+        !self.allow.contains(&lint) && (lint.warns_by_default() || self.warn.contains(&lint))
+    }
+
+    /// Prints a warning for every enabled lint the program triggers
+    ///
+    /// Returns an error if there were any warnings and they're being treated as errors.
+    pub(crate) fn check(&self, prog: &DecoratedProgram, style: &Style) -> Result<(), Message> {
+        let analysis = Analysis::from_program(prog);
+        let mut warnings = 0;
+        for finding in analysis.findings() {
+            let lint = Lint::of(finding);
+            if self.enabled(lint) {
+                warnings += 1;
+                style.warning(prog.file().display(), lint.name(), finding);
+            }
+        }
+        if self.strict && warnings > 0 {
+            return Err(Message::new(MessageId::Strict)
+                .with("file", prog.file().display())
+                .with("warnings", warnings));
+        }
+        Ok(())
+    }
+}
@@ -1,9 +1,39 @@
+mod batch;
+mod bench;
+mod cache;
 mod cli;
-use std::process;
+mod debugger;
+mod demo;
+mod difftest;
+mod dump;
+mod expected;
+mod golden;
+mod json;
+mod lint;
+mod messages;
+mod metrics;
+mod minimise;
+mod record;
+mod rpc;
+mod selftest;
+mod serve;
+mod style;
+mod terminal;
+mod throttle;
+mod utf8;
+mod visualize;
+use clap::Parser;
+use std::process::ExitCode;
 
-fn main() {
-    if let Err(e) = cli::run_bft() {
-        println!("{}: Error: {}", std::env::args().next().unwrap(), e,);
-        process::exit(1);
+fn main() -> ExitCode {
+    let args = cli::Cli::parse();
+    let style = style::Style::new(&args.style);
+    style.init_logging(args.style.verbose);
+    match cli::run_bft(args.command, &style) {
+        Ok(code) => code,
+        Err(e) => {
+            style.error(std::env::args().next().unwrap(), &e);
+            ExitCode::from(cli::exit_code(&e))
+        }
     }
 }
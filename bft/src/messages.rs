@@ -0,0 +1,325 @@
+//! The command line's own notes and errors, in every language bft_interp has a table for
+//!
+//! These work like [bft_interp::messages]: a [MessageId] for each message, one table of templates
+//! per language, and a [Message] holding the details to fill them in with.
+
+use std::fmt::{self, Display};
+
+use bft_interp::messages::{fill, Lang, Localize};
+
+/// A note or error of the command line's own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MessageId {
+    WaitingForChange,
+    WroteOperations,
+    WroteSymbols,
+    GolfSizes,
+    Suggestion,
+    Minimised,
+    RunsSucceeded,
+    RestoredSession,
+    Listening,
+    WatchNeedsFile,
+    OutputDiffers,
+    Diverged,
+    ExitCellOutOfRange,
+    NoCondition,
+    RunsFailed,
+    EnginesDisagree,
+    TestFailed,
+    TestsFailed,
+    SpeedTooLow,
+    CheckFailed,
+    ChecksFailed,
+    Strict,
+    UnreadableFile,
+    OutputFailed,
+    SymbolsMismatch,
+    EnginesAgree,
+    Followed,
+    CacheCleared,
+    /// One golden test or self-test's result, after its `{name}`
+    CasePassed,
+    CaseBlessed,
+    CaseFailed,
+    CaseFailedBecause,
+    TestSummary,
+    /// One line of `bft batch`'s table, ending with a `{status}` of [MessageId::RunOk] or
+    /// [MessageId::RunError]
+    BatchRow,
+    RunOk,
+    RunError,
+    UnknownLang,
+    /// The heading of `bft batch`'s table
+    BatchHeading,
+    RunningTests,
+    RunningChecks,
+}
+
+/// How many messages there are, which is how long each table is
+const MESSAGES: usize = MessageId::RunningChecks as usize + 1;
+
+/// A template for every message in one language, in the order of [MessageId]
+type Table = [(MessageId, &'static str); MESSAGES];
+
+const ENGLISH: Table = [
+    (MessageId::WaitingForChange, "Waiting for {file} to change"),
+    (
+        MessageId::WroteOperations,
+        "Wrote {operations} operations to {file}",
+    ),
+    (MessageId::WroteSymbols, "Wrote symbols to {file}"),
+    (
+        MessageId::GolfSizes,
+        "Original: {original} bytes, minified: {minified} bytes, golfed: {golfed} bytes",
+    ),
+    (MessageId::Suggestion, "{file}:{suggestion}"),
+    (
+        MessageId::Minimised,
+        "Minimised from {from} to {to} instructions, trying {tries} candidates",
+    ),
+    (
+        MessageId::RunsSucceeded,
+        "{succeeded} of {runs} runs succeeded",
+    ),
+    (
+        MessageId::RestoredSession,
+        "Restored the session from {file}",
+    ),
+    (MessageId::Listening, "Listening on http://{address}"),
+    (
+        MessageId::WatchNeedsFile,
+        "--watch needs a program file, not standard input",
+    ),
+    (MessageId::OutputDiffers, "output differs from {file}"),
+    (MessageId::Diverged, "{file}: {divergence}"),
+    (
+        MessageId::ExitCellOutOfRange,
+        "--exit-cell {cell} is beyond the end of the tape",
+    ),
+    (
+        MessageId::NoCondition,
+        "{file} doesn't meet the condition to begin with",
+    ),
+    (MessageId::RunsFailed, "{failed} runs failed"),
+    (MessageId::EnginesDisagree, "engines disagree: {divergence}"),
+    (MessageId::TestFailed, "1 test failed"),
+    (MessageId::TestsFailed, "{failed} tests failed"),
+    (MessageId::SpeedTooLow, "--speed must be at least 1"),
+    (MessageId::CheckFailed, "1 check failed"),
+    (MessageId::ChecksFailed, "{failed} checks failed"),
+    (
+        MessageId::Strict,
+        "{file} produced {warnings} warning(s), which --strict treats as errors",
+    ),
+    (MessageId::UnreadableFile, "couldn't read {file}: {error}"),
+    (MessageId::OutputFailed, "the program's output: {error}"),
+    (
+        MessageId::SymbolsMismatch,
+        "{file} was written for different bytecode",
+    ),
+    (MessageId::EnginesAgree, "All {engines} engines agree"),
+    (MessageId::Followed, "{program} followed {script}"),
+    (MessageId::CacheCleared, "Removed {count} cached programs"),
+    (MessageId::CasePassed, "{name} ... ok"),
+    (MessageId::CaseBlessed, "{name} ... blessed"),
+    (MessageId::CaseFailed, "{name} ... FAILED"),
+    (MessageId::CaseFailedBecause, "{name} ... FAILED: {error}"),
+    (MessageId::TestSummary, "{passed} passed, {failed} failed"),
+    (
+        MessageId::BatchRow,
+        "{input}\t{time}\t{steps} steps\t{bytes} bytes\t{status}",
+    ),
+    (MessageId::RunOk, "ok"),
+    (MessageId::RunError, "error: {error}"),
+    (
+        MessageId::UnknownLang,
+        "no messages in {tag}; the languages are {langs}",
+    ),
+    (MessageId::BatchHeading, "input\ttime\tsteps\tbytes\tstatus"),
+    (MessageId::RunningTests, "running {count} test(s)"),
+    (MessageId::RunningChecks, "running {count} check(s)"),
+];
+
+const FRENCH: Table = [
+    (
+        MessageId::WaitingForChange,
+        "En attente d'une modification de {file}",
+    ),
+    (
+        MessageId::WroteOperations,
+        "{operations} opérations écrites dans {file}",
+    ),
+    (MessageId::WroteSymbols, "Symboles écrits dans {file}"),
+    (
+        MessageId::GolfSizes,
+        "Original : {original} octets, minifié : {minified} octets, golfé : {golfed} octets",
+    ),
+    (MessageId::Suggestion, "{file}:{suggestion}"),
+    (
+        MessageId::Minimised,
+        "Réduit de {from} à {to} instructions, en essayant {tries} candidats",
+    ),
+    (
+        MessageId::RunsSucceeded,
+        "{succeeded} exécutions sur {runs} ont réussi",
+    ),
+    (
+        MessageId::RestoredSession,
+        "Session restaurée depuis {file}",
+    ),
+    (MessageId::Listening, "En écoute sur http://{address}"),
+    (
+        MessageId::WatchNeedsFile,
+        "--watch a besoin d'un fichier de programme, pas de l'entrée standard",
+    ),
+    (MessageId::OutputDiffers, "la sortie diffère de {file}"),
+    (MessageId::Diverged, "{file} : {divergence}"),
+    (
+        MessageId::ExitCellOutOfRange,
+        "--exit-cell {cell} est au-delà de la fin de la bande",
+    ),
+    (
+        MessageId::NoCondition,
+        "{file} ne remplit pas la condition au départ",
+    ),
+    (MessageId::RunsFailed, "{failed} exécutions ont échoué"),
+    (
+        MessageId::EnginesDisagree,
+        "les moteurs ne sont pas d'accord : {divergence}",
+    ),
+    (MessageId::TestFailed, "1 test a échoué"),
+    (MessageId::TestsFailed, "{failed} tests ont échoué"),
+    (MessageId::SpeedTooLow, "--speed doit valoir au moins 1"),
+    (MessageId::CheckFailed, "1 vérification a échoué"),
+    (MessageId::ChecksFailed, "{failed} vérifications ont échoué"),
+    (
+        MessageId::Strict,
+        "{file} a produit {warnings} avertissement(s), que --strict traite comme des erreurs",
+    ),
+    (
+        MessageId::UnreadableFile,
+        "impossible de lire {file} : {error}",
+    ),
+    (MessageId::OutputFailed, "la sortie du programme : {error}"),
+    (
+        MessageId::SymbolsMismatch,
+        "{file} a été écrit pour un autre bytecode",
+    ),
+    (
+        MessageId::EnginesAgree,
+        "Les {engines} moteurs sont d'accord",
+    ),
+    (MessageId::Followed, "{program} a suivi {script}"),
+    (
+        MessageId::CacheCleared,
+        "{count} programmes supprimés du cache",
+    ),
+    (MessageId::CasePassed, "{name} ... ok"),
+    (MessageId::CaseBlessed, "{name} ... validé"),
+    (MessageId::CaseFailed, "{name} ... ÉCHEC"),
+    (MessageId::CaseFailedBecause, "{name} ... ÉCHEC : {error}"),
+    (
+        MessageId::TestSummary,
+        "{passed} réussi(s), {failed} échoué(s)",
+    ),
+    (
+        MessageId::BatchRow,
+        "{input}\t{time}\t{steps} pas\t{bytes} octets\t{status}",
+    ),
+    (MessageId::RunOk, "ok"),
+    (MessageId::RunError, "erreur : {error}"),
+    (
+        MessageId::UnknownLang,
+        "aucun message en {tag} ; les langues sont {langs}",
+    ),
+    (
+        MessageId::BatchHeading,
+        "entrée\ttemps\tpas\toctets\tstatut",
+    ),
+    (MessageId::RunningTests, "{count} test(s) à exécuter"),
+    (
+        MessageId::RunningChecks,
+        "{count} vérification(s) à exécuter",
+    ),
+];
+
+fn template(lang: Lang, id: MessageId) -> &'static str {
+    let table = match lang {
+        Lang::English => &ENGLISH,
+        Lang::French => &FRENCH,
+    };
+    table[id as usize].1
+}
+
+/// A message with its details, ready to be written in any language
+#[derive(Debug)]
+pub(crate) struct Message {
+    id: MessageId,
+    details: Vec<(&'static str, String)>,
+}
+
+impl Message {
+    pub(crate) fn new(id: MessageId) -> Message {
+        Message {
+            id,
+            details: Vec::new(),
+        }
+    }
+
+    /// Adds the detail the templates call `name`
+    pub(crate) fn with(mut self, name: &'static str, value: impl Display) -> Message {
+        self.details.push((name, value.to_string()));
+        self
+    }
+}
+
+impl Localize for Message {
+    fn write_in(&self, lang: Lang, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let details: Vec<(&str, &dyn Display)> = self
+            .details
+            .iter()
+            .map(|(name, value)| (*name, value as &dyn Display))
+            .collect();
+        fill(f, template(lang, self.id), &details)
+    }
+}
+
+/// Writes the message in English
+impl Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_in(Lang::English, f)
+    }
+}
+
+impl std::error::Error for Message {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_interp::messages::details;
+
+    #[test]
+    fn every_table_has_every_message_with_the_same_details() {
+        for table in [&ENGLISH, &FRENCH] {
+            for (index, (id, text)) in table.iter().enumerate() {
+                assert_eq!(*id as usize, index, "{id:?} is out of order");
+                let mut names: Vec<&str> = details(text).collect();
+                let mut english: Vec<&str> = details(ENGLISH[index].1).collect();
+                names.sort_unstable();
+                english.sort_unstable();
+                assert_eq!(names, english, "{id:?} names different details");
+            }
+        }
+    }
+
+    #[test]
+    fn messages_are_filled_in_the_chosen_language() {
+        let message = Message::new(MessageId::TestsFailed).with("failed", 3);
+        assert_eq!(message.to_string(), "3 tests failed");
+        assert_eq!(
+            message.localized(Lang::French).to_string(),
+            "3 tests ont échoué"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Counters kept by `bft serve`, written in the Prometheus text format for `GET /metrics`
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use bft_interp::limits::Limit;
+use bft_interp::VMError;
+
+use crate::cli::Engine;
+
+/// The engines requests can choose, in the order their metrics are kept
+const ENGINES: [Engine; 2] = [Engine::Interp, Engine::Opt];
+
+/// The HTTP statuses the server replies with. Requests are counted under the last if none match
+const STATUSES: [u16; 7] = [200, 400, 404, 405, 413, 503, 500];
+
+/// The upper bounds, in seconds, of the execution time histogram's buckets
+const BUCKETS: [f64; 7] = [0.0001, 0.001, 0.01, 0.1, 1.0, 10.0, f64::INFINITY];
+
+/// The limits a run can go over, by name
+const LIMITS: [&str; 3] = ["steps", "output_bytes", "tape"];
+
+/// Everything the server counts, shared by every connection
+#[derive(Default)]
+pub(crate) struct Metrics {
+    requests: [AtomicU64; STATUSES.len()],
+    executions: [AtomicU64; ENGINES.len()],
+    steps: AtomicU64,
+    limit_violations: [AtomicU64; LIMITS.len()],
+    timings: [Histogram; ENGINES.len()],
+}
+
+/// How long executions took, counted into cumulative buckets
+#[derive(Default)]
+struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    /// The total time, in nanoseconds
+    sum: AtomicU64,
+}
+
+impl Metrics {
+    /// Counts a reply with the given HTTP status
+    pub(crate) fn request(&self, status: u16) {
+        let index = STATUSES
+            .iter()
+            .position(|&known| known == status)
+            .unwrap_or(STATUSES.len() - 1);
+        self.requests[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a program that was run, however it ended
+    pub(crate) fn execution(
+        &self,
+        engine: Engine,
+        steps: u64,
+        time: Duration,
+        error: Option<&VMError>,
+    ) {
+        let engine = engine_index(engine);
+        self.executions[engine].fetch_add(1, Ordering::Relaxed);
+        self.steps.fetch_add(steps, Ordering::Relaxed);
+        if let Some(VMError::LimitExceeded { limit, .. }) = error {
+            let index = match limit {
+                Limit::Steps(_) => 0,
+                Limit::OutputBytes(_) => 1,
+                Limit::Tape(_) => 2,
+            };
+            self.limit_violations[index].fetch_add(1, Ordering::Relaxed);
+        }
+        let timing = &self.timings[engine];
+        let seconds = time.as_secs_f64();
+        for (bucket, bound) in timing.buckets.iter().zip(BUCKETS) {
+            if seconds <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        timing.count.fetch_add(1, Ordering::Relaxed);
+        timing
+            .sum
+            .fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Writes every metric in the Prometheus text exposition format
+    pub(crate) fn render(&self) -> String {
+        let mut text = String::new();
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        text.push_str("# HELP bft_requests_total HTTP requests answered, by status.\n");
+        text.push_str("# TYPE bft_requests_total counter\n");
+        for (status, count) in STATUSES.iter().zip(&self.requests) {
+            let _ = writeln!(
+                text,
+                "bft_requests_total{{status=\"{status}\"}} {}",
+                get(count)
+            );
+        }
+        text.push_str("# HELP bft_executions_total Programs run, by engine.\n");
+        text.push_str("# TYPE bft_executions_total counter\n");
+        for (engine, count) in ENGINES.iter().zip(&self.executions) {
+            let _ = writeln!(
+                text,
+                "bft_executions_total{{engine=\"{}\"}} {}",
+                engine.name(),
+                get(count)
+            );
+        }
+        text.push_str("# HELP bft_steps_total Steps executed by every program run.\n");
+        text.push_str("# TYPE bft_steps_total counter\n");
+        let _ = writeln!(text, "bft_steps_total {}", get(&self.steps));
+        text.push_str("# HELP bft_limit_violations_total Programs stopped by a limit, by limit.\n");
+        text.push_str("# TYPE bft_limit_violations_total counter\n");
+        for (limit, count) in LIMITS.iter().zip(&self.limit_violations) {
+            let _ = writeln!(
+                text,
+                "bft_limit_violations_total{{limit=\"{limit}\"}} {}",
+                get(count)
+            );
+        }
+        text.push_str("# HELP bft_execution_seconds How long programs took to run, by engine.\n");
+        text.push_str("# TYPE bft_execution_seconds histogram\n");
+        for (engine, timing) in ENGINES.iter().zip(&self.timings) {
+            let engine = engine.name();
+            for (bound, count) in BUCKETS.iter().zip(&timing.buckets) {
+                let bound = if bound.is_infinite() {
+                    "+Inf".to_string()
+                } else {
+                    bound.to_string()
+                };
+                let _ = writeln!(
+                    text,
+                    "bft_execution_seconds_bucket{{engine=\"{engine}\",le=\"{bound}\"}} {}",
+                    get(count)
+                );
+            }
+            let _ = writeln!(
+                text,
+                "bft_execution_seconds_sum{{engine=\"{engine}\"}} {}",
+                get(&timing.sum) as f64 / 1e9
+            );
+            let _ = writeln!(
+                text,
+                "bft_execution_seconds_count{{engine=\"{engine}\"}} {}",
+                get(&timing.count)
+            );
+        }
+        text
+    }
+}
+
+fn engine_index(engine: Engine) -> usize {
+    match engine {
+        Engine::Interp => 0,
+        Engine::Opt => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histograms_are_cumulative() {
+        let metrics = Metrics::default();
+        metrics.request(200);
+        metrics.request(418);
+        metrics.execution(Engine::Opt, 10, Duration::from_millis(5), None);
+        metrics.execution(Engine::Opt, 5, Duration::from_secs(2), None);
+        let text = metrics.render();
+        assert!(text.contains("bft_requests_total{status=\"200\"} 1\n"));
+        assert!(text.contains("bft_requests_total{status=\"500\"} 1\n"));
+        assert!(text.contains("bft_steps_total 15\n"));
+        assert!(text.contains("bft_execution_seconds_bucket{engine=\"opt\",le=\"0.01\"} 1\n"));
+        assert!(text.contains("bft_execution_seconds_bucket{engine=\"opt\",le=\"+Inf\"} 2\n"));
+        assert!(text.contains("bft_execution_seconds_count{engine=\"interp\"} 0\n"));
+    }
+}
@@ -0,0 +1,152 @@
+//! The conditions `bft minimize` keeps true while it shrinks a program
+
+use std::io::Cursor;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use bft_interp::bytecode::Bytecode;
+use bft_interp::error::BftError;
+use bft_interp::expect;
+use bft_interp::limits::Limits;
+use bft_interp::{Machine, VMError};
+use bft_types::DecoratedProgram;
+
+use crate::cli::{run_engine, Engine};
+use crate::difftest;
+
+/// Something a program does that makes it worth keeping while it's minimised
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Condition {
+    /// `error`: it fails with any error other than going over a limit
+    Error,
+    /// `error:CODE`: it fails with the error that has this code, such as E0201
+    ErrorCode(String),
+    /// `panic`: the engine panics while running it
+    Panic,
+    /// `output=TEXT`: it writes exactly this output
+    Output(Vec<u8>),
+    /// `output!=TEXT`: it writes anything but this output
+    NotOutput(Vec<u8>),
+    /// `disagree`: the engines don't agree about what it does
+    Disagree,
+    /// `sh:COMMAND`: this shell command succeeds when given the program's file as $1
+    Shell(String),
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Condition, String> {
+        if let Some(code) = s.strip_prefix("error:") {
+            return Ok(Condition::ErrorCode(code.to_string()));
+        }
+        if let Some(text) = s.strip_prefix("output!=") {
+            return expect::unescape(text).map(Condition::NotOutput);
+        }
+        if let Some(text) = s.strip_prefix("output=") {
+            return expect::unescape(text).map(Condition::Output);
+        }
+        if let Some(command) = s.strip_prefix("sh:") {
+            return Ok(Condition::Shell(command.to_string()));
+        }
+        match s {
+            "error" => Ok(Condition::Error),
+            "panic" => Ok(Condition::Panic),
+            "disagree" => Ok(Condition::Disagree),
+            _ => Err(format!(
+                "expected error, error:CODE, panic, output=TEXT, output!=TEXT, disagree or sh:COMMAND, not {s:?}"
+            )),
+        }
+    }
+}
+
+impl Condition {
+    /// Returns whether the program, whose code is `code`, meets the condition
+    ///
+    /// `scratch` is where the code is written for shell commands to read.
+    pub(crate) fn holds(
+        &self,
+        prog: &DecoratedProgram,
+        code: &str,
+        engine: Engine,
+        limits: Limits,
+        input: &[u8],
+        scratch: &Path,
+    ) -> bool {
+        let run = || {
+            let bytecode = match engine {
+                Engine::Interp => Bytecode::compile_unoptimised(prog),
+                Engine::Opt => Bytecode::compile(prog),
+            };
+            let mut machine: Machine<u8> = Machine::new(None, false, prog);
+            machine.set_limits(limits);
+            let mut output = Vec::new();
+            let result = run_engine(
+                engine,
+                &mut machine,
+                &bytecode,
+                &mut Cursor::new(input),
+                &mut output,
+                &mut (),
+            );
+            (result, output)
+        };
+        match self {
+            Self::Error => !matches!(run().0, Ok(()) | Err(VMError::LimitExceeded { .. })),
+            Self::ErrorCode(expected) => run()
+                .0
+                .is_err_and(|error| BftError::from(error).code() == expected),
+            Self::Panic => panic::catch_unwind(AssertUnwindSafe(run)).is_err(),
+            Self::Output(expected) => run().1 == *expected,
+            Self::NotOutput(expected) => {
+                let (result, output) = run();
+                // A run cut short by a limit hasn't shown what it would have written
+                !matches!(result, Err(VMError::LimitExceeded { .. })) && output != *expected
+            }
+            Self::Disagree => {
+                difftest::compare(prog, &[Engine::Interp, Engine::Opt], limits, input).is_some()
+            }
+            Self::Shell(command) => {
+                if std::fs::write(scratch, code).is_err() {
+                    return false;
+                }
+                Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .arg("sh")
+                    .arg(scratch)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status()
+                    .is_ok_and(|status| status.success())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditions_are_parsed() {
+        assert_eq!("error".parse(), Ok(Condition::Error));
+        assert_eq!(
+            "error:E0201".parse(),
+            Ok(Condition::ErrorCode("E0201".to_string()))
+        );
+        assert_eq!(
+            "output=hi\\n".parse(),
+            Ok(Condition::Output(b"hi\n".to_vec()))
+        );
+        assert_eq!("output!=".parse(), Ok(Condition::NotOutput(Vec::new())));
+        assert_eq!(
+            "sh:grep -q x \"$1\"".parse(),
+            Ok(Condition::Shell("grep -q x \"$1\"".to_string()))
+        );
+        assert!("sometimes".parse::<Condition>().is_err());
+    }
+}
@@ -0,0 +1,254 @@
+//! Run records, which hold everything needed to run a program again exactly as it ran before
+//!
+//! A record starts with a header of `key value` lines: the program's file name and escaped
+//! source, then each option that changes how it runs. A blank line follows, then the
+//! [Transcript] of everything the program read and wrote.
+//!
+//! ```text
+//! bft-record 1
+//! file hello.b
+//! source ,[.,]\n
+//! engine opt
+//! max-steps 1000
+//!
+//! 0.000012 1 in 104
+//! 0.000015 4 out 104
+//! ```
+
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use bft_interp::expect;
+use bft_interp::transcript::Transcript;
+use clap::ValueEnum;
+
+use crate::cli::{EofPolicy, RunArgs, SeekPolicy};
+
+/// The first line of every record, which changes if the format does
+const MAGIC: &str = "bft-record 1";
+
+/// A recorded run
+pub(crate) struct Record {
+    pub(crate) file: PathBuf,
+    pub(crate) source: String,
+    /// The options that change how the program runs, by the names of their flags
+    settings: Vec<(String, String)>,
+    pub(crate) transcript: Transcript,
+}
+
+impl Record {
+    /// Records a run of `source` with `args`
+    pub(crate) fn new(
+        file: PathBuf,
+        source: String,
+        args: &RunArgs,
+        transcript: Transcript,
+    ) -> Record {
+        let mut settings = vec![
+            ("extensible", args.extensible.to_string()),
+            ("cell-size", name(args.cell_size)),
+            ("engine", name(args.engine)),
+            ("buffering", name(args.buffering)),
+            ("broken-pipe", name(args.broken_pipe)),
+            ("seek-too-low", name(args.seek_too_low)),
+            ("seek-too-high", name(args.seek_too_high)),
+            ("eof", name(args.eof)),
+        ];
+        let optional = [
+            ("cells", args.cells.map(|cells| cells.to_string())),
+            ("max-steps", args.max_steps.map(|max| max.to_string())),
+            (
+                "max-output-bytes",
+                args.max_output_bytes.map(|max| max.to_string()),
+            ),
+            ("max-tape", args.max_tape.map(|max| max.to_string())),
+            (
+                "max-loop-iterations",
+                args.max_loop_iterations.map(|max| max.to_string()),
+            ),
+            ("exit-cell", args.exit_cell.map(|cell| cell.to_string())),
+            ("utf8", args.utf8.map(name)),
+            ("output-delay", args.output_delay.map(|ms| ms.to_string())),
+            ("fps", args.fps.map(|fps| fps.to_string())),
+        ];
+        settings.extend(
+            optional
+                .into_iter()
+                .filter_map(|(key, value)| Some((key, value?))),
+        );
+        Record {
+            file,
+            source,
+            settings: settings
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+            transcript,
+        }
+    }
+
+    /// Sets every option in `args` that changes how the program runs to what was recorded
+    pub(crate) fn apply(&self, args: &mut RunArgs) -> Result<(), String> {
+        args.cells = None;
+        args.max_steps = None;
+        args.max_output_bytes = None;
+        args.max_tape = None;
+        args.max_loop_iterations = None;
+        args.exit_cell = None;
+        args.utf8 = None;
+        args.output_delay = None;
+        args.fps = None;
+        // Records made before the policies could be set ran with the defaults
+        args.seek_too_low = SeekPolicy::Error;
+        args.seek_too_high = SeekPolicy::Error;
+        args.eof = EofPolicy::Error;
+        for (key, value) in &self.settings {
+            let bad = |reason: String| format!("bad {key} in record: {reason}");
+            match key.as_str() {
+                "extensible" => args.extensible = parse(value).map_err(bad)?,
+                "cell-size" => args.cell_size = ValueEnum::from_str(value, false).map_err(bad)?,
+                "engine" => args.engine = ValueEnum::from_str(value, false).map_err(bad)?,
+                "buffering" => args.buffering = ValueEnum::from_str(value, false).map_err(bad)?,
+                "broken-pipe" => {
+                    args.broken_pipe = ValueEnum::from_str(value, false).map_err(bad)?
+                }
+                "seek-too-low" => {
+                    args.seek_too_low = ValueEnum::from_str(value, false).map_err(bad)?
+                }
+                "seek-too-high" => {
+                    args.seek_too_high = ValueEnum::from_str(value, false).map_err(bad)?
+                }
+                "eof" => args.eof = ValueEnum::from_str(value, false).map_err(bad)?,
+                "cells" => args.cells = Some(parse(value).map_err(bad)?),
+                "max-steps" => args.max_steps = Some(parse(value).map_err(bad)?),
+                "max-output-bytes" => args.max_output_bytes = Some(parse(value).map_err(bad)?),
+                "max-tape" => args.max_tape = Some(parse(value).map_err(bad)?),
+                "max-loop-iterations" => {
+                    args.max_loop_iterations = Some(parse(value).map_err(bad)?)
+                }
+                "exit-cell" => args.exit_cell = Some(parse(value).map_err(bad)?),
+                "utf8" => args.utf8 = Some(ValueEnum::from_str(value, false).map_err(bad)?),
+                "output-delay" => args.output_delay = Some(parse(value).map_err(bad)?),
+                "fps" => args.fps = Some(parse(value).map_err(bad)?),
+                _ => return Err(format!("unknown setting {key} in record")),
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{MAGIC}")?;
+        writeln!(out, "file {}", self.file.display())?;
+        writeln!(out, "source {}", expect::escape(self.source.as_bytes()))?;
+        for (key, value) in &self.settings {
+            writeln!(out, "{key} {value}")?;
+        }
+        writeln!(out)?;
+        self.transcript.write_to(out)
+    }
+
+    /// Reads a record saved by [Record::write_to]
+    pub(crate) fn read_from(mut input: impl BufRead) -> Result<Record, String> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            let read = input.read_line(&mut line).map_err(|e| e.to_string())?;
+            let line = line.trim_end_matches(['\r', '\n']).to_string();
+            if lines.is_empty() && line != MAGIC {
+                return Err(format!(
+                    "not a run record, expected it to start with {MAGIC}"
+                ));
+            }
+            if read == 0 {
+                return Err("record ended before its transcript".to_string());
+            }
+            if line.is_empty() {
+                break;
+            }
+            lines.push(line);
+        }
+
+        let mut file = None;
+        let mut source = None;
+        let mut settings = Vec::new();
+        for line in &lines[1..] {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "file" => file = Some(PathBuf::from(value)),
+                "source" => {
+                    let bytes = expect::unescape(value)?;
+                    source = Some(String::from_utf8(bytes).map_err(|e| e.to_string())?);
+                }
+                _ => settings.push((key.to_string(), value.to_string())),
+            }
+        }
+        let transcript = Transcript::read_from(input).map_err(|e| format!("transcript: {e}"))?;
+        Ok(Record {
+            file: file.ok_or("record has no file")?,
+            source: source.ok_or("record has no source")?,
+            settings,
+            transcript,
+        })
+    }
+}
+
+/// The name a value is given by on the command line
+fn name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .expect("No values are skipped")
+        .get_name()
+        .to_string()
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value.parse().map_err(|e: T::Err| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Command, Engine};
+    use clap::Parser;
+
+    fn run_args(command_line: &[&str]) -> RunArgs {
+        match Cli::parse_from(command_line).command {
+            Command::Run(args) => *args,
+            _ => unreachable!("only run commands are parsed"),
+        }
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let args = run_args(&[
+            "bft",
+            "run",
+            "--engine",
+            "interp",
+            "--max-steps",
+            "9",
+            "x.b",
+        ]);
+        let record = Record::new(
+            PathBuf::from("x.b"),
+            "+[\t.]\n\u{e9}".to_string(),
+            &args,
+            Transcript::new(),
+        );
+        let mut written = Vec::new();
+        record.write_to(&mut written).unwrap();
+        let read = Record::read_from(&written[..]).unwrap();
+        assert_eq!(read.source, record.source);
+        assert_eq!(read.file, record.file);
+
+        let mut replayed = run_args(&["bft", "run", "--max-tape", "3", "y.b"]);
+        read.apply(&mut replayed).unwrap();
+        assert!(replayed.engine == Engine::Interp);
+        assert_eq!(replayed.max_steps, Some(9));
+        assert_eq!(replayed.max_tape, None);
+    }
+}
@@ -0,0 +1,443 @@
+//! A JSON-RPC 2.0 service over standard input and output, for tools that drive bft as a backend
+//!
+//! Each request is one line of JSON, and each reply is one line. Notifications, which have no
+//! `id`, get no reply. The methods are:
+//!
+//! - `parse {source}`: checks the program's brackets and counts its instructions
+//! - `check {source}`: lists the suspicious code `bft check` would warn about
+//! - `open {session, source, input?, cells?, extensible?, max_steps?, max_output_bytes?,
+//!   max_tape?, max_loop_iterations?}`: loads a program into a named session, replacing any
+//!   session of that name
+//! - `step {session, count?, input?}`: executes up to `count` instructions, default 1
+//! - `run {session, input?, max_steps?}`: executes until the program finishes, needs more input,
+//!   or has taken `max_steps` steps in this call, default 10 million
+//! - `state {session, start?, count?}`: describes the session, with `count` cells from `start`
+//! - `close {session}`: forgets a session
+//!
+//! `input` is text added to the end of the session's input. Sessions stop before a `,` with no
+//! input left rather than failing, so the caller can send more, unless `open`, `step` or `run` was
+//! given `"end": true` to say the input is complete. `step` and `run` reply with the
+//! session's state and the output written during the call, as base64.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::num::NonZeroUsize;
+
+use bft_interp::analysis::Analysis;
+use bft_interp::error::BftError;
+use bft_interp::limits::Limits;
+use bft_interp::suspend::Suspendable;
+use bft_interp::Machine;
+use bft_types::{DecoratedProgram, Program};
+
+use crate::json::{self, Value};
+use crate::lint::Lint;
+
+/// How many steps `run` takes in one call if it isn't told
+const DEFAULT_RUN_STEPS: u64 = 10_000_000;
+
+/// The most cells `state` describes at once
+const MAX_STATE_CELLS: u64 = 4096;
+
+/// A JSON-RPC error, as put in the reply's `error` member
+struct RpcError {
+    code: i32,
+    message: String,
+    /// Details of a bft error, as a JSON object
+    data: Option<String>,
+}
+
+impl RpcError {
+    fn invalid_params(message: impl Into<String>) -> RpcError {
+        RpcError {
+            code: -32602,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// The program couldn't be parsed
+    fn program(error: BftError, position: Option<(usize, usize)>) -> RpcError {
+        RpcError {
+            code: -32000,
+            message: error.to_string(),
+            data: Some(error_json(&error, position)),
+        }
+    }
+}
+
+/// A program being run a step at a time
+struct Session {
+    program: Suspendable<'static, u8>,
+    /// The error the last step stopped with, as JSON
+    error: Option<String>,
+}
+
+/// Answers requests, keeping sessions between them
+#[derive(Default)]
+pub(crate) struct Service {
+    sessions: HashMap<String, Session>,
+}
+
+impl Service {
+    /// Answers every line of `requests` until it ends
+    pub(crate) fn serve(
+        &mut self,
+        requests: impl BufRead,
+        mut replies: impl Write,
+    ) -> io::Result<()> {
+        for line in requests.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(reply) = self.handle(&line) {
+                writeln!(replies, "{reply}")?;
+                replies.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers one request, or returns None for a notification
+    fn handle(&mut self, line: &str) -> Option<String> {
+        let request = match json::parse(line) {
+            Ok(request) => request,
+            Err(e) => return Some(reply(&Value::Null, Err(rpc_error(-32700, e)))),
+        };
+        let id = request.get("id").cloned();
+        let result = match request.get("method").and_then(Value::as_str) {
+            Some(method) => {
+                let params = request.get("params").unwrap_or(&Value::Null);
+                self.call(method, params)
+            }
+            None => Err(rpc_error(-32600, "the request has no method")),
+        };
+        id.map(|id| reply(&id, result))
+    }
+
+    fn call(&mut self, method: &str, params: &Value) -> Result<String, RpcError> {
+        match method {
+            "parse" => {
+                let prog = parse(params)?;
+                Ok(format!(
+                    r#"{{"instructions":{}}}"#,
+                    prog.decorated_instructions().len()
+                ))
+            }
+            "check" => {
+                let prog = parse(params)?;
+                let findings: Vec<String> = Analysis::from_program(&prog)
+                    .findings()
+                    .iter()
+                    .map(|finding| {
+                        format!(
+                            r#"{{"lint":"{}","message":{},"line":{},"character":{}}}"#,
+                            Lint::of(finding).name(),
+                            json::string(&finding.to_string()),
+                            finding.instruction().line(),
+                            finding.instruction().character()
+                        )
+                    })
+                    .collect();
+                Ok(format!(r#"{{"findings":[{}]}}"#, findings.join(",")))
+            }
+            "open" => {
+                let prog = parse(params)?;
+                let cells = match number(params, "cells")? {
+                    Some(cells) => Some(
+                        NonZeroUsize::new(cells as usize)
+                            .ok_or_else(|| RpcError::invalid_params("cells must be at least 1"))?,
+                    ),
+                    None => None,
+                };
+                let extensible = match params.get("extensible") {
+                    Some(value) => value.as_bool().ok_or_else(|| {
+                        RpcError::invalid_params("extensible must be true or false")
+                    })?,
+                    None => false,
+                };
+                let mut machine = Machine::owning(cells, extensible, prog);
+                machine.set_limits(Limits {
+                    max_steps: number(params, "max_steps")?,
+                    max_output_bytes: number(params, "max_output_bytes")?,
+                    max_tape: number(params, "max_tape")?.map(|max| max as usize),
+                    max_loop_iterations: number(params, "max_loop_iterations")?,
+                });
+                let mut session = Session {
+                    program: Suspendable::new(machine),
+                    error: None,
+                };
+                feed(&mut session, params)?;
+                self.sessions
+                    .insert(text(params, "session")?.to_string(), session);
+                Ok("{}".to_string())
+            }
+            "step" => {
+                let session = self.session(params)?;
+                feed(session, params)?;
+                let count = number(params, "count")?.unwrap_or(1);
+                Ok(advance(session, count))
+            }
+            "run" => {
+                let session = self.session(params)?;
+                feed(session, params)?;
+                let count = number(params, "max_steps")?.unwrap_or(DEFAULT_RUN_STEPS);
+                Ok(advance(session, count))
+            }
+            "state" => {
+                let session = self.session(params)?;
+                let count = number(params, "count")?.unwrap_or(16).min(MAX_STATE_CELLS) as usize;
+                let start = match number(params, "start")? {
+                    Some(start) => start as usize,
+                    None => session.program.machine().head().saturating_sub(count / 2),
+                };
+                let cells = session.program.machine().cells();
+                let window: Vec<String> = cells
+                    .iter()
+                    .skip(start)
+                    .take(count)
+                    .map(u8::to_string)
+                    .collect();
+                Ok(format!(
+                    r#"{{{},"start":{start},"cells":[{}]}}"#,
+                    state(session),
+                    window.join(",")
+                ))
+            }
+            "close" => {
+                let name = text(params, "session")?;
+                match self.sessions.remove(name) {
+                    Some(_) => Ok("{}".to_string()),
+                    None => Err(RpcError::invalid_params(format!(
+                        "no session called {name}"
+                    ))),
+                }
+            }
+            _ => Err(rpc_error(-32601, format!("no method called {method}"))),
+        }
+    }
+
+    fn session(&mut self, params: &Value) -> Result<&mut Session, RpcError> {
+        let name = text(params, "session")?;
+        self.sessions
+            .get_mut(name)
+            .ok_or_else(|| RpcError::invalid_params(format!("no session called {name}")))
+    }
+}
+
+fn rpc_error(code: i32, message: impl Into<String>) -> RpcError {
+    RpcError {
+        code,
+        message: message.into(),
+        data: None,
+    }
+}
+
+/// Formats the reply to the request with `id`
+fn reply(id: &Value, result: Result<String, RpcError>) -> String {
+    match result {
+        Ok(result) => format!(r#"{{"jsonrpc":"2.0","id":{id},"result":{result}}}"#),
+        Err(error) => format!(
+            r#"{{"jsonrpc":"2.0","id":{id},"error":{{"code":{},"message":{}{}}}}}"#,
+            error.code,
+            json::string(&error.message),
+            error
+                .data
+                .map(|data| format!(r#","data":{data}"#))
+                .unwrap_or_default()
+        ),
+    }
+}
+
+/// The string parameter called `name`, which must be given
+fn text<'p>(params: &'p Value, name: &str) -> Result<&'p str, RpcError> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::invalid_params(format!("{name} must be a string")))
+}
+
+/// The number parameter called `name`, if it's given
+fn number(params: &Value, name: &str) -> Result<Option<u64>, RpcError> {
+    params
+        .get(name)
+        .map(|value| {
+            value
+                .as_u64()
+                .ok_or_else(|| RpcError::invalid_params(format!("{name} must be a whole number")))
+        })
+        .transpose()
+}
+
+/// Parses the `source` parameter
+fn parse(params: &Value) -> Result<DecoratedProgram, RpcError> {
+    let source = text(params, "source")?;
+    DecoratedProgram::from_program(&Program::new("<rpc>", source)).map_err(|e| {
+        let position = e.position();
+        RpcError::program(e.into(), Some((position.line(), position.character())))
+    })
+}
+
+/// Adds the `input` parameter, if there is one, to the session's input, and ends the input if
+/// asked
+fn feed(session: &mut Session, params: &Value) -> Result<(), RpcError> {
+    if params.get("input").is_some() {
+        session
+            .program
+            .provide_input(text(params, "input")?.as_bytes());
+    }
+    if let Some(end) = params.get("end") {
+        let end = end
+            .as_bool()
+            .ok_or_else(|| RpcError::invalid_params("end must be true or false"))?;
+        if end {
+            session.program.end_input();
+        }
+    }
+    Ok(())
+}
+
+/// Steps the session up to `count` times, stopping early if it finishes, fails or needs input
+fn advance(session: &mut Session, count: u64) -> String {
+    let mut output = Vec::new();
+    session.error = None;
+    if let Err(error) = session.program.resume_for(count, &mut output) {
+        let position = error
+            .instruction()
+            .map(|instruction| (instruction.line(), instruction.character()));
+        session.error = Some(error_json(&error.into(), position));
+    }
+    format!(
+        r#"{{{},"output":{}}}"#,
+        state(session),
+        json::base64(&output)
+    )
+}
+
+/// The members describing where a session has got to
+fn state(session: &Session) -> String {
+    let machine = session.program.machine();
+    let status = if session.error.is_some() {
+        "error"
+    } else if machine.next_instruction().is_none() {
+        "finished"
+    } else if session.program.needs_input() {
+        "waiting"
+    } else {
+        "running"
+    };
+    let next = match machine.next_instruction() {
+        Some(next) => format!(
+            r#"{{"line":{},"character":{}}}"#,
+            next.instruction().line(),
+            next.instruction().character()
+        ),
+        None => "null".to_string(),
+    };
+    format!(
+        r#""status":"{status}","error":{},"next":{next},"steps":{},"output_bytes":{},"head":{},"tape_len":{},"input_left":{}"#,
+        session.error.as_deref().unwrap_or("null"),
+        machine.steps(),
+        machine.output_bytes(),
+        machine.head(),
+        machine.cells().len(),
+        session.program.input_left()
+    )
+}
+
+/// Describes a bft error as a JSON object
+fn error_json(error: &BftError, position: Option<(usize, usize)>) -> String {
+    let position = match position {
+        Some((line, character)) => format!(r#","line":{line},"character":{character}"#),
+        None => String::new(),
+    };
+    format!(
+        r#"{{"code":"{}","message":{}{position}}}"#,
+        error.code(),
+        json::string(&error.to_string())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange(service: &mut Service, request: &str) -> Value {
+        json::parse(&service.handle(request).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn sessions_step_and_wait_for_input() {
+        let mut service = Service::default();
+        let reply = exchange(
+            &mut service,
+            r#"{"jsonrpc":"2.0","id":1,"method":"open","params":{"session":"a","source":"+,[.,]"}}"#,
+        );
+        assert_eq!(reply.get("result"), Some(&Value::Object(vec![])));
+
+        let reply = exchange(
+            &mut service,
+            r#"{"jsonrpc":"2.0","id":2,"method":"run","params":{"session":"a"}}"#,
+        );
+        let result = reply.get("result").unwrap();
+        assert_eq!(
+            result.get("status").and_then(Value::as_str),
+            Some("waiting")
+        );
+        assert_eq!(result.get("steps").and_then(Value::as_u64), Some(1));
+
+        let reply = exchange(
+            &mut service,
+            r#"{"jsonrpc":"2.0","id":3,"method":"run","params":{"session":"a","input":"hi"}}"#,
+        );
+        let result = reply.get("result").unwrap();
+        assert_eq!(result.get("output").and_then(Value::as_str), Some("aGk="));
+        assert_eq!(
+            result.get("status").and_then(Value::as_str),
+            Some("waiting")
+        );
+
+        let reply = exchange(
+            &mut service,
+            r#"{"jsonrpc":"2.0","id":4,"method":"state","params":{"session":"a","start":0,"count":2}}"#,
+        );
+        let result = reply.get("result").unwrap();
+        assert_eq!(
+            result.get("cells"),
+            Some(&Value::Array(vec![
+                Value::Number(105.0),
+                Value::Number(0.0)
+            ]))
+        );
+        assert!(service
+            .handle(r#"{"jsonrpc":"2.0","method":"close","params":{"session":"a"}}"#)
+            .is_none());
+        assert!(service.sessions.is_empty());
+    }
+
+    #[test]
+    fn errors_follow_json_rpc() {
+        let mut service = Service::default();
+        let code = |reply: &Value| reply.get("error").and_then(|e| e.get("code")).cloned();
+        let reply = exchange(&mut service, "{");
+        assert_eq!(code(&reply), Some(Value::Number(-32700.0)));
+        let reply = exchange(&mut service, r#"{"jsonrpc":"2.0","id":"x","method":"fly"}"#);
+        assert_eq!(reply.get("id"), Some(&Value::String("x".to_string())));
+        assert_eq!(code(&reply), Some(Value::Number(-32601.0)));
+        let reply = exchange(
+            &mut service,
+            r#"{"jsonrpc":"2.0","id":1,"method":"parse","params":{"source":"+]"}}"#,
+        );
+        assert_eq!(code(&reply), Some(Value::Number(-32000.0)));
+        let data = reply.get("error").unwrap().get("data").unwrap();
+        assert_eq!(data.get("code").and_then(Value::as_str), Some("E0101"));
+
+        let reply = exchange(
+            &mut service,
+            r#"{"jsonrpc":"2.0","id":2,"method":"check","params":{"source":"[-]"}}"#,
+        );
+        let findings = reply.get("result").unwrap().get("findings").unwrap();
+        assert!(matches!(findings, Value::Array(findings) if findings.len() == 1));
+    }
+}
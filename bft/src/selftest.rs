@@ -0,0 +1,165 @@
+//! Programs that check bft behaves as documented
+//!
+//! Each one probes a corner of the semantics: how cells wrap, where the tape ends, what happens
+//! at the end of input and how loops nest. Running them on an engine shows whether it follows
+//! the rules.
+
+use std::io::Cursor;
+
+use bft_interp::{bytecode::Bytecode, limits::Limits, Machine, VMError};
+use bft_types::{DecoratedProgram, Program};
+
+use crate::cli::{run_engine, Engine};
+
+/// Far more steps than any check needs, in case an engine loops forever
+const MAX_STEPS: u64 = 10_000_000;
+
+/// How a check should end
+pub(crate) enum Expected {
+    /// The program finishes, having written this
+    Output(&'static [u8]),
+    /// The program fails in this way
+    Fails(fn(&VMError) -> bool),
+    /// The program isn't valid Brainfuck
+    Invalid,
+}
+
+/// A program that probes one rule
+pub(crate) struct Check {
+    pub(crate) name: &'static str,
+    source: &'static str,
+    input: &'static [u8],
+    expected: Expected,
+}
+
+/// Every check, in the order they're run
+pub(crate) const CHECKS: &[Check] = &[
+    Check {
+        name: "cells wrap below zero",
+        source: "-.",
+        input: b"",
+        expected: Expected::Output(b"\xff"),
+    },
+    Check {
+        name: "cells wrap above 255",
+        source: "+[+].",
+        input: b"",
+        expected: Expected::Output(b"\0"),
+    },
+    Check {
+        name: "input is read a byte at a time",
+        source: ",.,.",
+        input: b"ab",
+        expected: Expected::Output(b"ab"),
+    },
+    Check {
+        name: "reading past the end of input fails",
+        source: ",",
+        input: b"",
+        expected: Expected::Fails(|e| matches!(e, VMError::IOError { .. })),
+    },
+    Check {
+        name: "the tape has no cells left of the start",
+        source: "<",
+        input: b"",
+        expected: Expected::Fails(|e| matches!(e, VMError::SeekTooLow(_))),
+    },
+    Check {
+        name: "the tape ends on the right",
+        source: "+[>+]",
+        input: b"",
+        expected: Expected::Fails(|e| matches!(e, VMError::SeekTooHigh(_))),
+    },
+    Check {
+        name: "loops on a zero cell are skipped",
+        source: "[.]+.",
+        input: b"",
+        expected: Expected::Output(b"\x01"),
+    },
+    Check {
+        name: "loops repeat until their cell is zero",
+        source: "+++[>+<-]>.",
+        input: b"",
+        expected: Expected::Output(b"\x03"),
+    },
+    Check {
+        name: "nested loops run inside each other",
+        source: "++[>++[>+<-]<-]>>.",
+        input: b"",
+        expected: Expected::Output(b"\x04"),
+    },
+    Check {
+        name: "an inner loop can end the outer one",
+        source: "+[[-]]+.",
+        input: b"",
+        expected: Expected::Output(b"\x01"),
+    },
+    Check {
+        name: "anything else is a comment",
+        source: "add one + then print it .",
+        input: b"",
+        expected: Expected::Output(b"\x01"),
+    },
+    Check {
+        name: "brackets must balance",
+        source: "+[.",
+        input: b"",
+        expected: Expected::Invalid,
+    },
+];
+
+impl Check {
+    /// Runs the check on an engine, returning what went wrong if it didn't behave as expected
+    pub(crate) fn run(&self, engine: Engine) -> Result<(), String> {
+        let prog = Program::new(self.name, self.source);
+        let decorated = match (DecoratedProgram::from_program(&prog), &self.expected) {
+            (Err(_), Expected::Invalid) => return Ok(()),
+            (Err(e), _) => return Err(format!("it didn't parse: {e}")),
+            (Ok(_), Expected::Invalid) => return Err("it parsed".to_string()),
+            (Ok(decorated), _) => decorated,
+        };
+        let bytecode = match engine {
+            Engine::Interp => Bytecode::compile_unoptimised(&decorated),
+            Engine::Opt => Bytecode::compile(&decorated),
+        };
+        let mut machine: Machine<u8> = Machine::new(None, false, &decorated);
+        machine.set_limits(Limits {
+            max_steps: Some(MAX_STEPS),
+            ..Limits::default()
+        });
+        let mut output = Vec::new();
+        let result = run_engine(
+            engine,
+            &mut machine,
+            &bytecode,
+            &mut Cursor::new(self.input),
+            &mut output,
+            &mut (),
+        );
+        match (&self.expected, result) {
+            (Expected::Output(expected), Ok(())) if output == *expected => Ok(()),
+            (Expected::Output(expected), Ok(())) => {
+                Err(format!("it wrote {output:?} instead of {expected:?}"))
+            }
+            (Expected::Output(_), Err(e)) => Err(format!("it failed: {e}")),
+            (Expected::Fails(_), Ok(())) => Err("it finished without failing".to_string()),
+            (Expected::Fails(expected), Err(e)) if expected(&e) => Ok(()),
+            (Expected::Fails(_), Err(e)) => Err(format!("it failed the wrong way: {e}")),
+            (Expected::Invalid, _) => unreachable!("invalid programs aren't run"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_engine_passes() {
+        for check in CHECKS {
+            for engine in [Engine::Interp, Engine::Opt] {
+                assert_eq!(check.run(engine), Ok(()), "{}", check.name);
+            }
+        }
+    }
+}
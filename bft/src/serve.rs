@@ -0,0 +1,458 @@
+//! A small HTTP server that runs programs for anyone who can reach it
+//!
+//! `POST /run` runs the request body as a program and replies with a JSON result record. Options
+//! go in the query string:
+//!
+//! - `input`: the program's input, percent-encoded
+//! - `engine`: `interp` or `opt`, the default
+//! - `cells` and `extensible`: the tape, as for `bft run`, but never longer than `--max-tape`
+//! - `max_steps` and `max_output_bytes`: lower limits than the server's own
+//!
+//! Every run is held to the server's limits, so no request can tie the server up for long.
+//! `GET /metrics` reports what the server has done in the Prometheus text format.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bft_interp::bytecode::Bytecode;
+use bft_interp::error::BftError;
+use bft_interp::limits::Limits;
+use bft_interp::{Machine, DEFAULT_CELLS};
+use bft_types::{DecoratedProgram, Program};
+
+use crate::cli::{run_engine, Engine};
+use crate::json;
+use crate::metrics::Metrics;
+
+/// How long a client may take to send its request or read the reply
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most header bytes read before a request is refused
+const MAX_HEADER_BYTES: u64 = 16 * 1024;
+
+const USAGE: &str = "POST a Brainfuck program to /run. Query options: input, engine, cells, \
+extensible, max_steps, max_output_bytes. GET /metrics for Prometheus metrics.\n";
+
+/// What every request is held to
+pub(crate) struct Settings {
+    /// The most any run may use. Tape limits only apply to extensible tapes
+    pub(crate) limits: Limits,
+    /// The largest program accepted
+    pub(crate) max_program_bytes: usize,
+    /// How many requests are handled at once before the server replies that it's busy
+    pub(crate) max_connections: usize,
+}
+
+/// Handles connections on `listener` forever, each on its own thread
+pub(crate) fn serve(listener: TcpListener, settings: Settings) -> io::Result<()> {
+    let settings = Arc::new(settings);
+    let active = Arc::new(AtomicUsize::new(0));
+    let metrics = Arc::new(Metrics::default());
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to accept a connection");
+                continue;
+            }
+        };
+        if active.fetch_add(1, Ordering::SeqCst) >= settings.max_connections {
+            active.fetch_sub(1, Ordering::SeqCst);
+            metrics.request(503);
+            let _ = reply(
+                &stream,
+                &Response::error(503, "busy", "too many requests at once"),
+            );
+            continue;
+        }
+        let settings = Arc::clone(&settings);
+        let active = Arc::clone(&active);
+        let metrics = Arc::clone(&metrics);
+        thread::spawn(move || {
+            if let Err(e) = connection(stream, &settings, &metrics) {
+                tracing::debug!(error = %e, "Connection failed");
+            }
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+fn connection(stream: TcpStream, settings: &Settings, metrics: &Metrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let response = match read_request(BufReader::new(&stream), settings) {
+        Ok(request) => respond(&request, settings, metrics),
+        Err(response) => response,
+    };
+    metrics.request(response.status);
+    reply(&stream, &response)
+}
+
+/// An HTTP request, reduced to the parts the server uses
+struct Request {
+    method: String,
+    path: String,
+    /// Each `key=value` pair of the query string, percent-decoded
+    query: Vec<(String, Vec<u8>)>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: String) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    /// A JSON reply for a request that couldn't be run
+    fn error(status: u16, kind: &str, message: &str) -> Response {
+        Response::json(
+            status,
+            format!(
+                r#"{{"status":"{kind}","error":{{"message":{}}}}}"#,
+                json::string(message)
+            ),
+        )
+    }
+}
+
+fn reply(mut stream: impl Write, response: &Response) -> io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => "",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+fn read_request(mut reader: impl BufRead, settings: &Settings) -> Result<Request, Response> {
+    let bad = |message: &str| Response::error(400, "bad_request", message);
+    let mut head = (&mut reader).take(MAX_HEADER_BYTES);
+    let mut line = String::new();
+    head.read_line(&mut line)
+        .map_err(|_| bad("the request line isn't text"))?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(bad("the request line is missing"));
+    };
+    let (method, target) = (method.to_string(), target.to_string());
+
+    let mut length = 0;
+    loop {
+        line.clear();
+        if head
+            .read_line(&mut line)
+            .map_err(|_| bad("a header isn't text"))?
+            == 0
+        {
+            return Err(bad("the headers never ended"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| bad("Content-Length isn't a number"))?;
+            }
+        }
+    }
+    if length > settings.max_program_bytes {
+        return Err(Response::error(
+            413,
+            "too_large",
+            &format!(
+                "programs are limited to {} bytes",
+                settings.max_program_bytes
+            ),
+        ));
+    }
+    let mut body = vec![0; length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|_| bad("the body is shorter than Content-Length"))?;
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let key = String::from_utf8(percent_decode(key)?).ok()?;
+            Some((key, percent_decode(value)?))
+        })
+        .collect::<Option<_>>()
+        .ok_or_else(|| bad("the query string is badly encoded"))?;
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        query,
+        body,
+    })
+}
+
+/// Decodes `%XX` escapes and `+` for space, or returns None if an escape is broken
+fn percent_decode(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = text.bytes();
+    let mut decoded = Vec::new();
+    while let Some(byte) = bytes.next() {
+        decoded.push(match byte {
+            b'+' => b' ',
+            b'%' => {
+                let digits = [bytes.next()?, bytes.next()?];
+                u8::from_str_radix(std::str::from_utf8(&digits).ok()?, 16).ok()?
+            }
+            byte => byte,
+        });
+    }
+    Some(decoded)
+}
+
+fn respond(request: &Request, settings: &Settings, metrics: &Metrics) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/run") => run(request, settings, metrics).unwrap_or_else(|response| response),
+        ("GET", "/metrics") => Response {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body: metrics.render(),
+        },
+        ("GET", "/") => Response {
+            status: 200,
+            content_type: "text/plain",
+            body: USAGE.to_string(),
+        },
+        (_, "/run" | "/" | "/metrics") => Response::error(405, "bad_method", "use POST /run"),
+        _ => Response::error(404, "not_found", "use POST /run"),
+    }
+}
+
+/// Runs the request's program, or says why it couldn't
+fn run(request: &Request, settings: &Settings, metrics: &Metrics) -> Result<Response, Response> {
+    let bad = |message: String| Response::error(400, "bad_request", &message);
+    let mut input = &[][..];
+    let mut engine = Engine::Opt;
+    let mut cells = None;
+    let mut extensible = false;
+    let mut limits = settings.limits;
+    for (key, value) in &request.query {
+        let text = || String::from_utf8_lossy(value);
+        let number = || {
+            text()
+                .parse::<u64>()
+                .map_err(|_| bad(format!("{key} must be a number")))
+        };
+        match key.as_str() {
+            "input" => input = value,
+            "engine" => {
+                engine = match &*text() {
+                    "interp" => Engine::Interp,
+                    "opt" => Engine::Opt,
+                    _ => return Err(bad("engine must be interp or opt".to_string())),
+                }
+            }
+            "cells" => {
+                let count = NonZeroUsize::new(number()? as usize)
+                    .ok_or_else(|| bad("cells must be at least 1".to_string()))?;
+                if limits.max_tape.is_some_and(|max| count.get() > max) {
+                    return Err(bad("cells is over the server's limit".to_string()));
+                }
+                cells = Some(count);
+            }
+            "extensible" => extensible = text() == "true",
+            "max_steps" => limits.max_steps = lower(limits.max_steps, number()?),
+            "max_output_bytes" => {
+                limits.max_output_bytes = lower(limits.max_output_bytes, number()?)
+            }
+            _ => return Err(bad(format!("unknown option {key}"))),
+        }
+    }
+
+    let source = String::from_utf8_lossy(&request.body);
+    let prog = DecoratedProgram::from_program(&Program::new("<request>", &source))
+        .map_err(|e| Response::json(400, failure("parse_error", &e.position(), &e.into())))?;
+    let bytecode = match engine {
+        Engine::Interp => Bytecode::compile_unoptimised(&prog),
+        Engine::Opt => Bytecode::compile(&prog),
+    };
+    // Without a size, the tape is as long as the server's limit allows, up to the usual length
+    let cells = cells.or_else(|| {
+        let max = limits.max_tape?;
+        NonZeroUsize::new(max.min(DEFAULT_CELLS))
+    });
+    let mut machine: Machine<u8> = Machine::new(cells, extensible, &prog);
+    machine.set_limits(limits);
+    let mut output = Vec::new();
+    let start = Instant::now();
+    let result = run_engine(
+        engine,
+        &mut machine,
+        &bytecode,
+        &mut input,
+        &mut output,
+        &mut (),
+    );
+    let time = start.elapsed();
+    metrics.execution(engine, machine.steps(), time, result.as_ref().err());
+    let error = match result {
+        Ok(()) => "null".to_string(),
+        Err(error) => {
+            let instruction = error.instruction();
+            let error = BftError::from(error);
+            let position = match instruction {
+                Some(instruction) => format!(
+                    r#","line":{},"character":{}"#,
+                    instruction.line(),
+                    instruction.character()
+                ),
+                None => String::new(),
+            };
+            format!(
+                r#"{{"code":"{}","message":{}{position}}}"#,
+                error.code(),
+                json::string(&error.to_string())
+            )
+        }
+    };
+    Ok(Response::json(
+        200,
+        format!(
+            r#"{{"status":"{}","error":{error},"engine":"{}","steps":{},"output_bytes":{},"head":{},"cells":{},"time":{},"output":{}}}"#,
+            if error == "null" { "ok" } else { "error" },
+            engine.name(),
+            machine.steps(),
+            machine.output_bytes(),
+            machine.head(),
+            machine.cells().len(),
+            time.as_secs_f64(),
+            json::base64(&output)
+        ),
+    ))
+}
+
+/// Describes an error that stopped the program being run at all
+fn failure(status: &str, position: &bft_types::PositionedInstruction, error: &BftError) -> String {
+    format!(
+        r#"{{"status":"{status}","error":{{"code":"{}","message":{},"line":{},"character":{}}}}}"#,
+        error.code(),
+        json::string(&error.to_string()),
+        position.line(),
+        position.character()
+    )
+}
+
+/// The lower of a server limit and the limit a request asks for
+fn lower(server: Option<u64>, requested: u64) -> Option<u64> {
+    Some(server.map_or(requested, |max| max.min(requested)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        Settings {
+            limits: Limits {
+                max_steps: Some(10_000),
+                max_tape: Some(64),
+                ..Limits::default()
+            },
+            max_program_bytes: 100,
+            max_connections: 1,
+        }
+    }
+
+    fn exchange(request: &str) -> String {
+        exchange_counted(request, &Metrics::default())
+    }
+
+    fn exchange_counted(request: &str, metrics: &Metrics) -> String {
+        let response = match read_request(request.as_bytes(), &settings()) {
+            Ok(request) => respond(&request, &settings(), metrics),
+            Err(response) => response,
+        };
+        let mut written = Vec::new();
+        reply(&mut written, &response).unwrap();
+        String::from_utf8(written).unwrap()
+    }
+
+    #[test]
+    fn programs_are_run_with_their_input() {
+        let response = exchange("POST /run?input=h%69 HTTP/1.1\r\nContent-Length: 6\r\n\r\n,.,.+.");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(r#""status":"ok","error":null"#));
+        assert!(response.contains(r#""output":"aGlq""#));
+    }
+
+    #[test]
+    fn limits_and_bad_requests_are_reported() {
+        let response =
+            exchange("POST /run?max_steps=99999 HTTP/1.1\r\nContent-Length: 3\r\n\r\n+[]");
+        assert!(response.contains(r#""code":"E0204""#));
+        assert!(response.contains(r#""steps":10001"#));
+
+        let response = exchange("POST /run HTTP/1.1\r\nContent-Length: 2\r\n\r\n+]");
+        assert!(response.starts_with("HTTP/1.1 400"));
+        assert!(response.contains(r#""code":"E0101""#));
+
+        let response = exchange("POST /run HTTP/1.1\r\nContent-Length: 101\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 413"));
+        assert!(exchange("GET /elsewhere HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn the_default_tape_keeps_to_the_servers_limit() {
+        let response = exchange("POST /run HTTP/1.1\r\nContent-Length: 1\r\n\r\n+");
+        assert!(response.contains(r#""cells":64"#));
+        let response = exchange("POST /run?cells=65 HTTP/1.1\r\nContent-Length: 1\r\n\r\n+");
+        assert!(response.starts_with("HTTP/1.1 400"));
+    }
+
+    #[test]
+    fn metrics_count_runs_and_limits() {
+        let metrics = Metrics::default();
+        exchange_counted(
+            "POST /run?engine=interp HTTP/1.1\r\nContent-Length: 3\r\n\r\n+[]",
+            &metrics,
+        );
+        exchange_counted("POST /run HTTP/1.1\r\nContent-Length: 1\r\n\r\n+", &metrics);
+        let response = exchange_counted("GET /metrics HTTP/1.1\r\n\r\n", &metrics);
+        assert!(response.contains("bft_executions_total{engine=\"interp\"} 1\n"));
+        assert!(response.contains("bft_executions_total{engine=\"opt\"} 1\n"));
+        assert!(response.contains("bft_limit_violations_total{limit=\"steps\"} 1\n"));
+    }
+
+    #[test]
+    fn queries_are_percent_decoded() {
+        assert_eq!(percent_decode("a+b%0A%ff"), Some(b"a b\n\xff".to_vec()));
+        assert_eq!(percent_decode("%4"), None);
+    }
+}
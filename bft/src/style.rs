@@ -0,0 +1,213 @@
+//! How the CLI's own messages look: whether they're coloured, and whether they're shown at all
+
+use bft_interp::analysis::Finding;
+use bft_interp::error::BftError;
+use bft_interp::messages::{Label, Lang, Localize};
+use clap::{Args, ValueEnum};
+#[cfg(test)]
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::io::{self, IsTerminal};
+use tracing_subscriber::EnvFilter;
+
+use crate::messages::{Message, MessageId};
+
+/// When to colour messages
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum ColorChoice {
+    /// Colour messages going to a terminal, unless NO_COLOR is set
+    Auto,
+    Always,
+    Never,
+}
+
+// Command line options shared by every subcommand. This isn't a doc comment, as clap would use it
+// as the description of bft itself
+#[derive(Args)]
+pub(crate) struct StyleArgs {
+    /// When to colour bft's own messages
+    #[arg(
+        long,
+        value_enum,
+        default_value = "auto",
+        global = true,
+        env = "BFT_COLOR"
+    )]
+    pub(crate) color: ColorChoice,
+    /// Only print errors, not warnings or other messages from bft itself
+    ///
+    /// The program's own output is unaffected.
+    #[arg(short, long, global = true, env = "BFT_QUIET")]
+    pub(crate) quiet: bool,
+    /// Don't print banners or the headings of tables, so output can be read by other programs
+    #[arg(long, global = true, env = "BFT_NO_BANNER")]
+    pub(crate) no_banner: bool,
+    /// Log what bft is doing to standard error. Repeat for more detail, e.g. -vv
+    ///
+    /// BFT_LOG takes precedence, and accepts filters such as `bft_interp=trace`.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub(crate) verbose: u8,
+    /// The language to write messages in, such as `fr`. If not given, it's taken from LC_ALL,
+    /// LC_MESSAGES or LANG, falling back to English
+    #[arg(long, global = true, env = "BFT_LANG", value_parser = parse_lang)]
+    pub(crate) lang: Option<Lang>,
+}
+
+fn parse_lang(tag: &str) -> Result<Lang, String> {
+    Lang::from_tag(tag).ok_or_else(|| {
+        let known: Vec<&str> = Lang::ALL.iter().map(|lang| lang.tag()).collect();
+        // The language hasn't been chosen yet, so this is in the locale's
+        Message::new(MessageId::UnknownLang)
+            .with("tag", tag)
+            .with("langs", known.join(", "))
+            .localized(Lang::detect())
+            .to_string()
+    })
+}
+
+const RED: &str = "31";
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+const CYAN: &str = "36";
+
+/// Where a line bft prints goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Decides how to print messages, based on the command line and environment
+pub(crate) struct Style {
+    stdout_color: bool,
+    stderr_color: bool,
+    quiet: bool,
+    banner: bool,
+    lang: Lang,
+    /// The lines printed so far, which tests keep instead of printing
+    #[cfg(test)]
+    pub(crate) printed: RefCell<Vec<(Stream, String)>>,
+}
+
+impl Style {
+    pub(crate) fn new(args: &StyleArgs) -> Style {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        let wanted = |is_terminal: bool| match args.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => is_terminal && !no_color,
+        };
+        Style {
+            stdout_color: wanted(io::stdout().is_terminal()),
+            stderr_color: wanted(io::stderr().is_terminal()),
+            quiet: args.quiet,
+            banner: !args.no_banner,
+            lang: args.lang.unwrap_or_else(Lang::detect),
+            #[cfg(test)]
+            printed: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Prints a line to stdout or stderr
+    fn print_to(&self, stream: Stream, line: impl Display) {
+        #[cfg(test)]
+        self.printed.borrow_mut().push((stream, line.to_string()));
+        #[cfg(not(test))]
+        match stream {
+            Stream::Stdout => println!("{line}"),
+            Stream::Stderr => eprintln!("{line}"),
+        }
+    }
+
+    /// Sends log messages to stderr, as detailed as -v asks for, or as BFT_LOG filters them
+    pub(crate) fn init_logging(&self, verbose: u8) {
+        let default = match verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        };
+        let filter = EnvFilter::try_from_env("BFT_LOG").unwrap_or_else(|_| EnvFilter::new(default));
+        tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(io::stderr)
+            .with_ansi(self.stderr_color)
+            .init();
+    }
+
+    /// Prints an error and its code to stderr. These are shown even when quiet
+    pub(crate) fn error(&self, source: impl Display, error: &BftError) {
+        let label = format!("{}[{}]", self.lang.label(Label::Error), error.code());
+        let label = paint(self.stderr_color, RED, &label);
+        self.print_to(
+            Stream::Stderr,
+            format_args!("{source}: {label}: {}", error.localized(self.lang)),
+        );
+    }
+
+    /// Prints a lint's finding to stderr as a warning
+    pub(crate) fn warning(&self, source: impl Display, kind: &str, finding: &Finding) {
+        if !self.quiet {
+            let label = format!("{}[{kind}]", self.lang.label(Label::Warning));
+            let label = paint(self.stderr_color, YELLOW, &label);
+            self.print_to(
+                Stream::Stderr,
+                format_args!("{source}: {label}: {}", finding.localized(self.lang)),
+            );
+        }
+    }
+
+    /// Prints an informational message to stderr
+    pub(crate) fn note(&self, message: &Message) {
+        if !self.quiet {
+            self.print_to(Stream::Stderr, message.localized(self.lang));
+        }
+    }
+
+    /// Prints a banner or table heading to stdout, unless --no-banner was given
+    pub(crate) fn banner(&self, text: impl Display) {
+        if self.banner {
+            self.print_to(Stream::Stdout, text);
+        }
+    }
+
+    /// Prints a line of a command's results to stdout. These are shown even when quiet
+    pub(crate) fn result(&self, message: &Message) {
+        self.print_to(Stream::Stdout, message.localized(self.lang));
+    }
+
+    /// The language messages are written in
+    pub(crate) fn lang(&self) -> Lang {
+        self.lang
+    }
+
+    /// Whether standard output should be coloured
+    pub(crate) fn stdout_color(&self) -> bool {
+        self.stdout_color
+    }
+
+    /// Prints the output of a diff to stdout, colouring removals, additions and hunk headers
+    pub(crate) fn diff(&self, diff: impl Display) {
+        for line in diff.to_string().lines() {
+            let color = match line.chars().next() {
+                Some('-') => RED,
+                Some('+') => GREEN,
+                Some('@') => CYAN,
+                _ => "",
+            };
+            self.print_to(
+                Stream::Stdout,
+                paint(self.stdout_color && !color.is_empty(), color, line),
+            );
+        }
+    }
+}
+
+/// Wraps text in an ANSI colour escape, if colour is enabled
+fn paint(enabled: bool, color: &str, text: &str) -> String {
+    if enabled {
+        format!("\x1b[{color}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
@@ -0,0 +1,233 @@
+//! Switching the terminal into raw mode, so that programs see each keypress as it happens
+
+/// Raw mode on standard input, which is switched off again when this is dropped
+pub(crate) struct RawMode(());
+
+#[cfg(unix)]
+mod unix {
+    use std::io::{self, IsTerminal};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Once, OnceLock};
+
+    use super::RawMode;
+
+    /// The terminal settings from before raw mode was switched on, and the raw ones
+    static SETTINGS: OnceLock<(libc::termios, libc::termios)> = OnceLock::new();
+    /// Whether the terminal is in raw mode
+    static ACTIVE: AtomicBool = AtomicBool::new(false);
+    /// The signals that would otherwise leave the terminal in raw mode
+    const SIGNALS: [libc::c_int; 5] = [
+        libc::SIGINT,
+        libc::SIGTERM,
+        libc::SIGHUP,
+        libc::SIGQUIT,
+        libc::SIGTSTP,
+    ];
+
+    impl RawMode {
+        /// Switches standard input into raw mode, if it's a terminal
+        ///
+        /// Input is no longer echoed or gathered into lines, but Ctrl-C still interrupts bft and
+        /// Ctrl-Z still suspends it, putting the terminal back first.
+        pub(crate) fn enable() -> io::Result<Option<RawMode>> {
+            if !io::stdin().is_terminal() {
+                return Ok(None);
+            }
+            let (_, raw) = match SETTINGS.get() {
+                Some(settings) => settings,
+                None => {
+                    // SAFETY: termios is plain old data, which tcgetattr fills in
+                    let mut original: libc::termios = unsafe { std::mem::zeroed() };
+                    // SAFETY: the pointer is to a live termios
+                    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    let mut raw = original;
+                    raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+                    raw.c_cc[libc::VMIN] = 1;
+                    raw.c_cc[libc::VTIME] = 0;
+                    SETTINGS.get_or_init(|| (original, raw))
+                }
+            };
+            install_handlers();
+            set(raw)?;
+            ACTIVE.store(true, Ordering::SeqCst);
+            Ok(Some(RawMode(())))
+        }
+    }
+
+    /// Applies terminal settings to standard input
+    fn set(termios: &libc::termios) -> io::Result<()> {
+        // SAFETY: the pointer is to a live termios. tcsetattr is safe to call from signal handlers
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Puts the terminal back the way it was before raw mode, if raw mode is on
+    pub(super) fn restore() {
+        if let (true, Some((original, _))) = (ACTIVE.swap(false, Ordering::SeqCst), SETTINGS.get())
+        {
+            // If this fails there's nothing more to do
+            let _ = set(original);
+        }
+    }
+
+    /// Restores the terminal before bft is stopped by a signal, and before a panic message is
+    /// printed so that it's readable
+    fn install_handlers() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                restore();
+                previous(info);
+            }));
+            for signal in SIGNALS {
+                handle(signal);
+            }
+        });
+    }
+
+    /// Sends `signal` to [on_signal] from now on
+    fn handle(signal: libc::c_int) {
+        let handler = on_signal as extern "C" fn(libc::c_int);
+        // SAFETY: the handler only makes async-signal-safe calls
+        unsafe { libc::signal(signal, handler as libc::sighandler_t) };
+    }
+
+    /// Restores the terminal, then lets the signal do what it would have done
+    extern "C" fn on_signal(signal: libc::c_int) {
+        let Some((original, raw)) = SETTINGS.get() else {
+            return;
+        };
+        let active = ACTIVE.load(Ordering::SeqCst);
+        if active {
+            let _ = set(original);
+        }
+        // SAFETY: these calls are all async-signal-safe. The signal is blocked while its handler
+        // runs, so it has to be unblocked for raising it again to take effect straight away
+        unsafe {
+            let mut blocked: libc::sigset_t = std::mem::zeroed();
+            libc::sigemptyset(&mut blocked);
+            libc::sigaddset(&mut blocked, signal);
+            libc::sigprocmask(libc::SIG_UNBLOCK, &blocked, std::ptr::null_mut());
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+        // Only a stop signal returns here, once bft has been continued
+        if active {
+            let _ = set(raw);
+        }
+        handle(signal);
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::{Command, ExitStatus, Stdio};
+
+        use super::*;
+
+        /// Tells [child] which case to run, when it's run by [in_child]
+        const CASE: &str = "BFT_TERMINAL_CASE";
+
+        /// Opens a pseudo-terminal, returning its controlling end and the terminal itself
+        fn pty() -> (OwnedFd, OwnedFd) {
+            let (mut controller, mut terminal) = (0, 0);
+            // SAFETY: the pointers are to live ints, and the rest may be null
+            let opened = unsafe {
+                libc::openpty(
+                    &mut controller,
+                    &mut terminal,
+                    std::ptr::null_mut(),
+                    std::ptr::null(),
+                    std::ptr::null(),
+                )
+            };
+            assert_eq!(opened, 0, "{}", io::Error::last_os_error());
+            // SAFETY: openpty just opened both, and nothing else owns them
+            unsafe {
+                (
+                    OwnedFd::from_raw_fd(controller),
+                    OwnedFd::from_raw_fd(terminal),
+                )
+            }
+        }
+
+        /// The local modes of the terminal `fd`, such as whether it echoes
+        fn local_modes(fd: RawFd) -> libc::tcflag_t {
+            // SAFETY: as in RawMode::enable
+            let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+            // SAFETY: the pointer is to a live termios
+            assert_eq!(unsafe { libc::tcgetattr(fd, &mut termios) }, 0);
+            termios.c_lflag
+        }
+
+        /// Runs [child] in a new copy of the tests, so that raw mode and signals don't touch this one
+        fn in_child(case: &str, stdin: Stdio) -> ExitStatus {
+            Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "terminal::unix::tests::child"])
+                .env(CASE, case)
+                .stdin(stdin)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .unwrap()
+        }
+
+        /// Switches raw mode on, then off again by dropping it or by being killed, as the case says
+        #[test]
+        fn child() {
+            let Ok(case) = std::env::var(CASE) else {
+                return;
+            };
+            let raw = RawMode::enable().unwrap();
+            if case == "not a terminal" {
+                assert!(raw.is_none());
+                return;
+            }
+            let modes = local_modes(libc::STDIN_FILENO);
+            assert_eq!(modes & (libc::ICANON | libc::ECHO), 0);
+            if case == "signal" {
+                // SAFETY: raising a signal is always safe; this one ends the process
+                unsafe { libc::raise(libc::SIGTERM) };
+            }
+            drop(raw);
+            assert_ne!(local_modes(libc::STDIN_FILENO) & libc::ICANON, 0);
+        }
+
+        #[test]
+        fn the_terminal_is_put_back_after_raw_mode() {
+            assert!(in_child("not a terminal", Stdio::null()).success());
+            let (_controller, terminal) = pty();
+            let original = local_modes(terminal.as_raw_fd());
+            let stdin = || Stdio::from(terminal.try_clone().unwrap());
+            assert!(in_child("drop", stdin()).success());
+            assert_eq!(local_modes(terminal.as_raw_fd()), original);
+            let status = in_child("signal", stdin());
+            assert_eq!(status.signal(), Some(libc::SIGTERM));
+            assert_eq!(local_modes(terminal.as_raw_fd()), original);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl RawMode {
+    /// Raw mode is only supported on Unix-like systems
+    pub(crate) fn enable() -> std::io::Result<Option<RawMode>> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "raw mode isn't supported on this platform",
+        ))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unix::restore();
+    }
+}
@@ -0,0 +1,82 @@
+//! Slowing a program's output down, so that animations can be watched
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Output that starts a new screen: moving the cursor home, clearing the screen, resetting the
+/// terminal, or a form feed
+const FRAME_MARKERS: [&[u8]; 4] = [b"\x1b[H", b"\x1b[2J", b"\x1bc", b"\x0c"];
+
+/// Returns whether the bytes ending at the newest one start a new screen
+fn starts_frame(recent: &[u8]) -> bool {
+    FRAME_MARKERS.iter().any(|marker| recent.ends_with(marker))
+}
+
+/// Pauses after each byte written, and before each new screen, as it's told to
+pub(crate) struct Throttle<W> {
+    inner: W,
+    byte_delay: Option<Duration>,
+    frame_interval: Option<Duration>,
+    last_frame: Option<Instant>,
+    /// The most recent bytes written, enough to hold the longest frame marker
+    recent: [u8; 4],
+}
+
+impl<W: Write> Throttle<W> {
+    /// Pauses for `byte_delay` after every byte, and draws no more than `fps` screens a second
+    pub(crate) fn new(inner: W, byte_delay: Option<Duration>, fps: Option<u32>) -> Throttle<W> {
+        Throttle {
+            inner,
+            byte_delay,
+            frame_interval: fps.map(|fps| Duration::from_secs(1) / fps),
+            last_frame: None,
+            recent: [0; 4],
+        }
+    }
+
+    /// Shows everything written so far, then waits until the next screen is due
+    fn wait_for_frame(&mut self, interval: Duration) -> io::Result<()> {
+        self.inner.flush()?;
+        if let Some(due) = self.last_frame.map(|last| last + interval) {
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+        self.last_frame = Some(Instant::now());
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Throttle<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            self.recent.rotate_left(1);
+            self.recent[3] = byte;
+            if let Some(interval) = self.frame_interval.filter(|_| starts_frame(&self.recent)) {
+                self.wait_for_frame(interval)?;
+            }
+            self.inner.write_all(&[byte])?;
+            if let Some(delay) = self.byte_delay {
+                self.inner.flush()?;
+                std::thread::sleep(delay);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screens_start_at_clears_and_cursor_homes() {
+        assert!(starts_frame(b"ab\x1b[H"));
+        assert!(starts_frame(b"\x1b[2J"));
+        assert!(starts_frame(b"\0\0\0\x0c"));
+        assert!(!starts_frame(b"\x1b[2K"));
+        assert!(!starts_frame(b"\x1b[H\n"));
+    }
+}
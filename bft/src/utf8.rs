@@ -0,0 +1,196 @@
+//! Treating a program's input and output as UTF-8 text
+
+use std::io::{self, Read, Write};
+
+use clap::ValueEnum;
+
+/// What to do with bytes that aren't valid UTF-8
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub(crate) enum Utf8Mode {
+    /// Replace each invalid sequence with U+FFFD
+    Replace,
+    /// Fail with an I/O error
+    Strict,
+}
+
+/// The Unicode replacement character, as UTF-8
+const REPLACEMENT: &[u8] = "\u{FFFD}".as_bytes();
+
+/// Checks a stream of bytes is UTF-8, holding back sequences until they're complete
+struct Decoder {
+    mode: Utf8Mode,
+    pending: Vec<u8>,
+}
+
+impl Decoder {
+    fn new(mode: Utf8Mode) -> Decoder {
+        Decoder {
+            mode,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Adds `bytes` to the stream, appending every complete character to `out`
+    fn push(&mut self, bytes: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        self.pending.extend_from_slice(bytes);
+        loop {
+            match std::str::from_utf8(&self.pending) {
+                Ok(text) => {
+                    out.extend_from_slice(text.as_bytes());
+                    self.pending.clear();
+                    return Ok(());
+                }
+                Err(e) => {
+                    let valid = e.valid_up_to();
+                    out.extend_from_slice(&self.pending[..valid]);
+                    let Some(invalid) = e.error_len() else {
+                        // The rest may be the start of a character that hasn't finished arriving
+                        self.pending.drain(..valid);
+                        return Ok(());
+                    };
+                    self.pending.drain(..valid + invalid);
+                    self.invalid(out)?;
+                }
+            }
+        }
+    }
+
+    /// Ends the stream, which mustn't stop partway through a character
+    fn finish(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.pending.clear();
+        self.replace(out, "the text ended partway through a UTF-8 character")
+    }
+
+    fn invalid(&self, out: &mut Vec<u8>) -> io::Result<()> {
+        self.replace(out, "the text is not valid UTF-8")
+    }
+
+    /// Stands in for an invalid sequence, or fails with `message` in strict mode
+    fn replace(&self, out: &mut Vec<u8>, message: &str) -> io::Result<()> {
+        match self.mode {
+            Utf8Mode::Replace => {
+                out.extend_from_slice(REPLACEMENT);
+                Ok(())
+            }
+            Utf8Mode::Strict => Err(io::Error::new(io::ErrorKind::InvalidData, message)),
+        }
+    }
+}
+
+/// Writes only valid UTF-8 to the writer it wraps
+pub(crate) struct Utf8Writer<W> {
+    inner: W,
+    decoder: Decoder,
+}
+
+impl<W: Write> Utf8Writer<W> {
+    pub(crate) fn new(inner: W, mode: Utf8Mode) -> Utf8Writer<W> {
+        Utf8Writer {
+            inner,
+            decoder: Decoder::new(mode),
+        }
+    }
+
+    /// Deals with any unfinished character at the end of the output, and flushes it
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        let mut out = Vec::new();
+        self.decoder.finish(&mut out)?;
+        self.inner.write_all(&out)?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for Utf8Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut out = Vec::new();
+        self.decoder.push(buf, &mut out)?;
+        self.inner.write_all(&out)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reads only valid UTF-8 from the reader it wraps
+pub(crate) struct Utf8Reader<R> {
+    inner: R,
+    decoder: Decoder,
+    ready: Vec<u8>,
+}
+
+impl<R: Read> Utf8Reader<R> {
+    pub(crate) fn new(inner: R, mode: Utf8Mode) -> Utf8Reader<R> {
+        Utf8Reader {
+            inner,
+            decoder: Decoder::new(mode),
+            ready: Vec::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for Utf8Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut chunk = [0; 1024];
+        while self.ready.is_empty() {
+            let read = self.inner.read(&mut chunk)?;
+            if read == 0 {
+                self.decoder.finish(&mut self.ready)?;
+                break;
+            }
+            self.decoder.push(&chunk[..read], &mut self.ready)?;
+        }
+        let count = buf.len().min(self.ready.len());
+        buf[..count].copy_from_slice(&self.ready[..count]);
+        self.ready.drain(..count);
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `bytes` one at a time, as a Machine does
+    fn write_bytewise(bytes: &[u8], mode: Utf8Mode) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut writer = Utf8Writer::new(&mut out, mode);
+        for byte in bytes {
+            writer.write_all(&[*byte])?;
+        }
+        writer.finish()?;
+        Ok(out)
+    }
+
+    #[test]
+    fn characters_split_across_writes_pass_through() {
+        let text = "héllo, wörld ✓";
+        assert_eq!(
+            write_bytewise(text.as_bytes(), Utf8Mode::Strict).unwrap(),
+            text.as_bytes()
+        );
+    }
+
+    #[test]
+    fn invalid_sequences_are_replaced_or_rejected() {
+        assert_eq!(
+            write_bytewise(b"a\xffb\xe2\x9c", Utf8Mode::Replace).unwrap(),
+            "a\u{FFFD}b\u{FFFD}".as_bytes()
+        );
+        assert!(write_bytewise(b"a\xff", Utf8Mode::Strict).is_err());
+        assert!(write_bytewise(b"\xe2\x9c", Utf8Mode::Strict).is_err());
+    }
+
+    #[test]
+    fn input_is_checked_too() {
+        let mut text = String::new();
+        Utf8Reader::new(&b"ok\xc0!"[..], Utf8Mode::Replace)
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "ok\u{FFFD}!");
+    }
+}
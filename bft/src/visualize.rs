@@ -0,0 +1,274 @@
+//! Animating a program in the terminal: the tape as coloured cells, the head, and the instruction
+//! about to run
+//!
+//! When standard input is a terminal, keys control the animation: space pauses and resumes, `s`
+//! steps once while paused, `+` and `-` double and halve the speed, and `q` quits.
+
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use bft_interp::error::BftError;
+use bft_interp::Machine;
+
+use crate::terminal::RawMode;
+
+/// How many frames are drawn a second
+const FPS: f64 = 30.0;
+
+/// How many instructions either side of the current one are shown
+const CONTEXT: usize = 30;
+
+/// How many lines of the program's output are shown
+const OUTPUT_LINES: usize = 4;
+
+/// How the animation looks
+pub(crate) struct Settings {
+    /// How many instructions are executed a second to begin with
+    pub(crate) speed: f64,
+    /// How many cells either side of the head are shown
+    pub(crate) window: usize,
+    pub(crate) color: bool,
+}
+
+/// Where the animation has got to
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Running,
+    Paused,
+    Finished,
+}
+
+/// Runs the program on `input`, drawing a frame after each batch of steps, until it finishes or
+/// the viewer quits
+///
+/// When keys are being read, the last frame stays up until one is pressed.
+pub(crate) fn visualize(
+    machine: &mut Machine<u8>,
+    mut input: &[u8],
+    mut settings: Settings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let raw = RawMode::enable()?;
+    let keys = raw.as_ref().map(|_| read_keys());
+    let mut stdout = io::stdout().lock();
+    // Hide the cursor, so it doesn't flicker across the frame
+    write!(stdout, "\x1b[?25l\x1b[2J")?;
+    let mut output = Vec::new();
+    let mut state = State::Running;
+    let mut error = None;
+    let mut budget = 0.0;
+    loop {
+        let mut steps = 0;
+        for key in keys.iter().flat_map(Receiver::try_iter) {
+            match (key, state) {
+                (b'q', _) => state = State::Finished,
+                (b' ', State::Running) => state = State::Paused,
+                (b' ', State::Paused) => state = State::Running,
+                (b's', State::Paused) => steps += 1,
+                (b'+', _) => settings.speed *= 2.0,
+                (b'-', _) => settings.speed = (settings.speed / 2.0).max(1.0),
+                _ => {}
+            }
+        }
+        if state == State::Finished {
+            break;
+        }
+        if state == State::Running {
+            budget += settings.speed / FPS;
+            steps = budget as u64;
+            budget -= steps as f64;
+        }
+        for _ in 0..steps {
+            match machine.step(&mut input, &mut output) {
+                Ok(true) => {}
+                Ok(false) => state = State::Finished,
+                Err(e) => {
+                    error = Some(BftError::from(e));
+                    state = State::Finished;
+                }
+            }
+            if state == State::Finished {
+                break;
+            }
+        }
+        write!(
+            stdout,
+            "\x1b[H{}\x1b[J",
+            frame(machine, &output, state, error.as_ref(), &settings)
+        )?;
+        stdout.flush()?;
+        if state == State::Finished {
+            // Leave the last frame up until the viewer has seen it
+            if let Some(keys) = &keys {
+                let _ = keys.recv();
+            }
+            break;
+        }
+        thread::sleep(Duration::from_secs_f64(1.0 / FPS));
+    }
+    write!(stdout, "\x1b[?25h")?;
+    stdout.flush()?;
+    match error {
+        Some(error) => Err(error.into()),
+        None => Ok(()),
+    }
+}
+
+/// Reads keys from standard input on another thread, which is left blocked reading when bft exits
+fn read_keys() -> Receiver<u8> {
+    let (sender, keys) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        let mut keys = [0; 16];
+        while let Ok(read @ 1..) = stdin.read(&mut keys) {
+            if keys[..read].iter().any(|&key| sender.send(key).is_err()) {
+                break;
+            }
+        }
+    });
+    keys
+}
+
+/// Draws the tape, the program around the current instruction, and the end of the output
+fn frame(
+    machine: &Machine<u8>,
+    output: &[u8],
+    state: State,
+    error: Option<&BftError>,
+    settings: &Settings,
+) -> String {
+    let mut lines = Vec::new();
+    let status = match (state, error) {
+        (_, Some(error)) => format!("stopped: {error}"),
+        (State::Finished, None) => "finished".to_string(),
+        (State::Paused, None) => "paused".to_string(),
+        (State::Running, None) => "running".to_string(),
+    };
+    lines.push(format!(
+        "step {}  head {}  speed {}/s  {status}",
+        machine.steps(),
+        machine.head(),
+        settings.speed
+    ));
+    lines.push("[space] pause  [s] step  [+/-] speed  [q] quit".to_string());
+    lines.push(String::new());
+
+    let cells = machine.cells();
+    let head = machine.head();
+    let start = head.saturating_sub(settings.window);
+    let end = (head + settings.window + 1).min(cells.len());
+    let (mut indices, mut values, mut marks, mut chars) =
+        (String::new(), String::new(), String::new(), String::new());
+    for (index, &value) in cells.iter().enumerate().take(end).skip(start) {
+        indices.push_str(&format!("{:>4}  ", index % 10_000));
+        values.push_str(&cell(value, index == head, settings.color));
+        values.push(' ');
+        marks.push_str(if index == head { "  ^   " } else { "      " });
+        let shown = if value.is_ascii_graphic() {
+            value as char
+        } else {
+            '.'
+        };
+        chars.push_str(&format!("  {shown}   "));
+    }
+    lines.extend([indices, values, marks, chars, String::new()]);
+
+    let instructions = machine.prog().decorated_instructions();
+    match machine.next_instruction() {
+        Some(next) => {
+            let next = next.instruction();
+            let current = machine
+                .prog()
+                .position_to_index(next.line(), next.character())
+                .unwrap_or(0);
+            let first = current.saturating_sub(CONTEXT);
+            let mut code = String::new();
+            for (index, instruction) in instructions
+                .iter()
+                .enumerate()
+                .take(current + CONTEXT + 1)
+                .skip(first)
+            {
+                let symbol = instruction.instruction().instruction().to_byte() as char;
+                if index == current {
+                    code.push_str(&highlight(symbol, settings.color));
+                } else {
+                    code.push(symbol);
+                }
+            }
+            lines.push(next.to_string());
+            lines.push(code);
+        }
+        None => {
+            lines.push("end of program".to_string());
+            lines.push(String::new());
+        }
+    }
+    lines.push(String::new());
+
+    lines.push(format!("output ({} bytes):", output.len()));
+    let text = String::from_utf8_lossy(output);
+    let shown: Vec<&str> = text.lines().collect();
+    for line in &shown[shown.len().saturating_sub(OUTPUT_LINES)..] {
+        lines.push(line.chars().filter(|c| !c.is_control()).collect());
+    }
+    // Clearing to the end of each line wipes whatever the last frame left there
+    lines.iter().map(|line| format!("{line}\x1b[K\n")).collect()
+}
+
+/// Draws one cell as a block shaded by its value, or in brackets without colour
+fn cell(value: u8, head: bool, color: bool) -> String {
+    match (color, head) {
+        (false, true) => format!("[{value:>3}]"),
+        (false, false) => format!(" {value:>3} "),
+        (true, true) => format!("\x1b[30;43m {value:>3} \x1b[0m"),
+        (true, false) if value == 0 => format!(" {value:>3} "),
+        (true, false) => {
+            // The 24 greys run from 232, nearly black, to 255, nearly white
+            let grey = 232 + u16::from(value) * 23 / 255;
+            let text = if grey > 243 { 30 } else { 37 };
+            format!("\x1b[{text};48;5;{grey}m {value:>3} \x1b[0m")
+        }
+    }
+}
+
+fn highlight(symbol: char, color: bool) -> String {
+    if color {
+        format!("\x1b[7m{symbol}\x1b[0m")
+    } else {
+        format!("({symbol})")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft_types::{DecoratedProgram, Program};
+
+    #[test]
+    fn frames_show_the_tape_and_the_next_instruction() {
+        let prog = DecoratedProgram::from_program(&Program::new("<test>", "++>+++<.")).unwrap();
+        let mut machine: Machine<u8> = Machine::new(None, false, &prog);
+        for _ in 0..3 {
+            machine.step(&mut io::empty(), &mut io::sink()).unwrap();
+        }
+        let settings = Settings {
+            speed: 10.0,
+            window: 1,
+            color: false,
+        };
+        let frame = frame(&machine, b"ab\ncd", State::Paused, None, &settings);
+        let lines: Vec<&str> = frame
+            .lines()
+            .map(|line| line.trim_end_matches("\x1b[K"))
+            .collect();
+        assert_eq!(lines[0], "step 3  head 1  speed 10/s  paused");
+        assert_eq!(lines[3], "   0     1     2  ");
+        assert_eq!(lines[4], "   2  [  0]    0  ");
+        assert_eq!(lines[5], "        ^         ");
+        assert_eq!(lines[8], "1:4 Increment the byte at the current location");
+        assert_eq!(lines[9], "++>(+)++<.");
+        assert_eq!(&lines[11..], ["output (5 bytes):", "ab", "cd"]);
+    }
+}